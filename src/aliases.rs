@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+/// Alias definitions created with the `alias` builtin.
+///
+/// Aliases are expanded by the script parser, not the expander: an alias
+/// value may contain operators (`alias ll='ls -l | less'`), so it has to be
+/// spliced into the token stream before pipelines and lists are built.
+#[derive(Debug, Clone, Default)]
+pub struct AliasTable {
+    // BTreeMap so `alias` with no arguments lists names in sorted order.
+    aliases: BTreeMap<String, String>,
+}
+
+impl AliasTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.aliases.insert(name.to_string(), value.to_string());
+    }
+
+    /// Remove an alias, returning false if it was not defined.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.aliases.clear();
+    }
+
+    /// All aliases as `(name, value)` pairs, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// Alias names may not contain characters the tokenizer treats specially,
+/// or `=` which separates the name from the value in `alias name=value`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|c| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '=' | '/' | '$' | '`' | '\'' | '"' | '\\' | '|' | '&' | ';' | '<' | '>' | '(' | ')'
+                )
+        })
+}
+
+/// Render an alias the way `alias` lists it, so the output can be pasted
+/// back into the shell: `alias ll='ls -l'`.
+pub fn format_definition(name: &str, value: &str) -> String {
+    format!("alias {name}='{}'", value.replace('\'', r"'\''"))
+}
@@ -1,4 +1,6 @@
-use crate::parser::Word;
+use std::fmt;
+
+use crate::parser::{self, Word};
 
 /// Controls whether a chained command runs based on the previous exit code.
 #[derive(Debug, Clone, PartialEq)]
@@ -11,14 +13,183 @@ pub enum Connector {
     Or,
 }
 
-/// One pipeline's worth of words, annotated with the connector that
-/// determines whether it should run given the previous exit code.
+/// A sequence of and-or lists separated by `;`, `&`, or newlines.
+///
+/// This is the body of a whole script as well as of every block inside a
+/// compound command (`then ...`, `else ...`).
+///
+/// A list is parsed once and can be run any number of times: its words
+/// are only expanded when [`Shell::run_list`](crate::shell::Shell::run_list)
+/// reaches them, so a body that runs repeatedly never goes back through the
+/// tokenizer.
+pub type CommandList = Vec<AndOrList>;
+
+/// Pipelines joined by `&&` / `||`, run as one unit.
+///
+/// A trailing `&` backgrounds the whole list, so `a && b &` runs both `a`
+/// and `b` in the background with the `&&` gate intact.
+#[derive(Debug, Clone)]
+pub struct AndOrList {
+    pub entries: Vec<ChainEntry>,
+    /// True when the list was terminated by `&`.
+    pub background: bool,
+}
+
+/// One pipeline, annotated with the connector that determines whether it
+/// should run given the previous exit code.
 ///
 /// The first entry in a chain always uses [`Connector::Sequence`].
 #[derive(Debug, Clone)]
 pub struct ChainEntry {
-    /// Raw words for this pipeline segment (pipe `|` tokens still embedded).
-    pub words: Vec<Word>,
+    pub pipeline: Pipeline,
     /// How to decide whether to run this entry based on the last exit code.
     pub connector: Connector,
 }
+
+/// Commands connected by `|`.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    /// Set when the pipeline is preceded by the `time` reserved word.
+    pub time: Option<Timing>,
+}
+
+/// How `time` reports the pipeline it runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// `time`: in the format given by `$TIMEFORMAT`.
+    Format,
+    /// `time -p`: in the POSIX format.
+    Posix,
+}
+
+/// A single pipeline stage.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Raw words of a simple command, redirection operators still embedded.
+    /// Expansion happens at execution time because it depends on `$?`.
+    Simple(Vec<Word>),
+    /// A compound command followed by redirection words that apply to the
+    /// whole of it, e.g. `{ a; b; } > out.txt 2>&1`.
+    Compound(CompoundCommand, Vec<Word>),
+}
+
+/// Commands built from other commands.
+#[derive(Debug, Clone)]
+pub enum CompoundCommand {
+    /// `{ list; }` — runs in the current shell, unlike a subshell.
+    BraceGroup(CommandList),
+    /// `if ...; then ...; elif ...; else ...; fi`
+    If(IfClause),
+}
+
+/// An `if` statement with any number of `elif` branches.
+#[derive(Debug, Clone)]
+pub struct IfClause {
+    /// The `if` branch followed by each `elif` branch, tried in order.
+    pub branches: Vec<IfBranch>,
+    /// Body of the `else` branch, if present.
+    pub else_body: Option<CommandList>,
+}
+
+/// A condition list and the body that runs when it exits 0.
+#[derive(Debug, Clone)]
+pub struct IfBranch {
+    pub condition: CommandList,
+    pub body: CommandList,
+}
+
+// ── Source rendering ──
+//
+// `Display` renders a node back to one-line shell source. The text is what
+// `jobs` shows and what a child shell is fed to run a compound command.
+
+/// Write each list item followed by its terminator (`;` or ` &`).
+fn fmt_terminated_list(list: &CommandList, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (idx, item) in list.iter().enumerate() {
+        if idx > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{item}{}", if item.background { " &" } else { ";" })?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for AndOrList {
+    /// The trailing `&` is not part of the rendering: a backgrounded list is
+    /// labelled in `jobs` by its command text alone.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            match entry.connector {
+                Connector::Sequence => {}
+                Connector::And => write!(f, " && ")?,
+                Connector::Or => write!(f, " || ")?,
+            }
+            write!(f, "{}", entry.pipeline)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time {
+            Some(Timing::Format) => write!(f, "time ")?,
+            Some(Timing::Posix) => write!(f, "time -p ")?,
+            None => {}
+        }
+        for (idx, command) in self.commands.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{command}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Simple(words) => write!(f, "{}", parser::words_to_source(words)),
+            Command::Compound(compound, redirects) => {
+                write!(f, "{compound}")?;
+                if !redirects.is_empty() {
+                    write!(f, " {}", parser::words_to_source(redirects))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundCommand::BraceGroup(body) => {
+                write!(f, "{{ ")?;
+                fmt_terminated_list(body, f)?;
+                write!(f, " }}")
+            }
+            CompoundCommand::If(clause) => write!(f, "{clause}"),
+        }
+    }
+}
+
+impl fmt::Display for IfClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, branch) in self.branches.iter().enumerate() {
+            write!(f, "{} ", if idx == 0 { "if" } else { "elif" })?;
+            fmt_terminated_list(&branch.condition, f)?;
+            write!(f, " then ")?;
+            fmt_terminated_list(&branch.body, f)?;
+            write!(f, " ")?;
+        }
+        if let Some(body) = &self.else_body {
+            write!(f, "else ")?;
+            fmt_terminated_list(body, f)?;
+            write!(f, " ")?;
+        }
+        write!(f, "fi")
+    }
+}
@@ -1,99 +1,687 @@
+use std::collections::{BTreeMap, HashMap};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[cfg(unix)]
+#[cfg(any(unix, windows))]
 use crate::job_control;
-use crate::jobs::{JobStatus, JobTable};
-use crate::status;
+use crate::aliases::{self, AliasTable};
+use crate::command_hash::CommandHash;
+use crate::completion::{CompletionSpec, CompletionTable};
+use crate::help;
+use crate::history::{self, ForeignHistory};
+use crate::jobs::{self, JobStatus, JobTable};
+use crate::options::{self, ShellOptions};
+use crate::printf;
+use crate::reader::ScriptLines;
+use crate::script_parser;
+use crate::shell::{self, Shell};
+use crate::traps::{self, TrapTable};
+use crate::variables::{self, Variable, Variables};
+use crate::wslpath;
+
+/// What a builtin does to the shell running it, which decides where it can
+/// run: on a thread in a pipeline, in a subshell, or only in this shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinKind {
+    /// Only reads shell state and writes output, so a copy of the shell on
+    /// a thread can run it as any pipeline stage.
+    Pure,
+    /// Exists to change this shell's own state (its directory, variables,
+    /// options, or jobs). In a subshell it would have no lasting effect, so
+    /// it is not backgrounded.
+    Stateful,
+    /// Runs other commands in this shell. Its redirections become the
+    /// shell's default streams rather than the writers it is handed, and it
+    /// cannot be a pipeline stage.
+    RunsCommands,
+    /// Anything else; it runs in a subshell wherever it cannot run in this
+    /// shell.
+    Other,
+}
+
+/// The streams a builtin reads and writes, already redirected.
+pub struct BuiltinIo<'a> {
+    pub stdin: &'a mut dyn Read,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+/// A command run inside the shell rather than as a separate process.
+///
+/// The shell's own builtins are registered at compile time in a static
+/// table; programs embedding the shell add theirs with
+/// [`BuiltinTable::register`] on [`Shell::builtins`].
+pub trait Builtin: Send + Sync {
+    /// The command name it runs as.
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> BuiltinKind {
+        BuiltinKind::Pure
+    }
+
+    /// Run with `args`, not including the name. A non-`Pure` builtin may be
+    /// handed a throwaway copy of the shell, as in a pipeline.
+    fn run(&self, shell: &mut Shell, args: &[String], io: &mut BuiltinIo<'_>) -> BuiltinAction;
+
+    /// What `help` says about it. Without this it is left out of `help`.
+    fn help(&self) -> Option<&BuiltinHelp> {
+        None
+    }
+}
+
+/// A builtin's entry in `help`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinHelp {
+    /// How it is called, e.g. `cd [-L|-P] [dir|-]`: the first line of
+    /// `help NAME` and the first column of the overview.
+    pub usage: &'static str,
+    /// One line for the overview, also shown by `help NAME` when there are
+    /// no `details`.
+    pub summary: &'static str,
+    /// The rest of `help NAME`, a line each, indented as they are to show.
+    pub details: &'static [&'static str],
+}
+
+type BuiltinFn = fn(&mut Shell, &[String], &mut BuiltinIo<'_>) -> BuiltinAction;
+
+/// One of the shell's own builtins: a name, its help, and the function
+/// that runs it.
+#[derive(Clone, Copy)]
+pub(crate) struct CoreBuiltin {
+    name: &'static str,
+    kind: BuiltinKind,
+    help: &'static BuiltinHelp,
+    run: BuiltinFn,
+}
+
+impl Builtin for CoreBuiltin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn kind(&self) -> BuiltinKind {
+        self.kind
+    }
+
+    fn run(&self, shell: &mut Shell, args: &[String], io: &mut BuiltinIo<'_>) -> BuiltinAction {
+        (self.run)(shell, args, io)
+    }
+
+    fn help(&self) -> Option<&BuiltinHelp> {
+        Some(self.help)
+    }
+}
+
+pub(crate) const fn core(name: &'static str, kind: BuiltinKind, help: &'static BuiltinHelp, run: BuiltinFn) -> CoreBuiltin {
+    CoreBuiltin { name, kind, help, run }
+}
 
-/// The list of all builtin command names.
-const BUILTINS: &[&str] = &[
-    "cd", "pwd", "exit", "echo", "export", "unset", "type", "jobs", "fg", "bg", "wait", "help",
+/// The builtins every shell starts with. `exec`, `coproc`, and `timeout` are
+/// run by the executor itself, which needs the unexpanded redirections and
+/// its own path for starting programs.
+static CORE: &[CoreBuiltin] = &[
+    core("cd", BuiltinKind::Stateful, &help::CD, |shell, args, io| {
+        let spell = shell.options.cdspell && shell.interactive;
+        let directories = (&mut shell.cwd, &mut shell.drive_directories);
+        let code = builtin_cd(args, &mut shell.variables, directories, spell, io.stdout, io.stderr);
+        BuiltinAction::Continue(code)
+    }),
+    core("pwd", BuiltinKind::Pure, &help::PWD, |shell, args, io| {
+        BuiltinAction::Continue(builtin_pwd(args, &shell.variables, &shell.cwd, io.stdout, io.stderr))
+    }),
+    core("exit", BuiltinKind::Stateful, &help::EXIT, |shell, args, io| builtin_exit(args, shell, io.stderr)),
+    core("echo", BuiltinKind::Pure, &help::ECHO, |shell, args, io| {
+        BuiltinAction::Continue(builtin_echo(args, shell.options.xpg_echo, io.stdout))
+    }),
+    core("printf", BuiltinKind::Pure, &help::PRINTF, |_, args, io| {
+        BuiltinAction::Continue(builtin_printf(args, io.stdout, io.stderr))
+    }),
+    core("export", BuiltinKind::Stateful, &help::EXPORT, |shell, args, io| {
+        BuiltinAction::Continue(builtin_export(args, &mut shell.variables, io.stdout, io.stderr))
+    }),
+    core("unset", BuiltinKind::Stateful, &help::UNSET, |shell, args, io| {
+        BuiltinAction::Continue(builtin_unset(args, &mut shell.variables, io.stderr))
+    }),
+    core("declare", BuiltinKind::Stateful, &help::DECLARE, |shell, args, io| {
+        BuiltinAction::Continue(builtin_declare("declare", args, &mut shell.variables, io.stdout, io.stderr))
+    }),
+    core("local", BuiltinKind::Stateful, &help::LOCAL, |shell, args, io| {
+        BuiltinAction::Continue(builtin_declare("local", args, &mut shell.variables, io.stdout, io.stderr))
+    }),
+    core("readonly", BuiltinKind::Stateful, &help::READONLY, |shell, args, io| {
+        BuiltinAction::Continue(builtin_declare("readonly", args, &mut shell.variables, io.stdout, io.stderr))
+    }),
+    core("type", BuiltinKind::Pure, &help::TYPE, |shell, args, io| {
+        BuiltinAction::Continue(builtin_type(args, shell, io.stdout, io.stderr))
+    }),
+    core("command", BuiltinKind::Other, &help::COMMAND, |shell, args, io| {
+        BuiltinAction::Continue(builtin_command(args, shell, io.stdout, io.stderr))
+    }),
+    core("hash", BuiltinKind::Stateful, &help::HASH, |shell, args, io| {
+        let search_path = shell.variables.get("PATH").unwrap_or_default();
+        BuiltinAction::Continue(builtin_hash(args, &shell.builtins, &mut shell.command_hash, search_path, io.stdout, io.stderr))
+    }),
+    core("history", BuiltinKind::Other, &help::HISTORY, |shell, args, io| {
+        BuiltinAction::Continue(builtin_history(args, &shell.cwd, &mut shell.history_rewritten, io.stdout, io.stderr))
+    }),
+    core("jobs", BuiltinKind::Stateful, &help::JOBS, |shell, args, io| {
+        BuiltinAction::Continue(builtin_jobs(args, shell, io.stdout, io.stderr))
+    }),
+    core("fg", BuiltinKind::Stateful, &help::FG, |shell, args, io| {
+        BuiltinAction::Continue(builtin_fg(args, &mut shell.job_table, io.stdout, io.stderr))
+    }),
+    core("bg", BuiltinKind::Stateful, &help::BG, |shell, args, io| {
+        BuiltinAction::Continue(builtin_bg(args, &mut shell.job_table, io.stdout, io.stderr))
+    }),
+    core("wait", BuiltinKind::Stateful, &help::WAIT, |shell, args, io| {
+        let jobtimes = shell.options.jobtimes;
+        BuiltinAction::Continue(builtin_wait(args, &mut shell.job_table, jobtimes, io.stdout, io.stderr))
+    }),
+    core("disown", BuiltinKind::Stateful, &help::DISOWN, |shell, args, io| {
+        BuiltinAction::Continue(builtin_disown(args, &mut shell.job_table, io.stderr))
+    }),
+    core("suspend", BuiltinKind::Stateful, &help::SUSPEND, |shell, args, io| {
+        BuiltinAction::Continue(builtin_suspend(args, shell.login, io.stderr))
+    }),
+    core("kill", BuiltinKind::Stateful, &help::KILL, |shell, args, io| {
+        BuiltinAction::Continue(builtin_kill(args, &mut shell.job_table, io.stdout, io.stderr))
+    }),
+    core("help", BuiltinKind::Pure, &help::HELP, |shell, args, io| {
+        BuiltinAction::Continue(help::builtin_help(args, &shell.builtins, io.stdout, io.stderr))
+    }),
+    core("alias", BuiltinKind::Stateful, &help::ALIAS, |shell, args, io| {
+        BuiltinAction::Continue(builtin_alias(args, &mut shell.aliases, io.stdout, io.stderr))
+    }),
+    core("unalias", BuiltinKind::Stateful, &help::UNALIAS, |shell, args, io| {
+        BuiltinAction::Continue(builtin_unalias(args, &mut shell.aliases, io.stderr))
+    }),
+    core("source", BuiltinKind::RunsCommands, &help::SOURCE, |shell, args, io| builtin_source("source", args, shell, io.stderr)),
+    core(".", BuiltinKind::RunsCommands, &help::SOURCE, |shell, args, io| builtin_source(".", args, shell, io.stderr)),
+    core("return", BuiltinKind::Stateful, &help::RETURN, |shell, args, io| {
+        BuiltinAction::Continue(builtin_return(args, shell, io.stderr))
+    }),
+    core("eval", BuiltinKind::RunsCommands, &help::EVAL, |shell, args, _| builtin_eval(args, shell)),
+    core("exec", BuiltinKind::RunsCommands, &help::EXEC, |_, _, io| executor_only("exec", io.stderr)),
+    core("coproc", BuiltinKind::RunsCommands, &help::COPROC, |_, _, io| executor_only("coproc", io.stderr)),
+    core("timeout", BuiltinKind::Other, &help::TIMEOUT, |_, _, io| executor_only("timeout", io.stderr)),
+    core("shift", BuiltinKind::Stateful, &help::SHIFT, |shell, args, io| {
+        BuiltinAction::Continue(builtin_shift(args, shell, io.stderr))
+    }),
+    core("set", BuiltinKind::Stateful, &help::SET, |shell, args, io| {
+        BuiltinAction::Continue(builtin_set(args, shell, io.stdout, io.stderr))
+    }),
+    core("shopt", BuiltinKind::Stateful, &help::SHOPT, |shell, args, io| {
+        BuiltinAction::Continue(builtin_shopt(args, &mut shell.options, io.stdout, io.stderr))
+    }),
+    core("trap", BuiltinKind::Stateful, &help::TRAP, |shell, args, io| {
+        BuiltinAction::Continue(builtin_trap(args, &mut shell.traps, io.stdout, io.stderr))
+    }),
+    core("complete", BuiltinKind::Stateful, &help::COMPLETE, |shell, args, io| {
+        BuiltinAction::Continue(builtin_complete(args, &mut shell.completions, io.stdout, io.stderr))
+    }),
+    core("wslpath", BuiltinKind::Pure, &help::WSLPATH, |shell, args, io| {
+        BuiltinAction::Continue(builtin_wslpath(args, &shell.variables, io.stdout, io.stderr))
+    }),
 ];
 
+fn executor_only(name: &str, stderr: &mut dyn Write) -> BuiltinAction {
+    let _ = writeln!(stderr, "jsh: {name}: can only run as a simple command");
+    BuiltinAction::Continue(1)
+}
+
+/// The shell's own builtins. With the `coreutils` feature these include
+/// the ones standing in for programs the system lacks, judged by the
+/// `$PATH` the program was started with.
+fn core_builtins() -> impl Iterator<Item = &'static CoreBuiltin> {
+    #[cfg(feature = "coreutils")]
+    let extra = missing_programs().iter().copied();
+    #[cfg(not(feature = "coreutils"))]
+    let extra = [].iter();
+    CORE.iter().chain(extra)
+}
+
+/// The `coreutils` builtins whose programs are not on the `$PATH` the
+/// program was started with. `$PATH` is searched for them once, the first
+/// time a builtin is looked up.
+#[cfg(feature = "coreutils")]
+fn missing_programs() -> &'static [&'static CoreBuiltin] {
+    static MISSING: std::sync::OnceLock<Vec<&'static CoreBuiltin>> = std::sync::OnceLock::new();
+    MISSING.get_or_init(|| {
+        let search_path = std::env::var("PATH").unwrap_or_default();
+        crate::coreutils::BUILTINS
+            .iter()
+            .filter(|builtin| find_in_path(builtin.name, &search_path).is_none())
+            .collect()
+    })
+}
+
+/// The shell's own builtin called `name`, as [`core_builtins`] has it.
+fn core_builtin(name: &str) -> Option<&'static CoreBuiltin> {
+    core_builtins().find(|builtin| builtin.name == name)
+}
+
+/// Names of the builtins every shell has, whatever else is registered.
+pub fn core_names() -> impl Iterator<Item = &'static str> {
+    core_builtins().map(|builtin| builtin.name)
+}
+
+/// The builtins a shell knows: its own, plus any registered by the program
+/// embedding it, which take precedence.
+#[derive(Clone)]
+pub struct BuiltinTable {
+    registered: HashMap<String, Arc<dyn Builtin>>,
+}
+
+impl BuiltinTable {
+    pub fn new() -> Self {
+        Self {
+            registered: HashMap::new(),
+        }
+    }
+
+    /// Add `builtin`, replacing any builtin of the same name.
+    pub fn register(&mut self, builtin: impl Builtin + 'static) {
+        self.registered.insert(builtin.name().to_string(), Arc::new(builtin));
+    }
+
+    /// Remove a registered builtin. The shell's own cannot be removed.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.registered.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Builtin> {
+        match self.registered.get(name) {
+            Some(builtin) => Some(builtin.as_ref()),
+            None => core_builtin(name).map(|builtin| builtin as &dyn Builtin),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// How the builtin called `name` runs, if there is one.
+    pub fn kind(&self, name: &str) -> Option<BuiltinKind> {
+        self.get(name).map(Builtin::kind)
+    }
+
+    /// Whether `name` is a builtin that a `jsh -c` subshell also has, so
+    /// the executor may run it in one. A registered builtin exists only in
+    /// the shell it was registered with.
+    pub fn in_subshells(&self, name: &str) -> bool {
+        !self.registered.contains_key(name) && core_builtin(name).is_some()
+    }
+
+    /// Every builtin: the shell's own in the order they are defined, less
+    /// any replaced by a registered one, then the registered ones by name.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Builtin> {
+        let mut registered: Vec<&Arc<dyn Builtin>> = self.registered.values().collect();
+        registered.sort_by(|a, b| a.name().cmp(b.name()));
+        let core = core_builtins().filter(|builtin| !self.registered.contains_key(builtin.name));
+        core.map(|builtin| builtin as &dyn Builtin).chain(registered.into_iter().map(|builtin| builtin.as_ref()))
+    }
+
+    /// Every builtin name, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let core = core_builtins().map(|builtin| builtin.name()).filter(|name| !self.registered.contains_key(*name));
+        self.registered.keys().map(String::as_str).chain(core)
+    }
+}
+
+impl Default for BuiltinTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug)]
 pub enum BuiltinAction {
     Continue(i32),
     Exit(i32),
 }
 
-/// Returns true if the command name is a shell builtin.
-pub fn is_builtin(name: &str) -> bool {
-    BUILTINS.contains(&name)
-}
-
-/// Execute a builtin command, writing output to the provided streams.
-/// Returns the exit code.
+/// Execute the builtin called `program` in `shell`, writing output to the
+/// provided streams.
 pub fn execute(
     program: &str,
     args: &[String],
-    _stdin: &mut dyn Read,
+    stdin: &mut dyn Read,
     stdout: &mut dyn Write,
     stderr: &mut dyn Write,
-    job_table: &mut JobTable,
+    shell: &mut Shell,
 ) -> BuiltinAction {
-    match program {
-        "cd" => BuiltinAction::Continue(builtin_cd(args, stderr)),
-        "pwd" => BuiltinAction::Continue(builtin_pwd(stdout, stderr)),
-        "exit" => builtin_exit(args, stderr),
-        "echo" => BuiltinAction::Continue(builtin_echo(args, stdout)),
-        "export" => BuiltinAction::Continue(builtin_export(args, stderr)),
-        "unset" => BuiltinAction::Continue(builtin_unset(args)),
-        "type" => BuiltinAction::Continue(builtin_type(args, stdout, stderr)),
-        "jobs" => BuiltinAction::Continue(builtin_jobs(job_table, stdout)),
-        "fg" => BuiltinAction::Continue(builtin_fg(args, job_table, stdout, stderr)),
-        "bg" => BuiltinAction::Continue(builtin_bg(args, job_table, stdout, stderr)),
-        "wait" => BuiltinAction::Continue(builtin_wait(args, job_table, stdout, stderr)),
-        "help" => BuiltinAction::Continue(builtin_help(args, stdout, stderr)),
-        _ => {
-            let _ = writeln!(stderr, "jsh: unknown builtin: {program}");
+    let mut io = BuiltinIo { stdin, stdout, stderr };
+    // Cloned out of the table, since running it borrows the shell.
+    if let Some(builtin) = shell.builtins.registered.get(program).cloned() {
+        return builtin.run(shell, args, &mut io);
+    }
+    match core_builtin(program) {
+        Some(builtin) => builtin.run(shell, args, &mut io),
+        None => {
+            let _ = writeln!(io.stderr, "jsh: unknown builtin: {program}");
             BuiltinAction::Continue(1)
         }
     }
 }
 
-fn builtin_cd(args: &[String], stderr: &mut dyn Write) -> i32 {
-    let target = match args.first() {
+/// `cd [-L|-P] [dir]`: change directory and keep `$PWD` and `$OLDPWD` up to
+/// date.
+///
+/// By default (`-L`) `..` is resolved against the logical `$PWD`, so
+/// `cd link/..` returns to where the symlink lives; `-P` resolves symlinks
+/// and sets `$PWD` to the physical directory. A relative `dir` that does
+/// not start with `.` or `..` is looked for under each `$CDPATH` entry
+/// first. When that search or `cd -` picks the directory, it is printed.
+/// With `spell` (`shopt -s cdspell` in an interactive shell), a `dir` that
+/// does not exist but is a typo away from one is offered as a correction.
+///
+/// On Windows, `D:` or `D:dir` is taken from the directory last left on
+/// drive D, as cmd.exe does, and `$PWD` is kept with backslashes.
+///
+/// Only the shell's own working directory changes, never the process's.
+fn builtin_cd(
+    args: &[String],
+    variables: &mut Variables,
+    (cwd, drive_directories): (&mut PathBuf, &mut BTreeMap<char, PathBuf>),
+    spell: bool,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let mut physical = false;
+    let mut operands = args;
+    while let Some(flags) = operands.first().and_then(|arg| arg.strip_prefix('-')) {
+        if flags.is_empty() {
+            break;
+        }
+        operands = &operands[1..];
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'L' => physical = false,
+                'P' => physical = true,
+                _ => {
+                    let _ = writeln!(stderr, "jsh: cd: -{flag}: invalid option");
+                    let _ = writeln!(stderr, "cd: usage: cd [-L|-P] [dir]");
+                    return 2;
+                }
+            }
+        }
+    }
+
+    let old_dir = logical_pwd(variables, cwd).ok();
+    let mut print_dir = false;
+    let target = match operands.first() {
         Some(dir) if dir == "-" => {
             // cd - : go to previous directory
-            match std::env::var("OLDPWD") {
-                Ok(prev) => prev,
-                Err(_) => {
+            match variables.get("OLDPWD") {
+                Some(prev) => {
+                    print_dir = true;
+                    PathBuf::from(prev)
+                }
+                None => {
                     let _ = writeln!(stderr, "cd: OLDPWD not set");
                     return 1;
                 }
             }
         }
-        Some(dir) => dir.clone(),
+        Some(dir) => match drive_relative(dir).filter(|_| cfg!(windows)) {
+            Some((drive, rest)) => {
+                let current = old_dir.as_deref().filter(|cwd| drive_letter(&cwd.to_string_lossy()) == Some(drive));
+                let base = match current.or(drive_directories.get(&drive).map(PathBuf::as_path)) {
+                    Some(base) => base.to_path_buf(),
+                    None => PathBuf::from(format!("{drive}:\\")),
+                };
+                if rest.is_empty() { base } else { base.join(rest) }
+            }
+            None => match cdpath_directory(dir, variables, cwd) {
+                Some(found) => {
+                    print_dir = true;
+                    found
+                }
+                None => PathBuf::from(dir),
+            },
+        },
         None => {
             // cd with no args → go home
-            std::env::var("HOME")
-                .or_else(|_| std::env::var("USERPROFILE"))
-                .unwrap_or_else(|_| ".".to_string())
+            PathBuf::from(
+                variables
+                    .get("HOME")
+                    .or_else(|| variables.get("USERPROFILE"))
+                    .unwrap_or("."),
+            )
         }
     };
 
-    let old_dir = std::env::current_dir().ok();
+    let target = match spell && !cwd.join(&target).is_dir() {
+        true => match spelling_correction(&target, cwd) {
+            Some(corrected) if confirm_correction(&target, &corrected, stderr) => corrected,
+            _ => target,
+        },
+        false => target,
+    };
 
-    // On success, update OLDPWD to the directory we left.
-    // SAFETY: We only mutate env vars on the main thread. The ctrlc handler
-    // thread does not read or write environment variables.
-    if let Err(e) = std::env::set_current_dir(&target) {
-        let _ = writeln!(stderr, "cd: {target}: {e}");
-        return 1;
-    }
+    // Logically, `dir/..` is `dir`'s parent even when `dir` is a symlink.
+    // If that path cannot be entered, fall back to the physical route.
+    let logical = match (physical, &old_dir) {
+        (false, Some(cwd)) => Some(normalize_path(&cwd.join(&target))),
+        _ => None,
+    };
+    let new_dir = match logical {
+        Some(path) if enterable_directory(&path).is_ok() => path,
+        _ => {
+            let path = cwd.join(&target);
+            if let Err(e) = enterable_directory(&path) {
+                let _ = writeln!(stderr, "cd: {}: {e}", target.display());
+                return 1;
+            }
+            match physical_path(&path) {
+                Ok(path) => path,
+                Err(e) => {
+                    let _ = writeln!(stderr, "cd: {e}");
+                    return 1;
+                }
+            }
+        }
+    };
+    cwd.clone_from(&new_dir);
 
+    // On success, update OLDPWD to the directory we left.
     if let Some(cwd) = old_dir {
-        // SAFETY: We only mutate env vars on the main thread. The ctrlc handler
-        // thread does not read or write environment variables.
-        unsafe { std::env::set_var("OLDPWD", cwd) };
+        if let Some(drive) = drive_letter(&cwd.to_string_lossy()).filter(|_| cfg!(windows)) {
+            drive_directories.insert(drive, cwd.clone());
+        }
+        variables.export("OLDPWD", Some(&cwd.to_string_lossy()));
+    }
+    let new_dir = native_separators(new_dir);
+    variables.export("PWD", Some(&new_dir.to_string_lossy()));
+    if print_dir {
+        let _ = writeln!(stdout, "{}", new_dir.display());
     }
 
     0
 }
 
-fn builtin_pwd(stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
-    match std::env::current_dir() {
+/// Whether `path` is a directory that could be made the working directory:
+/// one that exists and, on Unix, that may be searched.
+fn enterable_directory(path: &Path) -> std::io::Result<()> {
+    if !std::fs::metadata(path)?.is_dir() {
+        #[cfg(unix)]
+        return Err(std::io::Error::from_raw_os_error(libc::ENOTDIR));
+        #[cfg(not(unix))]
+        return Err(std::io::ErrorKind::NotADirectory.into());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        if unsafe { libc::access(c_path.as_ptr(), libc::X_OK) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// `path` with every symlink, `.` and `..` resolved, as the system would
+/// report it after changing into it. Windows's `\\?\` prefix is dropped
+/// from paths on a drive.
+pub(crate) fn physical_path(path: &Path) -> std::io::Result<PathBuf> {
+    let path = path.canonicalize()?;
+    let text = path.to_string_lossy();
+    match text.strip_prefix(r"\\?\").filter(|rest| cfg!(windows) && drive_letter(rest).is_some()) {
+        Some(rest) => Ok(PathBuf::from(rest)),
+        None => Ok(path),
+    }
+}
+
+/// The drive letter of a path such as `C:\Users` or `c:`, in upper case.
+fn drive_letter(path: &str) -> Option<char> {
+    let mut chars = path.chars();
+    let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+    (chars.next() == Some(':')).then(|| drive.to_ascii_uppercase())
+}
+
+/// `D:` or `D:dir`, relative to a directory on drive D rather than to its
+/// root, split into the drive letter and the rest.
+fn drive_relative(dir: &str) -> Option<(char, &str)> {
+    let drive = drive_letter(dir)?;
+    let rest = &dir[2..];
+    (!rest.starts_with(['/', '\\'])).then_some((drive, rest))
+}
+
+/// `path` with forward slashes turned into backslashes on Windows, where
+/// both separate components but paths are shown with backslashes.
+fn native_separators(path: PathBuf) -> PathBuf {
+    match cfg!(windows) {
+        true => PathBuf::from(path.to_string_lossy().replace('/', "\\")),
+        false => path,
+    }
+}
+
+/// Where `$CDPATH` finds `dir`, when it is a relative path that does not
+/// start with `.` or `..`. An empty entry stands for the current directory,
+/// `cwd`, and is not reported as a CDPATH match.
+fn cdpath_directory(dir: &str, variables: &Variables, cwd: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let path = Path::new(dir);
+    if path.is_absolute() || matches!(path.components().next(), Some(Component::CurDir | Component::ParentDir) | None) {
+        return None;
+    }
+    let cdpath = variables.get("CDPATH")?;
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    cdpath
+        .split(separator)
+        .map(|entry| (entry, Path::new(if entry.is_empty() { "." } else { entry }).join(path)))
+        .find(|(_, candidate)| cwd.join(candidate).is_dir())
+        .and_then(|(entry, candidate)| (!entry.is_empty()).then_some(candidate))
+}
+
+/// `cdspell`: `dir` with each component that does not exist replaced by
+/// the one entry beside it that is a single typo away, if the result is a
+/// directory. A relative `dir` is looked for under `cwd`.
+fn spelling_correction(dir: &Path, cwd: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let mut corrected = PathBuf::new();
+    for component in dir.components() {
+        let Component::Normal(name) = component else {
+            corrected.push(component);
+            continue;
+        };
+        if cwd.join(&corrected).join(name).exists() {
+            corrected.push(name);
+            continue;
+        }
+        let name = name.to_str()?;
+        let mut candidates: Vec<String> = std::fs::read_dir(cwd.join(&corrected))
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|entry| one_typo_apart(name, entry))
+            .collect();
+        candidates.sort();
+        corrected.push(candidates.first()?);
+    }
+    (corrected != dir && cwd.join(&corrected).is_dir()).then_some(corrected)
+}
+
+/// Whether `a` and `b` differ by one changed, missing, or extra character,
+/// or by two neighbouring characters swapped.
+fn one_typo_apart(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    match (a.len(), b.len()) {
+        (0, 0) => false,
+        (m, n) if m == n => a[1..] == b[1..] || (m >= 2 && a[0] == b[1] && a[1] == b[0] && a[2..] == b[2..]),
+        (m, n) if m == n + 1 => a[1..] == *b,
+        (m, n) if m + 1 == n => *a == b[1..],
+        _ => false,
+    }
+}
+
+/// Ask on the terminal whether to use a `cdspell` correction.
+fn confirm_correction(typed: &Path, corrected: &Path, stderr: &mut dyn Write) -> bool {
+    let _ = write!(stderr, "jsh: cd: correct '{}' to '{}'? [y/n] ", typed.display(), corrected.display());
+    let _ = stderr.flush();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
+
+/// The logical working directory: `$PWD` if it is an absolute path naming
+/// the same directory as `cwd`, otherwise `cwd` itself.
+pub(crate) fn logical_pwd(variables: &Variables, cwd: &Path) -> std::io::Result<PathBuf> {
+    let physical = physical_path(cwd)?;
+    let same_directory = |path: &Path| physical_path(path).is_ok_and(|path| path == physical);
+    match variables.get("PWD").map(PathBuf::from) {
+        Some(pwd) if pwd.is_absolute() && same_directory(&pwd) => Ok(native_separators(pwd)),
+        _ => Ok(cwd.to_path_buf()),
+    }
+}
+
+/// Resolve `.` and `..` in `path` without looking at the file system, as a
+/// logical `cd` does.
+fn normalize_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// `pwd [-L|-P]`: print the logical working directory (`$PWD`), or with
+/// `-P` the physical one with symlinks resolved.
+fn builtin_pwd(args: &[String], variables: &Variables, cwd: &Path, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let mut physical = false;
+    for arg in args {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            _ => {
+                let _ = writeln!(stderr, "jsh: pwd: {arg}: invalid option");
+                let _ = writeln!(stderr, "pwd: usage: pwd [-LP]");
+                return 2;
+            }
+        }
+    }
+    let dir = match physical {
+        true => physical_path(cwd),
+        false => logical_pwd(variables, cwd),
+    };
+    match dir {
         Ok(path) => {
             let _ = writeln!(stdout, "{}", path.display());
             0
@@ -105,262 +693,1330 @@ fn builtin_pwd(stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
     }
 }
 
-fn builtin_exit(args: &[String], stderr: &mut dyn Write) -> BuiltinAction {
-    match args.first() {
-        None => BuiltinAction::Exit(0),
-        Some(s) => match s.parse::<i32>() {
-            Ok(code) => BuiltinAction::Exit(code),
+/// `wslpath [-u|-w|-m] path...`: print each path as the other side of WSL
+/// or MSYS names it, Unix (`-u`, the default), Windows (`-w`), or Windows
+/// with forward slashes (`-m`).
+fn builtin_wslpath(args: &[String], variables: &Variables, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let mut style = wslpath::Style::Unix;
+    let mut paths = args;
+    while let Some((arg, rest)) = paths.split_first() {
+        match arg.as_str() {
+            "-u" => style = wslpath::Style::Unix,
+            "-w" => style = wslpath::Style::Windows,
+            "-m" => style = wslpath::Style::Mixed,
+            "--" => {
+                paths = rest;
+                break;
+            }
+            option if option.starts_with('-') && option.len() > 1 => {
+                let _ = writeln!(stderr, "jsh: wslpath: {option}: invalid option");
+                let _ = writeln!(stderr, "wslpath: usage: wslpath [-u|-w|-m] path...");
+                return 2;
+            }
+            _ => break,
+        }
+        paths = rest;
+    }
+    if paths.is_empty() {
+        let _ = writeln!(stderr, "wslpath: usage: wslpath [-u|-w|-m] path...");
+        return 2;
+    }
+    let mounts = wslpath::Mounts::from_variables(variables);
+    let mut status = 0;
+    for path in paths {
+        match mounts.translate(path, style) {
+            Some(translated) => {
+                let _ = writeln!(stdout, "{translated}");
+            }
+            None => {
+                let _ = writeln!(stderr, "wslpath: {path}: cannot translate");
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+fn builtin_exit(args: &[String], shell: &mut Shell, stderr: &mut dyn Write) -> BuiltinAction {
+    if !shell.may_exit(stderr) {
+        return BuiltinAction::Continue(1);
+    }
+    match args.first() {
+        None => BuiltinAction::Exit(0),
+        Some(s) => match s.parse::<i32>() {
+            Ok(code) => BuiltinAction::Exit(code),
+            Err(_) => {
+                let _ = writeln!(stderr, "exit: {s}: numeric argument required");
+                BuiltinAction::Exit(2)
+            }
+        },
+    }
+}
+
+/// `echo [-neE] [args...]`: print the arguments separated by spaces. `-n`
+/// leaves off the newline, `-e` decodes backslash escapes (`\c` ends the
+/// output), and `-E` does not; `escapes` (`shopt -s xpg_echo`) makes `-e`
+/// the default. As in bash, only leading words made entirely of these
+/// letters are options, and `--` is printed like any other word.
+fn builtin_echo(args: &[String], escapes: bool, stdout: &mut dyn Write) -> i32 {
+    let mut newline = true;
+    let mut escapes = escapes;
+    let mut words = args;
+    while let Some(flags) = words.first().and_then(|word| word.strip_prefix('-')) {
+        if flags.is_empty() || !flags.chars().all(|flag| matches!(flag, 'n' | 'e' | 'E')) {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        words = &words[1..];
+    }
+
+    let text = words.join(" ");
+    let mut output = Vec::with_capacity(text.len() + 1);
+    if escapes && printf::expand_escapes(&text, &mut output) {
+        newline = false;
+    } else if !escapes {
+        output.extend_from_slice(text.as_bytes());
+    }
+    if newline {
+        output.push(b'\n');
+    }
+    let _ = stdout.write_all(&output);
+    0
+}
+
+/// `printf format [arguments]`: print the arguments as `format` directs,
+/// reusing it until they run out.
+fn builtin_printf(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let args = match args.first() {
+        Some(first) if first == "--" => &args[1..],
+        _ => args,
+    };
+    let Some((format, args)) = args.split_first() else {
+        let _ = writeln!(stderr, "printf: usage: printf format [arguments]");
+        return 2;
+    };
+
+    let formatted = printf::format(format, args);
+    let _ = stdout.write_all(&formatted.output);
+    for error in &formatted.errors {
+        let _ = writeln!(stderr, "{error}");
+    }
+    if formatted.errors.is_empty() { 0 } else { 1 }
+}
+
+/// `export NAME=value` assigns and exports; `export NAME` exports an
+/// existing shell variable so child processes see it. `export -n NAME`
+/// stops exporting it, and `export` or `export -p` lists exported
+/// variables as `declare -x` commands that recreate them.
+fn builtin_export(
+    args: &[String],
+    variables: &mut Variables,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let mut unexport = false;
+    let mut print = false;
+    let mut names = args;
+    while let Some(flags) = names.first().and_then(|arg| arg.strip_prefix('-')) {
+        names = &names[1..];
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => unexport = true,
+                'p' => print = true,
+                _ => {
+                    let _ = writeln!(stderr, "jsh: export: -{flag}: invalid option");
+                    let _ = writeln!(stderr, "export: usage: export [-n] [name[=value] ...] or export -p");
+                    return 2;
+                }
+            }
+        }
+    }
+    if names.is_empty() || (print && !unexport) {
+        return builtin_declare("declare", &["-x".to_string()], variables, stdout, stderr);
+    }
+
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !variables::is_valid_name(name) {
+            let _ = writeln!(stderr, "jsh: export: `{arg}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value
+            && let Err(msg) = variables.assign(name, value)
+        {
+            let _ = writeln!(stderr, "{msg}");
+            status = 1;
+            continue;
+        }
+        match unexport {
+            true => variables.unexport(name),
+            false => variables.export(name, None),
+        }
+    }
+    status
+}
+
+fn builtin_unset(args: &[String], variables: &mut Variables, stderr: &mut dyn Write) -> i32 {
+    let mut status = 0;
+    for arg in args {
+        if let Some((name, key)) = variables::split_subscript(arg) {
+            if variables.is_readonly(name) {
+                let _ = writeln!(stderr, "jsh: unset: {name}: cannot unset: readonly variable");
+                status = 1;
+            } else {
+                variables.unset_element(name, key);
+            }
+            continue;
+        }
+        if !variables::is_valid_name(arg) {
+            let _ = writeln!(stderr, "jsh: unset: `{arg}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+        if variables.is_readonly(arg) {
+            let _ = writeln!(stderr, "jsh: unset: {arg}: cannot unset: readonly variable");
+            status = 1;
+            continue;
+        }
+        variables.unset(arg);
+    }
+    status
+}
+
+/// `declare [-Airxp] [+ix] [name[=value] ...]`, `local` (the same, scoped
+/// to the running function), and `readonly [-p] [name[=value] ...]` (as
+/// `declare -r`). `-` turns an attribute on and `+` off: `-A` associative
+/// array, `-i` integer, `-r` readonly, `-x` exported. With no names, or with `-p`, variables are
+/// printed as `declare` commands; with no names only those with the given
+/// attributes are.
+fn builtin_declare(
+    command: &str,
+    args: &[String],
+    variables: &mut Variables,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let usage = match command {
+        "readonly" => "[-p] [name[=value] ...]",
+        _ => "[-Airxp] [+ix] [name[=value] ...]",
+    };
+    let mut on = Variable {
+        readonly: command == "readonly",
+        ..Variable::default()
+    };
+    let mut off = Variable::default();
+    let mut print = false;
+    let mut names = Vec::new();
+    for arg in args {
+        let (attributes, flags) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(flags), _) if names.is_empty() && !flags.is_empty() => (&mut on, flags),
+            (_, Some(flags)) if names.is_empty() && !flags.is_empty() && command != "readonly" => (&mut off, flags),
+            _ => {
+                names.push(arg.as_str());
+                continue;
+            }
+        };
+        for flag in flags.chars() {
+            match flag {
+                'A' if command != "readonly" => attributes.assoc = Some(Default::default()),
+                'i' if command != "readonly" => attributes.integer = true,
+                'r' if command != "readonly" => attributes.readonly = true,
+                'x' if command != "readonly" => attributes.exported = true,
+                'p' => print = true,
+                _ => {
+                    let _ = writeln!(stderr, "jsh: {command}: {}{flag}: invalid option", &arg[..1]);
+                    let _ = writeln!(stderr, "{command}: usage: {command} {usage}");
+                    return 2;
+                }
+            }
+        }
+    }
+
+    let declaration = |name: &str, var: &Variable| match &var.assoc {
+        Some(map) => {
+            let elements: Vec<(&str, &str)> = map.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+            format!("declare {} {}", var.flags(), variables::format_assoc(name, &elements))
+        }
+        None => format!("declare {} {}", var.flags(), variables::format_assignment(name, &var.value)),
+    };
+    if names.is_empty() && (print || command != "local") {
+        for (name, var) in variables.iter() {
+            let wanted = [
+                (on.assoc.is_some(), var.assoc.is_some()),
+                (on.integer, var.integer),
+                (on.readonly, var.readonly),
+                (on.exported, var.exported),
+            ];
+            if wanted.iter().all(|&(filter, has)| !filter || has) {
+                let _ = writeln!(stdout, "{}", declaration(name, var));
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        if !variables::is_valid_name(name) {
+            let _ = writeln!(stderr, "jsh: {command}: `{arg}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+        if print {
+            match variables.lookup(name) {
+                Some(var) => {
+                    let _ = writeln!(stdout, "{}", declaration(name, var));
+                }
+                None => {
+                    let _ = writeln!(stderr, "jsh: {command}: {name}: not found");
+                    status = 1;
+                }
+            }
+            continue;
+        }
+        if off.readonly && variables.is_readonly(name) {
+            let _ = writeln!(stderr, "jsh: {command}: {name}: readonly variable");
+            status = 1;
+            continue;
+        }
+        if off.assoc.is_some() && variables.lookup(name).is_some_and(|var| var.assoc.is_some()) {
+            let _ = writeln!(stderr, "jsh: {command}: {name}: cannot destroy array variables in this way");
+            status = 1;
+            continue;
+        }
+        if command == "local"
+            && let Err(msg) = variables.make_local(name)
+        {
+            let _ = writeln!(stderr, "jsh: local: {msg}");
+            return 1;
+        }
+        if on.assoc.is_some()
+            && let Err(msg) = variables.make_assoc(name)
+        {
+            let _ = writeln!(stderr, "{msg}");
+            status = 1;
+            continue;
+        }
+        if on.integer || off.integer {
+            variables.set_integer(name, on.integer);
+        }
+        if let Some(value) = value
+            && let Err(msg) = variables.assign(name, value)
+        {
+            let _ = writeln!(stderr, "{msg}");
+            status = 1;
+            continue;
+        }
+        if on.exported {
+            variables.export(name, None);
+        } else if off.exported {
+            variables.unexport(name);
+        }
+        if on.readonly {
+            variables.set_readonly(name);
+        }
+    }
+    status
+}
+
+/// What a command name refers to, in the order the shell tries them.
+enum CommandKind {
+    Alias(String),
+    Keyword,
+    Builtin,
+    /// An executable file, remembered in the hash table if `hashed`.
+    File { path: PathBuf, hashed: bool },
+}
+
+/// Each thing `name` refers to, the one that would run first first. Only
+/// that one unless `all`, which also lists every executable called `name`
+/// along `$PATH`.
+fn command_kinds(name: &str, shell: &Shell, all: bool) -> Vec<CommandKind> {
+    let search_path = shell.variables.get("PATH").unwrap_or_default();
+    let mut kinds = Vec::new();
+    if let Some(value) = shell.aliases.get(name) {
+        kinds.push(CommandKind::Alias(value.to_string()));
+    }
+    if script_parser::is_reserved_word(name) {
+        kinds.push(CommandKind::Keyword);
+    }
+    if shell.builtins.contains(name) {
+        kinds.push(CommandKind::Builtin);
+    }
+    if names_a_path(name) {
+        if is_executable(&shell.cwd.join(name)) {
+            kinds.push(CommandKind::File {
+                path: PathBuf::from(name),
+                hashed: false,
+            });
+        }
+    } else if all {
+        kinds.extend(find_all_in_path(name, search_path).into_iter().map(|path| CommandKind::File { path, hashed: false }));
+    } else if kinds.is_empty() {
+        let file = match shell.command_hash.get(name, search_path) {
+            Some(path) => Some(CommandKind::File {
+                path: path.to_path_buf(),
+                hashed: true,
+            }),
+            None => find_in_path(name, search_path).map(|path| CommandKind::File { path, hashed: false }),
+        };
+        kinds.extend(file);
+    }
+    if !all {
+        kinds.truncate(1);
+    }
+    kinds
+}
+
+/// `name is a shell builtin` and so on, as `type` and `command -V` say it.
+fn describe_command(name: &str, kind: &CommandKind) -> String {
+    match kind {
+        CommandKind::Alias(value) => format!("{name} is aliased to `{value}'"),
+        CommandKind::Keyword => format!("{name} is a shell keyword"),
+        CommandKind::Builtin => format!("{name} is a shell builtin"),
+        CommandKind::File { path, hashed: true } => format!("{name} is hashed ({})", path.display()),
+        CommandKind::File { path, hashed: false } => format!("{name} is {}", path.display()),
+    }
+}
+
+/// `type [-aptP] name...`: say what each name would run. `-t` prints just
+/// `alias`, `keyword`, `builtin`, or `file`; `-p` prints the path if it is
+/// a file and `-P` searches `$PATH` even if it is not; `-a` lists every
+/// match instead of only the first.
+fn builtin_type(
+    args: &[String],
+    shell: &Shell,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let mut all = false;
+    let mut kind_only = false;
+    let mut path_only = false;
+    let mut force_path = false;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'a' => all = true,
+                        't' => kind_only = true,
+                        'p' => path_only = true,
+                        'P' => force_path = true,
+                        _ => {
+                            let _ = writeln!(stderr, "jsh: type: -{flag}: invalid option");
+                            let _ = writeln!(stderr, "type: usage: type [-aptP] name [name ...]");
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => names.push(arg.as_str()),
+        }
+    }
+
+    let search_path = shell.variables.get("PATH").unwrap_or_default();
+    let mut exit_code = 0;
+    for name in names {
+        let kinds = match force_path {
+            true => match all {
+                true => find_all_in_path(name, search_path),
+                false => find_in_path(name, search_path).into_iter().collect(),
+            }
+            .into_iter()
+            .map(|path| CommandKind::File { path, hashed: false })
+            .collect(),
+            false => command_kinds(name, shell, all),
+        };
+        if kinds.is_empty() {
+            if !kind_only && !path_only && !force_path {
+                let _ = writeln!(stderr, "{name}: not found");
+            }
+            exit_code = 1;
+            continue;
+        }
+        for kind in &kinds {
+            if kind_only {
+                let word = match kind {
+                    CommandKind::Alias(_) => "alias",
+                    CommandKind::Keyword => "keyword",
+                    CommandKind::Builtin => "builtin",
+                    CommandKind::File { .. } => "file",
+                };
+                let _ = writeln!(stdout, "{word}");
+            } else if path_only || force_path {
+                if let CommandKind::File { path, .. } = kind {
+                    let _ = writeln!(stdout, "{}", path.display());
+                }
+            } else {
+                let _ = writeln!(stdout, "{}", describe_command(name, kind));
+            }
+        }
+    }
+    exit_code
+}
+
+/// `command -v name...` prints how each name would be run: the path of a
+/// file, the name of a builtin or keyword, or the definition of an alias.
+/// `command -V name...` describes them as `type` does. Without either, the
+/// shell has already dropped `command` and run the name it was given, so
+/// only a bare `command` gets here.
+fn builtin_command(
+    args: &[String],
+    shell: &Shell,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let verbose = match args.first().map(String::as_str) {
+        None => return 0,
+        Some("-v") => false,
+        Some("-V") => true,
+        Some(other) => {
+            let _ = writeln!(stderr, "jsh: command: {other}: invalid option");
+            let _ = writeln!(stderr, "command: usage: command [-vV] name [arg ...]");
+            return 2;
+        }
+    };
+
+    let mut exit_code = 0;
+    for name in &args[1..] {
+        let Some(kind) = command_kinds(name, shell, false).pop() else {
+            if verbose {
+                let _ = writeln!(stderr, "jsh: command: {name}: not found");
+            }
+            exit_code = 1;
+            continue;
+        };
+        let line = match (verbose, &kind) {
+            (true, _) => describe_command(name, &kind),
+            (false, CommandKind::Alias(value)) => aliases::format_definition(name, value),
+            (false, CommandKind::File { path, .. }) => path.display().to_string(),
+            (false, CommandKind::Keyword | CommandKind::Builtin) => name.clone(),
+        };
+        let _ = writeln!(stdout, "{line}");
+    }
+    exit_code
+}
+
+/// `command name args...` runs `name` as a builtin or a file, never as an
+/// alias. Aliases only apply to the first word, so dropping `command` once
+/// the words are expanded is all it takes. `-v` and `-V` are left for the
+/// builtin.
+pub fn strip_command_prefix(args: &mut Vec<String>) {
+    while args.first().is_some_and(|word| word == "command") {
+        match args.get(1).map(String::as_str) {
+            Some("--") if args.len() > 2 => drop(args.drain(..2)),
+            Some(name) if !name.starts_with('-') => drop(args.remove(0)),
+            _ => break,
+        }
+    }
+}
+
+// ── Alias builtins ──
+
+/// `alias [-p] [name[=value] ...]`: define aliases, or print them.
+fn builtin_alias(
+    args: &[String],
+    aliases: &mut AliasTable,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let args: Vec<&String> = args.iter().filter(|arg| arg.as_str() != "-p").collect();
+    if args.is_empty() {
+        for (name, value) in aliases.iter() {
+            let _ = writeln!(stdout, "{}", aliases::format_definition(name, value));
+        }
+        return 0;
+    }
+
+    let mut exit_code = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                if !aliases::is_valid_name(name) {
+                    let _ = writeln!(stderr, "alias: `{name}': invalid alias name");
+                    exit_code = 1;
+                    continue;
+                }
+                aliases.set(name, value);
+            }
+            None => match aliases.get(arg) {
+                Some(value) => {
+                    let _ = writeln!(stdout, "{}", aliases::format_definition(arg, value));
+                }
+                None => {
+                    let _ = writeln!(stderr, "alias: {arg}: not found");
+                    exit_code = 1;
+                }
+            },
+        }
+    }
+    exit_code
+}
+
+/// `unalias [-a] name...`: remove aliases.
+fn builtin_unalias(args: &[String], aliases: &mut AliasTable, stderr: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let _ = writeln!(stderr, "unalias: usage: unalias [-a] name [name ...]");
+        return 2;
+    }
+
+    let mut exit_code = 0;
+    for arg in args {
+        if arg == "-a" {
+            aliases.clear();
+        } else if !aliases.remove(arg) {
+            let _ = writeln!(stderr, "unalias: {arg}: not found");
+            exit_code = 1;
+        }
+    }
+    exit_code
+}
+
+/// `set [-Ceux] [-o name] [--] [arg...]`: turn shell options on (`-`) or off
+/// (`+`), and replace the positional parameters with any remaining
+/// arguments. With no arguments, print every shell variable; `set -o` and
+/// `set +o` list the options.
+fn builtin_set(args: &[String], shell: &mut Shell, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        for (name, var) in shell.variables.iter() {
+            let _ = writeln!(stdout, "{}", variables::format_assignment(name, &var.value));
+        }
+        return 0;
+    }
+
+    let mut args = args.iter().peekable();
+    let mut params = None;
+    while let Some(arg) = args.next() {
+        let (on, flags) = match arg.as_bytes().first() {
+            Some(b'-') => (true, &arg[1..]),
+            Some(b'+') => (false, &arg[1..]),
+            _ => {
+                params = Some(std::iter::once(arg).chain(args).cloned().collect());
+                break;
+            }
+        };
+        if arg == "--" || arg == "-" {
+            params = Some(args.cloned().collect());
+            break;
+        }
+        for flag in flags.chars() {
+            let name = match flag {
+                'o' => match args.next() {
+                    Some(name) => name.as_str(),
+                    None => {
+                        for (name, enabled) in shell.options.list(options::SET_NAMES) {
+                            if on {
+                                let _ = writeln!(stdout, "{name:<15}\t{}", if enabled { "on" } else { "off" });
+                            } else if enabled {
+                                let argument = shell.options.set_argument(name);
+                                let _ = writeln!(stdout, "set -o {}", shell::quote_word(&argument));
+                            } else {
+                                let _ = writeln!(stdout, "set +o {name}");
+                            }
+                        }
+                        continue;
+                    }
+                },
+                _ => match options::flag_name(flag) {
+                    Some(name) => name,
+                    None => {
+                        let _ = writeln!(stderr, "jsh: set: {}{flag}: invalid option", if on { '-' } else { '+' });
+                        let _ = writeln!(stderr, "set: usage: set [-Ceux] [-o option-name] [--] [arg ...]");
+                        return 2;
+                    }
+                },
+            };
+            // Only `-o bglog=DIR` takes a value.
+            let (option, value) = match name.split_once('=') {
+                Some((option, value)) => (option, Some(value)),
+                None => (name, None),
+            };
+            let bad_value = value.is_some_and(|value| option != "bglog" || !on || value.is_empty());
+            if !options::SET_NAMES.contains(&option) || bad_value {
+                let _ = writeln!(stderr, "jsh: set: {name}: invalid option name");
+                return 1;
+            }
+            if option == "bglog" {
+                shell.options.bglog_dir = value.map(|dir| shell.cwd.join(dir));
+            }
+            shell.options.set(option, on);
+        }
+    }
+
+    if let Some(params) = params {
+        shell.set_positional_params(params);
+    }
+    0
+}
+
+/// `trap [action] signal...`: run `action` when a signal arrives or
+/// (`EXIT`) when the shell exits. An empty action ignores the signal, and `-`
+/// or a lone signal restores the default. With no arguments or `-p`, list
+/// the traps in a form that can be reused as input; `-l` lists the signals.
+fn builtin_trap(args: &[String], traps: &mut TrapTable, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let (print, args) = match args.first().map(String::as_str) {
+        Some("-l") => {
+            for &(name, number) in traps::SIGNALS {
+                let _ = writeln!(stdout, "{number:>2}) SIG{name}");
+            }
+            return 0;
+        }
+        Some("-p") => (true, &args[1..]),
+        Some("--") => (false, &args[1..]),
+        _ => (false, args),
+    };
+    if args.is_empty() {
+        for (signal, action) in traps.iter() {
+            let _ = writeln!(stdout, "trap -- {} {}", quote_trap_action(action), traps::signal_name(signal));
+        }
+        return 0;
+    }
+
+    let (action, specs) = match args {
+        _ if print => (None, args),
+        [_] => (Some("-"), args),
+        [action, specs @ ..] => (Some(action.as_str()), specs),
+        [] => unreachable!(),
+    };
+
+    let mut exit_code = 0;
+    for spec in specs {
+        let Some(signal) = traps::parse_signal(spec) else {
+            let _ = writeln!(stderr, "jsh: trap: {spec}: invalid signal specification");
+            exit_code = 1;
+            continue;
+        };
+        match action {
+            None => {
+                if let Some(action) = traps.get(signal) {
+                    let _ = writeln!(stdout, "trap -- {} {}", quote_trap_action(action), traps::signal_name(signal));
+                }
+            }
+            Some("-") => traps.reset(signal),
+            Some(action) => traps.set(signal, action),
+        }
+    }
+    exit_code
+}
+
+fn quote_trap_action(action: &str) -> String {
+    format!("'{}'", action.replace('\'', r"'\''"))
+}
+
+/// `complete [-fdc] [-W words] [-C command] name...`: register how Tab
+/// completes the arguments of each named command. `-p` prints specs in a
+/// form that can be reused as input and `-r` removes them; with no names,
+/// both apply to every spec.
+fn builtin_complete(
+    args: &[String],
+    completions: &mut CompletionTable,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let mut spec = CompletionSpec::default();
+    let mut print = args.is_empty();
+    let mut remove = false;
+    let mut rest = args.iter();
+    let mut names = Vec::new();
+    while let Some(arg) = rest.next() {
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| names.is_empty() && !flags.is_empty()) else {
+            names.push(arg.as_str());
+            continue;
+        };
+        if flags == "-" {
+            names.extend(rest.by_ref().map(String::as_str));
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'f' => spec.files = true,
+                'd' => spec.directories = true,
+                'c' => spec.commands = true,
+                'p' => print = true,
+                'r' => remove = true,
+                'W' | 'C' => {
+                    let Some(value) = rest.next() else {
+                        let _ = writeln!(stderr, "jsh: complete: -{flag}: option requires an argument");
+                        return 2;
+                    };
+                    match flag {
+                        'W' => spec.wordlist = Some(value.clone()),
+                        _ => spec.generator = Some(value.clone()),
+                    }
+                }
+                _ => {
+                    let _ = writeln!(stderr, "jsh: complete: -{flag}: invalid option");
+                    let _ = writeln!(stderr, "complete: usage: complete [-pr] [-fdc] [-W wordlist] [-C command] [name ...]");
+                    return 2;
+                }
+            }
+        }
+    }
+
+    if print || remove {
+        if names.is_empty() {
+            if remove {
+                completions.clear();
+            } else {
+                for (name, spec) in completions.iter() {
+                    let _ = writeln!(stdout, "{}", spec.format(name));
+                }
+            }
+            return 0;
+        }
+        let mut exit_code = 0;
+        for name in names {
+            let found = match completions.get(name) {
+                Some(spec) if !remove => {
+                    let _ = writeln!(stdout, "{}", spec.format(name));
+                    true
+                }
+                _ => completions.remove(name),
+            };
+            if !found {
+                let _ = writeln!(stderr, "jsh: complete: {name}: no completion specification");
+                exit_code = 1;
+            }
+        }
+        return exit_code;
+    }
+
+    if names.is_empty() {
+        let _ = writeln!(stderr, "complete: usage: complete [-pr] [-fdc] [-W wordlist] [-C command] [name ...]");
+        return 2;
+    }
+    for name in names {
+        completions.set(name, spec.clone());
+    }
+    0
+}
+
+/// `hash [-r] [-d|-t] [name...]`: remember where commands are on `$PATH`,
+/// or list what has been remembered with hit counts. `-r` forgets
+/// everything, `-d` forgets the named commands, and `-t` prints where each
+/// named command was found.
+fn builtin_hash(
+    args: &[String],
+    builtins: &BuiltinTable,
+    command_hash: &mut CommandHash,
+    search_path: &str,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let mut forget = false;
+    let mut show = false;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'r' => command_hash.clear(),
+                        'd' => forget = true,
+                        't' => show = true,
+                        _ => {
+                            let _ = writeln!(stderr, "jsh: hash: -{flag}: invalid option");
+                            let _ = writeln!(stderr, "hash: usage: hash [-r] [-d|-t] [name ...]");
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => names.push(arg.as_str()),
+        }
+    }
+
+    if names.is_empty() {
+        if forget || show || args.iter().any(|arg| arg.starts_with('-')) {
+            return 0;
+        }
+        if command_hash.iter(search_path).next().is_none() {
+            let _ = writeln!(stdout, "hash: hash table empty");
+            return 0;
+        }
+        let _ = writeln!(stdout, "hits\tcommand");
+        for (_, path, hits) in command_hash.iter(search_path) {
+            let _ = writeln!(stdout, "{hits:4}\t{}", path.display());
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in names {
+        let found = match (forget, show) {
+            (true, _) => command_hash.remove(name),
+            (false, true) => match command_hash.get(name, search_path) {
+                Some(path) => {
+                    let _ = writeln!(stdout, "{}", path.display());
+                    true
+                }
+                None => false,
+            },
+            // Builtins are never looked up on $PATH, so there is nothing to
+            // remember.
+            (false, false) => builtins.contains(name) || command_hash.remember(name, search_path),
+        };
+        if !found {
+            let _ = writeln!(stderr, "jsh: hash: {name}: not found");
+            status = 1;
+        }
+    }
+    status
+}
+
+/// `history --import FORMAT FILE` / `history --export FORMAT FILE`: copy
+/// history between `~/.jsh_history` and a bash or fish history file.
+/// Imported commands go before jsh's own, since they are older; exported
+/// ones are appended to FILE. The running session's history is unchanged
+/// until the next start.
+fn builtin_history(
+    args: &[String],
+    cwd: &Path,
+    rewritten: &mut bool,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let usage = "history: usage: history [n] | history --import|--export bash|fish file";
+    let [mode, format, file] = args else {
+        return match args {
+            [] => list_history(None, stdout, stderr),
+            [count] if !count.starts_with('-') => match count.parse() {
+                Ok(count) => list_history(Some(count), stdout, stderr),
+                Err(_) => {
+                    let _ = writeln!(stderr, "jsh: history: {count}: numeric argument required");
+                    1
+                }
+            },
+            _ => {
+                let _ = writeln!(stderr, "{usage}");
+                2
+            }
+        };
+    };
+    let Some(format) = ForeignHistory::from_name(format) else {
+        let _ = writeln!(stderr, "jsh: history: {format}: unknown history format (bash or fish)");
+        return 2;
+    };
+    let Some(own) = history::history_file_path() else {
+        let _ = writeln!(stderr, "jsh: history: HOME is not set");
+        return 1;
+    };
+
+    let (file, path) = (Path::new(file), cwd.join(file));
+    let result = match mode.as_str() {
+        "--import" => std::fs::read(&path)
+            .map_err(|e| format!("{}: {e}", file.display()))
+            .and_then(|bytes| {
+                let mut entries = format.import(&String::from_utf8_lossy(&bytes));
+                entries.extend(jsh_history_entries(&own)?);
+                let text: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+                *rewritten = true;
+                std::fs::write(&own, text).map_err(|e| format!("{}: {e}", own.display()))
+            }),
+        "--export" => jsh_history_entries(&own).and_then(|entries| {
+            let when = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut out| out.write_all(format.export(&entries, when).as_bytes()))
+                .map_err(|e| format!("{}: {e}", file.display()))
+        }),
+        _ => {
+            let _ = writeln!(stderr, "jsh: history: {mode}: invalid option");
+            let _ = writeln!(stderr, "{usage}");
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            let _ = writeln!(stderr, "jsh: history: {message}");
+            1
+        }
+    }
+}
+
+/// `history [n]`: print the last `n` entries in `~/.jsh_history`, or all of
+/// them, numbered from the oldest as bash numbers them.
+fn list_history(count: Option<usize>, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let entries = match history::history_file_path().ok_or_else(|| "HOME is not set".to_string()) {
+        Ok(own) => jsh_history_entries(&own),
+        Err(message) => Err(message),
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(message) => {
+            let _ = writeln!(stderr, "jsh: history: {message}");
+            return 1;
+        }
+    };
+    let skip = entries.len().saturating_sub(count.unwrap_or(entries.len()));
+    for (number, entry) in entries.iter().enumerate().skip(skip) {
+        if writeln!(stdout, "{:5}  {entry}", number + 1).is_err() {
+            break;
+        }
+    }
+    0
+}
+
+/// The entries in `~/.jsh_history`, oldest first. A missing file has none.
+fn jsh_history_entries(path: &Path) -> Result<Vec<String>, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+/// `shopt [-pqsu] [name...]`: set (`-s`), unset (`-u`), or query shell
+/// options. Querying returns 1 if any named option is off; `-q` suppresses
+/// the output and `-p` prints in a form that can be reused as input.
+fn builtin_shopt(
+    args: &[String],
+    options: &mut ShellOptions,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let mut setting = None;
+    let mut quiet = false;
+    let mut reusable = false;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        's' => setting = Some(true),
+                        'u' => setting = Some(false),
+                        'q' => quiet = true,
+                        'p' => reusable = true,
+                        _ => {
+                            let _ = writeln!(stderr, "jsh: shopt: -{flag}: invalid option");
+                            let _ = writeln!(stderr, "shopt: usage: shopt [-pqsu] [optname ...]");
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => names.push(arg.as_str()),
+        }
+    }
+
+    if let Some(name) = names.iter().find(|name| !options::SHOPT_NAMES.contains(name)) {
+        let _ = writeln!(stderr, "jsh: shopt: {name}: invalid shell option name");
+        return 1;
+    }
+
+    if let Some(on) = setting
+        && !names.is_empty()
+    {
+        for name in names {
+            options.set(name, on);
+        }
+        return 0;
+    }
+
+    // Query: the named options, or all of them (only those matching `-s`
+    // or `-u` if given).
+    let listed: Vec<(&str, bool)> = if names.is_empty() {
+        options
+            .list(options::SHOPT_NAMES)
+            .filter(|(_, on)| setting.is_none_or(|setting| setting == *on))
+            .collect()
+    } else {
+        names
+            .iter()
+            .map(|&name| (name, options.get(name).unwrap_or_default()))
+            .collect()
+    };
+    if !quiet {
+        for (name, on) in &listed {
+            if reusable {
+                let _ = writeln!(stdout, "shopt {} {name}", if *on { "-s" } else { "-u" });
+            } else {
+                let _ = writeln!(stdout, "{name:<15}\t{}", if *on { "on" } else { "off" });
+            }
+        }
+    }
+    if !names.is_empty() && listed.iter().any(|(_, on)| !on) { 1 } else { 0 }
+}
+
+/// `eval [arg...]`: join the arguments with spaces and run the result as
+/// commands in the current shell.
+fn builtin_eval(args: &[String], shell: &mut Shell) -> BuiltinAction {
+    let script = args.join(" ");
+    if script.trim().is_empty() {
+        return BuiltinAction::Continue(0);
+    }
+
+    match shell.run_source(&mut ScriptLines::new(&script), "eval") {
+        crate::executor::ExecutionAction::Continue(code) => BuiltinAction::Continue(code),
+        crate::executor::ExecutionAction::Exit(code) => BuiltinAction::Exit(code),
+    }
+}
+
+/// Run a file's commands in the current shell, so exports, `cd`, and aliases
+/// persist. Extra arguments become the positional parameters while the file
+/// runs; without them the caller's parameters stay visible.
+fn builtin_source(
+    program: &str,
+    args: &[String],
+    shell: &mut Shell,
+    stderr: &mut dyn Write,
+) -> BuiltinAction {
+    let Some(file) = args.first() else {
+        let _ = writeln!(stderr, "jsh: {program}: filename argument required");
+        let _ = writeln!(stderr, "{program}: usage: {program} filename [arguments]");
+        return BuiltinAction::Continue(2);
+    };
+
+    let has_params = args.len() > 1;
+    if has_params {
+        shell.push_positional_params(args[1..].to_vec());
+    }
+
+    shell.source_depth += 1;
+    let action = shell.source_file(Path::new(file));
+    shell.source_depth -= 1;
+    shell.returning = false;
+
+    if has_params {
+        shell.pop_positional_params();
+    }
+
+    match action {
+        crate::executor::ExecutionAction::Continue(code) => BuiltinAction::Continue(code),
+        crate::executor::ExecutionAction::Exit(code) => BuiltinAction::Exit(code),
+    }
+}
+
+/// `return [n]`: stop reading the file `source` is running, with status `n`
+/// (default `$?`). Outside a sourced file it is an error.
+fn builtin_return(args: &[String], shell: &mut Shell, stderr: &mut dyn Write) -> i32 {
+    if shell.source_depth == 0 {
+        let _ = writeln!(stderr, "jsh: return: can only `return' from a sourced script");
+        return 1;
+    }
+    let status = match args.first() {
+        None => shell.last_exit_code,
+        Some(arg) => match arg.parse::<i32>() {
+            Ok(status) => status,
+            Err(_) => {
+                let _ = writeln!(stderr, "jsh: return: {arg}: numeric argument required");
+                return 2;
+            }
+        },
+    };
+    shell.returning = true;
+    status
+}
+
+/// `shift [n]` — drop the first n positional parameters (default 1).
+fn builtin_shift(args: &[String], shell: &mut Shell, stderr: &mut dyn Write) -> i32 {
+    let count = match args.first() {
+        None => 1,
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(count) => count,
             Err(_) => {
-                let _ = writeln!(stderr, "exit: {s}: numeric argument required");
-                BuiltinAction::Exit(2)
+                let _ = writeln!(stderr, "jsh: shift: {arg}: numeric argument required");
+                return 1;
             }
         },
-    }
-}
+    };
 
-fn builtin_echo(args: &[String], stdout: &mut dyn Write) -> i32 {
-    let _ = writeln!(stdout, "{}", args.join(" "));
-    0
+    // Like bash, a count larger than $# fails quietly.
+    if shell.shift_positional_params(count) { 0 } else { 1 }
 }
 
-fn builtin_export(args: &[String], stderr: &mut dyn Write) -> i32 {
-    for arg in args {
-        if let Some((key, value)) = arg.split_once('=') {
-            // SAFETY: Env var mutation only happens on the main thread.
-            unsafe { std::env::set_var(key, value) };
-        } else {
-            // export VAR with no value — just mark for export (no-op for now)
-            let _ = writeln!(stderr, "export: usage: export VAR=value");
-        }
-    }
-    0
+// ── Job control builtins ──
+
+/// List all tracked jobs.
+fn builtin_jobs(args: &[String], shell: &mut Shell, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    // Poll rather than reap: jobs that just finished are reported by the
+    // listing itself, after which the table can forget every finished job.
+    shell.job_table.poll();
+    let status = list_jobs(args, &shell.job_table, stdout, stderr);
+    shell.job_table.forget_done();
+    status
 }
 
-fn builtin_unset(args: &[String]) -> i32 {
-    for arg in args {
-        // SAFETY: Env var mutation only happens on the main thread.
-        unsafe { std::env::remove_var(arg) };
+/// `jobs [-lprs]`: list jobs, marking the current job `+` and the previous
+/// one `-`. `-l` adds each job's PID, process group, and time since it
+/// started, `-p` prints only
+/// process group IDs (for `kill $(jobs -p)`), and `-r` / `-s` keep only
+/// running or stopped jobs. `--json` prints the listed jobs as a JSON array
+/// for scripts and status bars. `jobs -o [job]` instead prints where a
+/// job's output is logged under `set -o bglog`.
+fn list_jobs(
+    args: &[String],
+    job_table: &JobTable,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    if let [flag, rest @ ..] = args
+        && flag == "-o"
+    {
+        if rest.len() > 1 {
+            let _ = writeln!(stderr, "jobs: usage: jobs -o [job]");
+            return 2;
+        }
+        let id = match rest.first() {
+            Some(spec) => job_table.resolve_listed_spec(spec),
+            None => job_table.current_and_previous().0.ok_or_else(|| "no current job".to_string()),
+        };
+        let id = match id {
+            Ok(id) => id,
+            Err(message) => {
+                let _ = writeln!(stderr, "jobs: {message}");
+                return 1;
+            }
+        };
+        // Listings include the parent's jobs in a subshell, as in `$(jobs -o)`.
+        let Some(job) = job_table.listings().into_iter().find(|job| job.id == id) else {
+            let _ = writeln!(stderr, "jobs: %{id}: no such job");
+            return 1;
+        };
+        return match &job.log {
+            Some(path) => {
+                let _ = writeln!(stdout, "{}", path.display());
+                0
+            }
+            None => {
+                let _ = writeln!(stderr, "jsh: jobs: %{id}: no output log");
+                1
+            }
+        };
     }
-    0
-}
 
-fn builtin_type(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
-    let mut exit_code = 0;
+    let mut long = false;
+    let mut pids_only = false;
+    let mut running = false;
+    let mut stopped = false;
+    let mut json = false;
     for arg in args {
-        if is_builtin(arg) {
-            let _ = writeln!(stdout, "{arg} is a shell builtin");
-        } else {
-            match find_in_path(arg) {
-                Some(path) => {
-                    let _ = writeln!(stdout, "{arg} is {}", path.display());
-                }
-                None => {
-                    let _ = writeln!(stderr, "{arg}: not found");
-                    exit_code = 1;
+        if arg == "--json" {
+            json = true;
+            continue;
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            let _ = writeln!(stderr, "jsh: jobs: {arg}: job specs are not supported");
+            return 2;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'l' => long = true,
+                'p' => pids_only = true,
+                'r' => running = true,
+                's' => stopped = true,
+                _ => {
+                    let _ = writeln!(stderr, "jsh: jobs: -{flag}: invalid option");
+                    let _ = writeln!(stderr, "jobs: usage: jobs [-lprs] [--json] | jobs -o [job]");
+                    return 2;
                 }
             }
         }
     }
-    exit_code
-}
-
-fn builtin_help(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
-    match args.first().map(String::as_str) {
-        // ── no args: overview ────────────────────────────────────────────────
-        None => {
-            let _ = writeln!(stdout, "jsh — James Shell  (type 'help <topic>' for details)");
-            let _ = writeln!(stdout, "");
-            let _ = writeln!(stdout, "Builtins:");
-            let _ = writeln!(stdout, "  cd [dir|-]          Change directory (- goes to previous)");
-            let _ = writeln!(stdout, "  pwd                 Print working directory");
-            let _ = writeln!(stdout, "  echo [args...]      Print arguments");
-            let _ = writeln!(stdout, "  export VAR=value    Set and export environment variable");
-            let _ = writeln!(stdout, "  unset VAR           Remove environment variable");
-            let _ = writeln!(stdout, "  type name...        Show whether name is builtin or external");
-            let _ = writeln!(stdout, "  exit [code]         Exit the shell");
-            let _ = writeln!(stdout, "  jobs                List background jobs");
-            let _ = writeln!(stdout, "  fg [%N]             Bring job to foreground");
-            let _ = writeln!(stdout, "  bg [%N]             Resume stopped job in background");
-            let _ = writeln!(stdout, "  wait [%N]           Wait for background job(s)");
-            let _ = writeln!(stdout, "  Stateful builtins (cd/export/unset/fg/bg)");
-            let _ = writeln!(stdout, "    are not supported in non-terminal pipeline steps");
-            let _ = writeln!(stdout, "  help [topic]        Show this help or a topic reference");
-            let _ = writeln!(stdout, "");
-            let _ = writeln!(stdout, "Topics: variables  redirection  jobs  expansion  quotes  exit-codes");
-            0
-        }
-
-        // ── builtin-specific usage ────────────────────────────────────────────
-        Some("cd") => {
-            let _ = writeln!(stdout, "cd [dir|-]");
-            let _ = writeln!(stdout, "  Change the current directory.");
-            let _ = writeln!(stdout, "  No argument: go to $HOME.");
-            let _ = writeln!(stdout, "  '-': go to the previous directory ($OLDPWD).");
-            let _ = writeln!(stdout, "  Sets $OLDPWD to the directory you came from.");
-            0
-        }
-        Some("pwd") => {
-            let _ = writeln!(stdout, "pwd");
-            let _ = writeln!(stdout, "  Print the absolute path of the current directory.");
-            let _ = writeln!(stdout, "  This command does not run as a background job.");
-            0
-        }
-        Some("echo") => {
-            let _ = writeln!(stdout, "echo [args...]");
-            let _ = writeln!(stdout, "  Print arguments separated by spaces, followed by a newline.");
-            0
-        }
-        Some("export") => {
-            let _ = writeln!(stdout, "export VAR=value");
-            let _ = writeln!(stdout, "  Set VAR to value and export it to child processes.");
-            0
-        }
-        Some("unset") => {
-            let _ = writeln!(stdout, "unset VAR...");
-            let _ = writeln!(stdout, "  Remove one or more environment variables.");
-            0
-        }
-        Some("type") => {
-            let _ = writeln!(stdout, "type name...");
-            let _ = writeln!(stdout, "  For each name, report whether it is a shell builtin");
-            let _ = writeln!(stdout, "  or the full path of the external executable.");
-            let _ = writeln!(stdout, "  Exit code 1 if any name is not found.");
-            0
-        }
-        Some("exit") => {
-            let _ = writeln!(stdout, "exit [code]");
-            let _ = writeln!(stdout, "  Exit the shell with the given numeric exit code.");
-            let _ = writeln!(stdout, "  No argument: exit 0.  Non-numeric argument: exit 2.");
-            0
-        }
-        Some("help") => {
-            let _ = writeln!(stdout, "help [topic|builtin]");
-            let _ = writeln!(stdout, "  No argument: list all builtins and topics.");
-            let _ = writeln!(stdout, "  Builtin name: show usage for that builtin.");
-            let _ = writeln!(stdout, "  Topic name: show a reference section.");
-            let _ = writeln!(stdout, "  Topics: variables  redirection  jobs  expansion  quotes  exit-codes");
-            0
-        }
 
-        // ── topics ────────────────────────────────────────────────────────────
-        // Note: "jobs" covers both the builtin and the job-control topic in one arm
-        // to avoid an unreachable-pattern compiler error.
-        Some("jobs") => {
-            let _ = writeln!(stdout, "jobs");
-            let _ = writeln!(stdout, "  List background and stopped jobs with their IDs.");
-            let _ = writeln!(stdout, "  Status column: Running | Stopped | Done");
-            let _ = writeln!(stdout, "");
-            let _ = writeln!(stdout, "Job control summary:");
-            let _ = writeln!(stdout, "  cmd &           Run command in background");
-            let _ = writeln!(stdout, "  fg [%N]         Bring job to foreground");
-            let _ = writeln!(stdout, "  bg [%N]         Resume stopped job in background");
-            let _ = writeln!(stdout, "  wait [%N]       Wait for job(s) to finish");
-            let _ = writeln!(stdout, "  Ctrl-Z          Suspend foreground job (Unix only)");
-            0
-        }
-        Some("fg") => {
-            let _ = writeln!(stdout, "fg [%N]");
-            let _ = writeln!(stdout, "  Bring job %N to the foreground and wait for it.");
-            let _ = writeln!(stdout, "  No argument: use the most recently backgrounded job.");
-            0
-        }
-        Some("bg") => {
-            let _ = writeln!(stdout, "bg [%N]");
-            let _ = writeln!(stdout, "  Resume stopped job %N in the background.");
-            let _ = writeln!(stdout, "  No argument: use the most recently stopped job.");
-            0
-        }
-        Some("wait") => {
-            let _ = writeln!(stdout, "wait [%N]");
-            let _ = writeln!(stdout, "  Wait for background job %N to finish.");
-            let _ = writeln!(stdout, "  No argument: wait for all background jobs.");
-            let _ = writeln!(stdout, "  Sets $? to the exit code of the waited job.");
-            0
-        }
-        Some("variables") => {
-            let _ = writeln!(stdout, "Special variables:");
-            let _ = writeln!(stdout, "  $?        Exit code of the last command");
-            let _ = writeln!(stdout, "  $$        PID of the shell process");
-            let _ = writeln!(stdout, "  $0        Shell name (always 'jsh')");
-            let _ = writeln!(stdout, "  $HOME     Home directory");
-            let _ = writeln!(stdout, "  $PATH     Command search path");
-            let _ = writeln!(stdout, "  $PWD      Current directory");
-            let _ = writeln!(stdout, "  $OLDPWD   Previous directory (set by cd)");
-            let _ = writeln!(stdout, "  $USER     Current user name");
-            let _ = writeln!(stdout, "  $VAR      Value of any exported environment variable");
-            let _ = writeln!(stdout, "  ${{VAR}}    Same as $VAR (brace form)");
-            0
-        }
-        Some("redirection") => {
-            let _ = writeln!(stdout, "Redirection operators:");
-            let _ = writeln!(stdout, "  cmd > file      Write stdout to file (truncate)");
-            let _ = writeln!(stdout, "  cmd >> file     Append stdout to file");
-            let _ = writeln!(stdout, "  cmd < file      Read stdin from file");
-            let _ = writeln!(stdout, "  cmd 2> file     Write stderr to file");
-            let _ = writeln!(stdout, "  cmd 2>> file    Append stderr to file");
-            let _ = writeln!(stdout, "  cmd 2>&1        Merge stderr into stdout");
-            let _ = writeln!(stdout, "  cmd 1>&2        Merge stdout into stderr");
-            let _ = writeln!(stdout, "  cmd <<< word    Feed word as stdin (here-string)");
-            0
-        }
-        Some("expansion") => {
-            let _ = writeln!(stdout, "Word expansion (applied in order):");
-            let _ = writeln!(stdout, "  ~               Expands to $HOME");
-            let _ = writeln!(stdout, "  ~/path          Expands to $HOME/path");
-            let _ = writeln!(stdout, "  $VAR            Variable substitution");
-            let _ = writeln!(stdout, "  ${{VAR}}          Braced variable substitution");
-            let _ = writeln!(stdout, "  *               Matches any string of characters");
-            let _ = writeln!(stdout, "  ?               Matches any single character");
-            let _ = writeln!(stdout, "  [abc]           Matches any character in the set");
-            let _ = writeln!(stdout, "  Globs that match nothing are kept as literals.");
-            let _ = writeln!(stdout, "  Globs inside quotes are not expanded.");
-            0
-        }
-        Some("quotes") => {
-            let _ = writeln!(stdout, "Quoting:");
-            let _ = writeln!(stdout, "  'text'    Single quotes: no expansion of any kind");
-            let _ = writeln!(stdout, "  \"text\"    Double quotes: $VAR expanded, globs suppressed");
-            let _ = writeln!(stdout, "  \\c        Backslash: treat next character literally");
-            let _ = writeln!(stdout, "  Mixing quote styles in one word is allowed.");
-            0
-        }
-        Some("exit-codes") => {
-            let _ = writeln!(stdout, "Exit codes:");
-            let _ = writeln!(stdout, "  0          Success");
-            let _ = writeln!(stdout, "  1          General error");
-            let _ = writeln!(stdout, "  2          Bad usage (wrong arguments)");
-            let _ = writeln!(stdout, "  126        Command found but not executable");
-            let _ = writeln!(stdout, "  127        Command not found");
-            let _ = writeln!(stdout, "  128+N      Command killed by signal N");
-            let _ = writeln!(stdout, "  $?         Holds the exit code of the last command");
-            0
+    // With both -r and -s, as with neither, every job is listed.
+    let listed = job_table.listings().into_iter().filter(|job| match job.status {
+        JobStatus::Running => running || !stopped,
+        JobStatus::Stopped => stopped || !running,
+        JobStatus::Done(_) => !running && !stopped,
+    });
+    if json {
+        let objects: Vec<String> = listed.map(|job| job_json(&job)).collect();
+        let _ = writeln!(stdout, "[{}]", objects.join(","));
+        return 0;
+    }
+    for job in listed {
+        if pids_only {
+            let _ = writeln!(stdout, "{}", job.pgid);
+            continue;
         }
-
-        // ── unknown ───────────────────────────────────────────────────────────
-        Some(unknown) => {
-            let _ = writeln!(stderr, "help: no help for '{unknown}'");
-            1
+        let command = match job.status {
+            JobStatus::Running => format!("{} &", job.command),
+            _ => job.command.clone(),
+        };
+        if long {
+            let _ = writeln!(
+                stdout,
+                "[{}]{} {} {} {:<22}  {:>7} {command}",
+                job.id,
+                job.marker,
+                job.pid,
+                job.pgid,
+                job.state,
+                crate::jobs::format_duration(job.started.elapsed()),
+            );
+        } else {
+            let _ = writeln!(stdout, "{}", jobs::status_line(job.id, job.marker, &job.state, &command));
         }
     }
+    0
 }
 
-// ── Job control builtins ──
+/// One job as a JSON object for `jobs --json`. `started_at` is in seconds
+/// since the Unix epoch.
+fn job_json(job: &crate::jobs::JobListing) -> String {
+    let status = match job.status {
+        JobStatus::Running => "running",
+        JobStatus::Stopped => "stopped",
+        JobStatus::Done(_) => "done",
+    };
+    let started_at = std::time::SystemTime::now()
+        .checked_sub(job.started.elapsed())
+        .and_then(|started| started.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    format!(
+        r#"{{"id":{},"pid":{},"pgid":{},"status":"{status}","command":{},"started_at":{started_at}}}"#,
+        job.id,
+        job.pid,
+        job.pgid,
+        json_string(&job.command)
+    )
+}
 
-/// List all tracked jobs.
-fn builtin_jobs(job_table: &mut JobTable, stdout: &mut dyn Write) -> i32 {
-    // Reap first so any jobs that just finished show as "Done" if still tracked,
-    // but in practice reap() removes them — so jobs shows only live jobs.
-    job_table.reap();
-
-    for job in job_table.jobs_sorted() {
-        let status_str = match &job.status {
-            JobStatus::Running => "Running   ",
-            JobStatus::Stopped => "Stopped   ",
-            JobStatus::Done(_) => "Done      ",
-        };
-        let _ = writeln!(stdout, "[{}]  {} {}", job.id, status_str, job.command);
+/// `text` as a quoted JSON string.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
     }
-    0
+    out.push('"');
+    out
 }
 
 /// Bring a background or stopped job to the foreground and wait for it.
@@ -370,14 +2026,25 @@ fn builtin_fg(
     stdout: &mut dyn Write,
     stderr: &mut dyn Write,
 ) -> i32 {
-    let job_id = match resolve_job_id(args.first(), job_table.most_recent_id(), stderr) {
+    let current = job_table.current_and_previous().0;
+    let job_id = match resolve_job_id("fg", args.first(), current, job_table, stderr) {
         Some(id) => id,
         None => return 1,
     };
 
+    if let Some(JobStatus::Done(_)) = job_table.get(job_id).map(|job| &job.status) {
+        let _ = writeln!(stderr, "fg: job has terminated");
+        job_table.remove(job_id);
+        return 1;
+    }
+
     #[allow(unused_variables)]
     let (pid, pgid, command) = match job_table.get_mut(job_id) {
         Some(job) => {
+            #[cfg(windows)]
+            if let Err(e) = job.resume() {
+                let _ = writeln!(stderr, "fg: failed to resume job {}: {}", job_id, e);
+            }
             // Mark as running while we foreground it.
             job.status = JobStatus::Running;
             (job.pid, job.pgid, job.command.clone())
@@ -411,6 +2078,7 @@ fn builtin_fg(
             }
         }
 
+        let foreground = job_control::ForegroundJob::new(pgid as libc::pid_t);
         let outcome = match job_control::wait_for_pid(pid as libc::pid_t) {
             Ok(outcome) => outcome,
             Err(e) => {
@@ -419,17 +2087,25 @@ fn builtin_fg(
             }
         };
 
+        drop(foreground);
         drop(terminal_guard);
+        job_control::settle_terminal_modes(matches!(outcome, job_control::WaitOutcome::Exited(0)));
 
         match outcome {
             job_control::WaitOutcome::Stopped => {
                 if let Some(job) = job_table.get_mut(job_id) {
                     job.status = JobStatus::Stopped;
                 }
-                let _ = writeln!(stdout, "[{}]  Stopped  {}", job_id, command);
+                job_table.mark_current(job_id);
+                let _ = writeln!(stdout, "{}", jobs::status_line(job_id, job_table.marker(job_id), "Stopped", &command));
                 0
             }
             job_control::WaitOutcome::Exited(code) => {
+                // The rest of a pipeline has usually exited by now.
+                let code = match job_table.get_mut(job_id).map(|job| job.finish(code)) {
+                    Some(Ok(status)) => status,
+                    _ => code,
+                };
                 job_table.remove(job_id);
                 code
             }
@@ -438,18 +2114,20 @@ fn builtin_fg(
 
     #[cfg(not(unix))]
     {
-        let wait_result = match job_table.get_mut(job_id) {
-            Some(job) => job.child.wait(),
-            None => {
-                let _ = writeln!(stderr, "fg: {}: no such job", job_id);
-                return 1;
-            }
+        let Some(job) = job_table.get_mut(job_id) else {
+            let _ = writeln!(stderr, "fg: {}: no such job", job_id);
+            return 1;
         };
+        #[cfg(windows)]
+        let foreground = job_control::ForegroundJob::new(job.process_ids());
+        let wait_result = job.wait_for_exit();
+        #[cfg(windows)]
+        drop(foreground);
 
         match wait_result {
-            Ok(status) => {
+            Ok(code) => {
                 job_table.remove(job_id);
-                status::exit_code(status)
+                code
             }
             Err(e) => {
                 let _ = writeln!(stderr, "fg: error waiting for job {}: {}", job_id, e);
@@ -459,20 +2137,25 @@ fn builtin_fg(
     }
 }
 
-/// Resume a stopped job in the background (Unix only).
+/// Resume a stopped job in the background.
 fn builtin_bg(
     args: &[String],
     job_table: &mut JobTable,
     stdout: &mut dyn Write,
     stderr: &mut dyn Write,
 ) -> i32 {
-    let job_id = match resolve_job_id(args.first(), job_table.most_recent_stopped_id(), stderr) {
+    let stopped = job_table.most_recent_stopped_id();
+    let job_id = match resolve_job_id("bg", args.first(), stopped, job_table, stderr) {
         Some(id) => id,
         None => return 1,
     };
 
     match job_table.get_mut(job_id) {
         Some(job) => {
+            if let JobStatus::Done(_) = job.status {
+                let _ = writeln!(stderr, "bg: job has terminated");
+                return 1;
+            }
             if job.status != JobStatus::Stopped {
                 let _ = writeln!(stderr, "bg: job {} is not stopped", job_id);
                 return 1;
@@ -483,9 +2166,16 @@ fn builtin_bg(
                 let _ = writeln!(stderr, "bg: failed to resume job {}: {}", job_id, e);
                 return 1;
             }
+            #[cfg(windows)]
+            if let Err(e) = job.resume() {
+                let _ = writeln!(stderr, "bg: failed to resume job {}: {}", job_id, e);
+                return 1;
+            }
 
             job.status = JobStatus::Running;
-            let _ = writeln!(stdout, "[{}]  {} &", job.id, job.command);
+            let command = job.command.clone();
+            job_table.mark_current(job_id);
+            let _ = writeln!(stdout, "[{job_id}]{} {command} &", job_table.marker(job_id));
             0
         }
         None => {
@@ -499,6 +2189,7 @@ fn builtin_bg(
 fn builtin_wait(
     args: &[String],
     job_table: &mut JobTable,
+    show_times: bool,
     stdout: &mut dyn Write,
     stderr: &mut dyn Write,
 ) -> i32 {
@@ -506,22 +2197,22 @@ fn builtin_wait(
     let mut had_error = false;
 
     if args.is_empty() {
-        let ids = job_table.running_ids();
+        let ids = job_table.waitable_ids();
         for id in ids {
-            match wait_for_job(id, job_table, stdout, stderr) {
+            match wait_for_job(id, job_table, show_times, stdout, stderr) {
                 Ok(status) => last_status = status,
                 Err(()) => had_error = true,
             }
         }
     } else {
         for arg in args {
-            match arg.trim_start_matches('%').parse::<usize>() {
-                Ok(id) => match wait_for_job(id, job_table, stdout, stderr) {
+            match job_table.resolve_spec(arg) {
+                Ok(id) => match wait_for_job(id, job_table, show_times, stdout, stderr) {
                     Ok(status) => last_status = status,
                     Err(()) => had_error = true,
                 },
-                Err(_) => {
-                    let _ = writeln!(stderr, "wait: invalid job id: {}", arg);
+                Err(message) => {
+                    let _ = writeln!(stderr, "wait: {message}");
                     had_error = true;
                 }
             }
@@ -531,13 +2222,16 @@ fn builtin_wait(
     if had_error { 1 } else { last_status }
 }
 
-/// Blocking wait for a single job; removes it from the table when done.
+/// Blocking wait for a single job; removes it from the table when done. A
+/// job that had already finished gives the status it finished with.
 fn wait_for_job(
     job_id: usize,
     job_table: &mut JobTable,
+    show_times: bool,
     stdout: &mut dyn Write,
     stderr: &mut dyn Write,
 ) -> Result<i32, ()> {
+    let marker = job_table.marker(job_id);
     let job = match job_table.get_mut(job_id) {
         Some(j) => j,
         None => {
@@ -546,19 +2240,21 @@ fn wait_for_job(
         }
     };
 
-    if job.status != JobStatus::Running {
-        return Ok(0);
+    match job.status {
+        JobStatus::Running => {}
+        JobStatus::Stopped => return Ok(0),
+        JobStatus::Done(code) => {
+            if !job.reported {
+                let _ = writeln!(stdout, "{}", job.done_message(marker, show_times));
+            }
+            job_table.remove(job_id);
+            return Ok(code);
+        }
     }
 
-    let id = job.id;
-    let cmd = job.command.clone();
-
-    let wait_result = job.child.wait();
-
-    match wait_result {
-        Ok(status) => {
-            let code = status::exit_code(status);
-            let _ = writeln!(stdout, "[{}]  Done  {}", id, cmd);
+    match job.wait_for_exit() {
+        Ok(code) => {
+            let _ = writeln!(stdout, "{}", job.done_message(marker, show_times));
             job_table.remove(job_id);
             Ok(code)
         }
@@ -569,26 +2265,303 @@ fn wait_for_job(
     }
 }
 
+const DISOWN_USAGE: &str = "disown: usage: disown [-h] [-ar] [%job ...]";
+
+/// `disown [-h] [-ar] [%job...]`: forget jobs (default: the current one) so
+/// the shell no longer reports or hangs them up. `-h` keeps them in the
+/// table and only exempts them from SIGHUP on exit; `-a` means every job
+/// and `-r` every running job.
+fn builtin_disown(args: &[String], job_table: &mut JobTable, stderr: &mut dyn Write) -> i32 {
+    let (mut hup_only, mut all, mut running) = (false, false, false);
+    let mut specs = args;
+    while let Some(flags) = specs.first().filter(|word| word.len() > 1 && word.starts_with('-')) {
+        if flags == "--" {
+            specs = &specs[1..];
+            break;
+        }
+        for flag in flags[1..].chars() {
+            match flag {
+                'h' => hup_only = true,
+                'a' => all = true,
+                'r' => running = true,
+                _ => {
+                    let _ = writeln!(stderr, "jsh: disown: -{flag}: invalid option");
+                    let _ = writeln!(stderr, "jsh: {DISOWN_USAGE}");
+                    return 2;
+                }
+            }
+        }
+        specs = &specs[1..];
+    }
+
+    let mut exit_code = 0;
+    let ids: Vec<usize> = if !specs.is_empty() {
+        specs
+            .iter()
+            .filter_map(|spec| match job_table.resolve_spec(spec) {
+                Ok(id) => Some(id),
+                Err(message) => {
+                    let _ = writeln!(stderr, "jsh: disown: {message}");
+                    exit_code = 1;
+                    None
+                }
+            })
+            .collect()
+    } else if all || running {
+        job_table
+            .jobs_sorted()
+            .into_iter()
+            .filter(|job| !running || job.status == JobStatus::Running)
+            .map(|job| job.id)
+            .collect()
+    } else {
+        match job_table.current_and_previous().0 {
+            Some(id) => vec![id],
+            None => {
+                let _ = writeln!(stderr, "jsh: disown: no current job");
+                return 1;
+            }
+        }
+    };
+
+    for id in ids {
+        if hup_only {
+            if let Some(job) = job_table.get_mut(id) {
+                job.no_hup = true;
+            }
+        } else {
+            job_table.remove(id);
+        }
+    }
+    exit_code
+}
+
+/// `suspend [-f]`: stop the shell until it is continued, for a jsh run from
+/// another job-control shell. A login shell has no such parent to resume it,
+/// so it is only stopped with `-f`.
+fn builtin_suspend(args: &[String], login: bool, stderr: &mut dyn Write) -> i32 {
+    let force = match args {
+        [] => false,
+        [flag] if flag == "-f" => true,
+        _ => {
+            let _ = writeln!(stderr, "jsh: suspend: usage: suspend [-f]");
+            return 2;
+        }
+    };
+    if login && !force {
+        let _ = writeln!(stderr, "jsh: suspend: cannot suspend a login shell");
+        return 1;
+    }
+    suspend_shell(stderr)
+}
+
+#[cfg(unix)]
+fn suspend_shell(stderr: &mut dyn Write) -> i32 {
+    let _ = stderr.flush();
+    match job_control::suspend_self() {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(stderr, "jsh: suspend: {e}");
+            1
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn suspend_shell(stderr: &mut dyn Write) -> i32 {
+    let _ = writeln!(stderr, "jsh: suspend: not supported on this platform");
+    1
+}
+
+const KILL_USAGE: &str = "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | %job ... or kill -l [sigspec]";
+
+/// `kill [-s sig | -n num | -sig] pid | %job...`: send a signal (default
+/// `TERM`) to processes, or to a job's whole process group. `-l` lists the
+/// signals or translates between names and numbers.
+fn builtin_kill(
+    args: &[String],
+    job_table: &mut JobTable,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let Some(first) = args.first() else {
+        let _ = writeln!(stderr, "jsh: {KILL_USAGE}");
+        return 2;
+    };
+
+    if first == "-l" || first == "-L" {
+        return kill_list(&args[1..], stdout, stderr);
+    }
+
+    let mut signal = traps::parse_kill_signal("TERM").unwrap_or(15);
+    let mut targets = args;
+    if let Some(option) = first.strip_prefix('-') {
+        let spec = match option {
+            "-" => None,
+            "s" | "n" => match args.get(1) {
+                Some(spec) => {
+                    targets = &args[1..];
+                    Some(spec.as_str())
+                }
+                None => {
+                    let _ = writeln!(stderr, "jsh: kill: -{option}: option requires an argument");
+                    return 2;
+                }
+            },
+            spec => Some(spec),
+        };
+        if let Some(spec) = spec {
+            match traps::parse_kill_signal(spec) {
+                Some(number) => signal = number,
+                None => {
+                    let _ = writeln!(stderr, "jsh: kill: {spec}: invalid signal specification");
+                    return 1;
+                }
+            }
+        }
+        targets = &targets[1..];
+    }
+
+    if targets.is_empty() {
+        let _ = writeln!(stderr, "jsh: {KILL_USAGE}");
+        return 2;
+    }
+
+    let mut exit_code = 0;
+    for target in targets {
+        if let Err(message) = kill_target(target, signal, job_table) {
+            let _ = writeln!(stderr, "jsh: kill: {message}");
+            exit_code = 1;
+        }
+    }
+    exit_code
+}
+
+/// `kill -l [sigspec...]`: list all signals, or print the name of each
+/// number (including `128+N` exit statuses) and the number of each name.
+fn kill_list(specs: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    if specs.is_empty() {
+        for &(name, number) in traps::ALL_SIGNALS {
+            let _ = writeln!(stdout, "{number:>2}) SIG{name}");
+        }
+        return 0;
+    }
+
+    let mut exit_code = 0;
+    for spec in specs {
+        let translated = match spec.parse::<i32>() {
+            Ok(number) => {
+                let number = if number > 128 { number - 128 } else { number };
+                traps::kill_signal_name(number).map(str::to_string)
+            }
+            Err(_) => traps::parse_kill_signal(spec).map(|number| number.to_string()),
+        };
+        match translated {
+            Some(text) => {
+                let _ = writeln!(stdout, "{text}");
+            }
+            None => {
+                let _ = writeln!(stderr, "jsh: kill: {spec}: invalid signal specification");
+                exit_code = 1;
+            }
+        }
+    }
+    exit_code
+}
+
+/// Signal one `kill` operand: a `%` job spec signals the job's process
+/// group, anything else must be a PID.
+fn kill_target(target: &str, signal: i32, job_table: &mut JobTable) -> Result<(), String> {
+    if target.starts_with('%') {
+        let id = job_table.resolve_spec(target)?;
+        let job = job_table
+            .get_mut(id)
+            .ok_or_else(|| format!("{target}: no such job"))?;
+        return signal_job(job, signal).map_err(|e| format!("{target}: {e}"));
+    }
+
+    let pid = target
+        .parse::<i32>()
+        .map_err(|_| format!("{target}: arguments must be process or job IDs"))?;
+    signal_pid(pid, signal).map_err(|e| format!("({pid}) - {e}"))
+}
+
+/// Send `signal` to every process in `job`, keeping its status in step.
+#[cfg(unix)]
+fn signal_job(job: &mut crate::jobs::Job, signal: i32) -> std::io::Result<()> {
+    let pgid = job.pgid as libc::pid_t;
+    job_control::send_signal(-pgid, signal)?;
+    match signal {
+        libc::SIGSTOP | libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU => job.status = JobStatus::Stopped,
+        0 => {}
+        _ if job.status == JobStatus::Stopped => {
+            // A stopped job cannot act on the signal until it runs again.
+            if signal != libc::SIGCONT {
+                job_control::send_continue_to_group(pgid)?;
+            }
+            job.status = JobStatus::Running;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Without signals, STOP and TSTP suspend the job's processes and CONT
+/// resumes them (Windows); any other signal terminates the job, along with
+/// every process it started.
+#[cfg(not(unix))]
+fn signal_job(job: &mut crate::jobs::Job, signal: i32) -> std::io::Result<()> {
+    match traps::kill_signal_name(signal) {
+        _ if signal == 0 => Ok(()),
+        #[cfg(windows)]
+        Some("STOP" | "TSTP") => job.suspend(),
+        #[cfg(windows)]
+        Some("CONT") => job.resume(),
+        _ => {
+            job.terminate(signal)?;
+            // A suspended job still ends, so it is reaped as a running one.
+            job.status = JobStatus::Running;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn signal_pid(pid: i32, signal: i32) -> std::io::Result<()> {
+    job_control::send_signal(pid, signal)
+}
+
+#[cfg(not(unix))]
+fn signal_pid(_pid: i32, _signal: i32) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "signalling processes outside the job table is not supported",
+    ))
+}
+
 // ── Helpers ──
 
-/// Parse a job ID from an argument (accepts `%N` or `N`), falling back to
-/// `default` when no argument is given.
+/// Resolve a job spec argument for builtin `name` (see
+/// [`JobTable::resolve_spec`]), falling back to `default` when no argument
+/// is given.
 fn resolve_job_id(
+    name: &str,
     arg: Option<&String>,
     default: Option<usize>,
+    job_table: &JobTable,
     stderr: &mut dyn Write,
 ) -> Option<usize> {
     match arg {
-        Some(s) => match s.trim_start_matches('%').parse::<usize>() {
+        Some(s) => match job_table.resolve_spec(s) {
             Ok(id) => Some(id),
-            Err(_) => {
-                let _ = writeln!(stderr, "invalid job id: {s}");
+            Err(message) => {
+                let _ = writeln!(stderr, "{name}: {message}");
                 None
             }
         },
         None => {
             if default.is_none() {
-                let _ = writeln!(stderr, "no current job");
+                let _ = writeln!(stderr, "{name}: no current job");
             }
             default
         }
@@ -596,7 +2569,7 @@ fn resolve_job_id(
 }
 
 /// Check if a path points to an executable file.
-fn is_executable(path: &Path) -> bool {
+pub(crate) fn is_executable(path: &Path) -> bool {
     let Ok(meta) = path.metadata() else {
         return false;
     };
@@ -608,7 +2581,7 @@ fn is_executable(path: &Path) -> bool {
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
-        return meta.permissions().mode() & 0o111 != 0;
+        meta.permissions().mode() & 0o111 != 0
     }
 
     // On Windows, being a file with the right extension is sufficient
@@ -630,30 +2603,104 @@ fn is_windows_executable_extension(extension: &str) -> bool {
         .any(|ext| extension == ext.trim_start_matches('.').to_ascii_lowercase())
 }
 
-/// Search PATH for an executable with the given name.
-fn find_in_path(cmd: &str) -> Option<PathBuf> {
-    let path_var = std::env::var("PATH").ok()?;
+/// Whether a command name is a path to run as given, like `./configure`,
+/// rather than a name to look up.
+pub(crate) fn names_a_path(name: &str) -> bool {
+    name.contains('/') || (cfg!(windows) && name.contains('\\'))
+}
+
+/// Search `search_path`, a `$PATH` value, for an executable with the given
+/// name.
+pub(crate) fn find_in_path(cmd: &str, search_path: &str) -> Option<PathBuf> {
+    path_matches(cmd, search_path).next()
+}
+
+/// Every executable with the given name along `search_path`, in search
+/// order.
+pub(crate) fn find_all_in_path(cmd: &str, search_path: &str) -> Vec<PathBuf> {
+    path_matches(cmd, search_path).collect()
+}
+
+fn path_matches(cmd: &str, path_var: &str) -> impl Iterator<Item = PathBuf> {
     let separator = if cfg!(windows) { ';' } else { ':' };
+    let dirs: Vec<PathBuf> = match path_var.is_empty() {
+        true => Vec::new(),
+        false => path_var.split(separator).map(|dir| Path::new(dir).join(cmd)).collect(),
+    };
+    // On Windows, also try PATHEXT-configured executable extensions.
+    let exts: Vec<String> = match cfg!(windows) {
+        true => std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+            .collect(),
+        false => Vec::new(),
+    };
 
-    for dir in path_var.split(separator) {
-        let full_path = Path::new(dir).join(cmd);
+    dirs.into_iter().filter_map(move |full_path| {
         if is_executable(&full_path) {
             return Some(full_path);
         }
-        // On Windows, also try PATHEXT-configured executable extensions.
-        if cfg!(windows) {
-            let exts =
-                std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
-            let exts = exts
-                .split(';')
-                .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase());
-            for ext in exts {
-                let with_ext = full_path.with_extension(ext);
-                if is_executable(&with_ext) {
-                    return Some(with_ext);
-                }
-            }
-        }
+        exts.iter()
+            .map(|ext| full_path.with_extension(ext))
+            .find(|with_ext| is_executable(with_ext))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drive_relative_paths_are_split_from_their_drive() {
+        assert_eq!(drive_relative("D:"), Some(('D', "")));
+        assert_eq!(drive_relative("d:src\\jsh"), Some(('D', "src\\jsh")));
+        assert_eq!(drive_relative("D:\\src"), None);
+        assert_eq!(drive_relative("D:/src"), None);
+        assert_eq!(drive_relative("DD:"), None);
+        assert_eq!(drive_relative("\\\\server\\share"), None);
+        assert_eq!(drive_letter("c:\\Users"), Some('C'));
+        assert_eq!(drive_letter("/c/Users"), None);
+    }
+
+    #[test]
+    fn typos_are_one_edit_or_a_swap() {
+        assert!(one_typo_apart("projcets", "projects"));
+        assert!(one_typo_apart("projets", "projects"));
+        assert!(one_typo_apart("projectss", "projects"));
+        assert!(one_typo_apart("prajects", "projects"));
+        assert!(!one_typo_apart("projects", "projects"));
+        assert!(!one_typo_apart("prjcets", "projects"));
+        assert!(!one_typo_apart("src", "docs"));
+    }
+
+    #[test]
+    fn spelling_correction_fixes_each_component() {
+        let root = std::env::temp_dir().join(format!("jsh_cdspell_unit_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("projects").join("shell")).unwrap();
+
+        let corrected = spelling_correction(&root.join("porjects").join("shel"), Path::new("/"));
+        let relative = spelling_correction(Path::new("porjects"), &root);
+        let exact = spelling_correction(&root.join("projects"), Path::new("/"));
+        let hopeless = spelling_correction(&root.join("elsewhere"), Path::new("/"));
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(corrected, Some(root.join("projects").join("shell")));
+        assert_eq!(relative, Some(PathBuf::from("projects")));
+        assert_eq!(exact, None);
+        assert_eq!(hopeless, None);
+    }
+
+    #[test]
+    fn command_prefix_is_dropped_unless_querying() {
+        let strip = |words: &[&str]| {
+            let mut args: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+            strip_command_prefix(&mut args);
+            args
+        };
+        assert_eq!(strip(&["command", "ls", "-l"]), vec!["ls", "-l"]);
+        assert_eq!(strip(&["command", "--", "command", "echo"]), vec!["echo"]);
+        assert_eq!(strip(&["command", "-v", "ls"]), vec!["command", "-v", "ls"]);
+        assert_eq!(strip(&["command"]), vec!["command"]);
     }
-    None
 }
@@ -0,0 +1,217 @@
+/// Command-line arguments of the `jsh` binary.
+///
+/// Parsing is kept apart from `main` so it can be tested without spawning
+/// the shell. Options come first, as in bash: `-c`, `-i`, `-l`, `-s`, the
+/// single-letter `set` flags (`-e`, `+x`, ...), `-o name` / `+o name`, and
+/// the long options. The first word that is not an option ends them.
+use crate::options;
+
+/// What the shell was asked to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Read commands from stdin: the REPL, or a script piped in (`-s`).
+    Stdin,
+    /// `-c string`: run the string and exit.
+    Command(String),
+    /// `jsh file [arg...]`: run the script at the path and exit.
+    Script(String),
+    /// `--check [file...]`: parse scripts without running them.
+    Check(Vec<String>),
+    Help,
+    Version,
+}
+
+/// A parsed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub mode: Mode,
+    /// `-i`: interactive even when stdin is not a terminal.
+    pub interactive: bool,
+    /// `-l` / `--login`: source `~/.jsh_profile` before anything else.
+    pub login: bool,
+    /// `--norc`: do not source `~/.jshrc` or `$JSH_RC`.
+    pub norc: bool,
+    /// `-s`: read commands from stdin even though there are operands.
+    pub from_stdin: bool,
+    /// `set -o` options to turn on or off, in the order given.
+    pub options: Vec<(&'static str, bool)>,
+    /// The positional parameters, `$1` onwards.
+    pub args: Vec<String>,
+    /// `$0`: the `name` after `-c string`, or the script's path.
+    pub name: Option<String>,
+}
+
+/// One-line summary of the command line, for errors and `--help`.
+pub const USAGE: &str = "Usage: jsh [option...] [-c string [name [arg...]] | -s [arg...] | file [arg...]]";
+
+/// Text printed by `jsh --help`.
+pub fn help_text() -> String {
+    [
+        USAGE,
+        "",
+        "Options:",
+        "  -c string      Run the commands in string, then exit",
+        "  -i             Run interactively even if stdin is not a terminal",
+        "  -l, --login    Source ~/.jsh_profile before anything else",
+        "  -s             Read commands from stdin; the operands become $1, $2, ...",
+        "  -o name        Turn on a set -o option (+o turns it off)",
+        "  -e -n -u -x -C",
+        "                 Turn on the set option with that letter (+e turns it off)",
+        "  --norc         Do not source ~/.jshrc or $JSH_RC",
+        "  --check [file...]",
+        "                 Report syntax errors in scripts without running them",
+        "  --help         Show this help and exit",
+        "  --version      Show the version and exit",
+        "",
+        "Type 'help' in the shell for its builtins and syntax.",
+    ]
+    .join("\n")
+}
+
+/// Text printed by `jsh --version`.
+pub fn version_text() -> String {
+    format!("jsh (james-shell) {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Parse the arguments after the program name. An error is the message to
+/// print before [`USAGE`]; the shell exits with status 2.
+pub fn parse(args: &[String]) -> Result<Invocation, String> {
+    let mut invocation = Invocation {
+        mode: Mode::Stdin,
+        interactive: false,
+        login: false,
+        norc: false,
+        from_stdin: false,
+        options: Vec::new(),
+        args: Vec::new(),
+        name: None,
+    };
+    let mut command = false;
+    let mut check = false;
+
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        i += 1;
+        match arg.as_str() {
+            "--" | "-" => break,
+            "--help" => invocation.mode = Mode::Help,
+            "--version" => invocation.mode = Mode::Version,
+            "--login" => invocation.login = true,
+            "--norc" => invocation.norc = true,
+            "--check" => check = true,
+            long if long.starts_with("--") => return Err(format!("jsh: {long}: invalid option")),
+            flags if flags.len() > 1 && (flags.starts_with('-') || flags.starts_with('+')) => {
+                let on = flags.starts_with('-');
+                let sign = &flags[..1];
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'c' if on => command = true,
+                        'i' if on => invocation.interactive = true,
+                        'l' if on => invocation.login = true,
+                        's' if on => invocation.from_stdin = true,
+                        'o' => {
+                            let Some(name) = args.get(i) else {
+                                return Err(format!("jsh: {sign}o: option requires an argument"));
+                            };
+                            i += 1;
+                            let Some(&name) = options::SET_NAMES.iter().find(|known| *known == name) else {
+                                return Err(format!("jsh: {name}: invalid option name"));
+                            };
+                            invocation.options.push((name, on));
+                        }
+                        _ => match options::flag_name(flag) {
+                            Some(name) => invocation.options.push((name, on)),
+                            None => return Err(format!("jsh: {sign}{flag}: invalid option")),
+                        },
+                    }
+                }
+            }
+            _ => {
+                i -= 1;
+                break;
+            }
+        }
+    }
+    let mut operands = args[i..].iter().cloned();
+
+    if matches!(invocation.mode, Mode::Help | Mode::Version) {
+        return Ok(invocation);
+    }
+    invocation.mode = if check {
+        Mode::Check(operands.by_ref().collect())
+    } else if command {
+        let Some(string) = operands.next() else {
+            return Err("jsh: -c: option requires an argument".to_string());
+        };
+        invocation.name = operands.next();
+        Mode::Command(string)
+    } else if invocation.from_stdin {
+        Mode::Stdin
+    } else {
+        invocation.name = operands.next();
+        invocation.name.clone().map_or(Mode::Stdin, Mode::Script)
+    };
+    invocation.args = operands.collect();
+    Ok(invocation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_words(words: &[&str]) -> Result<Invocation, String> {
+        parse(&words.iter().map(|word| word.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn modes_and_positional_parameters() {
+        let invocation = parse_words(&[]).unwrap();
+        assert_eq!(invocation.mode, Mode::Stdin);
+        assert!(invocation.args.is_empty());
+
+        let invocation = parse_words(&["-c", "echo $1", "name", "a", "b"]).unwrap();
+        assert_eq!(invocation.mode, Mode::Command("echo $1".to_string()));
+        assert_eq!(invocation.name.as_deref(), Some("name"));
+        assert_eq!(invocation.args, ["a", "b"]);
+
+        let invocation = parse_words(&["-s", "x", "y"]).unwrap();
+        assert_eq!(invocation.mode, Mode::Stdin);
+        assert!(invocation.from_stdin);
+        assert_eq!(invocation.args, ["x", "y"]);
+
+        let invocation = parse_words(&["script.jsh", "-e", "z"]).unwrap();
+        assert_eq!(invocation.mode, Mode::Script("script.jsh".to_string()));
+        assert_eq!(invocation.name.as_deref(), Some("script.jsh"));
+        assert_eq!(invocation.args, ["-e", "z"]);
+
+        let invocation = parse_words(&["--check", "a.jsh", "b.jsh"]).unwrap();
+        assert_eq!(invocation.mode, Mode::Check(vec!["a.jsh".to_string(), "b.jsh".to_string()]));
+        assert_eq!(parse_words(&["--", "-c"]).unwrap().mode, Mode::Script("-c".to_string()));
+        assert_eq!(parse_words(&["-x", "--version"]).unwrap().mode, Mode::Version);
+    }
+
+    #[test]
+    fn flags_and_set_options() {
+        let invocation = parse_words(&["-ilex", "+o", "xtrace", "-o", "pipefail", "--norc", "-c", "true"]).unwrap();
+        assert!(invocation.interactive && invocation.login && invocation.norc);
+        assert_eq!(
+            invocation.options,
+            [("errexit", true), ("xtrace", true), ("xtrace", false), ("pipefail", true)]
+        );
+
+        // Options may be bundled with -c; the string is still the first operand.
+        let invocation = parse_words(&["-ec", "false"]).unwrap();
+        assert_eq!(invocation.mode, Mode::Command("false".to_string()));
+        assert_eq!(invocation.options, [("errexit", true)]);
+    }
+
+    #[test]
+    fn bad_options_are_errors() {
+        assert_eq!(parse_words(&["-q"]).unwrap_err(), "jsh: -q: invalid option");
+        assert_eq!(parse_words(&["+i"]).unwrap_err(), "jsh: +i: invalid option");
+        assert_eq!(parse_words(&["--frobnicate"]).unwrap_err(), "jsh: --frobnicate: invalid option");
+        assert_eq!(parse_words(&["-o", "nullglob"]).unwrap_err(), "jsh: nullglob: invalid option name");
+        assert_eq!(parse_words(&["-o"]).unwrap_err(), "jsh: -o: option requires an argument");
+        assert_eq!(parse_words(&["-c"]).unwrap_err(), "jsh: -c: option requires an argument");
+    }
+}
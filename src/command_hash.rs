@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::builtins;
+
+/// Where external commands were found on `$PATH`, remembered so running one
+/// again skips the search, and listed by the `hash` builtin.
+///
+/// Every entry belongs to the `$PATH` it was found on: when `$PATH` changes
+/// the table is emptied, as assigning `PATH` does in bash.
+#[derive(Debug, Clone, Default)]
+pub struct CommandHash {
+    search_path: String,
+    // BTreeMap so `hash` lists names in sorted order.
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    path: PathBuf,
+    /// How many times the command has been run through this entry.
+    hits: usize,
+}
+
+impl CommandHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The full path to run for `name` from `search_path`, the shell's
+    /// `$PATH`, counting a hit. Names with a path separator are run as given
+    /// and never hashed, and an entry whose file has gone is looked up again.
+    pub fn lookup(&mut self, name: &str, search_path: &str) -> Option<PathBuf> {
+        let entry = self.find(name, search_path)?;
+        entry.hits += 1;
+        Some(entry.path.clone())
+    }
+
+    /// Look `name` up on `$PATH` and remember it without counting a hit, as
+    /// `hash name` does. Returns false if it was not found.
+    pub fn remember(&mut self, name: &str, search_path: &str) -> bool {
+        self.find(name, search_path).is_some()
+    }
+
+    /// The remembered path for `name`, if it has been hashed on
+    /// `search_path`.
+    pub fn get(&self, name: &str, search_path: &str) -> Option<&Path> {
+        self.iter(search_path).find(|(entry, _, _)| *entry == name).map(|(_, path, _)| path)
+    }
+
+    /// Forget `name`, returning false if it was not hashed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// All entries as `(name, path, hits)`, sorted by name. There are none
+    /// once `$PATH` has changed from `search_path`.
+    pub fn iter(&self, search_path: &str) -> impl Iterator<Item = (&str, &Path, usize)> {
+        let current = search_path == self.search_path;
+        self.entries
+            .iter()
+            .filter(move |_| current)
+            .map(|(name, entry)| (name.as_str(), entry.path.as_path(), entry.hits))
+    }
+
+    fn find(&mut self, name: &str, search_path: &str) -> Option<&mut Entry> {
+        if name.is_empty() || builtins::names_a_path(name) {
+            return None;
+        }
+        if search_path != self.search_path {
+            self.entries.clear();
+            self.search_path = search_path.to_string();
+        }
+        if self.entries.get(name).is_some_and(|entry| !builtins::is_executable(&entry.path)) {
+            self.entries.remove(name);
+        }
+        if !self.entries.contains_key(name) {
+            let path = builtins::find_in_path(name, search_path)?;
+            self.entries.insert(name.to_string(), Entry { path, hits: 0 });
+        }
+        self.entries.get_mut(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_never_hashed() {
+        let mut hash = CommandHash::new();
+        assert_eq!(hash.lookup("./configure", "/bin"), None);
+        assert_eq!(hash.lookup("/bin/sh", "/bin"), None);
+        assert!(!hash.remember("no-such-command-anywhere", "/bin"));
+        assert_eq!(hash.iter("/bin").count(), 0);
+    }
+}
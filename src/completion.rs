@@ -0,0 +1,442 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::builtins;
+use crate::executor;
+use crate::shell::Shell;
+
+/// How to complete the arguments of one command, as registered with the
+/// `complete` builtin.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionSpec {
+    /// `-f`: file names.
+    pub files: bool,
+    /// `-d`: directory names.
+    pub directories: bool,
+    /// `-c`: command names.
+    pub commands: bool,
+    /// `-W words`: the words of this list, split on whitespace.
+    pub wordlist: Option<String>,
+    /// `-C command`: run this in a subshell and offer each line it prints.
+    /// It gets the command name, the word being completed, and the word
+    /// before it as `$1`, `$2`, and `$3`, and the line as `$COMP_LINE`.
+    pub generator: Option<String>,
+}
+
+impl CompletionSpec {
+    /// Render the spec the way `complete -p` lists it, so the output can be
+    /// pasted back into the shell.
+    pub fn format(&self, name: &str) -> String {
+        let mut text = String::from("complete");
+        for (on, flag) in [(self.files, " -f"), (self.directories, " -d"), (self.commands, " -c")] {
+            if on {
+                text.push_str(flag);
+            }
+        }
+        if let Some(words) = &self.wordlist {
+            text.push_str(&format!(" -W {}", single_quote(words)));
+        }
+        if let Some(command) = &self.generator {
+            text.push_str(&format!(" -C {}", single_quote(command)));
+        }
+        text.push(' ');
+        text.push_str(name);
+        text
+    }
+}
+
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Completion specs keyed by command name.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionTable {
+    // BTreeMap so `complete -p` lists names in sorted order.
+    specs: BTreeMap<String, CompletionSpec>,
+}
+
+impl CompletionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CompletionSpec> {
+        self.specs.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, spec: CompletionSpec) {
+        self.specs.insert(name.to_string(), spec);
+    }
+
+    /// Remove a spec, returning false if there was none.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.specs.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.specs.clear();
+    }
+
+    /// All specs as `(name, spec)` pairs, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CompletionSpec)> {
+        self.specs.iter().map(|(name, spec)| (name.as_str(), spec))
+    }
+}
+
+/// The candidates for the word under the cursor.
+#[derive(Debug, Default, PartialEq)]
+pub struct Completion {
+    /// Char index in the line where the word being completed starts; the
+    /// text from here to the cursor is replaced by the chosen candidate.
+    pub start: usize,
+    /// Replacement words, sorted and without duplicates. A directory ends in
+    /// `/` so completing it does not end the word.
+    pub candidates: Vec<String>,
+}
+
+/// Something the line editor can ask for completions.
+pub trait Completer {
+    /// Complete the word ending at char index `cursor` of `line`.
+    fn complete(&self, line: &str, cursor: usize) -> Completion;
+}
+
+/// The word being completed and the simple command it belongs to.
+struct CompletionContext {
+    /// Char index where the current word starts.
+    start: usize,
+    word: String,
+    /// Words of the current simple command before the one being completed.
+    preceding: Vec<String>,
+}
+
+/// Split the line up to `cursor` into the current simple command's words.
+/// Quotes are not interpreted; operators end the command.
+fn context(line: &str, cursor: usize) -> CompletionContext {
+    let chars: Vec<char> = line.chars().take(cursor).collect();
+    let is_break = |c: char| c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | '<' | '>');
+    let start = chars.iter().rposition(|&c| is_break(c)).map_or(0, |i| i + 1);
+    let command_start = chars[..start]
+        .iter()
+        .rposition(|&c| matches!(c, ';' | '|' | '&' | '('))
+        .map_or(0, |i| i + 1);
+    let preceding = chars[command_start..start]
+        .iter()
+        .collect::<String>()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    CompletionContext {
+        start,
+        word: chars[start..].iter().collect(),
+        preceding,
+    }
+}
+
+impl Completer for Shell {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let context = context(line, cursor);
+        let word = context.word.as_str();
+        let mut candidates = match variable_candidates(word, self) {
+            Some(candidates) => candidates,
+            None => argument_candidates(word, &context, line, self),
+        };
+        candidates.sort();
+        candidates.dedup();
+        Completion {
+            start: context.start,
+            candidates,
+        }
+    }
+}
+
+/// Candidates for a command name or argument: commands in the first word,
+/// then what the command's spec names, or file names without a spec.
+fn argument_candidates(word: &str, context: &CompletionContext, line: &str, shell: &Shell) -> Vec<String> {
+    match context.preceding.first() {
+        None if !word.contains('/') => command_names(word, shell),
+        None => file_names(word, false, &shell.cwd),
+        Some(command) => match shell.completions.get(command) {
+            Some(spec) => spec_candidates(spec, command, context, line, shell),
+            None => file_names(word, false, &shell.cwd),
+        },
+    }
+}
+
+/// When `word` ends in a parameter reference being typed (`$HO`, `${HO`),
+/// the variable names it could become, with the word's text before the `$`
+/// kept and the braced form closed. `None` if the word ends some other way.
+fn variable_candidates(word: &str, shell: &Shell) -> Option<Vec<String>> {
+    let dollar = word.rfind('$')?;
+    let (before, reference) = word.split_at(dollar);
+    let (prefix, braced) = match reference[1..].strip_prefix('{') {
+        Some(name) => (name, true),
+        None => (&reference[1..], false),
+    };
+    if !prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some(
+        shell
+            .variables
+            .iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| match braced {
+                true => format!("{before}${{{name}}}"),
+                false => format!("{before}${name}"),
+            })
+            .collect(),
+    )
+}
+
+/// Candidates from a registered spec. Every source the spec names
+/// contributes, and only words starting with the one being completed count.
+fn spec_candidates(
+    spec: &CompletionSpec,
+    command: &str,
+    context: &CompletionContext,
+    line: &str,
+    shell: &Shell,
+) -> Vec<String> {
+    let word = context.word.as_str();
+    let mut candidates = Vec::new();
+    if spec.files || spec.directories {
+        candidates.extend(file_names(word, !spec.files, &shell.cwd));
+    }
+    if spec.commands {
+        candidates.extend(command_names(word, shell));
+    }
+    if let Some(words) = &spec.wordlist {
+        candidates.extend(words.split_whitespace().map(str::to_string));
+    }
+    if let Some(generator) = &spec.generator {
+        let previous = context.preceding.last().map_or("", String::as_str);
+        candidates.extend(run_generator(generator, &[command, word, previous], line, shell));
+    }
+    candidates.retain(|candidate| candidate.starts_with(word));
+    candidates
+}
+
+/// Run a `-C` generator in a subshell and return the lines it prints.
+/// Errors are not reported: a broken generator just offers nothing.
+fn run_generator(generator: &str, args: &[&str], line: &str, shell: &Shell) -> Vec<String> {
+    let Ok(mut child) = executor::subshell_command(shell)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return Vec::new();
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let quoted: Vec<String> = args.iter().map(|arg| single_quote(arg)).collect();
+        let _ = write!(stdin, "{}", shell.subshell_prelude());
+        let _ = writeln!(stdin, "COMP_LINE={}", single_quote(line));
+        let _ = writeln!(stdin, "set -- {}", quoted.join(" "));
+        let _ = writeln!(stdin, "{generator}");
+    }
+
+    match child.wait_with_output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Aliases, builtins, and executables on `$PATH` starting with `prefix`.
+fn command_names(prefix: &str, shell: &Shell) -> Vec<String> {
+    let mut names: Vec<String> = shell
+        .aliases
+        .iter()
+        .map(|(name, _)| name)
+        .chain(shell.builtins.names())
+        .filter(|name| name.starts_with(prefix))
+        .map(str::to_string)
+        .collect();
+
+    let path_var = shell.variables.get("PATH").unwrap_or_default();
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    for dir in path_var.split(separator).filter(|dir| !dir.is_empty()) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(prefix) && builtins::is_executable(&entry.path()) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Paths starting with `word`, relative to `cwd` unless `word` is absolute.
+/// Names starting with `.` are only offered when the word does too.
+fn file_names(word: &str, directories_only: bool, cwd: &Path) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => (&word[..=slash], &word[slash + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = std::fs::read_dir(cwd.join(dir)) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            match is_dir {
+                true => Some(format!("{dir}{name}/")),
+                false if directories_only => None,
+                false => Some(format!("{dir}{name}")),
+            }
+        })
+        .collect()
+}
+
+/// The builtin or executable on `search_path` closest to a `program` that
+/// was not found, for a "did you mean" hint: never `program` itself, at
+/// most two edits away (and fewer than the name's length), nearest first
+/// and then alphabetically.
+pub fn suggest_command(program: &str, search_path: &str) -> Option<String> {
+    if program.contains('/') || program.contains('\\') {
+        return None;
+    }
+    let limit = 2.min(program.chars().count().saturating_sub(1));
+    let mut best: Option<(usize, String)> = None;
+    let mut consider = |name: String, distance: usize| {
+        if name != program && distance <= limit && best.as_ref().is_none_or(|(d, n)| (distance, &name) < (*d, n)) {
+            best = Some((distance, name));
+        }
+    };
+
+    for name in builtins::core_names() {
+        consider(name.to_string(), edit_distance(program, name));
+    }
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    for dir in search_path.split(separator).filter(|dir| !dir.is_empty()) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let distance = edit_distance(program, &name);
+            if distance <= limit && builtins::is_executable(&entry.path()) {
+                consider(name, distance);
+            }
+        }
+    }
+    best.map(|(_, name)| name)
+}
+
+/// Edits (insertions, deletions, substitutions, and swaps of neighbouring
+/// characters) needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Rows for b[..j] against a[..i - 2], a[..i - 1], and a[..i].
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (previous[j] + 1).min(row[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, row);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_finds_word_and_its_command() {
+        let ctx = context("ls | git checkout ma", 20);
+        assert_eq!(ctx.start, 18);
+        assert_eq!(ctx.word, "ma");
+        assert_eq!(ctx.preceding, vec!["git", "checkout"]);
+
+        let ctx = context("echo hi; gi", 11);
+        assert_eq!(ctx.word, "gi");
+        assert!(ctx.preceding.is_empty());
+    }
+
+    #[test]
+    fn first_word_completes_builtins_and_aliases() {
+        let mut shell = Shell::new();
+        shell.aliases.set("shoptalias", "shopt");
+        let completion = shell.complete("sho", 3);
+        assert_eq!(completion.start, 0);
+        assert!(completion.candidates.contains(&"shopt".to_string()));
+        assert!(completion.candidates.contains(&"shoptalias".to_string()));
+    }
+
+    #[test]
+    fn dollar_completes_variable_names() {
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_COMPLETE_ME", "1");
+        let completion = shell.complete("echo $JSH_COMP", 14);
+        assert_eq!(completion.start, 5);
+        assert_eq!(completion.candidates, vec!["$JSH_COMPLETE_ME"]);
+
+        let completion = shell.complete("echo x${JSH_COMP", 16);
+        assert_eq!(completion.candidates, vec!["x${JSH_COMPLETE_ME}"]);
+
+        // Not a name being typed: fall back to file names.
+        let completion = shell.complete("echo ${JSH_COMPLETE_ME}/zzz-no-such", 35);
+        assert!(completion.candidates.is_empty());
+    }
+
+    #[test]
+    fn registered_wordlist_completes_arguments() {
+        let mut shell = Shell::new();
+        let spec = CompletionSpec {
+            wordlist: Some("start stop status".to_string()),
+            ..CompletionSpec::default()
+        };
+        shell.completions.set("svc", spec);
+        let completion = shell.complete("svc st", 6);
+        assert_eq!(completion.start, 4);
+        assert_eq!(completion.candidates, vec!["start", "status", "stop"]);
+        assert_eq!(
+            shell.completions.get("svc").unwrap().format("svc"),
+            "complete -W 'start stop status' svc"
+        );
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_as_one_edit() {
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("gt", "git"), 1);
+        assert_eq!(edit_distance("giit", "git"), 1);
+        assert_eq!(edit_distance("ehco", "echo"), 1);
+        assert_eq!(edit_distance("pwdd", "pd"), 2);
+        assert_eq!(edit_distance("", "cd"), 2);
+    }
+
+    #[test]
+    fn not_found_commands_suggest_builtins() {
+        let search_path = std::env::var("PATH").unwrap_or_default();
+        assert_eq!(suggest_command("ehco", &search_path).as_deref(), Some("echo"));
+        assert_eq!(suggest_command("./ehco", &search_path), None);
+        assert_eq!(suggest_command("zzzzzzzzzz", &search_path), None);
+        assert_ne!(suggest_command("cd", "").as_deref(), Some("cd"));
+    }
+}
@@ -0,0 +1,429 @@
+/// Minimal builtin versions of common coreutils: `cat`, `head`, `tail`,
+/// `wc`, `sleep`, and `which`.
+///
+/// They are compiled in with the `coreutils` feature, for systems such as a
+/// bare Windows install that have none of their own, and each one is a
+/// builtin only while `$PATH` has no program of that name. Like `echo` they
+/// are pure builtins, so a pipeline runs them on threads of the shell. Only
+/// the options scripts use most are supported.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::builtins::{self, Builtin, BuiltinAction, BuiltinHelp, BuiltinKind, CoreBuiltin, core};
+use crate::redirect;
+use crate::timeout;
+use crate::traps;
+
+pub(crate) static BUILTINS: &[CoreBuiltin] = &[
+    core("cat", BuiltinKind::Pure, &CAT_HELP, |shell, args, io| {
+        BuiltinAction::Continue(builtin_cat(args, &shell.cwd, io.stdin, io.stdout, io.stderr))
+    }),
+    core("head", BuiltinKind::Pure, &HEAD_HELP, |shell, args, io| {
+        BuiltinAction::Continue(builtin_head_tail("head", args, &shell.cwd, io.stdin, io.stdout, io.stderr))
+    }),
+    core("tail", BuiltinKind::Pure, &TAIL_HELP, |shell, args, io| {
+        BuiltinAction::Continue(builtin_head_tail("tail", args, &shell.cwd, io.stdin, io.stdout, io.stderr))
+    }),
+    core("wc", BuiltinKind::Pure, &WC_HELP, |shell, args, io| {
+        BuiltinAction::Continue(builtin_wc(args, &shell.cwd, io.stdin, io.stdout, io.stderr))
+    }),
+    core("sleep", BuiltinKind::Pure, &SLEEP_HELP, |_, args, io| BuiltinAction::Continue(builtin_sleep(args, io.stderr))),
+    core("which", BuiltinKind::Pure, &WHICH_HELP, |shell, args, io| {
+        let search_path = shell.variables.get("PATH").unwrap_or_default();
+        BuiltinAction::Continue(builtin_which(args, search_path, io.stdout, io.stderr))
+    }),
+];
+
+static CAT_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "cat [file...]",
+    summary: "Copy each file, or stdin ('-' or none), to stdout.",
+    details: &[],
+};
+
+static HEAD_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "head [-n N | -N] [file...]",
+    summary: "Print the first N lines (default 10) of each file or stdin.",
+    details: &[],
+};
+
+static TAIL_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "tail [-n N | -N | -n +N] [file...]",
+    summary: "Print the last N lines (default 10), or from line N on with +N.",
+    details: &[],
+};
+
+static WC_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "wc [-lwc] [file...]",
+    summary: "Count lines, words, and bytes (all three unless some are chosen).",
+    details: &[],
+};
+
+static SLEEP_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "sleep duration...",
+    summary: "Wait for the sum of the durations (1.5, 30s, 2m, 1h, 1d). Ctrl-C stops it.",
+    details: &[],
+};
+
+static WHICH_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "which [-a] name...",
+    summary: "Print where each command is on $PATH; -a prints every match.",
+    details: &[],
+};
+
+/// Complain about a bad option and return the usage status.
+fn bad_option(name: &str, option: &str, stderr: &mut dyn Write) -> i32 {
+    let _ = writeln!(stderr, "{name}: {option}: invalid option");
+    let usage = BUILTINS.iter().find(|builtin| builtin.name() == name).and_then(|builtin| builtin.help());
+    let _ = writeln!(stderr, "{name}: usage: {}", usage.map_or("", |help| help.usage));
+    2
+}
+
+/// Run `each` on every input named in `files`, stdin for `-`, `/dev/stdin`,
+/// or when there are none, with the name to show for it. Relative names are
+/// under `cwd`. An input that cannot be read is
+/// reported and makes the status 1; output that has been closed ends the
+/// command quietly.
+fn for_each_input(
+    name: &str,
+    files: &[String],
+    cwd: &Path,
+    stdin: &mut dyn Read,
+    stderr: &mut dyn Write,
+    mut each: impl FnMut(&mut dyn Read, &str) -> io::Result<()>,
+) -> i32 {
+    let stdin_only = ["-".to_string()];
+    let files = if files.is_empty() { &stdin_only[..] } else { files };
+    let mut status = 0;
+    for file in files {
+        let result = match file.as_str() {
+            "-" => each(stdin, "standard input"),
+            path if redirect::device_fd(path) == Some(0) => each(stdin, path),
+            path => File::open(cwd.join(path)).and_then(|mut input| each(&mut input, path)),
+        };
+        match result {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return status,
+            Err(e) => {
+                let _ = writeln!(stderr, "{name}: {file}: {e}");
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+fn builtin_cat(args: &[String], cwd: &Path, stdin: &mut dyn Read, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    if let Some(option) = args.iter().find(|arg| arg.starts_with('-') && arg.len() > 1) {
+        return bad_option("cat", option, stderr);
+    }
+    for_each_input("cat", args, cwd, stdin, stderr, |input, _| io::copy(input, stdout).map(drop))
+}
+
+/// How many lines `head` or `tail` keeps: the first or last `count`, or
+/// with `tail -n +N` everything from line `N` on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineCount {
+    Count(usize),
+    From(usize),
+}
+
+/// Parse `-n N`, `-nN`, and `-N` (and for `tail`, `+N`) from the front of
+/// `args`, returning the count and the file operands.
+fn parse_line_count<'a>(name: &str, args: &'a [String]) -> Result<(LineCount, &'a [String]), String> {
+    let mut count = LineCount::Count(10);
+    let mut rest = args;
+    while let Some(arg) = rest.first() {
+        let value = match arg.as_str() {
+            "--" => {
+                rest = &rest[1..];
+                break;
+            }
+            "-n" => {
+                let value = rest.get(1).ok_or_else(|| format!("{name}: -n: option requires an argument"))?;
+                rest = &rest[2..];
+                value.as_str()
+            }
+            option if option.starts_with("-n") => {
+                rest = &rest[1..];
+                &option[2..]
+            }
+            option if option.len() > 1 && option[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                rest = &rest[1..];
+                &option[1..]
+            }
+            option if option.starts_with('-') && option.len() > 1 => return Err(option.to_string()),
+            _ => break,
+        };
+        let invalid = || format!("{name}: {value}: invalid number of lines");
+        count = match value.strip_prefix('+') {
+            Some(from) if name == "tail" => LineCount::From(from.parse().map_err(|_| invalid())?),
+            _ => LineCount::Count(value.parse().map_err(|_| invalid())?),
+        };
+    }
+    Ok((count, rest))
+}
+
+fn builtin_head_tail(
+    name: &str,
+    args: &[String],
+    cwd: &Path,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let (count, files) = match parse_line_count(name, args) {
+        Ok(parsed) => parsed,
+        Err(message) if message.starts_with('-') => return bad_option(name, &message, stderr),
+        Err(message) => {
+            let _ = writeln!(stderr, "{message}");
+            return 1;
+        }
+    };
+    let headers = files.len() > 1;
+    let mut first = true;
+    for_each_input(name, files, cwd, stdin, stderr, |input, label| {
+        if headers {
+            writeln!(stdout, "{}==> {label} <==", if first { "" } else { "\n" })?;
+            first = false;
+        }
+        let lines = BufReader::new(input).split(b'\n');
+        match (name, count) {
+            ("head", LineCount::Count(count)) => write_lines(lines.take(count), stdout),
+            (_, LineCount::From(from)) => write_lines(lines.skip(from.saturating_sub(1)), stdout),
+            (_, LineCount::Count(count)) => {
+                let mut last = VecDeque::with_capacity(count.min(1024));
+                for line in lines {
+                    if last.len() == count {
+                        last.pop_front();
+                    }
+                    let line = line?;
+                    if count > 0 {
+                        last.push_back(line);
+                    }
+                }
+                write_lines(last.into_iter().map(Ok), stdout)
+            }
+        }
+    })
+}
+
+/// Write each line back out with its newline.
+fn write_lines(lines: impl Iterator<Item = io::Result<Vec<u8>>>, stdout: &mut dyn Write) -> io::Result<()> {
+    for line in lines {
+        let mut line = line?;
+        line.push(b'\n');
+        stdout.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// Lines, words, and bytes in one input.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+}
+
+impl Counts {
+    fn of(input: &mut dyn Read) -> io::Result<Counts> {
+        let mut counts = Counts::default();
+        let mut in_word = false;
+        let mut buffer = [0; 64 * 1024];
+        loop {
+            let read = match input.read(&mut buffer) {
+                Ok(0) => return Ok(counts),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            counts.bytes += read;
+            // A word is counted where it starts, which may be in an earlier
+            // read than where it ends.
+            for &byte in &buffer[..read] {
+                counts.lines += usize::from(byte == b'\n');
+                let space = byte.is_ascii_whitespace();
+                counts.words += usize::from(!in_word && !space);
+                in_word = !space;
+            }
+        }
+    }
+
+    fn add(&mut self, other: Counts) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.bytes += other.bytes;
+    }
+}
+
+fn builtin_wc(args: &[String], cwd: &Path, stdin: &mut dyn Read, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let (mut lines, mut words, mut bytes) = (false, false, false);
+    let mut files = args;
+    while let Some(flags) = files.first().and_then(|arg| arg.strip_prefix('-')).filter(|flags| !flags.is_empty()) {
+        files = &files[1..];
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'l' => lines = true,
+                'w' => words = true,
+                'c' => bytes = true,
+                _ => return bad_option("wc", &format!("-{flag}"), stderr),
+            }
+        }
+    }
+    if !(lines || words || bytes) {
+        (lines, words, bytes) = (true, true, true);
+    }
+
+    // A lone count for stdin is printed bare, as scripts expect of `wc -l`.
+    let padded = files.len() > 1 || [lines, words, bytes].iter().filter(|&&on| on).count() > 1;
+    let mut report = |counts: Counts, label: Option<&str>| -> io::Result<()> {
+        let chosen = [(lines, counts.lines), (words, counts.words), (bytes, counts.bytes)];
+        let mut fields: Vec<String> = chosen
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, count)| if padded { format!("{count:>7}") } else { count.to_string() })
+            .collect();
+        fields.extend(label.map(str::to_string));
+        writeln!(stdout, "{}", fields.join(" "))
+    };
+
+    let mut total = Counts::default();
+    let status = for_each_input("wc", files, cwd, stdin, stderr, |input, label| {
+        let counts = Counts::of(input)?;
+        total.add(counts);
+        report(counts, (!files.is_empty() && label != "standard input").then_some(label))
+    });
+    if files.len() > 1 {
+        let _ = report(total, Some("total"));
+    }
+    status
+}
+
+fn builtin_sleep(args: &[String], stderr: &mut dyn Write) -> i32 {
+    if args.is_empty() {
+        let _ = writeln!(stderr, "sleep: usage: {}", SLEEP_HELP.usage);
+        return 2;
+    }
+    let mut total = Duration::ZERO;
+    for arg in args {
+        match timeout::parse_duration(arg) {
+            Ok(duration) => total = total.saturating_add(duration),
+            Err(_) => {
+                let _ = writeln!(stderr, "sleep: {arg}: invalid time interval");
+                return 1;
+            }
+        }
+    }
+    // Sleep in slices so a Ctrl-C, which only sets a flag, ends it.
+    let deadline = Instant::now() + total;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
+        if traps::interrupted() {
+            return 130;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(50)));
+    }
+    0
+}
+
+fn builtin_which(args: &[String], search_path: &str, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+    let (all, names) = match args.first().map(String::as_str) {
+        Some("-a") => (true, &args[1..]),
+        Some(option) if option.starts_with('-') && option.len() > 1 => return bad_option("which", option, stderr),
+        _ => (false, args),
+    };
+    let mut status = 0;
+    for name in names {
+        let found = match all {
+            true => builtins::find_all_in_path(name, search_path),
+            false => builtins::find_in_path(name, search_path).into_iter().collect(),
+        };
+        if found.is_empty() {
+            status = 1;
+        }
+        for path in found {
+            let _ = writeln!(stdout, "{}", path.display());
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    type FileBuiltin = fn(&[String], &Path, &mut dyn Read, &mut dyn Write, &mut dyn Write) -> i32;
+
+    /// Run `builtin` on `input`, returning its status, stdout, and stderr.
+    fn run(
+        builtin: FileBuiltin,
+        args: &[&str],
+        input: &str,
+    ) -> (i32, String, String) {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let status = builtin(&words(args), Path::new("/"), &mut input.as_bytes(), &mut stdout, &mut stderr);
+        (status, String::from_utf8(stdout).unwrap(), String::from_utf8(stderr).unwrap())
+    }
+
+    fn head(args: &[String], cwd: &Path, stdin: &mut dyn Read, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+        builtin_head_tail("head", args, cwd, stdin, stdout, stderr)
+    }
+
+    fn tail(args: &[String], cwd: &Path, stdin: &mut dyn Read, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+        builtin_head_tail("tail", args, cwd, stdin, stdout, stderr)
+    }
+
+    #[test]
+    fn head_and_tail_take_line_counts() {
+        let input = "1\n2\n3\n4\n5\n";
+        assert_eq!(run(head, &["-n", "2"], input), (0, "1\n2\n".to_string(), String::new()));
+        assert_eq!(run(head, &["-3"], input).1, "1\n2\n3\n");
+        assert_eq!(run(tail, &["-n2"], input).1, "4\n5\n");
+        assert_eq!(run(tail, &["-n", "+4"], input).1, "4\n5\n");
+        assert_eq!(run(tail, &["-0"], input).1, "");
+        assert_eq!(run(head, &[], "a\nb").1, "a\nb\n");
+        assert_eq!(run(head, &["-n", "x"], input).2, "head: x: invalid number of lines\n");
+        assert_eq!(run(tail, &["-q"], input).0, 2);
+    }
+
+    #[test]
+    fn wc_counts_across_buffer_boundaries() {
+        assert_eq!(run(builtin_wc, &["-l"], "a b\nc\n"), (0, "2\n".to_string(), String::new()));
+        assert_eq!(run(builtin_wc, &[], " one  two\tthree\n").1, "      1       3      16\n");
+        let long = format!("{} tail", "x".repeat(64 * 1024 + 3));
+        assert_eq!(Counts::of(&mut long.as_bytes()).unwrap().words, 2);
+        assert_eq!(Counts::of(&mut "word".as_bytes()).unwrap().words, 1);
+    }
+
+    #[test]
+    fn cat_reports_missing_files_and_goes_on() {
+        let (status, stdout, stderr) = run(builtin_cat, &["/no/such/file", "-"], "from stdin\n");
+        assert_eq!((status, stdout.as_str()), (1, "from stdin\n"));
+        assert!(stderr.starts_with("cat: /no/such/file: "), "{stderr}");
+    }
+
+    #[test]
+    fn cat_reads_dev_stdin_from_its_own_input() {
+        assert_eq!(run(builtin_cat, &["/dev/stdin"], "piped\n"), (0, "piped\n".to_string(), String::new()));
+        assert_eq!(run(builtin_cat, &["/dev/fd/0"], "piped\n").1, "piped\n");
+    }
+
+    #[test]
+    fn sleep_sums_its_durations() {
+        let mut stderr = Vec::new();
+        let started = Instant::now();
+        assert_eq!(builtin_sleep(&words(&["0.05", "0.05s"]), &mut stderr), 0);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(builtin_sleep(&words(&["soon"]), &mut stderr), 1);
+        assert_eq!(String::from_utf8(stderr).unwrap(), "sleep: soon: invalid time interval\n");
+    }
+}
@@ -0,0 +1,163 @@
+use std::cell::Cell;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Variables whose value the shell computes each time they are expanded:
+/// `$SECONDS`, `$RANDOM`, `$LINENO`, and `$EPOCHSECONDS`.
+///
+/// The expander asks here before the variable table, so these names always
+/// read as their computed value. Assigning to one sets the state it is
+/// computed from instead of storing the value.
+#[derive(Debug, Clone)]
+pub struct DynamicVariables {
+    /// When `$SECONDS` was last assigned, or the shell started.
+    seconds_since: Instant,
+    /// What `$SECONDS` was assigned at `seconds_since`.
+    seconds_base: i64,
+    /// State of the `$RANDOM` generator; never zero.
+    random: Cell<u32>,
+    /// The line of the input the current command started on.
+    line: usize,
+}
+
+impl DynamicVariables {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        DynamicVariables {
+            seconds_since: Instant::now(),
+            seconds_base: 0,
+            random: Cell::new(seed(nanos ^ std::process::id())),
+            line: 0,
+        }
+    }
+
+    /// The value of `name`, or `None` if it is not one of these variables.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "SECONDS" => {
+                let elapsed = self.seconds_since.elapsed().as_secs() as i64;
+                Some((self.seconds_base + elapsed).to_string())
+            }
+            "RANDOM" => Some(self.next_random().to_string()),
+            "LINENO" => Some(self.line.to_string()),
+            "EPOCHSECONDS" => Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs())
+                    .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Assign `value` to `name` if it is one of these variables, returning
+    /// false if it is an ordinary name. As in bash, `SECONDS=n` restarts the
+    /// count from `n`, `RANDOM=n` seeds the generator, and assignments to
+    /// `EPOCHSECONDS` are ignored.
+    pub fn assign(&mut self, name: &str, value: &str) -> bool {
+        let number = value.trim().parse::<i64>().unwrap_or(0);
+        match name {
+            "SECONDS" => {
+                self.seconds_since = Instant::now();
+                self.seconds_base = number;
+            }
+            "RANDOM" => self.random.set(seed(number as u32)),
+            "LINENO" => self.line = number.max(0) as usize,
+            "EPOCHSECONDS" => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// `$SECONDS` to the millisecond, for a subshell to carry on from.
+    pub fn seconds_millis(&self) -> i64 {
+        self.seconds_base * 1000 + self.seconds_since.elapsed().as_millis() as i64
+    }
+
+    /// Count `$SECONDS` on from `millis`, as a subshell does from its
+    /// parent's [`Self::seconds_millis`].
+    pub fn resume_seconds(&mut self, millis: i64) {
+        let now = Instant::now();
+        let into_second = std::time::Duration::from_millis(millis.rem_euclid(1000) as u64);
+        self.seconds_since = now.checked_sub(into_second).unwrap_or(now);
+        self.seconds_base = millis.div_euclid(1000);
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    /// The next value of `$RANDOM`, from 0 to 32767.
+    fn next_random(&self) -> u32 {
+        // xorshift32: fast, and good enough for a shell's `$RANDOM`.
+        let mut state = self.random.get();
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        self.random.set(state);
+        (state >> 16) & 0x7fff
+    }
+}
+
+impl Default for DynamicVariables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A nonzero generator state for `seed`, mixed so nearby seeds diverge.
+fn seed(seed: u32) -> u32 {
+    let mixed = seed.wrapping_mul(0x9e37_79b9).rotate_left(16) ^ 0x2545_f491;
+    if mixed == 0 { 1 } else { mixed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_restart_from_the_assigned_value() {
+        let mut vars = DynamicVariables::new();
+        assert_eq!(vars.get("SECONDS").as_deref(), Some("0"));
+        assert!(vars.assign("SECONDS", "100"));
+        assert_eq!(vars.get("SECONDS").as_deref(), Some("100"));
+        assert!(!vars.assign("PATH", "/bin"));
+        assert_eq!(vars.get("PATH"), None);
+    }
+
+    #[test]
+    fn seconds_resume_from_a_parents_count() {
+        let mut vars = DynamicVariables::new();
+        vars.resume_seconds(61_900);
+        assert_eq!(vars.get("SECONDS").as_deref(), Some("61"));
+        assert!(vars.seconds_millis() >= 61_900);
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        assert_eq!(vars.get("SECONDS").as_deref(), Some("62"));
+    }
+
+    #[test]
+    fn random_is_in_range_and_repeats_for_a_seed() {
+        let mut vars = DynamicVariables::new();
+        vars.assign("RANDOM", "42");
+        let first: Vec<String> = (0..5).filter_map(|_| vars.get("RANDOM")).collect();
+        vars.assign("RANDOM", "42");
+        let second: Vec<String> = (0..5).filter_map(|_| vars.get("RANDOM")).collect();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|n| n.parse::<u32>().unwrap() <= 32767));
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn epochseconds_ignores_assignment() {
+        let mut vars = DynamicVariables::new();
+        assert!(vars.assign("EPOCHSECONDS", "5"));
+        assert!(vars.get("EPOCHSECONDS").unwrap().parse::<u64>().unwrap() > 1_600_000_000);
+        vars.set_line(7);
+        assert_eq!(vars.get("LINENO").as_deref(), Some("7"));
+    }
+}
@@ -1,16 +1,23 @@
-use std::fs::OpenOptions;
-use std::io::{self, BufRead, Write};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use crossterm::{
     cursor,
     event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
-    execute,
+    queue,
     terminal::{self, ClearType},
     tty::IsTty,
 };
 
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::completion::{Completer, Completion};
+use crate::history::{self, HistoryPolicy};
+
 // ── Raw-mode sentinel ─────────────────────────────────────────────────────────
 
 /// `true` while the line editor holds the terminal in raw mode.
@@ -28,12 +35,28 @@ struct RawModeGuard;
 
 impl RawModeGuard {
     fn enter() -> io::Result<Self> {
-        terminal::enable_raw_mode()?;
+        if terminal::enable_raw_mode().is_err() {
+            recover_terminal();
+            terminal::enable_raw_mode()?;
+        }
         EDITOR_ACTIVE.store(true, Ordering::Relaxed);
         Ok(RawModeGuard)
     }
 }
 
+/// A light `reset`, for when raw mode cannot be entered: put back the
+/// terminal modes saved at startup and undo the display settings a crashed
+/// full-screen program tends to leave behind (hidden cursor, attributes,
+/// application cursor keys and keypad).
+fn recover_terminal() {
+    #[cfg(unix)]
+    crate::job_control::restore_terminal_modes();
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "\x1b[0m\x1b[?1l\x1b>");
+    let _ = queue!(stdout, cursor::Show);
+    let _ = stdout.flush();
+}
+
 impl Drop for RawModeGuard {
     fn drop(&mut self) {
         let _ = terminal::disable_raw_mode();
@@ -43,8 +66,6 @@ impl Drop for RawModeGuard {
 
 // ── Line editor ───────────────────────────────────────────────────────────────
 
-const MAX_HISTORY_SIZE: usize = 10_000;
-
 /// A line editor with cursor movement, Emacs keybindings, and persistent history.
 pub struct LineEditor {
     /// Current line content, stored as `char`s for Unicode-safe cursor indexing.
@@ -60,6 +81,65 @@ pub struct LineEditor {
     saved_buffer: String,
     /// Path to `~/.jsh_history`, or `None` when HOME is not set.
     history_path: Option<PathBuf>,
+    /// Byte offset in the history file before which lines have not been
+    /// loaded yet; 0 once the whole file is in `history`.
+    history_unloaded: u64,
+    /// Lines in the history file, counted as they are added, so it is only
+    /// rewritten once it outgrows `HISTFILESIZE` by a tenth; `None` until
+    /// first needed, to keep startup cheap.
+    history_file_lines: Option<usize>,
+    /// What is recorded and how much is kept.
+    history_policy: HistoryPolicy,
+    /// Buffer and cursor before each edit of the current line, newest last.
+    undo_stack: Vec<(Vec<char>, usize)>,
+    /// The last edit typed a character, so further typing joins its undo step.
+    typing: bool,
+    /// Ctrl-X was pressed and the next key completes a two-key binding.
+    ctrl_x_pending: bool,
+    /// Screen row of the terminal cursor, counted from the row the prompt
+    /// starts on, as left by the last redraw.
+    cursor_row: usize,
+    /// Columns the line is laid out in: read at each prompt and updated
+    /// when the terminal is resized.
+    width: usize,
+    /// Lines of a multi-line prompt before its last, which redraws leave
+    /// alone; reprinted after Ctrl-C and Ctrl-L.
+    prompt_head: String,
+    /// The buffer as it is on screen after the prompt, so a redraw only
+    /// rewrites what changed; `None` when the line must be drawn in full.
+    drawn: Option<Vec<char>>,
+    /// Terminal output for the current event, written in one go by
+    /// `flush_output` so the screen never shows a half-finished update.
+    out: Vec<u8>,
+    /// Lines of the unfinished command above the one being edited, which
+    /// Ctrl-Z stashes along with it.
+    pending: Vec<String>,
+    /// Commands put aside with Alt-Q or Ctrl-Z, newest last, like zsh's
+    /// buffer stack.
+    stash: Vec<Vec<String>>,
+    /// Lines of a stashed command still to be put back, one per prompt.
+    unstashed: VecDeque<String>,
+    /// A line has been submitted since the last stash, so the next new
+    /// command starts from the stash.
+    stash_due: bool,
+}
+
+/// The error [`LineEditor::read_line`] returns when Ctrl-Z stashed a command
+/// that had lines before the current one, so the caller drops them too.
+#[derive(Debug)]
+pub struct LineStashed;
+
+impl std::fmt::Display for LineStashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("command stashed")
+    }
+}
+
+impl std::error::Error for LineStashed {}
+
+/// Whether `err` is [`LineStashed`].
+pub fn is_line_stashed(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<LineStashed>())
 }
 
 impl Default for LineEditor {
@@ -69,35 +149,64 @@ impl Default for LineEditor {
 }
 
 impl LineEditor {
-    /// Create a new editor and load history from `~/.jsh_history`.
+    /// Create a new editor and load the most recent history from
+    /// `~/.jsh_history`. Older entries are read when first needed.
     pub fn new() -> Self {
-        let history_path = history_file_path();
-        let history = history_path
+        Self::with_history_file(history::history_file_path())
+    }
+
+    /// Create an editor with no history file, for child shells that only
+    /// run what they are fed and have no use for the user's history.
+    pub fn without_history() -> Self {
+        Self::with_history_file(None)
+    }
+
+    fn with_history_file(history_path: Option<PathBuf>) -> Self {
+        let (history, history_unloaded) = history_path
             .as_deref()
-            .map(load_history)
+            .map(|path| load_history_tail(path, HISTORY_TAIL_ENTRIES))
             .unwrap_or_default();
-        let history_idx = history.len();
-        LineEditor {
+        let mut editor = LineEditor {
             buffer: Vec::new(),
             cursor: 0,
+            history_idx: history.len(),
+            history_unloaded,
+            // When the tail read reached the start, it is the whole file.
+            history_file_lines: (history_unloaded == 0 && history_path.is_some()).then_some(history.len()),
             history,
-            history_idx,
             saved_buffer: String::new(),
             history_path,
-        }
+            history_policy: HistoryPolicy::default(),
+            undo_stack: Vec::new(),
+            typing: false,
+            ctrl_x_pending: false,
+            cursor_row: 0,
+            width: terminal_width(),
+            prompt_head: String::new(),
+            drawn: None,
+            out: Vec::new(),
+            pending: Vec::new(),
+            stash: Vec::new(),
+            unstashed: VecDeque::new(),
+            stash_due: false,
+        };
+        editor.apply_history_policy();
+        editor
     }
 
-    /// Read one line of input, displaying `prompt` to the left.
+    /// Read one line of input, displaying `prompt` to the left. Tab asks
+    /// `completer` to complete the word before the cursor.
     ///
     /// Returns:
     /// - `Ok(Some(line))` — the user submitted a line (may be empty)
     /// - `Ok(None)` — EOF (Ctrl-D on an empty buffer, or stdin was closed)
-    /// - `Err(_)` — I/O error (including `ErrorKind::Interrupted` for SIGINT)
+    /// - `Err(_)` — I/O error (including `ErrorKind::Interrupted` for SIGINT),
+    ///   or [`LineStashed`] when Ctrl-Z put an unfinished command aside
     ///
     /// When stdout is not a TTY (e.g. integration tests that pipe stdin/stdout)
     /// the method falls back to a plain `read_line()` call so tests work
     /// without modification.
-    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+    pub fn read_line(&mut self, prompt: &str, completer: &dyn Completer) -> io::Result<Option<String>> {
         // Gate on stdin, not stdout: interactive editing requires a keyboard on
         // the *input* side. `printf 'cmd\n' | james-shell` has stdout on a
         // terminal but stdin on a pipe — entering raw mode there would hand
@@ -107,11 +216,19 @@ impl LineEditor {
         }
 
         self.reset_state();
+        self.unstash();
         let _guard = RawModeGuard::enter()?;
-
-        // Raw mode disables echo; we must display the prompt ourselves.
-        print!("{prompt}");
-        io::stdout().flush()?;
+        self.width = terminal_width();
+
+        // Raw mode disables echo; we must display the prompt ourselves. From
+        // here on `prompt` is only its last line, the one the buffer follows.
+        let (head, prompt) = prompt.rsplit_once('\n').unwrap_or(("", prompt));
+        self.prompt_head = if head.is_empty() { String::new() } else { format!("{head}\n").replace('\n', "\r\n") };
+        write!(self.out, "{}{prompt}", self.prompt_head)?;
+        if !self.buffer.is_empty() {
+            self.redraw(prompt)?;
+        }
+        self.flush_output()?;
 
         loop {
             let ev = match event::read() {
@@ -121,8 +238,14 @@ impl LineEditor {
                 Err(e) => return Err(e),
             };
 
-            let Event::Key(key) = ev else {
-                continue; // ignore mouse, resize, paste, etc.
+            let key = match ev {
+                Event::Key(key) => key,
+                Event::Resize(columns, _) => {
+                    self.resize(usize::from(columns), prompt)?;
+                    self.flush_output()?;
+                    continue;
+                }
+                _ => continue, // ignore mouse, paste, etc.
             };
 
             // Filter out key-release events that Windows may generate.
@@ -130,9 +253,44 @@ impl LineEditor {
                 continue;
             }
 
-            match self.handle_key(key, prompt)? {
-                KeyAction::Submit(line) => return Ok(Some(line)),
+            let action = self.handle_key(key, prompt);
+            self.flush_output()?;
+            match action? {
+                KeyAction::Submit(line) => {
+                    self.stash_due = true;
+                    return Ok(Some(line));
+                }
                 KeyAction::Eof => return Ok(None),
+                KeyAction::Stash => {
+                    // A command begun on earlier lines cannot be cleared in
+                    // place; it is abandoned and a fresh prompt shown.
+                    let abandoned = !self.pending.is_empty();
+                    if abandoned {
+                        self.move_below_line(prompt)?;
+                    }
+                    self.stash_line();
+                    if abandoned {
+                        self.flush_output()?;
+                        return Err(io::Error::other(LineStashed));
+                    }
+                    self.redraw(prompt)?;
+                    self.flush_output()?;
+                }
+                KeyAction::Complete => {
+                    let line: String = self.buffer.iter().collect();
+                    let completion = completer.complete(&line, self.cursor);
+                    let before = (self.buffer.clone(), self.cursor);
+                    let listing = self.apply_completion(completion);
+                    self.record_undo(before, false);
+                    if let Some(candidates) = listing {
+                        // Ambiguous with nothing more in common: list the choices
+                        // below the line, then redraw it.
+                        self.move_below_line(prompt)?;
+                        write!(self.out, "{}\r\n", candidates.join("  "))?;
+                    }
+                    self.redraw(prompt)?;
+                    self.flush_output()?;
+                }
                 KeyAction::Continue => {}
             }
         }
@@ -140,33 +298,171 @@ impl LineEditor {
 
     /// Add `line` to the in-memory history and append it to `~/.jsh_history`.
     ///
-    /// Empty lines (after trimming) and consecutive duplicates are silently
-    /// ignored. The in-memory list is trimmed to `MAX_HISTORY_SIZE`.
+    /// Empty lines (after trimming) are ignored, as are duplicates and lines
+    /// starting with a space when the history policy says so. The in-memory
+    /// list is trimmed to `HISTSIZE` entries. The file is cut back to
+    /// `HISTFILESIZE` lines once it is a tenth over, so it is not rewritten
+    /// for every command.
     pub fn add_to_history(&mut self, line: &str) {
         let trimmed = line.trim();
-        if trimmed.is_empty() {
+        let policy = self.history_policy;
+        if trimmed.is_empty() || policy.size == 0 {
             return;
         }
-        if self.history.last().map(String::as_str) == Some(trimmed) {
+        if policy.ignore_space && line.starts_with(char::is_whitespace) {
             return;
         }
+        if policy.ignore_dups && self.history.last().map(String::as_str) == Some(trimmed) {
+            return;
+        }
+        if policy.erase_dups {
+            self.history.retain(|entry| entry != trimmed);
+        }
         self.history.push(trimmed.to_string());
-        if self.history.len() > MAX_HISTORY_SIZE {
-            let excess = self.history.len() - MAX_HISTORY_SIZE;
-            self.history.drain(..excess);
+        self.apply_history_policy();
+        if let Some(path) = self.history_path.clone() {
+            append_to_history_file(&path, trimmed);
+            let limit = policy.file_size + policy.file_size / 10;
+            let lines = match self.history_file_lines {
+                Some(lines) => lines + 1,
+                // Only up to the limit needs counting: past it, the file is
+                // cut back and the count is known again.
+                None => match load_history_tail(&path, limit) {
+                    (lines, 0) => lines.len(),
+                    _ => limit + 1,
+                },
+            };
+            self.history_file_lines = Some(lines);
+            if lines > limit {
+                // Rewriting the file moves the lines not yet loaded.
+                self.load_older_history();
+                self.history_file_lines = Some(truncate_history_file(&path, policy.file_size));
+            }
         }
-        if let Some(ref path) = self.history_path {
-            append_to_history_file(path, trimmed);
+    }
+
+    /// Tell the editor the lines already entered of the command being read,
+    /// before reading its next line, so Ctrl-Z can stash the whole command.
+    pub fn set_pending_lines(&mut self, lines: &[String]) {
+        self.pending = lines.to_vec();
+    }
+
+    /// Switch to a new history policy, usually read from the shell's
+    /// `HIST*` variables before each command. Entries already recorded are
+    /// trimmed and de-duplicated to match.
+    pub fn set_history_policy(&mut self, policy: HistoryPolicy) {
+        if policy != self.history_policy {
+            self.history_policy = policy;
+            self.apply_history_policy();
         }
     }
 
+    /// Read the history file again after it was rewritten from outside, as
+    /// `history --import` does.
+    pub fn reload_history(&mut self) {
+        let Some(path) = self.history_path.as_deref() else {
+            return;
+        };
+        let (history, history_unloaded) = load_history_tail(path, HISTORY_TAIL_ENTRIES);
+        self.history_file_lines = (history_unloaded == 0).then_some(history.len());
+        self.history_unloaded = history_unloaded;
+        self.history = history;
+        self.history_idx = self.history.len();
+        self.apply_history_policy();
+    }
+
+    /// Recorded commands, oldest first. Reads any part of the history file
+    /// not loaded yet.
+    pub fn history(&mut self) -> &[String] {
+        self.load_older_history();
+        &self.history
+    }
+
     // ── Private ───────────────────────────────────────────────────────────────
 
+    /// Bring the in-memory history in line with the policy: keep only the
+    /// newest copy of each command under `erasedups`, then the newest
+    /// `HISTSIZE` entries.
+    fn apply_history_policy(&mut self) {
+        if self.history_policy.erase_dups {
+            let mut seen = std::collections::HashSet::new();
+            let mut newest_first: Vec<String> =
+                self.history.drain(..).rev().filter(|entry| seen.insert(entry.clone())).collect();
+            newest_first.reverse();
+            self.history = newest_first;
+        }
+        if self.history.len() >= self.history_policy.size {
+            let excess = self.history.len() - self.history_policy.size;
+            self.history.drain(..excess);
+            // Older entries would only be trimmed away again.
+            self.history_unloaded = 0;
+        }
+        self.history_idx = self.history.len();
+    }
+
+    /// Read the history file lines before those loaded at startup. Returns
+    /// false if there were none.
+    fn load_older_history(&mut self) -> bool {
+        if self.history_unloaded == 0 {
+            return false;
+        }
+        let older = match &self.history_path {
+            Some(path) => load_history_prefix(path, self.history_unloaded),
+            None => Vec::new(),
+        };
+        self.history_unloaded = 0;
+        self.history.splice(..0, older);
+        self.apply_history_policy();
+        true
+    }
+
     fn reset_state(&mut self) {
         self.buffer.clear();
         self.cursor = 0;
         self.history_idx = self.history.len();
         self.saved_buffer.clear();
+        self.undo_stack.clear();
+        self.typing = false;
+        self.ctrl_x_pending = false;
+        self.cursor_row = 0;
+        // Called just before the prompt is shown, with nothing after it.
+        self.drawn = Some(Vec::new());
+    }
+
+    /// Alt-Q or Ctrl-Z: put the command being entered aside, like zsh's
+    /// push-line, and clear the line. It comes back at the first new
+    /// command after another line has been entered.
+    fn stash_line(&mut self) {
+        let mut lines = std::mem::take(&mut self.pending);
+        lines.push(self.buffer.drain(..).collect());
+        lines.extend(self.unstashed.drain(..));
+        self.stash.push(lines);
+        self.stash_due = false;
+        self.cursor = 0;
+    }
+
+    /// Start the line from a stashed command: the next of its lines while
+    /// one is being put back, or, at a new command once one is due, the
+    /// first line of the newest stash.
+    fn unstash(&mut self) {
+        if self.pending.is_empty() && self.unstashed.is_empty() && self.stash_due {
+            if let Some(lines) = self.stash.pop() {
+                self.unstashed = lines.into();
+            }
+            self.stash_due = false;
+        }
+        if let Some(line) = self.unstashed.pop_front() {
+            self.buffer = line.chars().collect();
+            self.cursor = self.buffer.len();
+        }
+    }
+
+    /// Write the output queued for this event to the terminal.
+    fn flush_output(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&self.out)?;
+        self.out.clear();
+        stdout.flush()
     }
 
     /// Non-TTY path: print prompt and delegate to `BufRead::read_line`.
@@ -182,6 +478,7 @@ impl LineEditor {
         }
     }
 
+    /// Apply one key press, recording an undo step if it changed the line.
     fn handle_key(
         &mut self,
         key: crossterm::event::KeyEvent,
@@ -190,12 +487,62 @@ impl LineEditor {
         use KeyCode::*;
         use KeyModifiers as Mod;
 
+        // Ctrl-_ (which terminals also send for Ctrl-/ and Ctrl-7) or
+        // Ctrl-X u: undo the last edit.
+        let ctrl_x_pending = std::mem::take(&mut self.ctrl_x_pending);
+        match (key.code, key.modifiers) {
+            (Char('_' | '/' | '7'), Mod::CONTROL) => {}
+            (Char('u'), Mod::NONE | Mod::CONTROL) if ctrl_x_pending => {}
+            (Char('x'), Mod::CONTROL) => {
+                self.ctrl_x_pending = true;
+                return Ok(KeyAction::Continue);
+            }
+            _ => {
+                let before = (self.buffer.clone(), self.cursor);
+                let action = self.edit(key, prompt)?;
+                let typed = matches!((key.code, key.modifiers), (Char(_), Mod::NONE | Mod::SHIFT));
+                self.record_undo(before, typed);
+                return Ok(action);
+            }
+        }
+        self.undo();
+        self.redraw(prompt)?;
+        Ok(KeyAction::Continue)
+    }
+
+    /// Push `before` as an undo step if the line has changed since. A run of
+    /// typed characters is one step.
+    fn record_undo(&mut self, before: (Vec<char>, usize), typed: bool) {
+        if before.0 == self.buffer {
+            return;
+        }
+        if !(typed && self.typing) {
+            self.undo_stack.push(before);
+        }
+        self.typing = typed;
+    }
+
+    fn undo(&mut self) {
+        if let Some((buffer, cursor)) = self.undo_stack.pop() {
+            self.buffer = buffer;
+            self.cursor = cursor;
+        }
+        self.typing = false;
+    }
+
+    fn edit(
+        &mut self,
+        key: crossterm::event::KeyEvent,
+        prompt: &str,
+    ) -> io::Result<KeyAction> {
+        use KeyCode::*;
+        use KeyModifiers as Mod;
+
         match (key.code, key.modifiers) {
             // ── Submit ────────────────────────────────────────────────────────
             (Enter, _) => {
                 // Raw mode suppresses the terminal's automatic newline on Enter.
-                print!("\r\n");
-                io::stdout().flush()?;
+                self.move_below_line(prompt)?;
                 let line: String = self.buffer.iter().collect();
                 return Ok(KeyAction::Submit(line));
             }
@@ -203,8 +550,7 @@ impl LineEditor {
             // ── Ctrl-D: EOF or delete-at-cursor ───────────────────────────────
             (Char('d'), Mod::CONTROL) => {
                 if self.buffer.is_empty() {
-                    print!("\r\n");
-                    io::stdout().flush()?;
+                    write!(self.out, "\r\n")?;
                     return Ok(KeyAction::Eof);
                 }
                 self.delete_at_cursor();
@@ -215,21 +561,20 @@ impl LineEditor {
             // In raw mode on Unix, ISIG is off so Ctrl-C arrives as a key event
             // rather than SIGINT — the ctrlc crate handler does not fire here.
             (Char('c'), Mod::CONTROL) => {
-                print!("^C\r\n{prompt}");
-                io::stdout().flush()?;
-                self.buffer.clear();
-                self.cursor = 0;
-                self.history_idx = self.history.len();
-                self.saved_buffer.clear();
+                self.cursor = self.buffer.len();
+                self.sync_cursor(prompt)?;
+                write!(self.out, "^C")?;
+                self.move_below_line(prompt)?;
+                write!(self.out, "{}{prompt}", self.prompt_head)?;
+                self.reset_state();
             }
 
             // ── Ctrl-L: clear screen ──────────────────────────────────────────
             (Char('l'), Mod::CONTROL) => {
-                execute!(
-                    io::stdout(),
-                    terminal::Clear(ClearType::All),
-                    cursor::MoveTo(0, 0),
-                )?;
+                queue!(self.out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+                write!(self.out, "{}", self.prompt_head)?;
+                self.cursor_row = 0;
+                self.drawn = None;
                 self.redraw(prompt)?;
             }
 
@@ -264,18 +609,40 @@ impl LineEditor {
                 self.redraw(prompt)?;
             }
 
+            // ── Alt-B / Alt-F / Ctrl-Arrow: move by word ──────────────────────
+            (Char('b'), Mod::ALT) | (Left, Mod::CONTROL) => {
+                self.cursor = self.word_start_before(self.cursor);
+                self.sync_cursor(prompt)?;
+            }
+            (Char('f'), Mod::ALT) | (Right, Mod::CONTROL) => {
+                self.cursor = self.word_end_after(self.cursor);
+                self.sync_cursor(prompt)?;
+            }
+
+            // ── Alt-D: delete next word ───────────────────────────────────────
+            (Char('d'), Mod::ALT) => {
+                self.delete_word_after_cursor();
+                self.redraw(prompt)?;
+            }
+
+            // ── Ctrl-T / Alt-T: transpose characters or words ─────────────────
+            (Char('t'), Mod::CONTROL) => {
+                self.transpose_chars();
+                self.redraw(prompt)?;
+            }
+            (Char('t'), Mod::ALT) => {
+                self.transpose_words();
+                self.redraw(prompt)?;
+            }
+
             // ── Arrow keys ────────────────────────────────────────────────────
-            (Left, _) => {
-                if self.cursor > 0 {
-                    self.cursor -= 1;
-                    self.sync_cursor(prompt)?;
-                }
+            (Left, _) if self.cursor > 0 => {
+                self.cursor = self.grapheme_before(self.cursor);
+                self.sync_cursor(prompt)?;
             }
-            (Right, _) => {
-                if self.cursor < self.buffer.len() {
-                    self.cursor += 1;
-                    self.sync_cursor(prompt)?;
-                }
+            (Right, _) if self.cursor < self.buffer.len() => {
+                self.cursor = self.grapheme_after(self.cursor);
+                self.sync_cursor(prompt)?;
             }
 
             // ── History navigation ────────────────────────────────────────────
@@ -289,12 +656,11 @@ impl LineEditor {
             }
 
             // ── Backspace / Delete ────────────────────────────────────────────
-            (Backspace, _) => {
-                if self.cursor > 0 {
-                    self.cursor -= 1;
-                    self.buffer.remove(self.cursor);
-                    self.redraw(prompt)?;
-                }
+            (Backspace, _) if self.cursor > 0 => {
+                let start = self.grapheme_before(self.cursor);
+                self.buffer.drain(start..self.cursor);
+                self.cursor = start;
+                self.redraw(prompt)?;
             }
             (Delete, _) => {
                 self.delete_at_cursor();
@@ -308,6 +674,18 @@ impl LineEditor {
                 self.redraw(prompt)?;
             }
 
+            // ── Tab: complete the word before the cursor ───────────────────────
+            (Tab, _) => return Ok(KeyAction::Complete),
+
+            // ── Alt-Q / Ctrl-Z: stash the command; nothing at an empty prompt ─
+            // With ISIG off Ctrl-Z arrives as a key rather than SIGTSTP.
+            (Char('q'), Mod::ALT) | (Char('z'), Mod::CONTROL)
+                if !self.buffer.is_empty() || !self.pending.is_empty() =>
+            {
+                return Ok(KeyAction::Stash);
+            }
+            (Char('q'), Mod::ALT) | (Char('z'), Mod::CONTROL) => {}
+
             // ── Everything else: ignore ───────────────────────────────────────
             _ => {}
         }
@@ -315,53 +693,229 @@ impl LineEditor {
         Ok(KeyAction::Continue)
     }
 
-    /// Erase the current line and redraw prompt + buffer, then reposition cursor.
-    fn redraw(&self, prompt: &str) -> io::Result<()> {
-        let line: String = self.buffer.iter().collect();
-        // Prompt length measured in chars (not bytes) for correct column math.
-        let col = (prompt.chars().count() + self.cursor) as u16;
-        execute!(
-            io::stdout(),
-            cursor::MoveToColumn(0),
-            terminal::Clear(ClearType::CurrentLine),
-        )?;
-        print!("{prompt}{line}");
-        io::stdout().flush()?;
-        execute!(io::stdout(), cursor::MoveToColumn(col))?;
-        Ok(())
+    /// Bring the screen up to date with the buffer, wrapped across rows.
+    /// Text before the first change since the last redraw is left alone;
+    /// with nothing drawn yet, the rows the line occupies are erased and the
+    /// prompt and buffer printed in full.
+    fn redraw(&mut self, prompt: &str) -> io::Result<()> {
+        let prompt_width = display_width(prompt);
+        let width = self.width;
+        let layout = Layout::new(prompt_width, &self.buffer, self.cursor, width);
+        let full = self.drawn.is_none();
+        let unchanged = match self.drawn.take() {
+            Some(drawn) => {
+                // Back up to a grapheme boundary in both versions, so a
+                // combining mark is never printed apart from its base.
+                let same = drawn.iter().zip(&self.buffer).take_while(|(a, b)| a == b).count();
+                let drawn_boundaries = grapheme_boundaries(&drawn);
+                let same = grapheme_boundaries(&self.buffer)
+                    .into_iter()
+                    .rev()
+                    .find(|b| *b <= same && drawn_boundaries.contains(b))
+                    .unwrap_or(0);
+                let from = Layout::new(prompt_width, &self.buffer, same, width);
+                self.move_cursor_to(from.cursor_row, from.cursor_col)?;
+                if same < drawn.len() {
+                    queue!(self.out, terminal::Clear(ClearType::FromCursorDown))?;
+                }
+                same
+            }
+            None => {
+                if self.cursor_row > 0 {
+                    queue!(self.out, cursor::MoveUp(self.cursor_row as u16))?;
+                }
+                queue!(self.out, cursor::MoveToColumn(0), terminal::Clear(ClearType::FromCursorDown))?;
+                write!(self.out, "{prompt}")?;
+                0
+            }
+        };
+        let changed: String = self.buffer[unchanged..].iter().collect();
+        write!(self.out, "{changed}")?;
+        if (full || !changed.is_empty()) && layout.end_row > 0 && layout.end_col == 0 {
+            // Text exactly filling the last row leaves the terminal waiting to
+            // wrap; start the next row so the cursor is where Layout says.
+            write!(self.out, "\r\n")?;
+        }
+        self.cursor_row = layout.end_row;
+        self.drawn = Some(self.buffer.clone());
+        if (layout.cursor_row, layout.cursor_col) == (layout.end_row, layout.end_col) {
+            // Printing the line left the cursor where it belongs.
+            return Ok(());
+        }
+        self.move_cursor_to(layout.cursor_row, layout.cursor_col)
+    }
+
+    /// Lay the line out again for a terminal now `width` columns wide.
+    ///
+    /// Some terminals rewrap the rows of the line when resized and some do
+    /// not, so which row the cursor is on now is not certain. Taking the
+    /// lower of the rows before and after the rewrap never climbs past the
+    /// start of the line into earlier output; at worst a stale row is left
+    /// above the redrawn line.
+    fn resize(&mut self, width: usize, prompt: &str) -> io::Result<()> {
+        self.width = width.max(1);
+        let rewrapped = Layout::new(display_width(prompt), &self.buffer, self.cursor, self.width);
+        self.cursor_row = self.cursor_row.min(rewrapped.cursor_row);
+        self.drawn = None;
+        self.redraw(prompt)
     }
 
     /// Move the terminal cursor to match `self.cursor` without redrawing text.
     /// Used for pure cursor moves (Left/Right/Home/End) to avoid flicker.
-    fn sync_cursor(&self, prompt: &str) -> io::Result<()> {
-        let col = (prompt.chars().count() + self.cursor) as u16;
-        execute!(io::stdout(), cursor::MoveToColumn(col))?;
+    fn sync_cursor(&mut self, prompt: &str) -> io::Result<()> {
+        let layout = Layout::new(display_width(prompt), &self.buffer, self.cursor, self.width);
+        self.move_cursor_to(layout.cursor_row, layout.cursor_col)
+    }
+
+    /// Move the terminal cursor to the start of the row after the line, so
+    /// output that follows does not overwrite it.
+    fn move_below_line(&mut self, prompt: &str) -> io::Result<()> {
+        let layout = Layout::new(display_width(prompt), &self.buffer, self.buffer.len(), self.width);
+        self.move_cursor_to(layout.end_row, 0)?;
+        write!(self.out, "\r\n")?;
+        self.cursor_row = 0;
+        self.drawn = None;
         Ok(())
     }
 
-    fn delete_at_cursor(&mut self) {
-        if self.cursor < self.buffer.len() {
-            self.buffer.remove(self.cursor);
+    fn move_cursor_to(&mut self, row: usize, col: usize) -> io::Result<()> {
+        if row < self.cursor_row {
+            queue!(self.out, cursor::MoveUp((self.cursor_row - row) as u16))?;
+        } else if row > self.cursor_row {
+            queue!(self.out, cursor::MoveDown((row - self.cursor_row) as u16))?;
         }
+        queue!(self.out, cursor::MoveToColumn(col as u16))?;
+        self.cursor_row = row;
+        Ok(())
     }
 
-    fn delete_word_before_cursor(&mut self) {
-        if self.cursor == 0 {
-            return;
+    /// Replace the word being completed with the only candidate (followed
+    /// by a space unless it is a directory) or with the candidates' longest
+    /// common prefix. Returns the candidates when that would not add
+    /// anything, so the caller can list them.
+    fn apply_completion(&mut self, completion: Completion) -> Option<Vec<String>> {
+        let Completion { start, candidates } = completion;
+        let (first, rest) = candidates.split_first()?;
+        let replacement: Vec<char> = if rest.is_empty() {
+            let suffix = if first.ends_with('/') { "" } else { " " };
+            format!("{first}{suffix}").chars().collect()
+        } else {
+            let mut common: Vec<char> = first.chars().collect();
+            for candidate in rest {
+                let shared = common.iter().zip(candidate.chars()).take_while(|(a, b)| **a == *b).count();
+                common.truncate(shared);
+            }
+            common
+        };
+
+        if !rest.is_empty() && replacement.len() <= self.cursor - start {
+            return Some(candidates);
+        }
+        self.buffer.splice(start..self.cursor, replacement.iter().copied());
+        self.cursor = start + replacement.len();
+        None
+    }
+
+    fn delete_at_cursor(&mut self) {
+        let end = self.grapheme_after(self.cursor);
+        self.buffer.drain(self.cursor..end);
+    }
+
+    /// Swap the character before the cursor with the one under it and move
+    /// past both, as readline does; at the end of the line, swap the last
+    /// two characters.
+    fn transpose_chars(&mut self) {
+        let mut pos = self.cursor;
+        if pos == self.buffer.len() {
+            pos = self.grapheme_before(pos);
         }
-        // Skip spaces immediately before the cursor, then the non-space word.
-        let mut end = self.cursor;
-        while end > 0 && self.buffer[end - 1] == ' ' {
-            end -= 1;
+        if pos == 0 {
+            return;
         }
-        let mut start = end;
-        while start > 0 && self.buffer[start - 1] != ' ' {
-            start -= 1;
+        let start = self.grapheme_before(pos);
+        let end = self.grapheme_after(pos);
+        self.buffer[start..end].rotate_left(pos - start);
+        self.cursor = end;
+    }
+
+    /// Drag the word before the cursor past the word after it (or the last
+    /// word, at the end of the line) and leave the cursor after both.
+    fn transpose_words(&mut self) {
+        let second_end = self.word_end_after(self.cursor);
+        let second_start = self.word_start_before(second_end);
+        let first_start = self.word_start_before(second_start);
+        if first_start == second_start {
+            return;
         }
+        let first_end = self.word_end_after(first_start);
+        let mut swapped = self.buffer[second_start..second_end].to_vec();
+        swapped.extend_from_slice(&self.buffer[first_end..second_start]);
+        swapped.extend_from_slice(&self.buffer[first_start..first_end]);
+        self.buffer.splice(first_start..second_end, swapped);
+        self.cursor = second_end;
+    }
+
+    /// Start of the grapheme cluster before `pos`, so one key press steps
+    /// over a whole user-perceived character such as `e` + combining accent.
+    fn grapheme_before(&self, pos: usize) -> usize {
+        grapheme_boundaries(&self.buffer)
+            .into_iter()
+            .rev()
+            .find(|&b| b < pos)
+            .unwrap_or(0)
+    }
+
+    /// End of the grapheme cluster at `pos`.
+    fn grapheme_after(&self, pos: usize) -> usize {
+        grapheme_boundaries(&self.buffer)
+            .into_iter()
+            .find(|&b| b > pos)
+            .unwrap_or(self.buffer.len())
+    }
+
+    fn delete_word_before_cursor(&mut self) {
+        let start = self.word_start_before(self.cursor);
         self.buffer.drain(start..self.cursor);
         self.cursor = start;
     }
 
+    fn delete_word_after_cursor(&mut self) {
+        let end = self.word_end_after(self.cursor);
+        self.buffer.drain(self.cursor..end);
+    }
+
+    /// Start of the word before `pos`: skip spaces immediately before it,
+    /// then the non-space word. Steps by grapheme cluster, so a space with
+    /// a combining mark on it counts as part of the word.
+    fn word_start_before(&self, pos: usize) -> usize {
+        let mut start = pos;
+        while start > 0 && self.is_space_at(self.grapheme_before(start)) {
+            start = self.grapheme_before(start);
+        }
+        while start > 0 && !self.is_space_at(self.grapheme_before(start)) {
+            start = self.grapheme_before(start);
+        }
+        start
+    }
+
+    /// End of the word after `pos`: skip spaces immediately after it, then
+    /// the non-space word.
+    fn word_end_after(&self, pos: usize) -> usize {
+        let mut end = pos;
+        while end < self.buffer.len() && self.is_space_at(end) {
+            end = self.grapheme_after(end);
+        }
+        while end < self.buffer.len() && !self.is_space_at(end) {
+            end = self.grapheme_after(end);
+        }
+        end
+    }
+
+    /// Whether the grapheme cluster starting at `pos` is a lone space.
+    fn is_space_at(&self, pos: usize) -> bool {
+        self.buffer[pos] == ' ' && self.grapheme_after(pos) == pos + 1
+    }
+
     fn history_prev(&mut self) {
         if self.history.is_empty() {
             return;
@@ -370,6 +924,14 @@ impl LineEditor {
         if self.history_idx == self.history.len() {
             self.saved_buffer = self.buffer.iter().collect();
         }
+        if self.history_idx == 0 {
+            // Stepping past the oldest loaded entry: load the rest and keep
+            // the same entry selected.
+            let from_end = self.history.len();
+            if self.load_older_history() {
+                self.history_idx = self.history.len().saturating_sub(from_end);
+            }
+        }
         if self.history_idx > 0 {
             self.history_idx -= 1;
             self.buffer = self.history[self.history_idx].chars().collect();
@@ -392,51 +954,223 @@ impl LineEditor {
     }
 }
 
+// ── Screen layout ─────────────────────────────────────────────────────────────
+
+/// Where the prompt and buffer land on screen once wrapped, in rows counted
+/// from the one the prompt starts on.
+#[derive(Debug, PartialEq)]
+struct Layout {
+    /// Row and column the terminal cursor ends on after printing the whole
+    /// line.
+    end_row: usize,
+    end_col: usize,
+    cursor_row: usize,
+    cursor_col: usize,
+}
+
+impl Layout {
+    /// Lay out `text` after a prompt `prompt_width` columns wide, with the
+    /// cursor before char `cursor`. Columns come from each grapheme
+    /// cluster's display width, and a wide character that does not fit at
+    /// the end of a row moves to the next one, as terminals do.
+    fn new(prompt_width: usize, text: &[char], cursor: usize, width: usize) -> Self {
+        let width = width.max(1);
+        let (mut row, mut col) = (prompt_width / width, prompt_width % width);
+        let mut cursor_at = None;
+        let mut index = 0;
+        for grapheme in text.iter().collect::<String>().graphemes(true) {
+            let columns = grapheme.width();
+            if col > 0 && col + columns > width {
+                row += 1;
+                col = 0;
+            }
+            if index == cursor {
+                cursor_at = Some((row, col));
+            }
+            col += columns;
+            if col >= width {
+                row += 1;
+                col = 0;
+            }
+            index += grapheme.chars().count();
+        }
+        let (cursor_row, cursor_col) = cursor_at.unwrap_or((row, col));
+        Layout {
+            end_row: row,
+            end_col: col,
+            cursor_row,
+            cursor_col,
+        }
+    }
+}
+
+/// Char indices in `text` where grapheme clusters start, and its length.
+fn grapheme_boundaries(text: &[char]) -> Vec<usize> {
+    let mut boundaries = vec![0];
+    let mut index = 0;
+    for grapheme in text.iter().collect::<String>().graphemes(true) {
+        index += grapheme.chars().count();
+        boundaries.push(index);
+    }
+    boundaries
+}
+
+/// Columns `text` takes on screen, by display width (CJK and emoji take
+/// two, combining marks none), not counting terminal escape sequences such
+/// as prompt colours.
+fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            width += c.width().unwrap_or(0);
+            continue;
+        }
+        // CSI sequences (ESC [ ...) end at a byte in '@'..='~'; other escapes
+        // are ESC plus one char.
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    width
+}
+
+fn terminal_width() -> usize {
+    terminal::size().map_or(80, |(cols, _)| usize::from(cols).max(1))
+}
+
 // ── Internal return type ──────────────────────────────────────────────────────
 
 enum KeyAction {
     Continue,
+    /// Tab: complete the word before the cursor.
+    Complete,
     Submit(String),
     Eof,
+    /// Alt-Q or Ctrl-Z: put the command aside until after the next one.
+    Stash,
 }
 
 // ── History persistence ───────────────────────────────────────────────────────
 
-fn history_file_path() -> Option<PathBuf> {
-    std::env::var("HOME")
-        .or_else(|_| std::env::var("USERPROFILE"))
-        .ok()
-        .map(|home| PathBuf::from(home).join(".jsh_history"))
-}
+/// History entries read eagerly at startup; older ones wait until the user
+/// scrolls back to them or history expansion needs them.
+const HISTORY_TAIL_ENTRIES: usize = 1_000;
+
+/// Bytes read per step when scanning the history file from its end.
+const HISTORY_CHUNK: u64 = 64 * 1024;
 
 fn load_history(path: &std::path::Path) -> Vec<String> {
-    std::fs::read_to_string(path)
-        .unwrap_or_default()
+    history_lines(&std::fs::read(path).unwrap_or_default())
+}
+
+fn history_lines(bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
         .lines()
         .filter(|l| !l.is_empty())
         .map(str::to_string)
         .collect()
 }
 
+/// Read the last `count` lines of the history file by scanning backwards
+/// from its end, so startup reads only as much of the file as those lines
+/// take up. Returns the lines and the byte offset where they start.
+fn load_history_tail(path: &std::path::Path, count: usize) -> (Vec<String>, u64) {
+    let Ok(mut file) = File::open(path) else {
+        return (Vec::new(), 0);
+    };
+    let Ok(len) = file.seek(SeekFrom::End(0)) else {
+        return (Vec::new(), 0);
+    };
+
+    // Line `count` from the end starts after newline `count + 1` from the
+    // end, counting the file's trailing newline. Chunks are collected last
+    // first and joined once.
+    let mut start = len;
+    let mut chunks: Vec<Vec<u8>> = Vec::new();
+    let mut newlines = 0;
+    while start > 0 && newlines <= count {
+        let chunk = HISTORY_CHUNK.min(start);
+        start -= chunk;
+        let mut buf = vec![0; chunk as usize];
+        if file.seek(SeekFrom::Start(start)).is_err() || file.read_exact(&mut buf).is_err() {
+            return (load_history(path), 0);
+        }
+        newlines += buf.iter().filter(|&&b| b == b'\n').count();
+        chunks.push(buf);
+    }
+    chunks.reverse();
+    let tail = chunks.concat();
+
+    let boundary = tail.iter().enumerate().rev().filter(|&(_, &b)| b == b'\n').nth(count);
+    match boundary {
+        Some((i, _)) => (history_lines(&tail[i + 1..]), start + i as u64 + 1),
+        None => (history_lines(&tail), 0),
+    }
+}
+
+/// Read the history file lines before byte `end`.
+fn load_history_prefix(path: &std::path::Path, end: u64) -> Vec<String> {
+    let mut bytes = Vec::new();
+    match File::open(path).and_then(|file| file.take(end).read_to_end(&mut bytes)) {
+        Ok(_) => history_lines(&bytes),
+        Err(_) => Vec::new(),
+    }
+}
+
 fn append_to_history_file(path: &std::path::Path, line: &str) {
     if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
         let _ = writeln!(f, "{line}");
     }
 }
 
+/// Rewrite the history file keeping only its last `max_lines` lines, and
+/// return how many it now holds. The new file is written beside it and
+/// renamed over it, so a crash or a full disk cannot leave it half written.
+fn truncate_history_file(path: &std::path::Path, max_lines: usize) -> usize {
+    let (kept, start) = load_history_tail(path, max_lines);
+    if start == 0 {
+        return kept.len();
+    }
+    let mut text = kept.join("\n");
+    if !kept.is_empty() {
+        text.push('\n');
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    let temp = PathBuf::from(temp);
+    let written = std::fs::write(&temp, text)
+        .and_then(|()| match std::fs::metadata(path) {
+            Ok(meta) => std::fs::set_permissions(&temp, meta.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|()| std::fs::rename(&temp, path));
+    match written {
+        Ok(()) => kept.len(),
+        Err(_) => {
+            let _ = std::fs::remove_file(&temp);
+            max_lines + 1
+        }
+    }
+}
+
 // ── Unit tests ────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::history::DEFAULT_HISTORY_SIZE;
     use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
     /// Build a `LineEditor` with a fixed history, bypassing file I/O.
     fn editor_with_history(entries: &[&str]) -> LineEditor {
-        let mut e = LineEditor::new();
+        let mut e = LineEditor::without_history();
         e.history = entries.iter().map(|s| s.to_string()).collect();
         e.history_idx = e.history.len();
-        e.history_path = None; // don't touch the real ~/.jsh_history in tests
         e
     }
 
@@ -466,6 +1200,91 @@ mod tests {
         assert_eq!(e.history.len(), 3);
     }
 
+    #[test]
+    fn history_policy_controls_recording() {
+        let mut e = editor_with_history(&["ls", "pwd", "ls", "date"]);
+        e.set_history_policy(HistoryPolicy {
+            size: 3,
+            ignore_space: true,
+            erase_dups: true,
+            ..HistoryPolicy::default()
+        });
+        assert_eq!(e.history, vec!["pwd", "ls", "date"]);
+
+        e.add_to_history(" echo secret");
+        e.add_to_history("pwd");
+        assert_eq!(e.history, vec!["ls", "date", "pwd"]);
+
+        e.set_history_policy(HistoryPolicy {
+            ignore_dups: false,
+            ..HistoryPolicy::default()
+        });
+        e.add_to_history("pwd");
+        assert_eq!(e.history, vec!["ls", "date", "pwd", "pwd"]);
+    }
+
+    #[test]
+    fn history_file_is_loaded_from_the_end_first() {
+        let path = std::env::temp_dir().join(format!("jsh_lazy_history_{}", std::process::id()));
+        let lines: Vec<String> = (0..20_000).map(|i| format!("cmd-{i}")).collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let (tail, offset) = load_history_tail(&path, 1_000);
+        assert_eq!(tail, lines[19_000..]);
+        assert_eq!(load_history_prefix(&path, offset), lines[..19_000]);
+        assert_eq!(load_history_tail(&path, 50_000), (lines.clone(), 0));
+
+        // Scrolling past the oldest loaded entry reads the rest.
+        let mut e = editor_with_history(&[]);
+        (e.history, e.history_unloaded) = load_history_tail(&path, 2);
+        e.history_path = Some(path.clone());
+        e.history_idx = e.history.len();
+        e.history_prev();
+        e.history_prev();
+        assert_eq!(e.buffer.iter().collect::<String>(), "cmd-19998");
+        e.history_prev();
+        assert_eq!(e.buffer.iter().collect::<String>(), "cmd-19997");
+        assert_eq!(e.history.len(), DEFAULT_HISTORY_SIZE);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn history_file_stays_within_a_tenth_of_histfilesize() {
+        let dir = std::env::temp_dir().join(format!("jsh_histfilesize_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history");
+        let mut e = editor_with_history(&[]);
+        e.history_path = Some(path.clone());
+        e.set_history_policy(HistoryPolicy { file_size: 20, ..HistoryPolicy::default() });
+        let (mut rewrites, mut previous) = (0, 0);
+        for i in 0..50 {
+            e.add_to_history(&format!("cmd-{i}"));
+            let lines = load_history(&path).len();
+            assert!(lines <= 22, "{lines} lines after cmd-{i}");
+            rewrites += usize::from(lines < previous);
+            previous = lines;
+        }
+        let expected: Vec<String> = (30..50).map(|i| format!("cmd-{i}")).collect();
+        assert_eq!(load_history(&path), expected);
+        // Cut back every third command past the limit, not every one.
+        assert_eq!(rewrites, 10);
+        let leftovers: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(leftovers, ["history"]);
+    }
+
+    #[test]
+    fn history_tail_spans_lines_longer_than_a_chunk() {
+        let path = std::env::temp_dir().join(format!("jsh_long_history_{}", std::process::id()));
+        let lines: Vec<String> = (0..40).map(|i| format!("{i}{}", "x".repeat(HISTORY_CHUNK as usize / 3))).collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let (tail, offset) = load_history_tail(&path, 7);
+        assert_eq!(tail, lines[33..]);
+        assert_eq!(load_history_prefix(&path, offset), lines[..33]);
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn history_navigation_saves_and_restores_buffer() {
         let mut e = editor_with_history(&["echo hello", "ls -la"]);
@@ -522,6 +1341,194 @@ mod tests {
         assert_eq!(e.cursor, 0);
     }
 
+    #[test]
+    fn word_movement_and_alt_d_use_ctrl_w_boundaries() {
+        let mut e = editor_with_history(&[]);
+        let prompt = "jsh> ";
+        let k = |code: KeyCode, mods: KeyModifiers| KeyEvent::new(code, mods);
+        e.buffer = "echo  hello world".chars().collect();
+        e.cursor = e.buffer.len();
+
+        e.handle_key(k(KeyCode::Char('b'), KeyModifiers::ALT), prompt).unwrap();
+        assert_eq!(e.cursor, "echo  hello ".len());
+        e.handle_key(k(KeyCode::Left, KeyModifiers::CONTROL), prompt).unwrap();
+        assert_eq!(e.cursor, "echo  ".len());
+        e.handle_key(k(KeyCode::Home, KeyModifiers::NONE), prompt).unwrap();
+        e.handle_key(k(KeyCode::Char('f'), KeyModifiers::ALT), prompt).unwrap();
+        assert_eq!(e.cursor, "echo".len());
+        e.handle_key(k(KeyCode::Right, KeyModifiers::CONTROL), prompt).unwrap();
+        assert_eq!(e.cursor, "echo  hello".len());
+
+        e.handle_key(k(KeyCode::Home, KeyModifiers::NONE), prompt).unwrap();
+        e.handle_key(k(KeyCode::Char('d'), KeyModifiers::ALT), prompt).unwrap();
+        assert_eq!(e.buffer.iter().collect::<String>(), "  hello world");
+        e.handle_key(k(KeyCode::Char('d'), KeyModifiers::ALT), prompt).unwrap();
+        assert_eq!(e.buffer.iter().collect::<String>(), " world");
+        assert_eq!(e.cursor, 0);
+    }
+
+    #[test]
+    fn ctrl_t_and_alt_t_transpose() {
+        let mut e = editor_with_history(&[]);
+        let ctrl_t = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL);
+        let alt_t = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::ALT);
+        let text = |e: &LineEditor| e.buffer.iter().collect::<String>();
+
+        e.buffer = "abcd".chars().collect();
+        e.cursor = 1;
+        e.handle_key(ctrl_t, "").unwrap();
+        assert_eq!((text(&e).as_str(), e.cursor), ("bacd", 2));
+        e.cursor = 4;
+        e.handle_key(ctrl_t, "").unwrap();
+        assert_eq!((text(&e).as_str(), e.cursor), ("badc", 4));
+        e.cursor = 0;
+        e.handle_key(ctrl_t, "").unwrap();
+        assert_eq!(text(&e), "badc");
+
+        e.buffer = "echo foo  bar baz".chars().collect();
+        e.cursor = 6; // inside "foo"
+        e.handle_key(alt_t, "").unwrap();
+        assert_eq!((text(&e).as_str(), e.cursor), ("foo echo  bar baz", 8));
+        e.cursor = e.buffer.len();
+        e.handle_key(alt_t, "").unwrap();
+        assert_eq!((text(&e).as_str(), e.cursor), ("foo echo  baz bar", 17));
+        e.cursor = 0;
+        e.handle_key(alt_t, "").unwrap();
+        assert_eq!(text(&e), "foo echo  baz bar");
+    }
+
+    #[test]
+    fn undo_reverts_edits_and_groups_typing() {
+        let mut e = editor_with_history(&[]);
+        let prompt = "jsh> ";
+        let k = |code: KeyCode, mods: KeyModifiers| KeyEvent::new(code, mods);
+        for c in "ls -l".chars() {
+            e.handle_key(k(KeyCode::Char(c), KeyModifiers::NONE), prompt).unwrap();
+        }
+        e.handle_key(k(KeyCode::Char('w'), KeyModifiers::CONTROL), prompt).unwrap();
+        e.handle_key(k(KeyCode::Char('u'), KeyModifiers::CONTROL), prompt).unwrap();
+        assert!(e.buffer.is_empty());
+
+        e.handle_key(k(KeyCode::Char('_'), KeyModifiers::CONTROL), prompt).unwrap();
+        assert_eq!(e.buffer.iter().collect::<String>(), "ls ");
+        e.handle_key(k(KeyCode::Char('x'), KeyModifiers::CONTROL), prompt).unwrap();
+        e.handle_key(k(KeyCode::Char('u'), KeyModifiers::NONE), prompt).unwrap();
+        assert_eq!(e.buffer.iter().collect::<String>(), "ls -l");
+        assert_eq!(e.cursor, 5);
+        // The typing was one step; undoing it empties the line.
+        e.handle_key(k(KeyCode::Char('_'), KeyModifiers::CONTROL), prompt).unwrap();
+        assert!(e.buffer.is_empty());
+        e.handle_key(k(KeyCode::Char('_'), KeyModifiers::CONTROL), prompt).unwrap();
+        assert!(e.buffer.is_empty());
+    }
+
+    #[test]
+    fn layout_wraps_at_terminal_width() {
+        let chars = |text: &str| text.chars().collect::<Vec<char>>();
+        // "jsh> " + 10 chars in 8 columns: rows "jsh> abc", "defghij".
+        assert_eq!(
+            Layout::new(5, &chars("abcdefghij"), 4, 8),
+            Layout { end_row: 1, end_col: 7, cursor_row: 1, cursor_col: 1 }
+        );
+        // Exactly filling a row puts the end on the next row, column 0.
+        assert_eq!(
+            Layout::new(5, &chars("abcdefghijk"), 11, 8),
+            Layout { end_row: 2, end_col: 0, cursor_row: 2, cursor_col: 0 }
+        );
+        assert_eq!(
+            Layout::new(5, &[], 0, 80),
+            Layout { end_row: 0, end_col: 5, cursor_row: 0, cursor_col: 5 }
+        );
+    }
+
+    #[test]
+    fn layout_uses_display_width() {
+        let chars = |text: &str| text.chars().collect::<Vec<char>>();
+        // Wide characters take two columns; one that would straddle the
+        // row end moves to the next row.
+        assert_eq!(
+            Layout::new(5, &chars("日本"), 1, 8),
+            Layout { end_row: 1, end_col: 2, cursor_row: 1, cursor_col: 0 }
+        );
+        // A combining mark adds no width.
+        assert_eq!(
+            Layout::new(0, &chars("e\u{301}x"), 2, 80),
+            Layout { end_row: 0, end_col: 2, cursor_row: 0, cursor_col: 1 }
+        );
+    }
+
+    #[test]
+    fn cursor_steps_over_grapheme_clusters() {
+        let mut e = editor_with_history(&[]);
+        let k = |code: KeyCode| KeyEvent::new(code, KeyModifiers::NONE);
+        e.buffer = "ae\u{301} 日x".chars().collect();
+        e.cursor = e.buffer.len();
+        e.handle_key(k(KeyCode::Left), "").unwrap();
+        e.handle_key(k(KeyCode::Left), "").unwrap();
+        e.handle_key(k(KeyCode::Left), "").unwrap();
+        assert_eq!(e.cursor, 3);
+        e.handle_key(k(KeyCode::Backspace), "").unwrap();
+        assert_eq!(e.buffer.iter().collect::<String>(), "a 日x");
+        e.cursor = e.buffer.len();
+        e.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL), "").unwrap();
+        assert_eq!(e.buffer.iter().collect::<String>(), "a ");
+    }
+
+    #[test]
+    fn redraw_rewrites_only_the_changed_suffix() {
+        let mut e = editor_with_history(&[]);
+        e.buffer = "echo help".chars().collect();
+        e.cursor = e.buffer.len();
+        e.drawn = Some("echo hello".chars().collect());
+        e.redraw("jsh> ").unwrap();
+        let out = String::from_utf8(std::mem::take(&mut e.out)).unwrap();
+        assert!(out.contains("\x1b[Jp"), "{out:?}");
+        assert!(!out.contains("echo") && !out.contains("jsh> "), "{out:?}");
+
+        // Typing at the end appends without clearing.
+        e.buffer.push('s');
+        e.cursor += 1;
+        e.redraw("jsh> ").unwrap();
+        let out = String::from_utf8(std::mem::take(&mut e.out)).unwrap();
+        assert!(out.ends_with('s') && !out.contains("\x1b[J"), "{out:?}");
+
+        // After Ctrl-L or a resize everything is drawn again.
+        e.drawn = None;
+        e.redraw("jsh> ").unwrap();
+        let out = String::from_utf8(std::mem::take(&mut e.out)).unwrap();
+        assert!(out.contains("jsh> echo helps"), "{out:?}");
+    }
+
+    #[test]
+    fn resizing_redraws_without_climbing_above_the_line() {
+        let mut e = editor_with_history(&[]);
+        e.width = 20;
+        e.buffer = vec!['x'; 30];
+        e.cursor = e.buffer.len();
+        e.redraw("jsh> ").unwrap();
+        assert_eq!(e.cursor_row, 1);
+        e.out.clear();
+
+        // Wider: the line now fits on one row, and the cursor may be on it.
+        e.resize(80, "jsh> ").unwrap();
+        let out = String::from_utf8(std::mem::take(&mut e.out)).unwrap();
+        assert!(!out.contains("\x1b[1A") && out.contains("jsh> xxx"), "{out:?}");
+        assert_eq!(e.cursor_row, 0);
+
+        // Narrower: the cursor stayed on the first row, so nothing moves up.
+        e.resize(10, "jsh> ").unwrap();
+        let out = String::from_utf8(std::mem::take(&mut e.out)).unwrap();
+        assert!(!out.contains('A'), "{out:?}");
+        assert_eq!((e.width, e.cursor_row), (10, 3));
+    }
+
+    #[test]
+    fn prompt_width_skips_escape_sequences() {
+        assert_eq!(display_width("jsh> "), 5);
+        assert_eq!(display_width("\x1b[1;32mök\x1b[0m$ "), 4);
+        assert_eq!(display_width("日本> "), 6);
+    }
+
     #[test]
     fn key_events_edit_buffer_like_terminal() {
         let mut e = editor_with_history(&[]);
@@ -580,15 +1587,82 @@ mod tests {
         assert_eq!(e.cursor, 0);
     }
 
+    #[test]
+    fn tab_completes_unique_candidate_or_common_prefix() {
+        let mut e = editor_with_history(&[]);
+        e.buffer = "git ch".chars().collect();
+        e.cursor = e.buffer.len();
+        let listed = e.apply_completion(Completion { start: 4, candidates: vec!["checkout".into()] });
+        assert_eq!(listed, None);
+        assert_eq!(e.buffer.iter().collect::<String>(), "git checkout ");
+
+        e.buffer = "cd sr".chars().collect();
+        e.cursor = e.buffer.len();
+        e.apply_completion(Completion { start: 3, candidates: vec!["src/".into()] });
+        assert_eq!(e.buffer.iter().collect::<String>(), "cd src/");
+
+        e.buffer = "ls m".chars().collect();
+        e.cursor = e.buffer.len();
+        let candidates = vec!["main.rs".to_string(), "mainly".to_string()];
+        let listed = e.apply_completion(Completion { start: 3, candidates: candidates.clone() });
+        assert_eq!(listed, None);
+        assert_eq!(e.buffer.iter().collect::<String>(), "ls main");
+        // Nothing more in common: the next Tab lists the candidates.
+        e.cursor = e.buffer.len();
+        let listed = e.apply_completion(Completion { start: 3, candidates: candidates.clone() });
+        assert_eq!(listed, Some(candidates));
+    }
+
     #[test]
     fn history_capped_at_max_size() {
         let mut e = editor_with_history(&[]);
-        for i in 0..MAX_HISTORY_SIZE + 5 {
+        for i in 0..DEFAULT_HISTORY_SIZE + 5 {
             // Each entry must be unique to avoid consecutive-duplicate filtering.
             e.add_to_history(&format!("cmd-{i}"));
         }
-        assert_eq!(e.history.len(), MAX_HISTORY_SIZE);
+        assert_eq!(e.history.len(), DEFAULT_HISTORY_SIZE);
         // Oldest entries should have been evicted; newest should still be present.
-        assert_eq!(e.history.last().unwrap(), &format!("cmd-{}", MAX_HISTORY_SIZE + 4));
+        assert_eq!(e.history.last().unwrap(), &format!("cmd-{}", DEFAULT_HISTORY_SIZE + 4));
+    }
+
+    #[test]
+    fn ctrl_z_stashes_the_command_until_after_the_next_one() {
+        let mut e = editor_with_history(&[]);
+        let prompt = "jsh> ";
+        let ctrl_z = KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL);
+        let text = |e: &LineEditor| e.buffer.iter().collect::<String>();
+
+        // Nothing to stash at an empty prompt.
+        let alt_q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::ALT);
+        assert!(matches!(e.handle_key(ctrl_z, prompt).unwrap(), KeyAction::Continue));
+        assert!(matches!(e.handle_key(alt_q, prompt).unwrap(), KeyAction::Continue));
+        e.buffer = "ls".chars().collect();
+        assert!(matches!(e.handle_key(alt_q, prompt).unwrap(), KeyAction::Stash));
+        e.buffer.clear();
+
+        e.set_pending_lines(&["if true; then".to_string()]);
+        e.buffer = "echo x".chars().collect();
+        e.cursor = e.buffer.len();
+        assert!(matches!(e.handle_key(ctrl_z, prompt).unwrap(), KeyAction::Stash));
+        e.stash_line();
+        assert_eq!(text(&e), "");
+
+        // It stays put until another line has been entered...
+        e.reset_state();
+        e.unstash();
+        assert_eq!(text(&e), "");
+        e.stash_due = true;
+
+        // ...then comes back a line at a time.
+        e.reset_state();
+        e.unstash();
+        assert_eq!(text(&e), "if true; then");
+        e.set_pending_lines(&["if true; then".to_string()]);
+        e.reset_state();
+        e.unstash();
+        assert_eq!((text(&e), e.cursor), ("echo x".to_string(), 6));
+        e.reset_state();
+        e.unstash();
+        assert_eq!(text(&e), "");
     }
 }
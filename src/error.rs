@@ -0,0 +1,119 @@
+use std::fmt;
+
+/// Where in the source a syntax error was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// 0-based line within the text that was parsed.
+    pub line: usize,
+    /// 0-based character column within that line.
+    pub column: usize,
+    /// The whole text of the line, for showing the error in context.
+    pub text: String,
+}
+
+impl Span {
+    /// The span of the character at byte `offset` of `source`.
+    pub fn at(source: &str, offset: usize) -> Span {
+        let offset = offset.min(source.len());
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..].find('\n').map_or(source.len(), |i| offset + i);
+        Span {
+            line: source[..line_start].matches('\n').count(),
+            column: source[line_start..offset].chars().count(),
+            text: source[line_start..line_end].to_string(),
+        }
+    }
+}
+
+/// An error from parsing, expanding, or starting a command.
+///
+/// `Display` gives the message the shell prints, already prefixed with
+/// `jsh:`; embedders can match on the kind instead of the text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JshError {
+    /// Input that is not valid shell syntax. `span` points at the offending
+    /// construct when the tokenizer knows where it began.
+    Syntax { message: String, span: Option<Span> },
+    /// A word could not be expanded, like `${name:?unset}`.
+    Expansion(String),
+    /// A redirection whose target is ambiguous, not a file descriptor, or
+    /// a file that cannot be opened.
+    Redirect(String),
+    /// A variable that cannot be assigned, like a readonly one.
+    Assignment(String),
+    /// A command whose streams could not be set up, or that could not be
+    /// started.
+    Spawn(String),
+}
+
+impl JshError {
+    /// A syntax error with no known position.
+    pub fn syntax(message: impl Into<String>) -> JshError {
+        JshError::Syntax {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// A syntax error at byte `offset` of `source`.
+    pub fn syntax_at(message: impl Into<String>, source: &str, offset: usize) -> JshError {
+        JshError::Syntax {
+            message: message.into(),
+            span: Some(Span::at(source, offset)),
+        }
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            JshError::Syntax { span, .. } => span.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The source line followed by a caret under the error position and its
+    /// 1-based column, for printing after the message.
+    pub fn caret(&self) -> Option<String> {
+        let span = self.span()?;
+        Some(format!(
+            "{}\n{}^ column {}",
+            span.text,
+            " ".repeat(span.column),
+            span.column + 1
+        ))
+    }
+}
+
+impl fmt::Display for JshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JshError::Syntax { message, .. }
+            | JshError::Expansion(message)
+            | JshError::Redirect(message)
+            | JshError::Assignment(message)
+            | JshError::Spawn(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for JshError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_locate_lines_and_columns() {
+        let span = Span::at("echo a\necho \"é b\n", 13);
+        assert_eq!(span.line, 1);
+        assert_eq!(span.column, 6);
+        assert_eq!(span.text, "echo \"é b");
+    }
+
+    #[test]
+    fn caret_points_under_the_span() {
+        let err = JshError::syntax_at("jsh: syntax error: unterminated single quote", "echo 'hi", 5);
+        assert_eq!(err.to_string(), "jsh: syntax error: unterminated single quote");
+        assert_eq!(err.caret().as_deref(), Some("echo 'hi\n     ^ column 6"));
+        assert_eq!(JshError::Redirect("jsh: x: ambiguous redirect".into()).caret(), None);
+    }
+}
@@ -1,15 +1,138 @@
 use os_pipe::{PipeReader, PipeWriter, pipe};
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
 use std::process::{Command, Stdio};
 
-use crate::builtins;
-#[cfg(unix)]
+use crate::builtins::{self, BuiltinKind};
+use crate::completion;
+#[cfg(any(unix, windows))]
 use crate::job_control;
-use crate::jobs::JobTable;
+use crate::jobs::{self, JobTable};
 use crate::parser;
-use crate::redirect::{RedirectTarget, Redirection, is_null_device};
+use crate::error::JshError;
+use crate::redirect::{RedirectTarget, Redirection, device_fd, is_null_device};
+use crate::shell::Shell;
+#[cfg(not(unix))]
 use crate::status;
+use crate::timeout::{self, TimeLimit, Watchdog};
+use crate::wslpath::Mounts;
+
+/// Environment variable marking a child jsh as a subshell, the analogue of
+/// bash's `BASH_SUBSHELL`. Its value is the nesting depth.
+pub const SUBSHELL_ENV: &str = "JSH_SUBSHELL";
+
+/// Environment variable carrying the parent's `$0` into a subshell.
+pub const NAME_ENV: &str = "JSH_PARENT_NAME";
+
+/// Build a `Command` that re-runs this jsh binary as a subshell which reads
+/// its script from stdin.
+///
+/// Subshells skip the prompt and history so they can be fed command text on
+/// a pipe (command substitution, whole-chain background jobs) without echoing
+/// prompts into captured output or recording the parent's input twice.
+pub fn subshell_command(shell: &Shell) -> Command {
+    let exe = std::env::current_exe().unwrap_or_else(|_| std::path::PathBuf::from("james-shell"));
+    let mut command = Command::new(exe);
+    in_shell_context(&mut command, shell);
+    command.env(SUBSHELL_ENV, (subshell_depth() + 1).to_string());
+    command
+}
+
+/// Have `process` run in `shell`'s working directory with its exported
+/// variables as the whole environment. The shell keeps both to itself
+/// rather than changing the process's own, which other shells in the same
+/// program may share.
+pub fn in_shell_context(process: &mut Command, shell: &Shell) {
+    process
+        .env_clear()
+        .envs(shell.variables.environment())
+        .current_dir(&shell.cwd);
+}
+
+/// [`subshell_command`] for a child that stands in for `shell` in a command
+/// substitution, a background list, or a builtin pipeline stage. It is told about the shell's
+/// `$0` and jobs, so `jobs` there lists them as in bash's forked subshell.
+pub(crate) fn forked_subshell_command(shell: &Shell) -> Command {
+    let mut command = subshell_command(shell);
+    command.env(NAME_ENV, &shell.name);
+    let jobs = shell.job_table.snapshot();
+    if !jobs.is_empty() {
+        command.env(jobs::JOBS_ENV, jobs);
+    }
+    command
+}
+
+/// [`forked_subshell_command`] for a child fed `prelude`, from
+/// [`Shell::subshell_prelude`], and then its commands on stdin.
+pub(crate) fn stdin_subshell_command(shell: &Shell, prelude: &str) -> Command {
+    let mut command = forked_subshell_command(shell);
+    let state = ParentState {
+        prelude_lines: prelude.matches('\n').count(),
+        status: shell.last_exit_code,
+        line: shell.dynamic.line(),
+        seconds_millis: shell.dynamic.seconds_millis(),
+    };
+    command.env(STATE_ENV, state.to_string());
+    command
+}
+
+/// Environment variable carrying a [`ParentState`] into a subshell fed its
+/// commands on stdin.
+pub const STATE_ENV: &str = "JSH_PARENT_STATE";
+
+/// What a subshell takes over from its parent once the prelude that
+/// recreates the parent's variables and options has run. Passed in
+/// [`STATE_ENV`] rather than as script text, so that replaying it cannot
+/// trip `set -e` or show under `set -x`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParentState {
+    /// How many lines of the child's input the prelude takes up.
+    pub prelude_lines: usize,
+    /// The parent's `$?`, which the first command after the prelude sees.
+    pub status: i32,
+    /// The parent's `$LINENO`, which the first command after the prelude
+    /// takes, so the prelude does not shift the child's line numbers.
+    pub line: usize,
+    /// The parent's `$SECONDS` in milliseconds, which the child counts on
+    /// from rather than from its own start.
+    pub seconds_millis: i64,
+}
+
+impl ParentState {
+    /// Read back what `to_string` wrote.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut fields = text.split(' ');
+        let state = ParentState {
+            prelude_lines: fields.next()?.parse().ok()?,
+            status: fields.next()?.parse().ok()?,
+            line: fields.next()?.parse().ok()?,
+            seconds_millis: fields.next()?.parse().ok()?,
+        };
+        fields.next().is_none().then_some(state)
+    }
+
+    /// The `$LINENO` for line `line` of the child's input, once past the
+    /// prelude.
+    pub fn line_number(&self, line: usize) -> usize {
+        (self.line + line).saturating_sub(self.prelude_lines + 1)
+    }
+}
+
+impl std::fmt::Display for ParentState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.prelude_lines, self.status, self.line, self.seconds_millis)
+    }
+}
+
+/// How many subshells deep this jsh is; 0 for the top-level shell.
+pub fn subshell_depth() -> u32 {
+    std::env::var(SUBSHELL_ENV)
+        .ok()
+        .and_then(|depth| depth.parse::<u32>().ok())
+        .unwrap_or(0)
+}
 
 #[derive(Debug)]
 pub struct PipelineCommand {
@@ -24,16 +147,20 @@ pub enum ExecutionAction {
 }
 
 /// Execute a parsed command with optional redirections.
-/// Builtins are checked first, then external programs.
-/// Builtins do not participate in job-control tracking.
+/// Builtins are checked first, then external programs. A backgrounded
+/// builtin runs in a subshell job, except one that only changes shell state,
+/// which runs in the foreground.
 pub fn execute(
     cmd: &parser::Command,
     redirections: &[Redirection],
     background: bool,
-    job_table: &mut JobTable,
+    shell: &mut Shell,
     command_text: &str,
 ) -> ExecutionAction {
-    if builtins::is_builtin(&cmd.program) {
+    if let Some(kind) = shell.builtins.kind(&cmd.program) {
+        if background && kind != BuiltinKind::Stateful && shell.builtins.in_subshells(&cmd.program) {
+            return shell.spawn_background_subshell(command_text);
+        }
         if background {
             eprintln!(
                 "jsh: builtin '{}' does not support background execution; running in the foreground",
@@ -41,22 +168,25 @@ pub fn execute(
             );
         }
 
-        return run_builtin(cmd, redirections, job_table);
+        return run_builtin(cmd, redirections, shell);
     }
 
-    ExecutionAction::Continue(run_external(
-        cmd,
-        redirections,
-        background,
-        job_table,
-        command_text,
-    ))
+    if shell.options.autocd && shell.interactive && cmd.args.is_empty() && shell.cwd.join(&cmd.program).is_dir() {
+        let cd = parser::Command {
+            program: "cd".to_string(),
+            args: vec!["--".to_string(), cmd.program.clone()],
+            assignments: Vec::new(),
+        };
+        return run_builtin(&cd, redirections, shell);
+    }
+
+    ExecutionAction::Continue(run_external(cmd, redirections, background, shell, command_text, None))
 }
 
 pub fn execute_pipeline(
     commands: Vec<PipelineCommand>,
     background: bool,
-    job_table: &mut JobTable,
+    shell: &mut Shell,
     command_text: &str,
 ) -> ExecutionAction {
     if commands.is_empty() {
@@ -69,7 +199,7 @@ pub fn execute_pipeline(
             &cmd.command,
             &cmd.redirections,
             background,
-            job_table,
+            shell,
             command_text,
         );
     }
@@ -79,45 +209,68 @@ pub fn execute_pipeline(
         return ExecutionAction::Continue(1);
     }
 
-    let mut warned_background_builtin = false;
+    if let Some(cmd) = commands
+        .iter()
+        .find(|cmd| shell.builtins.kind(&cmd.command.program) == Some(BuiltinKind::RunsCommands))
+    {
+        eprintln!("jsh: '{}' is not supported in pipelines", cmd.command.program);
+        return ExecutionAction::Continue(1);
+    }
+
+    // A background pipeline with a builtin stage runs as a whole in a
+    // subshell job, since the builtin cannot run in this process meanwhile.
+    // A registered builtin would be unknown there, so such a pipeline stays
+    // in this process.
+    let programs = || commands.iter().map(|cmd| cmd.command.program.as_str());
+    if background
+        && programs().any(|program| shell.builtins.contains(program))
+        && programs().all(|program| !shell.builtins.contains(program) || shell.builtins.in_subshells(program))
+    {
+        return shell.spawn_background_subshell(command_text);
+    }
 
     // On Unix, the first external child becomes the pipeline's process group
     // leader; subsequent stages join that group. Stored here so the background
     // path can register the correct pgid with the job table for later cleanup.
     // On non-Unix it stays None and the background path falls back to child.id().
-    #[allow(unused_mut)]
+    #[cfg_attr(not(unix), allow(unused_mut))]
     let mut pipeline_pgid: Option<u32> = None;
 
     let mut children: Vec<std::process::Child> = Vec::new();
     // Non-last pure builtins run on threads so the pipe has a reader before
     // they write and cannot block the pipeline.
     // Dropping a JoinHandle detaches the thread (used in background and error paths).
-    let mut builtin_threads: Vec<std::thread::JoinHandle<()>> = Vec::new();
+    let mut builtin_threads: Vec<(usize, std::thread::JoinHandle<i32>)> = Vec::new();
     let mut prev_pipe: Option<PipeReader> = None;
-    let mut last_status = 0;
-    let last_is_external = !builtins::is_builtin(
-        &commands
-            .last()
-            .map(|cmd| cmd.command.program.as_str())
-            .unwrap_or(""),
-    );
+    // Exit status of each stage, in pipeline order.
+    let mut statuses = vec![0; commands.len()];
+    // The stage each entry of `children` runs.
+    let mut child_stages: Vec<usize> = Vec::new();
     let mut last_external_index: Option<usize> = None;
 
     for (idx, segment) in commands.iter().enumerate() {
         let is_last = idx + 1 == commands.len();
-        let is_builtin = builtins::is_builtin(&segment.command.program);
-        if background && is_builtin && !warned_background_builtin {
-            eprintln!(
-                "jsh: builtin '{}' in pipeline does not support background execution; running in the foreground",
-                segment.command.program
-            );
-            warned_background_builtin = true;
-        }
-
-        let stdin_default = prev_pipe
-            .take()
-            .map(InputHandle::Pipe)
-            .unwrap_or(InputHandle::Inherit);
+        let kind = shell.builtins.kind(&segment.command.program);
+        let is_builtin = kind.is_some();
+        // A builtin that changes shell state runs in a subshell, as in bash,
+        // and its changes are lost; it cannot run on a thread here without
+        // changing this shell. With `lastpipe` the last stage runs in the
+        // shell instead. A registered builtin, which a subshell would not
+        // know, gets a throwaway copy of the shell instead.
+        let isolated = is_builtin && kind != Some(BuiltinKind::Pure) && !(is_last && shell.options.lastpipe);
+        let in_subshell = isolated && shell.builtins.in_subshells(&segment.command.program);
+
+        let stdin_default = match prev_pipe.take() {
+            Some(reader) => InputHandle::Pipe(reader),
+            None => match shell.streams.stdin.try_clone() {
+                Ok(stdin) => stdin,
+                Err(msg) => {
+                    eprintln!("{msg}");
+                    wait_children(&mut children);
+                    return ExecutionAction::Continue(1);
+                }
+            },
+        };
 
         let (stdout_default, next_pipe_reader) = if !is_last {
             match pipe() {
@@ -129,16 +282,42 @@ pub fn execute_pipeline(
                 }
             }
         } else {
-            (OutputHandle::Inherit, None)
+            match shell.streams.stdout.try_clone() {
+                Ok(stdout) => (stdout, None),
+                Err(msg) => {
+                    eprintln!("{msg}");
+                    wait_children(&mut children);
+                    return ExecutionAction::Continue(1);
+                }
+            }
+        };
+
+        let stderr_default = match shell.streams.stderr.try_clone() {
+            Ok(stderr) => stderr,
+            Err(msg) => {
+                eprintln!("{msg}");
+                wait_children(&mut children);
+                return ExecutionAction::Continue(1);
+            }
+        };
+
+        let extra_default = match clone_fds(&shell.streams.extra) {
+            Ok(extra) => extra,
+            Err(msg) => {
+                eprintln!("{msg}");
+                wait_children(&mut children);
+                return ExecutionAction::Continue(1);
+            }
         };
 
-        let defaults = RedirectionDefaults {
+        let defaults = StdStreams {
             stdin: stdin_default,
             stdout: stdout_default,
-            stderr: OutputHandle::Inherit,
+            stderr: stderr_default,
+            extra: extra_default,
         };
 
-        let resolved = match resolve_redirections(&segment.redirections, defaults) {
+        let resolved = match resolve_redirections(&segment.redirections, defaults, &shell.cwd) {
             Ok(resolved) => resolved,
             Err(msg) => {
                 eprintln!("{msg}");
@@ -151,10 +330,13 @@ pub fn execute_pipeline(
             stdin,
             stdout,
             stderr,
-            stdout_redirected,
+            extra,
+            stdout_abandoned,
         } = resolved;
 
-        if !is_last && stdout_redirected {
+        // The next stage reads what this one writes to the pipe on any
+        // descriptor, so `2>&1 > /dev/null | next` pipes only stderr.
+        if !is_last && stdout_abandoned {
             eprintln!(
                 "jsh: cannot redirect stdout of non-terminal pipeline command '{}'",
                 segment.command.program
@@ -163,16 +345,14 @@ pub fn execute_pipeline(
             return ExecutionAction::Continue(1);
         }
 
-        if is_builtin && !is_last && !is_pipeline_compatible_builtin(&segment.command.program) {
+        if in_subshell && shell.options.pipewarn {
             eprintln!(
-                "jsh: builtin '{}' is not supported in non-terminal pipeline positions",
+                "jsh: {}: runs in a subshell in a pipeline; its changes to the shell are discarded",
                 segment.command.program
             );
-            wait_children(&mut children);
-            return ExecutionAction::Continue(1);
         }
 
-        if is_builtin {
+        if is_builtin && !in_subshell {
             let mut stdin_reader = match stdin.into_reader() {
                 Ok(reader) => reader,
                 Err(msg) => {
@@ -181,7 +361,7 @@ pub fn execute_pipeline(
                     return ExecutionAction::Continue(1);
                 }
             };
-            let mut stdout_writer = match stdout.into_writer("stdout") {
+            let mut stdout_writer = match stdout.into_writer() {
                 Ok(writer) => writer,
                 Err(msg) => {
                     eprintln!("{msg}");
@@ -189,7 +369,7 @@ pub fn execute_pipeline(
                     return ExecutionAction::Continue(1);
                 }
             };
-            let mut stderr_writer = match stderr.into_writer("stderr") {
+            let mut stderr_writer = match stderr.into_writer() {
                 Ok(writer) => writer,
                 Err(msg) => {
                     eprintln!("{msg}");
@@ -201,84 +381,67 @@ pub fn execute_pipeline(
             if is_last {
                 // Last command: run synchronously so we can use the real job_table
                 // and capture the pipeline's final exit status.
-                let status = match builtins::execute(
-                    &segment.command.program,
-                    &segment.command.args,
+                let mut detached = isolated.then(|| shell.detached());
+                let status = match execute_builtin(
+                    &segment.command,
                     stdin_reader.as_mut(),
                     stdout_writer.as_mut(),
                     stderr_writer.as_mut(),
-                    job_table,
+                    detached.as_mut().unwrap_or(shell),
                 ) {
                     builtins::BuiltinAction::Continue(code)
                     | builtins::BuiltinAction::Exit(code) => code,
                 };
                 let _ = stdout_writer.flush();
                 let _ = stderr_writer.flush();
-                last_status = status;
+                statuses[idx] = status;
             } else {
             // Pure builtins are executed in parallel with downstream stages.
-            // Side-effecting builtins are rejected in this position by the
-            // compatibility check above to avoid mutating shared shell state.
+            // Side-effecting builtins run in a subshell in this position
+            // instead, so they cannot mutate shared shell state.
                 let program = segment.command.program.clone();
                 let args = segment.command.args.clone();
+                let mut detached = shell.detached();
                 let handle = std::thread::spawn(move || {
-                    let mut local_jt = crate::jobs::JobTable::new();
-                    let _ = builtins::execute(
+                    let action = builtins::execute(
                         &program,
                         &args,
                         stdin_reader.as_mut(),
                         stdout_writer.as_mut(),
                         stderr_writer.as_mut(),
-                        &mut local_jt,
+                        &mut detached,
                     );
                     let _ = stdout_writer.flush();
                     let _ = stderr_writer.flush();
+                    match action {
+                        builtins::BuiltinAction::Continue(code)
+                        | builtins::BuiltinAction::Exit(code) => code,
+                    }
                 });
-                builtin_threads.push(handle);
+                builtin_threads.push((idx, handle));
             }
         } else {
-            let mut process = Command::new(&segment.command.program);
-            process.args(&segment.command.args);
-
-            // ── Unix: reset shell-inherited signal handlers and join pipeline group ──
-            #[cfg(unix)]
-            {
-                use std::os::unix::process::CommandExt;
-                // Snapshot the current pgid *before* spawning so the closure
-                // captures the right leader pid (None = first stage, creates new group;
-                // Some(pgid) = subsequent stages, join that group).
-                let captured_pgid = pipeline_pgid;
-                unsafe {
-                    process.pre_exec(move || {
-                        // Reset signals the shell ignores back to SIG_DFL.
-                        // SIG_IGN survives exec(), so without this pipeline stages
-                        // would ignore Ctrl-Z, Ctrl-\, and SIGPIPE just like the shell.
-                        let signals = [
-                            libc::SIGINT,
-                            libc::SIGTSTP,
-                            libc::SIGQUIT,
-                            libc::SIGPIPE,
-                        ];
-                        for &sig in &signals {
-                            if libc::signal(sig, libc::SIG_DFL) == libc::SIG_ERR {
-                                return Err(std::io::Error::last_os_error());
-                            }
-                        }
-                        // First stage (captured_pgid == None): setpgid(0,0) creates a
-                        // new group with this child as leader.
-                        // Later stages: setpgid(0, leader_pid) joins that group.
-                        let target_pgid =
-                            captured_pgid.map(|p| p as libc::pid_t).unwrap_or(0);
-                        if libc::setpgid(0, target_pgid) != 0 {
-                            return Err(std::io::Error::last_os_error());
-                        }
-                        Ok(())
-                    });
-                }
+            let mut process = if in_subshell {
+                builtin_subshell_command(&segment.command, shell)
+            } else {
+                let fds = StartingFds { stdin: &stdin, stdout: &stdout, stderr: &stderr, extra: &extra };
+                external_command(&segment.command, shell, fds)
+            };
+            process.envs(segment.command.assignments.iter().map(|(name, value)| (name, value)));
+            if let Err(msg) = pass_extra_fds(&mut process, extra) {
+                eprintln!("{msg}");
+                wait_children(&mut children);
+                return ExecutionAction::Continue(1);
             }
 
-            let (stdin_stdio, here_string) = match stdin.into_stdio() {
-                Ok(result) => result,
+            // ── Join the pipeline's process group ──
+            // The first stage (pipeline_pgid == None) leads a new group; later
+            // stages join it. On Windows every stage leads its own.
+            #[cfg(any(unix, windows))]
+            join_process_group(&mut process, pipeline_pgid.unwrap_or(0));
+
+            let stdin_stdio = match stdin.into_stdio() {
+                Ok(stdio) => stdio,
                 Err(msg) => {
                     eprintln!("{msg}");
                     wait_children(&mut children);
@@ -307,17 +470,17 @@ pub fn execute_pipeline(
                 .stdout(stdout_stdio)
                 .stderr(stderr_stdio);
 
-            let mut child = match process.spawn() {
+            let child = match process.spawn() {
                 Ok(child) => child,
                 Err(e) => {
-                    let code = command_error(&segment.command.program, &e);
+                    let code = command_error(&segment.command.program, &e, shell);
                     wait_children(&mut children);
                     return ExecutionAction::Continue(code);
                 }
             };
 
             // ── Unix: close the setpgid race (parent side) ──
-            // Both the child's pre_exec and this parent call race to setpgid.
+            // Both the child's spawn and this parent call race to setpgid.
             // Whichever wins, the result is correct; the loser gets EACCES/ESRCH,
             // both of which are safe to ignore.
             #[cfg(unix)]
@@ -328,36 +491,31 @@ pub fn execute_pipeline(
                 pipeline_pgid = Some(child.id());
             }
 
-            if let Some(text) = here_string {
-                if let Some(mut stdin) = child.stdin.take() {
-                    let _ = writeln!(stdin, "{text}");
-                }
-            }
-
             children.push(child);
+            child_stages.push(idx);
             last_external_index = Some(children.len() - 1);
         }
 
         prev_pipe = next_pipe_reader;
     }
 
-    // ── Background pipeline: detach builtin threads, hand off last child ──
+    // ── Background pipeline: hand off last child ──
+    // Pipelines with builtins went to a subshell above, so every stage here
+    // is an external child.
     if background {
-        // Dropping JoinHandles detaches the threads; they write their data and
-        // close the pipe writers naturally, giving downstream processes EOF.
-        drop(builtin_threads);
         if let Some(last_idx) = last_external_index {
             // `swap_remove` moves the element at `last_idx` out.  Because we're
             // about to drop everything else anyway, reordering is irrelevant.
-            let last_child = children.swap_remove(last_idx);
-            drop(children); // drop remaining (may produce short-lived zombies until SIGCHLD in module 9)
+            // `remove` keeps the earlier stages in pipeline order; the job
+            // reaps them along with the last one.
+            let last_child = children.remove(last_idx);
             // Use the pipeline's true process group id so that kill(-pgid, …) in
             // shutdown cleanup reaches *all* stages, not just the last child.
             let pgid = pipeline_pgid.unwrap_or_else(|| last_child.id());
-            let (id, pid) = job_table.add_with_pgid(last_child, command_text.to_string(), pgid);
+            let (id, pid) = shell.job_table.add_with_pgid(last_child, command_text.to_string(), pgid);
+            shell.job_table.set_upstream(id, children, shell.options.pipefail);
             println!("[{}] {}", id, pid);
         }
-        // No external children (all builtins) — nothing to track.
         return ExecutionAction::Continue(0);
     }
 
@@ -367,15 +525,14 @@ pub fn execute_pipeline(
     // writers are closed (signalling EOF to downstream readers) by the time
     // we call child.wait().  In practice the children are already reading
     // concurrently, so threads finish promptly once the pipe buffer drains.
-    for handle in builtin_threads {
-        let _ = handle.join();
+    for (idx, handle) in builtin_threads {
+        if let Ok(code) = handle.join() {
+            statuses[idx] = code;
+        }
     }
 
     #[cfg(unix)]
-    {
-        if children.is_empty() {
-            return ExecutionAction::Continue(last_status);
-        }
+    if !children.is_empty() {
 
         let fg_pgid = pipeline_pgid.unwrap_or_else(|| children[0].id());
         let terminal_guard = match job_control::ForegroundTerminalGuard::new(fg_pgid as libc::pid_t) {
@@ -387,9 +544,9 @@ pub fn execute_pipeline(
         };
 
         let child_pids: Vec<u32> = children.iter().map(|child| child.id()).collect();
-        let last_external_pid = last_external_index.and_then(|idx| children.get(idx).map(|child| child.id()));
 
-        let wait_result = match wait_for_pipeline_process_group(&child_pids, fg_pgid as libc::pid_t, last_external_pid) {
+        let foreground = job_control::ForegroundJob::new(fg_pgid as libc::pid_t);
+        let wait_result = match wait_for_pipeline_process_group(&child_pids, fg_pgid as libc::pid_t) {
             Ok(outcome) => outcome,
             Err(e) => {
                 eprintln!("jsh: failed waiting for pipeline jobs: {e}");
@@ -398,7 +555,12 @@ pub fn execute_pipeline(
             }
         };
 
+        drop(foreground);
         drop(terminal_guard);
+        job_control::settle_terminal_modes(match &wait_result {
+            PipelineWaitOutcome::Stopped => false,
+            PipelineWaitOutcome::Exited(codes) => codes.iter().all(|&(_, code)| code == 0),
+        });
 
         match wait_result {
             PipelineWaitOutcome::Stopped => {
@@ -408,37 +570,59 @@ pub fn execute_pipeline(
                 } else {
                     children.swap_remove(0)
                 };
-                let (id, _) = job_table.add_stopped_with_pgid(stopped_child, command_text.to_string(), fg_pgid);
-                println!("[{}]  Stopped  {}", id, command_text);
+                let (id, _) = shell.job_table.add_stopped_with_pgid(stopped_child, command_text.to_string(), fg_pgid);
+                println!("{}", jobs::status_line(id, shell.job_table.marker(id), "Stopped", command_text));
                 return ExecutionAction::Continue(0);
             }
-            PipelineWaitOutcome::Exited(code) => {
-                if last_is_external {
-                    return ExecutionAction::Continue(code);
+            PipelineWaitOutcome::Exited(codes) => {
+                for (pid, code) in codes {
+                    if let Some(child) = child_pids.iter().position(|&child_pid| child_pid == pid) {
+                        statuses[child_stages[child]] = code;
+                    }
                 }
             }
         }
     }
 
+    #[cfg(windows)]
+    let foreground = job_control::ForegroundJob::new(children.iter().map(std::process::Child::id).collect());
     #[cfg(not(unix))]
-    for (idx, mut child) in children.into_iter().enumerate() {
-        match child.wait() {
-            Ok(status) => {
-                if last_is_external && Some(idx) == last_external_index {
-                    last_status = status::exit_code(status);
-                }
-            }
+    for (child, mut process) in children.into_iter().enumerate() {
+        match process.wait() {
+            Ok(status) => statuses[child_stages[child]] = status::exit_code(status),
             Err(_) => {
                 return ExecutionAction::Continue(1);
             }
         }
     }
 
-    ExecutionAction::Continue(last_status)
+    #[cfg(windows)]
+    drop(foreground);
+
+    ExecutionAction::Continue(pipeline_status(&statuses, shell.options.pipefail))
+}
+
+/// The exit status of a pipeline: that of the last stage, or with
+/// `pipefail` that of the last stage to fail.
+fn pipeline_status(statuses: &[i32], pipefail: bool) -> i32 {
+    let last = statuses.last().copied().unwrap_or(0);
+    if pipefail {
+        statuses.iter().rev().copied().find(|&code| code != 0).unwrap_or(0)
+    } else {
+        last
+    }
 }
 
-fn is_pipeline_compatible_builtin(name: &str) -> bool {
-    matches!(name, "echo" | "pwd" | "type" | "help")
+/// A `jsh -c` subshell that runs one already-expanded builtin command with a
+/// copy of this shell's variables and options.
+fn builtin_subshell_command(command: &parser::Command, shell: &Shell) -> Command {
+    let words: Vec<String> = std::iter::once(&command.program)
+        .chain(&command.args)
+        .map(|word| crate::shell::quote_word(word))
+        .collect();
+    let mut process = forked_subshell_command(shell);
+    process.arg("-c").arg(format!("{}{}", shell.subshell_prelude(), words.join(" ")));
+    process
 }
 
 // ── Redirection resolution ──
@@ -448,12 +632,16 @@ enum InputHandle {
     Inherit,
     Pipe(PipeReader),
     File(File),
+    /// Exact stdin contents for a here-string or heredoc.
     HereString(String),
 }
 
 #[derive(Debug)]
 enum OutputHandle {
-    Inherit,
+    /// The shell's own stdout, wherever it is being written to.
+    Stdout,
+    /// The shell's own stderr.
+    Stderr,
     Null,
     File(File),
     Pipe(PipeWriter),
@@ -463,49 +651,159 @@ struct ResolvedRedirections {
     stdin: InputHandle,
     stdout: OutputHandle,
     stderr: OutputHandle,
-    stdout_redirected: bool,
+    /// Descriptors above 2 still open after the redirections.
+    extra: BTreeMap<i32, FdHandle>,
+    /// No descriptor still refers to the default stdout, as after `> file`
+    /// but not after `2>&1 > file`.
+    stdout_abandoned: bool,
 }
 
-struct RedirectionDefaults {
+/// Where a command's standard streams point when it has no redirection of
+/// its own.
+///
+/// Normally this is the shell's own stdio. A compound command with
+/// redirections, like `{ a; b; } > file`, runs its body with these replaced,
+/// so every command inside shares the redirection without a subshell.
+#[derive(Debug)]
+pub struct StdStreams {
     stdin: InputHandle,
     stdout: OutputHandle,
     stderr: OutputHandle,
+    /// Descriptors above 2 the shell holds open, from `exec 3> file` or a
+    /// `coproc`, for redirections like `>&3` to duplicate.
+    extra: BTreeMap<i32, FdHandle>,
+}
+
+impl StdStreams {
+    /// The shell's own stdin, stdout, and stderr.
+    pub fn inherit() -> Self {
+        StdStreams {
+            stdin: InputHandle::Inherit,
+            stdout: OutputHandle::Stdout,
+            stderr: OutputHandle::Stderr,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// Open `redirections` on top of these streams, leaving `self` untouched.
+    /// Relative paths are taken from `cwd`.
+    pub fn redirected(&self, redirections: &[Redirection], cwd: &Path) -> Result<StdStreams, JshError> {
+        let resolved = resolve_redirections(redirections, self.try_clone()?, cwd)?;
+        Ok(StdStreams {
+            stdin: resolved.stdin,
+            stdout: resolved.stdout,
+            stderr: resolved.stderr,
+            extra: resolved.extra,
+        })
+    }
+
+    /// A reader for the current stdin, for input the shell itself reads,
+    /// like `source /dev/stdin`.
+    pub(crate) fn stdin_reader(&self) -> Result<Box<dyn Read + Send>, JshError> {
+        self.stdin.try_clone()?.into_reader()
+    }
+
+    /// A writer for the current stderr, for messages the shell itself
+    /// prints on behalf of a command.
+    pub(crate) fn stderr_writer(&self) -> Result<Box<dyn Write + Send>, JshError> {
+        self.stderr.try_clone()?.into_writer()
+    }
+
+    /// A writer for output descriptor `fd` as commands see it: 1 and 2 are
+    /// the current stdout and stderr, higher ones those held by `exec N>`.
+    pub(crate) fn output_writer(&self, fd: i32) -> Result<Box<dyn Write + Send>, JshError> {
+        let handle = match fd {
+            1 => self.stdout.try_clone()?,
+            2 => self.stderr.try_clone()?,
+            _ => match self.extra.get(&fd) {
+                Some(FdHandle::Output(handle)) => handle.try_clone()?,
+                _ => return Err(JshError::Redirect(format!("jsh: {fd}: bad file descriptor"))),
+            },
+        };
+        handle.into_writer()
+    }
+
+    /// These streams with output that would reach the terminal sent to
+    /// `log` instead, for a background job under `set -o bglog`.
+    pub(crate) fn logged_to(&self, log: &File) -> Result<StdStreams, JshError> {
+        let to_log = |handle: &OutputHandle| match handle {
+            OutputHandle::Stdout | OutputHandle::Stderr => log
+                .try_clone()
+                .map(OutputHandle::File)
+                .map_err(|e| JshError::Spawn(format!("jsh: failed to duplicate file: {e}"))),
+            other => other.try_clone(),
+        };
+        Ok(StdStreams {
+            stdin: self.stdin.try_clone()?,
+            stdout: to_log(&self.stdout)?,
+            stderr: to_log(&self.stderr)?,
+            extra: clone_fds(&self.extra)?,
+        })
+    }
+
+    /// The current stdout and stderr as `Stdio`, for a child that should
+    /// write where this shell's commands do.
+    pub(crate) fn output_stdio(&self) -> Result<(Stdio, Stdio), JshError> {
+        Ok((
+            self.stdout.try_clone()?.into_stdio()?,
+            self.stderr.try_clone()?.into_stdio()?,
+        ))
+    }
+
+    /// Keep a coprocess's pipe ends open as the two highest free descriptors
+    /// below 10, so `>&N` can reach them and `N>&-` can close them. Returns
+    /// `(read_fd, write_fd)`.
+    fn hold_coproc_fds(&mut self, reader: PipeReader, writer: PipeWriter) -> Result<(i32, i32), JshError> {
+        let mut free = (3..10).rev().filter(|fd| !self.extra.contains_key(fd));
+        let (Some(read_fd), Some(write_fd)) = (free.next(), free.next()) else {
+            return Err(JshError::Spawn("jsh: coproc: no free file descriptors".to_string()));
+        };
+        self.extra.insert(read_fd, FdHandle::Input(InputHandle::Pipe(reader)));
+        self.extra.insert(write_fd, FdHandle::Output(OutputHandle::Pipe(writer)));
+        Ok((read_fd, write_fd))
+    }
+
+    fn try_clone(&self) -> Result<StdStreams, JshError> {
+        Ok(StdStreams {
+            stdin: self.stdin.try_clone()?,
+            stdout: self.stdout.try_clone()?,
+            stderr: self.stderr.try_clone()?,
+            extra: clone_fds(&self.extra)?,
+        })
+    }
 }
 
 impl OutputHandle {
-    fn try_clone(&self) -> Result<OutputHandle, String> {
+    fn try_clone(&self) -> Result<OutputHandle, JshError> {
         match self {
-            OutputHandle::Inherit => Ok(OutputHandle::Inherit),
+            OutputHandle::Stdout => Ok(OutputHandle::Stdout),
+            OutputHandle::Stderr => Ok(OutputHandle::Stderr),
             OutputHandle::Null => Ok(OutputHandle::Null),
             OutputHandle::File(file) => file
                 .try_clone()
                 .map(OutputHandle::File)
-                .map_err(|e| format!("jsh: failed to duplicate file: {e}")),
+                .map_err(|e| JshError::Spawn(format!("jsh: failed to duplicate file: {e}"))),
             OutputHandle::Pipe(writer) => writer
                 .try_clone()
                 .map(OutputHandle::Pipe)
-                .map_err(|e| format!("jsh: failed to duplicate pipe: {e}")),
+                .map_err(|e| JshError::Spawn(format!("jsh: failed to duplicate pipe: {e}"))),
         }
     }
 
-    fn into_stdio(self) -> Result<Stdio, String> {
+    fn into_stdio(self) -> Result<Stdio, JshError> {
         Ok(match self {
-            OutputHandle::Inherit => Stdio::inherit(),
+            OutputHandle::Stdout => Stdio::from(io::stdout()),
+            OutputHandle::Stderr => Stdio::from(io::stderr()),
             OutputHandle::Null => Stdio::null(),
             OutputHandle::File(file) => Stdio::from(file),
             OutputHandle::Pipe(writer) => Stdio::from(writer),
         })
     }
 
-    fn into_writer(self, label: &str) -> Result<Box<dyn Write + Send>, String> {
+    fn into_writer(self) -> Result<Box<dyn Write + Send>, JshError> {
         match self {
-            OutputHandle::Inherit => {
-                if label == "stderr" {
-                    Ok(Box::new(io::stderr()))
-                } else {
-                    Ok(Box::new(io::stdout()))
-                }
-            }
+            OutputHandle::Stdout => Ok(Box::new(io::stdout())),
+            OutputHandle::Stderr => Ok(Box::new(io::stderr())),
             OutputHandle::Null => Ok(Box::new(io::sink())),
             OutputHandle::File(file) => Ok(Box::new(file)),
             OutputHandle::Pipe(writer) => Ok(Box::new(writer)),
@@ -514,120 +812,222 @@ impl OutputHandle {
 }
 
 impl InputHandle {
-    fn into_stdio(self) -> Result<(Stdio, Option<String>), String> {
+    fn try_clone(&self) -> Result<InputHandle, JshError> {
+        match self {
+            InputHandle::Inherit => Ok(InputHandle::Inherit),
+            InputHandle::Pipe(reader) => reader
+                .try_clone()
+                .map(InputHandle::Pipe)
+                .map_err(|e| JshError::Spawn(format!("jsh: failed to duplicate pipe: {e}"))),
+            InputHandle::File(file) => file
+                .try_clone()
+                .map(InputHandle::File)
+                .map_err(|e| JshError::Spawn(format!("jsh: failed to duplicate file: {e}"))),
+            InputHandle::HereString(text) => Ok(InputHandle::HereString(text.clone())),
+        }
+    }
+
+    fn into_stdio(self) -> Result<Stdio, JshError> {
         Ok(match self {
-            InputHandle::Inherit => (Stdio::inherit(), None),
-            InputHandle::Pipe(reader) => (Stdio::from(reader), None),
-            InputHandle::File(file) => (Stdio::from(file), None),
-            InputHandle::HereString(text) => (Stdio::piped(), Some(text)),
+            InputHandle::Inherit => Stdio::inherit(),
+            InputHandle::Pipe(reader) => Stdio::from(reader),
+            InputHandle::File(file) => Stdio::from(file),
+            InputHandle::HereString(text) => Stdio::from(here_string_pipe(text)?),
         })
     }
 
-    fn into_reader(self) -> Result<Box<dyn Read + Send>, String> {
+    fn into_reader(self) -> Result<Box<dyn Read + Send>, JshError> {
         match self {
             InputHandle::Inherit => Ok(Box::new(io::stdin())),
             InputHandle::Pipe(reader) => Ok(Box::new(reader)),
             InputHandle::File(file) => Ok(Box::new(file)),
-            InputHandle::HereString(text) => Ok(Box::new(Cursor::new(format!("{text}\n")))),
+            InputHandle::HereString(text) => Ok(Box::new(Cursor::new(text))),
         }
     }
 }
 
-fn resolve_redirections(
-    redirections: &[Redirection],
-    defaults: RedirectionDefaults,
-) -> Result<ResolvedRedirections, String> {
-    let mut stdin = defaults.stdin;
-    let mut stdout = defaults.stdout;
-    let mut stderr = defaults.stderr;
-    let mut stdout_redirected = false;
-
-    for redir in redirections {
-        match (&redir.target, redir.fd) {
-            // ── fd duplicated to itself — no-op ──
-            (RedirectTarget::Fd(target), fd) if *target == fd => {}
-
-            // ── stdout > file (truncate) ──
-            (RedirectTarget::File(path), 1) => {
-                stdout = open_output_file(path, false)?;
-                stdout_redirected = true;
-            }
-
-            // ── stdout >> file (append) ──
-            (RedirectTarget::FileAppend(path), 1) => {
-                stdout = open_output_file(path, true)?;
-                stdout_redirected = true;
-            }
+/// A pipe whose read end yields `text`, for a child's stdin.
+///
+/// The text is written from its own thread, like a builtin in a pipeline,
+/// so a here-string larger than the pipe buffer cannot deadlock against a
+/// child that is blocked writing its own output. The thread ends when the
+/// text is written or the child closes its stdin.
+fn here_string_pipe(text: String) -> Result<PipeReader, JshError> {
+    let (reader, mut writer) = pipe().map_err(|e| JshError::Spawn(format!("jsh: failed to create pipe: {e}")))?;
+    std::thread::spawn(move || {
+        let _ = writer.write_all(text.as_bytes());
+    });
+    Ok(reader)
+}
 
-            // ── stdin < file ──
-            (RedirectTarget::FileRead(path), 0) => {
-                stdin = open_input_file(path)?;
-            }
+/// An open file descriptor while redirections are being applied.
+#[derive(Debug)]
+enum FdHandle {
+    Input(InputHandle),
+    Output(OutputHandle),
+}
 
-            // ── stderr 2> file (truncate) ──
-            (RedirectTarget::File(path), 2) => {
-                stderr = open_output_file(path, false)?;
-            }
+impl FdHandle {
+    fn try_clone(&self) -> Result<FdHandle, JshError> {
+        Ok(match self {
+            FdHandle::Input(handle) => FdHandle::Input(handle.try_clone()?),
+            FdHandle::Output(handle) => FdHandle::Output(handle.try_clone()?),
+        })
+    }
 
-            // ── stderr 2>> file (append) ──
-            (RedirectTarget::FileAppend(path), 2) => {
-                stderr = open_output_file(path, true)?;
-            }
+    /// The descriptor itself, for a child to inherit.
+    #[cfg(unix)]
+    fn into_owned_fd(self) -> Result<std::os::fd::OwnedFd, JshError> {
+        use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 
-            // ── 2>&1: stderr → wherever stdout currently points ──
-            (RedirectTarget::Fd(1), 2) => {
-                stderr = stdout.try_clone()?;
-            }
+        let duplicate = |fd: BorrowedFd| {
+            fd.try_clone_to_owned()
+                .map_err(|e| JshError::Spawn(format!("jsh: failed to duplicate file descriptor: {e}")))
+        };
+        match self {
+            FdHandle::Input(InputHandle::Inherit) => duplicate(io::stdin().as_fd()),
+            FdHandle::Input(InputHandle::Pipe(reader)) => Ok(reader.into()),
+            FdHandle::Input(InputHandle::File(file)) => Ok(file.into()),
+            FdHandle::Input(InputHandle::HereString(text)) => Ok(here_string_pipe(text)?.into()),
+            FdHandle::Output(OutputHandle::Stdout) => duplicate(io::stdout().as_fd()),
+            FdHandle::Output(OutputHandle::Stderr) => duplicate(io::stderr().as_fd()),
+            FdHandle::Output(OutputHandle::Null) => OpenOptions::new()
+                .write(true)
+                .open("/dev/null")
+                .map(OwnedFd::from)
+                .map_err(|e| JshError::Spawn(format!("jsh: /dev/null: {e}"))),
+            FdHandle::Output(OutputHandle::File(file)) => Ok(file.into()),
+            FdHandle::Output(OutputHandle::Pipe(writer)) => Ok(writer.into()),
+        }
+    }
+}
 
-            // ── 1>&2: stdout → wherever stderr currently points ──
-            (RedirectTarget::Fd(2), 1) => {
-                stdout = stderr.try_clone()?;
-                stdout_redirected = true;
-            }
+fn clone_fds(fds: &BTreeMap<i32, FdHandle>) -> Result<BTreeMap<i32, FdHandle>, JshError> {
+    fds.iter().map(|(&fd, handle)| Ok((fd, handle.try_clone()?))).collect()
+}
 
-            // ── Here string: <<< text ──
-            (RedirectTarget::HereString(text), 0) => {
-                stdin = InputHandle::HereString(text.clone());
-            }
+/// Apply `redirections` left to right to a table of file descriptors that
+/// starts out as `defaults`, as a POSIX shell does before running a command.
+/// Relative paths are taken from `cwd`, the shell's working directory.
+///
+/// `N>&M` copies whatever `M` refers to at that point, so `> f 2>&1` sends
+/// both streams to `f` while `2>&1 > f` leaves stderr on the old stdout.
+/// Descriptors above 2 are passed on to external commands by
+/// [`pass_extra_fds`]. Closing 0, 1, or 2 leaves it empty or discarding.
+fn resolve_redirections(
+    redirections: &[Redirection],
+    defaults: StdStreams,
+    cwd: &Path,
+) -> Result<ResolvedRedirections, JshError> {
+    let mut fds = defaults.extra;
+    fds.insert(0, FdHandle::Input(defaults.stdin));
+    fds.insert(1, FdHandle::Output(defaults.stdout));
+    fds.insert(2, FdHandle::Output(defaults.stderr));
+    // The descriptors that are copies of the default stdout.
+    let mut on_stdout = std::collections::BTreeSet::from([1]);
 
-            _ => {
-                return Err(format!(
-                    "jsh: unsupported redirection: fd {} -> {:?}",
-                    redir.fd, redir.target
-                ));
+    for redir in redirections {
+        // `/dev/stdout`, `/dev/fd/N` and friends duplicate the current descriptor.
+        let mapped = match &redir.target {
+            RedirectTarget::File(path)
+            | RedirectTarget::FileAppend(path)
+            | RedirectTarget::FileNoClobber(path)
+            | RedirectTarget::FileRead(path) => device_fd(path).map(RedirectTarget::Fd),
+            _ => None,
+        };
+        let handle = match mapped.as_ref().unwrap_or(&redir.target) {
+            // ── fd duplicated to itself — no-op ──
+            RedirectTarget::Fd(source) if *source == redir.fd => continue,
+            RedirectTarget::Close if redir.fd == 0 => FdHandle::Input(InputHandle::HereString(String::new())),
+            RedirectTarget::Close if redir.fd <= 2 => FdHandle::Output(OutputHandle::Null),
+            RedirectTarget::Close => {
+                fds.remove(&redir.fd);
+                on_stdout.remove(&redir.fd);
+                continue;
             }
-        }
+            // ── N>&M: N → wherever M currently points ──
+            RedirectTarget::Fd(source) => fds
+                .get(source)
+                .ok_or_else(|| JshError::Redirect(format!("jsh: {source}: bad file descriptor")))?
+                .try_clone()?,
+            RedirectTarget::File(path) => FdHandle::Output(open_output_file(path, cwd, false)?),
+            RedirectTarget::FileNoClobber(path) => FdHandle::Output(open_new_output_file(path, cwd)?),
+            RedirectTarget::FileAppend(path) => FdHandle::Output(open_output_file(path, cwd, true)?),
+            RedirectTarget::FileRead(path) => FdHandle::Input(open_input_file(path, cwd)?),
+            RedirectTarget::HereString(text) => FdHandle::Input(InputHandle::HereString(format!("{text}\n"))),
+            RedirectTarget::HereDoc(body) => FdHandle::Input(InputHandle::HereString(body.clone())),
+        };
+        match mapped.as_ref().unwrap_or(&redir.target) {
+            RedirectTarget::Fd(source) if on_stdout.contains(source) => on_stdout.insert(redir.fd),
+            _ => on_stdout.remove(&redir.fd),
+        };
+        fds.insert(redir.fd, handle);
     }
 
+    let stdin = match fds.remove(&0) {
+        Some(FdHandle::Input(handle)) => handle,
+        _ => return Err(JshError::Redirect("jsh: 0: not open for reading".to_string())),
+    };
+    let mut output = |fd: i32| match fds.remove(&fd) {
+        Some(FdHandle::Output(handle)) => Ok(handle),
+        _ => Err(JshError::Redirect(format!("jsh: {fd}: not open for writing"))),
+    };
+    let stdout = output(1)?;
+    let stderr = output(2)?;
     Ok(ResolvedRedirections {
         stdin,
         stdout,
         stderr,
-        stdout_redirected,
+        extra: fds,
+        stdout_abandoned: on_stdout.is_empty(),
     })
 }
 
-fn open_output_file(path: &str, append: bool) -> Result<OutputHandle, String> {
+fn open_output_file(path: &str, cwd: &Path, append: bool) -> Result<OutputHandle, JshError> {
     if is_null_device(path) {
         return Ok(OutputHandle::Null);
     }
 
+    let full = cwd.join(path);
     let file = if append {
-        OpenOptions::new().create(true).append(true).open(path)
+        OpenOptions::new().create(true).append(true).open(full)
     } else {
         OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(path)
+            .open(full)
     };
 
     file.map(OutputHandle::File)
-        .map_err(|e| format!("jsh: {path}: {e}"))
+        .map_err(|e| JshError::Redirect(format!("jsh: {path}: {e}")))
 }
 
-fn open_input_file(path: &str) -> Result<InputHandle, String> {
-    let file = File::open(path).map_err(|e| format!("jsh: {path}: {e}"))?;
+/// Open `path` for `>` under `set -o noclobber`: the file is created with
+/// `O_EXCL`, so an existing regular file is never truncated. Other existing
+/// files, such as `/dev/tty` or a FIFO, are written to as usual, as in bash.
+fn open_new_output_file(path: &str, cwd: &Path) -> Result<OutputHandle, JshError> {
+    if is_null_device(path) {
+        return Ok(OutputHandle::Null);
+    }
+
+    let full = cwd.join(path);
+    let file = match OpenOptions::new().write(true).create_new(true).open(&full) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            if std::fs::metadata(&full).is_ok_and(|meta| meta.is_file()) {
+                return Err(JshError::Redirect(format!("jsh: {path}: cannot overwrite existing file")));
+            }
+            OpenOptions::new().write(true).open(&full)
+        }
+        result => result,
+    };
+
+    file.map(OutputHandle::File)
+        .map_err(|e| JshError::Redirect(format!("jsh: {path}: {e}")))
+}
+
+fn open_input_file(path: &str, cwd: &Path) -> Result<InputHandle, JshError> {
+    let file = File::open(cwd.join(path)).map_err(|e| JshError::Redirect(format!("jsh: {path}: {e}")))?;
     Ok(InputHandle::File(file))
 }
 
@@ -643,15 +1043,39 @@ fn wait_children(children: &mut Vec<std::process::Child>) {
 fn run_builtin(
     cmd: &parser::Command,
     redirections: &[Redirection],
-    job_table: &mut JobTable,
+    shell: &mut Shell,
 ) -> ExecutionAction {
-    let defaults = RedirectionDefaults {
-        stdin: InputHandle::Inherit,
-        stdout: OutputHandle::Inherit,
-        stderr: OutputHandle::Inherit,
-    };
+    if cmd.program == "exec" {
+        return run_exec(cmd, redirections, shell);
+    }
+    if cmd.program == "coproc" {
+        return run_coproc(cmd, redirections, shell);
+    }
+    if cmd.program == "timeout" {
+        return run_timeout(cmd, redirections, shell);
+    }
 
-    let resolved = match resolve_redirections(redirections, defaults) {
+    // Commands run by `source` write to the shell's default streams, so its
+    // redirections replace those for the duration, as for a brace group.
+    if shell.builtins.kind(&cmd.program) == Some(BuiltinKind::RunsCommands) && !redirections.is_empty() {
+        let streams = match shell.streams.redirected(redirections, &shell.cwd) {
+            Ok(streams) => streams,
+            Err(msg) => {
+                eprintln!("{msg}");
+                return ExecutionAction::Continue(1);
+            }
+        };
+        let saved = std::mem::replace(&mut shell.streams, streams);
+        let action = run_builtin(cmd, &[], shell);
+        shell.streams = saved;
+        return action;
+    }
+
+    let resolved = match shell
+        .streams
+        .try_clone()
+        .and_then(|defaults| resolve_redirections(redirections, defaults, &shell.cwd))
+    {
         Ok(resolved) => resolved,
         Err(msg) => {
             eprintln!("{msg}");
@@ -674,7 +1098,7 @@ fn run_builtin(
         }
     };
 
-    let mut stdout_writer = match stdout.into_writer("stdout") {
+    let mut stdout_writer = match stdout.into_writer() {
         Ok(writer) => writer,
         Err(msg) => {
             eprintln!("{msg}");
@@ -682,7 +1106,7 @@ fn run_builtin(
         }
     };
 
-    let mut stderr_writer = match stderr.into_writer("stderr") {
+    let mut stderr_writer = match stderr.into_writer() {
         Ok(writer) => writer,
         Err(msg) => {
             eprintln!("{msg}");
@@ -690,13 +1114,12 @@ fn run_builtin(
         }
     };
 
-    let action = match builtins::execute(
-        &cmd.program,
-        &cmd.args,
+    let action = match execute_builtin(
+        cmd,
         stdin_reader.as_mut(),
         stdout_writer.as_mut(),
         stderr_writer.as_mut(),
-        job_table,
+        shell,
     ) {
         builtins::BuiltinAction::Continue(code) => ExecutionAction::Continue(code),
         builtins::BuiltinAction::Exit(code) => ExecutionAction::Exit(code),
@@ -708,24 +1131,176 @@ fn run_builtin(
     action
 }
 
+/// `exec [command [args...]]`. With no command the redirections are applied
+/// to the shell itself and stay in effect for the rest of the session, as in
+/// `exec > log 2>&1` or `exec 3> file`. With one, the command runs in the
+/// shell's place and the shell exits with its status.
+fn run_exec(cmd: &parser::Command, redirections: &[Redirection], shell: &mut Shell) -> ExecutionAction {
+    let Some((program, args)) = cmd.args.split_first() else {
+        return match shell.streams.redirected(redirections, &shell.cwd) {
+            Ok(streams) => {
+                shell.streams = streams;
+                ExecutionAction::Continue(0)
+            }
+            Err(msg) => {
+                eprintln!("{msg}");
+                ExecutionAction::Continue(1)
+            }
+        };
+    };
+
+    let command = parser::Command {
+        program: program.clone(),
+        args: args.to_vec(),
+        assignments: cmd.assignments.clone(),
+    };
+    let command_text = cmd.args.join(" ");
+    match execute(&command, redirections, false, shell, &command_text) {
+        ExecutionAction::Continue(code) => ExecutionAction::Exit(code),
+        action => action,
+    }
+}
+
+/// `coproc command [args...]`: start the command in the background with its
+/// stdin and stdout connected to pipes the shell keeps open. `${COPROC[0]}`
+/// is the descriptor to read its output from, `${COPROC[1]}` the one to
+/// write its input to, and `$COPROC_PID` its process ID.
+fn run_coproc(cmd: &parser::Command, redirections: &[Redirection], shell: &mut Shell) -> ExecutionAction {
+    let Some((program, args)) = cmd.args.split_first() else {
+        eprintln!("jsh: coproc: usage: coproc command [args...]");
+        return ExecutionAction::Continue(2);
+    };
+
+    let pipes = pipe().and_then(|to_coproc| Ok((to_coproc, pipe()?)));
+    let ((coproc_in, shell_out), (shell_in, coproc_out)) = match pipes {
+        Ok(pipes) => pipes,
+        Err(e) => {
+            eprintln!("jsh: coproc: failed to create pipe: {e}");
+            return ExecutionAction::Continue(1);
+        }
+    };
+    let streams = shell.streams.try_clone().map(|streams| StdStreams {
+        stdin: InputHandle::Pipe(coproc_in),
+        stdout: OutputHandle::Pipe(coproc_out),
+        ..streams
+    });
+    let streams = match streams {
+        Ok(streams) => streams,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExecutionAction::Continue(1);
+        }
+    };
+
+    let command = parser::Command {
+        program: program.clone(),
+        args: args.to_vec(),
+        assignments: cmd.assignments.clone(),
+    };
+    let job_id = shell.job_table.next_id();
+    // The child's ends are dropped with `streams` once it has started, so
+    // it sees EOF when the shell closes its write end.
+    let saved = std::mem::replace(&mut shell.streams, streams);
+    let action = execute(&command, redirections, true, shell, &format!("coproc {}", cmd.args.join(" ")));
+    shell.streams = saved;
+
+    let Some(pid) = shell.job_table.get(job_id).map(|job| job.pid) else {
+        return action;
+    };
+    let (read_fd, write_fd) = match shell.streams.hold_coproc_fds(shell_in, shell_out) {
+        Ok(fds) => fds,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExecutionAction::Continue(1);
+        }
+    };
+    shell.variables.unset("COPROC");
+    let assigned = shell.variables.make_assoc("COPROC").and_then(|()| {
+        shell.variables.assign_element("COPROC", "0", &read_fd.to_string())?;
+        shell.variables.assign_element("COPROC", "1", &write_fd.to_string())
+    });
+    if let Err(msg) = assigned {
+        eprintln!("{msg}");
+        return ExecutionAction::Continue(1);
+    }
+    shell.variables.set("COPROC_PID", &pid.to_string());
+    action
+}
+
+/// `timeout [-s sig] [-k grace] duration command [args...]`: run command as
+/// an external program in the foreground, signalling it if it outlives the
+/// duration. A builtin with no program of the same name cannot be run.
+fn run_timeout(cmd: &parser::Command, redirections: &[Redirection], shell: &mut Shell) -> ExecutionAction {
+    let (limit, words) = match timeout::parse(&cmd.args) {
+        Ok(parsed) => parsed,
+        Err(msg) => {
+            eprintln!("jsh: {msg}");
+            return ExecutionAction::Continue(timeout::FAILED);
+        }
+    };
+    let program = words[0].as_str();
+    let search_path = shell.variables.get("PATH").unwrap_or_default().to_string();
+    if shell.builtins.contains(program)
+        && !builtins::names_a_path(program)
+        && shell.command_hash.lookup(program, &search_path).is_none()
+    {
+        eprintln!("jsh: timeout: {program}: a shell builtin cannot be run with a time limit");
+        return ExecutionAction::Continue(127);
+    }
+    let command = parser::Command {
+        program: words[0].clone(),
+        args: words[1..].to_vec(),
+        assignments: cmd.assignments.clone(),
+    };
+    ExecutionAction::Continue(run_external(&command, redirections, false, shell, &words.join(" "), Some(&limit)))
+}
+
+/// Run a builtin in this shell with its `NAME=value` prefix assignments
+/// exported only for the duration of the call, so that commands it runs
+/// (`FOO=1 source file`) see them.
+fn execute_builtin(
+    cmd: &parser::Command,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+    shell: &mut Shell,
+) -> builtins::BuiltinAction {
+    let saved: Vec<_> = cmd
+        .assignments
+        .iter()
+        .map(|(name, value)| {
+            let previous = shell.variables.lookup(name).cloned();
+            shell.variables.export(name, Some(value));
+            (name, previous)
+        })
+        .collect();
+
+    let action = builtins::execute(&cmd.program, &cmd.args, stdin, stdout, stderr, shell);
+
+    for (name, previous) in saved.into_iter().rev() {
+        shell.variables.restore(name, previous);
+    }
+    action
+}
+
 // ── External command execution with redirections ──
 
 /// Spawn an external program with I/O redirections applied.
 /// If `background` is true, the child is handed off to the job table immediately.
+/// A foreground child is signalled if it outlives `limit`.
 fn run_external(
     cmd: &parser::Command,
     redirections: &[Redirection],
     background: bool,
-    job_table: &mut JobTable,
+    shell: &mut Shell,
     command_text: &str,
+    limit: Option<&TimeLimit>,
 ) -> i32 {
-    let defaults = RedirectionDefaults {
-        stdin: InputHandle::Inherit,
-        stdout: OutputHandle::Inherit,
-        stderr: OutputHandle::Inherit,
-    };
-
-    let resolved = match resolve_redirections(redirections, defaults) {
+    let resolved = match shell
+        .streams
+        .try_clone()
+        .and_then(|defaults| resolve_redirections(redirections, defaults, &shell.cwd))
+    {
         Ok(resolved) => resolved,
         Err(msg) => {
             eprintln!("{msg}");
@@ -737,44 +1312,24 @@ fn run_external(
         stdin,
         stdout,
         stderr,
+        extra,
         ..
     } = resolved;
 
-    let mut process = Command::new(&cmd.program);
-    process.args(&cmd.args);
-    #[cfg(unix)]
-    {
-        use std::os::unix::process::CommandExt;
-        // Put the spawned process in its own process group before exec so
-        // terminal job-control signals (Ctrl-C / Ctrl-Z) can target it safely.
-        unsafe {
-            process.pre_exec(|| {
-                // Reset signals the shell ignores back to default before exec.
-                // SIG_IGN survives exec(), so without this reset children would
-                // also ignore Ctrl-Z, Ctrl-\, and SIGPIPE.
-                // SIGINT is included for clarity: exec() clears custom handlers
-                // automatically, but being explicit is better than implicit.
-                let signals = [
-                    libc::SIGINT,
-                    libc::SIGTSTP,
-                    libc::SIGQUIT,
-                    libc::SIGPIPE,
-                ];
-                for &sig in &signals {
-                    if libc::signal(sig, libc::SIG_DFL) == libc::SIG_ERR {
-                        return Err(std::io::Error::last_os_error());
-                    }
-                }
-                if libc::setpgid(0, 0) != 0 {
-                    return Err(std::io::Error::last_os_error());
-                }
-                Ok(())
-            });
-        }
+    let fds = StartingFds { stdin: &stdin, stdout: &stdout, stderr: &stderr, extra: &extra };
+    let mut process = external_command(cmd, shell, fds);
+    process.envs(cmd.assignments.iter().map(|(name, value)| (name, value)));
+    if let Err(msg) = pass_extra_fds(&mut process, extra) {
+        eprintln!("{msg}");
+        return 1;
     }
+    // Put the spawned process in its own process group before exec so
+    // terminal job-control signals (Ctrl-C / Ctrl-Z) can target it safely.
+    #[cfg(any(unix, windows))]
+    join_process_group(&mut process, 0);
 
-    let (stdin_stdio, here_string) = match stdin.into_stdio() {
-        Ok(result) => result,
+    let stdin_stdio = match stdin.into_stdio() {
+        Ok(stdio) => stdio,
         Err(msg) => {
             eprintln!("{msg}");
             return 1;
@@ -802,27 +1357,129 @@ fn run_external(
         .stdout(stdout_stdio)
         .stderr(stderr_stdio);
 
-    let mut child = match process.spawn() {
+    let child = match process.spawn() {
         Ok(child) => child,
-        Err(e) => return command_error(&cmd.program, &e),
+        Err(e) => return command_error(&cmd.program, &e, shell),
     };
 
-    if let Some(text) = here_string {
-        if let Some(mut stdin) = child.stdin.take() {
-            let _ = writeln!(stdin, "{text}");
-        }
-    }
-
     let pgid = child_process_group(&child, &cmd.program);
 
     // ── Background: hand off to job table ──
     if background {
-        let (id, pid) = job_table.add_with_pgid(child, command_text.to_string(), pgid);
+        let (id, pid) = shell.job_table.add_with_pgid(child, command_text.to_string(), pgid);
         println!("[{}] {}", id, pid);
         return 0;
     }
 
-    run_foreground(child, &cmd.program, pgid, job_table, command_text)
+    let watchdog = limit.map(|limit| limit.watch(child.id(), pgid));
+    let code = run_foreground(child, &cmd.program, pgid, &mut shell.job_table, command_text);
+    match watchdog.and_then(Watchdog::finish) {
+        Some(signal) => timeout::status(signal),
+        None => code,
+    }
+}
+
+/// Give `process` the descriptors above 2 in `extra` under the same numbers,
+/// so `cmd 3> file` or an earlier `exec 4< file` reaches the command as in
+/// a POSIX shell.
+///
+/// Each is first copied above every number to be filled, so that no
+/// `dup2` in the child overwrites one not yet placed. Every number to be
+/// filled is also kept open in the shell until the command has started:
+/// the standard library reports a failed exec through a pipe it opens
+/// while spawning, and that pipe must not land on a number the child is
+/// about to overwrite.
+#[cfg(unix)]
+fn pass_extra_fds(process: &mut Command, extra: BTreeMap<i32, FdHandle>) -> Result<(), JshError> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::process::CommandExt;
+
+    let Some(&highest) = extra.keys().next_back() else {
+        return Ok(());
+    };
+    let raise = |fd: &OwnedFd, above: i32, target: i32| {
+        // SAFETY: `fd` is open; the copy is owned by the result alone.
+        let raised = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, above) };
+        if raised < 0 {
+            return Err(JshError::Spawn(format!("jsh: {target}: {}", io::Error::last_os_error())));
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(raised) })
+    };
+    let fds = extra
+        .into_iter()
+        .map(|(target, handle)| Ok((target, raise(&handle.into_owned_fd()?, highest + 1, target)?)))
+        .collect::<Result<Vec<_>, JshError>>()?;
+    // A copy that lands on `target` itself holds the number; one that lands
+    // higher means something else already does, and is closed at once.
+    let mut reserved = Vec::new();
+    for (target, fd) in &fds {
+        let copy = raise(fd, *target, *target)?;
+        if copy.as_raw_fd() == *target {
+            reserved.push(copy);
+        }
+    }
+    // SAFETY: only async-signal-safe calls run between fork and exec.
+    unsafe {
+        process.pre_exec(move || {
+            // Owned by the hook so they stay open until `process` is dropped.
+            let _ = &reserved;
+            for (target, fd) in &fds {
+                if libc::dup2(fd.as_raw_fd(), *target) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+/// Windows has no numbered descriptors to hand on, so commands there see
+/// only their standard streams.
+#[cfg(not(unix))]
+fn pass_extra_fds(_process: &mut Command, _extra: BTreeMap<i32, FdHandle>) -> Result<(), JshError> {
+    Ok(())
+}
+
+/// Have `process` start in process group `pgid`, or lead a new one if it
+/// is 0, with the default action for the signals the shell survives.
+///
+/// The process group is left to the standard library, which then spawns
+/// with posix_spawn and `POSIX_SPAWN_SETPGROUP` instead of fork+exec. The
+/// shell catches rather than ignores those signals, so exec resets them,
+/// and std asks posix_spawn to reset SIGPIPE. Only after `trap ''` has made
+/// the shell ignore one of them is a `pre_exec` hook needed to reset it,
+/// which makes std fall back to fork+exec.
+#[cfg(unix)]
+fn join_process_group(process: &mut Command, pgid: u32) {
+    use std::os::unix::process::CommandExt;
+
+    process.process_group(pgid as i32);
+    if !job_control::CHILD_DEFAULT_SIGNALS.into_iter().any(job_control::is_ignored) {
+        return;
+    }
+    // SAFETY: only async-signal-safe calls run between fork and exec.
+    unsafe {
+        process.pre_exec(|| {
+            for signal in job_control::CHILD_DEFAULT_SIGNALS {
+                if libc::signal(signal, libc::SIG_DFL) == libc::SIG_ERR {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Windows has no process groups to join, so each command leads a console
+/// process group of its own, out of reach of a Ctrl-C at the console; see
+/// [`job_control::interrupt_foreground`].
+#[cfg(windows)]
+fn join_process_group(process: &mut Command, _pgid: u32) {
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP;
+
+    process.creation_flags(CREATE_NEW_PROCESS_GROUP);
 }
 
 fn child_process_group(
@@ -837,13 +1494,13 @@ fn child_process_group(
             eprintln!("jsh: {cmd_name}: failed to set process group: {e}");
         }
 
-        return match job_control::process_group_id(pid) {
+        match job_control::process_group_id(pid) {
             Ok(pgid) => pgid as u32,
             Err(e) => {
                 eprintln!("jsh: {cmd_name}: failed to read process group: {e}");
                 child.id()
             }
-        };
+        }
     }
 
     #[cfg(not(unix))]
@@ -860,7 +1517,7 @@ fn child_process_group(
 ///
 /// On Windows (and other non-Unix targets) we simply call `child.wait()`.
 fn run_foreground(
-    mut child: std::process::Child,
+    #[cfg_attr(unix, allow(unused_mut))] mut child: std::process::Child,
     cmd_name: &str,
     #[allow(unused_variables)] pgid: u32,
     // These are consumed only in the #[cfg(unix)] path; suppress the
@@ -879,6 +1536,7 @@ fn run_foreground(
             }
         };
 
+        let foreground = job_control::ForegroundJob::new(pgid as libc::pid_t);
         let wait_outcome = match job_control::wait_for_pid(pid) {
             Ok(outcome) => outcome,
             Err(e) => {
@@ -887,12 +1545,14 @@ fn run_foreground(
             }
         };
 
+        drop(foreground);
         drop(terminal_guard);
+        job_control::settle_terminal_modes(matches!(wait_outcome, job_control::WaitOutcome::Exited(0)));
 
         if let job_control::WaitOutcome::Stopped = wait_outcome {
             // Child was stopped by Ctrl-Z (SIGTSTP). Move it to the job table.
             let (id, _) = job_table.add_stopped_with_pgid(child, command_text.to_string(), pgid);
-            println!("[{}]  Stopped  {}", id, command_text);
+            println!("{}", jobs::status_line(id, job_table.marker(id), "Stopped", command_text));
             return 0;
         }
 
@@ -905,6 +1565,8 @@ fn run_foreground(
 
     #[cfg(not(unix))]
     {
+        #[cfg(windows)]
+        let _foreground = job_control::ForegroundJob::new(vec![child.id()]);
         match child.wait() {
             Ok(status) => status::exit_code(status),
             Err(e) => {
@@ -915,10 +1577,152 @@ fn run_foreground(
     }
 }
 
+/// A `Command` for `program` in `shell`'s context, run from where the hash
+/// table says it is so `$PATH` is only searched the first time, or from the
+/// shell's working directory for a relative path like `./configure`. It
+/// still sees its own name as `argv[0]`, and under `shopt -s wslpath` its
+/// path arguments are translated if it is on the other side of WSL or MSYS.
+/// On Windows, `/dev/stdin` and the like among them are replaced by names
+/// for what `fds` has open, as [`device_argument`] describes.
+fn external_command(cmd: &parser::Command, shell: &mut Shell, fds: StartingFds) -> Command {
+    let program = cmd.program.as_str();
+    let search_path = shell.variables.get("PATH").unwrap_or_default().to_string();
+    let path = match builtins::names_a_path(program) {
+        true => Some(shell.cwd.join(program)),
+        false => shell.command_hash.lookup(program, &search_path),
+    };
+    let mounts = path_translation(shell);
+    let translated = mounts.as_ref().and_then(|mounts| {
+        let target = path.as_deref().unwrap_or(Path::new(program));
+        mounts.translate_args(target, &cmd.args)
+    });
+    let args = translated.as_deref().unwrap_or(&cmd.args);
+    #[cfg(windows)]
+    let devices = translated.is_none().then(|| args.iter().map(|arg| device_argument(arg, &fds)).collect::<Vec<_>>());
+    #[cfg(windows)]
+    let args = devices.as_deref().unwrap_or(args);
+    #[cfg(not(windows))]
+    let _ = fds;
+    let mut command = Command::new(path.as_deref().unwrap_or(Path::new(program)));
+    in_shell_context(&mut command, shell);
+    #[cfg(unix)]
+    if path.is_some() {
+        use std::os::unix::process::CommandExt;
+        command.arg0(program);
+    }
+    command.args(args);
+    command
+}
+
+/// The descriptors a command starts with, which `/dev/stdin` and
+/// `/dev/fd/N` among its arguments refer to.
+#[cfg_attr(not(windows), allow(dead_code))]
+struct StartingFds<'a> {
+    stdin: &'a InputHandle,
+    stdout: &'a OutputHandle,
+    stderr: &'a OutputHandle,
+    extra: &'a BTreeMap<i32, FdHandle>,
+}
+
+/// `arg`, or if it is `/dev/stdin`, `/dev/stdout`, `/dev/stderr` or
+/// `/dev/fd/N`, a name Windows can open for what that descriptor is in
+/// `fds`: `CONIN$` or `CONOUT$` for the console, `NUL`, or the path of a
+/// file. A descriptor on a pipe has no such name, and `arg` is kept.
+#[cfg(windows)]
+fn device_argument(arg: &str, fds: &StartingFds) -> String {
+    let name = match device_fd(arg) {
+        Some(0) => fds.stdin.device_name(),
+        Some(1) => fds.stdout.device_name(),
+        Some(2) => fds.stderr.device_name(),
+        Some(fd) => match fds.extra.get(&fd) {
+            Some(FdHandle::Input(handle)) => handle.device_name(),
+            Some(FdHandle::Output(handle)) => handle.device_name(),
+            None => None,
+        },
+        None => None,
+    };
+    name.unwrap_or_else(|| arg.to_string())
+}
+
+#[cfg(windows)]
+impl InputHandle {
+    fn device_name(&self) -> Option<String> {
+        use std::os::windows::io::AsRawHandle;
+        match self {
+            InputHandle::Inherit => handle_name(io::stdin().as_raw_handle(), "CONIN$"),
+            InputHandle::File(file) => handle_name(file.as_raw_handle(), "CONIN$"),
+            InputHandle::Pipe(_) | InputHandle::HereString(_) => None,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl OutputHandle {
+    fn device_name(&self) -> Option<String> {
+        use std::os::windows::io::AsRawHandle;
+        match self {
+            OutputHandle::Stdout => handle_name(io::stdout().as_raw_handle(), "CONOUT$"),
+            OutputHandle::Stderr => handle_name(io::stderr().as_raw_handle(), "CONOUT$"),
+            OutputHandle::Null => Some("NUL".to_string()),
+            OutputHandle::File(file) => handle_name(file.as_raw_handle(), "CONOUT$"),
+            OutputHandle::Pipe(_) => None,
+        }
+    }
+}
+
+/// A name to open `handle` again by: `console` for a console, `NUL` for
+/// another character device, and the path of a file on disk.
+#[cfg(windows)]
+fn handle_name(handle: std::os::windows::io::RawHandle, console: &str) -> Option<String> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_NAME_NORMALIZED, FILE_TYPE_CHAR, FILE_TYPE_DISK, GetFileType, GetFinalPathNameByHandleW,
+    };
+    use windows_sys::Win32::System::Console::GetConsoleMode;
+
+    // SAFETY: `handle` stays open for these calls, and every buffer passed
+    // is as long as the length given with it.
+    unsafe {
+        match GetFileType(handle) {
+            FILE_TYPE_CHAR => {
+                let mut mode = 0;
+                Some(if GetConsoleMode(handle, &mut mode) != 0 { console } else { "NUL" }.to_string())
+            }
+            FILE_TYPE_DISK => {
+                let mut buffer = vec![0u16; 260];
+                loop {
+                    let len = GetFinalPathNameByHandleW(handle, buffer.as_mut_ptr(), buffer.len() as u32, FILE_NAME_NORMALIZED)
+                        as usize;
+                    match len {
+                        0 => return None,
+                        len if len < buffer.len() => {
+                            let path = std::path::PathBuf::from(OsString::from_wide(&buffer[..len]));
+                            let path = builtins::physical_path(&path).unwrap_or(path);
+                            return Some(path.to_string_lossy().into_owned());
+                        }
+                        len => buffer.resize(len, 0),
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Under `shopt -s wslpath`, the mounts to translate path arguments
+/// across for a program on the other side of WSL or MSYS.
+fn path_translation(shell: &Shell) -> Option<Mounts> {
+    shell.options.wslpath.then(|| Mounts::from_variables(&shell.variables))
+}
+
 /// Map a spawn/exec error to the appropriate exit code.
-fn command_error(program: &str, e: &std::io::Error) -> i32 {
+fn command_error(program: &str, e: &std::io::Error, shell: &Shell) -> i32 {
     if e.kind() == std::io::ErrorKind::NotFound {
         eprintln!("jsh: command not found: {program}");
+        if let Some(name) = completion::suggest_command(program, shell.variables.get("PATH").unwrap_or_default()) {
+            eprintln!("jsh: did you mean '{name}'?");
+        }
         127
     } else {
         eprintln!("jsh: {program}: {e}");
@@ -928,7 +1732,8 @@ fn command_error(program: &str, e: &std::io::Error) -> i32 {
 
 #[cfg(unix)]
 enum PipelineWaitOutcome {
-    Exited(i32),
+    /// Every child exited, with these `(pid, status)` pairs.
+    Exited(Vec<(u32, i32)>),
     Stopped,
 }
 
@@ -936,17 +1741,16 @@ enum PipelineWaitOutcome {
 fn wait_for_pipeline_process_group(
     child_pids: &[u32],
     pgid: libc::pid_t,
-    last_external_pid: Option<u32>,
 ) -> io::Result<PipelineWaitOutcome> {
     if child_pids.is_empty() {
-        return Ok(PipelineWaitOutcome::Exited(0));
+        return Ok(PipelineWaitOutcome::Exited(Vec::new()));
     }
 
     use std::collections::HashSet;
 
     let mut remaining: HashSet<libc::pid_t> =
         child_pids.iter().map(|pid| *pid as libc::pid_t).collect();
-    let mut last_exit_code: Option<i32> = None;
+    let mut codes = Vec::new();
 
     while !remaining.is_empty() {
         let mut raw_status: libc::c_int = 0;
@@ -960,7 +1764,7 @@ fn wait_for_pipeline_process_group(
             return Err(err);
         }
 
-        if unsafe { libc::WIFSTOPPED(raw_status) } {
+        if libc::WIFSTOPPED(raw_status) {
             return Ok(PipelineWaitOutcome::Stopped);
         }
 
@@ -968,18 +1772,16 @@ fn wait_for_pipeline_process_group(
             continue;
         }
 
-        let code = if unsafe { libc::WIFEXITED(raw_status) } {
-            unsafe { libc::WEXITSTATUS(raw_status) as i32 }
-        } else if unsafe { libc::WIFSIGNALED(raw_status) } {
-            128 + unsafe { libc::WTERMSIG(raw_status) }
+        let code = if libc::WIFEXITED(raw_status) {
+            libc::WEXITSTATUS(raw_status)
+        } else if libc::WIFSIGNALED(raw_status) {
+            128 + libc::WTERMSIG(raw_status)
         } else {
             1
         };
 
-        if Some(waited as u32) == last_external_pid {
-            last_exit_code = Some(code);
-        }
+        codes.push((waited as u32, code));
     }
 
-    Ok(PipelineWaitOutcome::Exited(last_exit_code.unwrap_or(0)))
+    Ok(PipelineWaitOutcome::Exited(codes))
 }
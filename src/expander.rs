@@ -1,47 +1,72 @@
-use crate::parser::{Word, WordSegment};
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::error::JshError;
+use crate::executor;
+use crate::options::ShellOptions;
+use crate::parser::{self, Word, WordSegment};
+use crate::shell::Shell;
+use crate::traps;
+use crate::variables;
 
 /// Expand a list of parsed words into final argument strings.
 /// Applies tilde, variable, and glob expansion according to quote context.
-pub fn expand_words(words: &[Word], last_exit_code: i32) -> Vec<String> {
+///
+/// Fails when an expansion reports an error, such as `${VAR:?message}`.
+pub fn expand_words<'a>(words: impl IntoIterator<Item = &'a Word>, shell: &mut Shell) -> Result<Vec<String>, JshError> {
     let mut result = Vec::new();
     for word in words {
-        result.extend(expand_word(word, last_exit_code));
+        result.extend(expand_word(word, shell)?);
     }
-    result
+    Ok(result)
+}
+
+/// Expand the value of a `NAME=value` assignment into a single string.
+/// Tilde and variable expansion apply, but not word splitting or globbing.
+pub fn expand_assignment_value(value: &[WordSegment], shell: &mut Shell) -> Result<String, JshError> {
+    value
+        .iter()
+        .map(|segment| match segment {
+            WordSegment::SingleQuoted(text) => Ok(text.to_string()),
+            WordSegment::DoubleQuoted(text) => Ok(expand_variable_fields(text, shell, true)?.join(" ")),
+            WordSegment::Unquoted(text) => expand_variables(&expand_tilde(text, shell), shell),
+        })
+        .collect()
 }
 
 /// Expand a single word (which may have mixed quoting) into one or more strings.
-fn expand_word(segments: &[WordSegment], last_exit_code: i32) -> Vec<String> {
+fn expand_word(segments: &[WordSegment], shell: &mut Shell) -> Result<Vec<String>, JshError> {
     // Track whether the accumulated text contains glob characters that originated
     // from unquoted segments. Mixed-quote globs should not expand.
     let mut partials: Vec<(String, bool)> = vec![(String::new(), false)];
 
     for segment in segments {
         let replacements: Vec<(String, bool)> = match segment {
-            WordSegment::SingleQuoted(text) => vec![(text.clone(), false)],
+            WordSegment::SingleQuoted(text) => vec![(text.to_string(), false)],
             WordSegment::DoubleQuoted(text) => {
-                vec![(expand_variables(text, last_exit_code), false)]
+                let fields = expand_variable_fields(text, shell, true)?;
+                // `"$@"` with no parameters removes the word, unless other
+                // parts of the word supply text.
+                if fields.is_empty() && segments.len() > 1 {
+                    vec![(String::new(), false)]
+                } else {
+                    fields.into_iter().map(|field| (field, false)).collect()
+                }
             }
             WordSegment::Unquoted(text) => {
-                let expanded = expand_variables(&expand_tilde(text), last_exit_code);
-                let split_fields = if has_unquoted_expansion(text) {
-                    let split: Vec<String> = expanded.split_whitespace().map(str::to_string).collect();
-                    if split.is_empty() {
-                        vec![String::new()]
-                    } else {
-                        split
-                    }
+                let fields = expand_variable_fields(&expand_tilde(text, shell), shell, false)?;
+                if fields.is_empty() && segments.len() > 1 {
+                    vec![(String::new(), false)]
                 } else {
-                    vec![expanded]
-                };
-
-                split_fields
-                    .into_iter()
-                    .map(|field| {
-                        let has_glob = contains_glob_chars(&field);
-                        (field, has_glob)
-                    })
-                    .collect()
+                    fields
+                        .into_iter()
+                        .map(|field| {
+                            let has_glob = contains_glob_chars(&field);
+                            (field, has_glob)
+                        })
+                        .collect()
+                }
             }
         };
 
@@ -58,58 +83,25 @@ fn expand_word(segments: &[WordSegment], last_exit_code: i32) -> Vec<String> {
         partials = next;
     }
 
-    partials
-        .into_iter()
-        .flat_map(|(text, can_glob)| {
-            if can_glob && contains_glob_chars(&text) {
-                expand_globs(&text).into_iter().collect::<Vec<_>>()
-            } else {
-                vec![text]
-            }
-        })
-        .collect()
-}
-
-fn has_unquoted_expansion(text: &str) -> bool {
-    let mut chars = text.chars().peekable();
-
-    while let Some(ch) = chars.next() {
-        if ch != '$' {
-            continue;
-        }
-
-        match chars.peek() {
-            None => continue,
-            Some('?') | Some('$') | Some('0') => return true,
-            Some(&'{') => {
-                chars.next();
-                let mut has_end = false;
-                for c in chars.by_ref() {
-                    if c == '}' {
-                        has_end = true;
-                        break;
-                    }
-                }
-                if has_end {
-                    return true;
-                }
-            }
-            Some(c) if c.is_ascii_alphabetic() || *c == '_' => return true,
-            _ => {}
+    let mut result = Vec::new();
+    for (text, can_glob) in partials {
+        if can_glob && contains_glob_chars(&text) {
+            result.extend(expand_globs(&text, &shell.options, &shell.cwd)?);
+        } else {
+            result.push(text);
         }
     }
-
-    false
+    Ok(result)
 }
 
 // ── Tilde Expansion ──
 
-fn expand_tilde(token: &str) -> String {
+fn expand_tilde(token: &str, shell: &Shell) -> String {
     if !token.starts_with('~') {
         return token.to_string();
     }
 
-    let home = get_home_dir();
+    let home = get_home_dir(shell);
 
     if token == "~" {
         return home;
@@ -123,62 +115,205 @@ fn expand_tilde(token: &str) -> String {
     token.to_string()
 }
 
-fn get_home_dir() -> String {
-    std::env::var("HOME")
-        .or_else(|_| std::env::var("USERPROFILE"))
-        .unwrap_or_else(|_| "~".to_string())
+fn get_home_dir(shell: &Shell) -> String {
+    shell
+        .variables
+        .get("HOME")
+        .or_else(|| shell.variables.get("USERPROFILE"))
+        .unwrap_or("~")
+        .to_string()
 }
 
 // ── Variable Expansion ──
 
-fn expand_variables(input: &str, last_exit_code: i32) -> String {
-    let mut result = String::new();
-    let mut chars = input.chars().peekable();
+/// Expand variables in unquoted text, joining any fields with spaces.
+/// Expand variables in unquoted text that is not split into fields, such as
+/// an assignment value: `y=$x` keeps the whitespace in `$x`.
+fn expand_variables(input: &str, shell: &mut Shell) -> Result<String, JshError> {
+    Ok(expand_into_fields(input, shell, Fields::unsplit())?.join(" "))
+}
+
+/// The fields produced by expanding one segment of a word.
+///
+/// In unquoted text, the results of expansions are split on whitespace into
+/// separate fields, while literal text is appended as it is. Inside double
+/// quotes nothing is split, and only `$@` starts a new field.
+struct Fields {
+    fields: Vec<String>,
+    current: String,
+    split: bool,
+    /// The text is double-quoted, so single quotes inside `${x:-'y'}` are
+    /// ordinary characters.
+    quoted: bool,
+    /// An unquoted expansion ended a field; the next text starts another.
+    pending_break: bool,
+    /// `$@` expanded with no positional parameters.
+    empty_at: bool,
+    /// Building a glob pattern: quoted text is escaped so it matches literally.
+    pattern: bool,
+}
+
+impl Fields {
+    fn new(split: bool) -> Self {
+        Self {
+            fields: Vec::new(),
+            current: String::new(),
+            split,
+            quoted: !split,
+            pending_break: false,
+            empty_at: false,
+            pattern: false,
+        }
+    }
+
+    /// Unquoted text whose expansions are not split.
+    fn unsplit() -> Self {
+        Self {
+            quoted: false,
+            ..Self::new(false)
+        }
+    }
+
+    fn pattern() -> Self {
+        Self {
+            pattern: true,
+            ..Self::new(false)
+        }
+    }
+
+    fn apply_break(&mut self) {
+        if self.pending_break {
+            self.fields.push(std::mem::take(&mut self.current));
+            self.pending_break = false;
+        }
+    }
+
+    /// Append text that is never split: literal or quoted.
+    fn push_literal(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.apply_break();
+        if self.pattern {
+            self.current.push_str(&glob::Pattern::escape(text));
+        } else {
+            self.current.push_str(text);
+        }
+    }
+
+    /// Append the result of an expansion, splitting it in unquoted text.
+    fn push_expansion(&mut self, value: &str) {
+        if !self.split {
+            self.current.push_str(value);
+            return;
+        }
+        if value.starts_with(char::is_whitespace) && !self.current.is_empty() {
+            self.pending_break = true;
+        }
+        for (idx, word) in value.split_whitespace().enumerate() {
+            if idx > 0 {
+                self.pending_break = true;
+            }
+            self.apply_break();
+            self.current.push_str(word);
+        }
+        if value.ends_with(char::is_whitespace) && !self.current.is_empty() {
+            self.pending_break = true;
+        }
+    }
+
+    /// Append the positional parameters for `$@`: the first joins the text
+    /// before it, each later one starts a new field.
+    fn push_positional(&mut self, params: &[String]) {
+        if params.is_empty() {
+            self.empty_at = true;
+        }
+        for (idx, param) in params.iter().enumerate() {
+            if idx > 0 {
+                self.fields.push(std::mem::take(&mut self.current));
+                self.pending_break = false;
+            }
+            self.push_expansion(param);
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if self.empty_at && self.fields.is_empty() && self.current.is_empty() {
+            return Vec::new();
+        }
+        self.fields.push(self.current);
+        self.fields
+    }
+}
+
+/// Expand variables in one segment of a word into fields.
+///
+/// `"a$@b"` with parameters `1 2` gives `a1` and `2b`, and with no
+/// parameters a lone `"$@"` gives no fields at all. `in_double_quotes` says
+/// whether `input` came from a double-quoted segment: if not, expansion
+/// results are also split on whitespace.
+fn expand_variable_fields(
+    input: &str,
+    shell: &mut Shell,
+    in_double_quotes: bool,
+) -> Result<Vec<String>, JshError> {
+    expand_into_fields(input, shell, Fields::new(!in_double_quotes))
+}
+
+fn expand_into_fields(input: &str, shell: &mut Shell, mut fields: Fields) -> Result<Vec<String>, JshError> {
+    let in_double_quotes = fields.quoted;
+    let mut literal = String::new();
+    let mut chars = parser::Cursor::new(input);
 
     while let Some(ch) = chars.next() {
         if ch != '$' {
-            result.push(ch);
+            literal.push(ch);
             continue;
         }
+        fields.push_literal(&std::mem::take(&mut literal));
 
         // Peek at what follows the $
         match chars.peek() {
             None => {
                 // Trailing $ — literal
-                result.push('$');
+                fields.push_literal("$");
             }
-            Some(&'?') => {
+            Some(&'@') => {
                 chars.next();
-                result.push_str(&last_exit_code.to_string());
+                fields.push_positional(shell.positional_params());
             }
-            Some(&'$') => {
+            Some(&c) if c.is_ascii_digit() || matches!(c, '?' | '$' | '!' | '#' | '*' | '-') => {
+                // Special parameters and `$1`-`$9` are a single character;
+                // `${10}` needs braces.
                 chars.next();
-                result.push_str(&std::process::id().to_string());
+                fields.push_expansion(&lookup_set_parameter(&c.to_string(), shell)?);
             }
-            Some(&'0') => {
-                chars.next();
-                result.push_str("jsh");
+            Some(&'(') => {
+                // Scan a copy so an unterminated `$(` can fall back to literal text.
+                let mut probe = chars.clone();
+                probe.next(); // consume '('
+                match parser::scan_command_substitution(&mut probe) {
+                    Some(command) => {
+                        chars = probe;
+                        fields.push_expansion(&command_substitution(&command, shell));
+                    }
+                    None => fields.push_literal("$"),
+                }
             }
             Some(&'{') => {
-                chars.next(); // consume '{'
-                let mut name = String::new();
-                let mut closed = false;
-                while let Some(c) = chars.next() {
-                    if c == '}' {
-                        closed = true;
-                        break;
+                // Scan a copy so an unterminated `${` can fall back to literal text.
+                let mut probe = chars.clone();
+                probe.next(); // consume '{'
+                match parser::scan_parameter_expansion(&mut probe, in_double_quotes) {
+                    Some(expansion) if expansion == "@" => {
+                        chars = probe;
+                        fields.push_positional(shell.positional_params());
                     }
-                    name.push(c);
-                }
-
-                if !closed {
-                    result.push_str("${");
-                    result.push_str(&name);
-                } else if name.is_empty() {
-                    result.push_str("${}");
-                } else {
-                    let value = std::env::var(&name).unwrap_or_default();
-                    result.push_str(&value);
+                    Some(expansion) => {
+                        chars = probe;
+                        expand_parameter(&expansion, shell, &mut fields)?;
+                    }
+                    None => fields.push_literal("$"),
                 }
             }
             Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
@@ -191,174 +326,708 @@ fn expand_variables(input: &str, last_exit_code: i32) -> String {
                         break;
                     }
                 }
-                let value = std::env::var(&name).unwrap_or_default();
-                result.push_str(&value);
+                fields.push_expansion(&lookup_set_parameter(&name, shell)?);
             }
             Some(_) => {
                 // $ followed by something that's not a valid var start — literal $
-                result.push('$');
+                fields.push_literal("$");
+            }
+        }
+    }
+    fields.push_literal(&literal);
+
+    Ok(fields.finish())
+}
+
+/// Expand the body of a `${...}` expansion — a parameter name, optionally
+/// followed by an operator and its word — into `fields`.
+///
+/// `${name#pattern}` and `${name%pattern}` remove the shortest matching
+/// prefix or suffix of the value; doubling the operator (`##`, `%%`) removes
+/// the longest. `${name/pattern/string}` replaces the first longest match of
+/// the pattern anywhere in the value, and `${name//pattern/string}` every
+/// match.
+///
+/// `${name-word}` uses `word` if `name` is unset, `${name=word}` also assigns
+/// it, `${name?word}` fails with `word` as the message, and `${name+word}`
+/// uses `word` only if `name` is set. With a colon (`${name:-word}`) an
+/// empty value counts as unset.
+///
+/// `name[key]` in place of `name` is an element of an associative array.
+/// `${name[@]}` expands to all of its values, `${!name[@]}` to its keys,
+/// and `${#name[@]}` to how many elements it has.
+fn expand_parameter(expansion: &str, shell: &mut Shell, fields: &mut Fields) -> Result<(), JshError> {
+    if let Some((array, all)) = expansion.strip_prefix('!').and_then(whole_array) {
+        let keys: Vec<String> = shell.variables.elements(array).into_iter().map(|(key, _)| key.to_string()).collect();
+        push_array(&keys, all, fields);
+        return Ok(());
+    }
+    if let Some((array, _)) = expansion.strip_prefix('#').and_then(whole_array) {
+        fields.push_expansion(&shell.variables.elements(array).len().to_string());
+        return Ok(());
+    }
+    if let Some((array, all)) = whole_array(expansion) {
+        let values: Vec<String> = shell.variables.elements(array).into_iter().map(|(_, value)| value.to_string()).collect();
+        push_array(&values, all, fields);
+        return Ok(());
+    }
+
+    let (name, operator) = split_parameter_name(expansion);
+    if name.is_empty() {
+        return Err(JshError::Expansion(format!("jsh: ${{{expansion}}}: bad substitution")));
+    }
+    let (key, operator) = match operator.strip_prefix('[') {
+        Some(rest) if variables::is_valid_name(name) => {
+            let Some(end) = find_unquoted(rest, ']') else {
+                return Err(JshError::Expansion(format!("jsh: ${{{expansion}}}: bad substitution")));
+            };
+            let mut key = Fields::new(false);
+            expand_operand(&rest[..end], shell, &mut key)?;
+            (Some(key.finish().join(" ")), &rest[end + 1..])
+        }
+        _ => (None, operator),
+    };
+    let value = match &key {
+        Some(key) => shell.variables.element(name, key).map(str::to_string),
+        None => lookup_parameter(name, shell),
+    };
+    let set_value = || match (&value, &key) {
+        (Some(value), _) => Ok(value.clone()),
+        (None, Some(key)) if shell.options.nounset => Err(unbound(&format!("{name}[{key}]"), shell)),
+        (None, None) if shell.options.nounset => Err(unbound(name, shell)),
+        (None, _) => Ok(String::new()),
+    };
+    if operator.is_empty() {
+        fields.push_expansion(&set_value()?);
+        return Ok(());
+    }
+
+    if let Some(side @ ('#' | '%')) = operator.chars().next() {
+        let value = set_value()?;
+        let longest = operator[1..].starts_with(side);
+        let pattern = expand_pattern(&operator[if longest { 2 } else { 1 }..], shell)?;
+        fields.push_expansion(remove_pattern(&value, &pattern, side == '#', longest));
+        return Ok(());
+    }
+
+    if let Some(rest) = operator.strip_prefix('/') {
+        let (all, rest) = match rest.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (pattern, replacement) = match find_unquoted(rest, '/') {
+            Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+            None => (rest, ""),
+        };
+        let value = set_value()?;
+        let pattern = expand_pattern(pattern, shell)?;
+        let mut expanded = Fields::new(false);
+        expand_operand(replacement, shell, &mut expanded)?;
+        let replacement = expanded.finish().join(" ");
+        fields.push_expansion(&replace_pattern(&value, &pattern, &replacement, all));
+        return Ok(());
+    }
+
+    let (colon, operator) = match operator.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, operator),
+    };
+    let mut operator_chars = operator.chars();
+    let op = operator_chars.next();
+    let word = operator_chars.as_str();
+    let missing = match &value {
+        None => true,
+        Some(value) => colon && value.is_empty(),
+    };
+
+    match op {
+        Some('-') if missing => expand_operand(word, shell, fields),
+        Some('=') if missing => {
+            if !variables::is_valid_name(name) {
+                return Err(JshError::Expansion(format!("jsh: ${name}: cannot assign in this way")));
+            }
+            // The assigned value has its quotes removed but is not split;
+            // the result of the expansion is split like any other.
+            let mut assigned = Fields::new(false);
+            expand_operand(word, shell, &mut assigned)?;
+            let assigned = assigned.finish().join(" ");
+            let assigned = match &key {
+                Some(key) => {
+                    shell.variables.assign_element(name, key, &assigned).map_err(JshError::Assignment)?;
+                    shell.variables.element(name, key)
+                }
+                None => {
+                    shell.variables.assign(name, &assigned).map_err(JshError::Assignment)?;
+                    shell.variables.get(name)
+                }
+            };
+            fields.push_expansion(assigned.unwrap_or_default());
+            Ok(())
+        }
+        Some('?') if missing => {
+            let message = if !word.is_empty() {
+                let mut message = Fields::new(false);
+                expand_operand(word, shell, &mut message)?;
+                message.finish().join(" ")
+            } else if colon {
+                "parameter null or not set".to_string()
+            } else {
+                "parameter not set".to_string()
+            };
+            shell.fatal_expansion.set(true);
+            Err(JshError::Expansion(format!("jsh: {name}: {message}")))
+        }
+        Some('+') if missing => Ok(()),
+        Some('+') => expand_operand(word, shell, fields),
+        Some('-' | '=' | '?') => {
+            fields.push_expansion(&value.unwrap_or_default());
+            Ok(())
+        }
+        _ => Err(JshError::Expansion(format!("jsh: ${{{expansion}}}: bad substitution"))),
+    }
+}
+
+/// Expand the pattern word of `${VAR#pat}` and similar. Quoted parts of
+/// the word match literally, and so does a `[` that starts no bracket
+/// expression; any other pattern that is not valid glob syntax is matched
+/// literally as a whole.
+fn expand_pattern(word: &str, shell: &mut Shell) -> Result<glob::Pattern, JshError> {
+    let mut pattern = Fields::pattern();
+    expand_operand(word, shell, &mut pattern)?;
+    let mut pattern: Vec<char> = pattern.finish().join(" ").chars().collect();
+    loop {
+        let text: String = pattern.iter().collect();
+        match glob::Pattern::new(&text) {
+            Ok(pattern) => return Ok(pattern),
+            Err(err) if pattern.get(err.pos) == Some(&'[') => {
+                pattern.splice(err.pos..=err.pos, "[[]".chars());
             }
+            Err(_) => {
+                return Ok(glob::Pattern::new(&glob::Pattern::escape(&text)).expect("escaped pattern is valid"));
+            }
+        }
+    }
+}
+
+/// Byte offsets of every character boundary in `value`, including the end.
+fn char_boundaries(value: &str) -> Vec<usize> {
+    value
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(std::iter::once(value.len()))
+        .collect()
+}
+
+/// Remove the shortest (or `longest`) match of `pattern` from the start
+/// (`prefix`) or end of `value`, as `${VAR#pat}` and friends do. Leaves
+/// `value` unchanged if nothing matches.
+fn remove_pattern<'a>(value: &'a str, pattern: &glob::Pattern, prefix: bool, longest: bool) -> &'a str {
+    let mut cuts = char_boundaries(value);
+    // Try the shortest candidate first unless asked for the longest.
+    if prefix == longest {
+        cuts.reverse();
+    }
+    for cut in cuts {
+        if prefix && pattern.matches(&value[..cut]) {
+            return &value[cut..];
+        }
+        if !prefix && pattern.matches(&value[cut..]) {
+            return &value[..cut];
         }
     }
+    value
+}
 
+/// Replace the first (or `all`) longest non-empty matches of `pattern` in
+/// `value` with `replacement`, as `${VAR/pat/rep}` does.
+fn replace_pattern(value: &str, pattern: &glob::Pattern, replacement: &str, all: bool) -> String {
+    let boundaries = char_boundaries(value);
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut start = 0;
+    while start < boundaries.len() {
+        let from = boundaries[start];
+        let matched = boundaries[start + 1..]
+            .iter()
+            .rposition(|&to| pattern.matches(&value[from..to]))
+            .map(|offset| start + 1 + offset);
+        match matched {
+            Some(end) => {
+                result.push_str(&value[copied..from]);
+                result.push_str(replacement);
+                copied = boundaries[end];
+                if !all {
+                    break;
+                }
+                start = end;
+            }
+            None => start += 1,
+        }
+    }
+    result.push_str(&value[copied..]);
     result
 }
 
+/// Byte offset of the first `target` in `word` that is not quoted, escaped,
+/// or inside a nested `${...}`.
+fn find_unquoted(word: &str, target: char) -> Option<usize> {
+    let mut chars = word.char_indices().peekable();
+    let mut depth = 0;
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\'' => {
+                chars.by_ref().find(|&(_, c)| c == '\'');
+            }
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '$' if chars.peek().is_some_and(|&(_, c)| c == '{') => {
+                chars.next();
+                depth += 1;
+            }
+            '}' if depth > 0 => depth -= 1,
+            c if c == target && depth == 0 => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split the body of a `${...}` expansion into the parameter name and the
+/// operator text that follows it.
+/// `name[@]` or `name[*]`, the whole of an array, as the name and whether
+/// it is `@`.
+fn whole_array(expansion: &str) -> Option<(&str, bool)> {
+    let (name, all) = match expansion.strip_suffix("[@]") {
+        Some(name) => (name, true),
+        None => (expansion.strip_suffix("[*]")?, false),
+    };
+    variables::is_valid_name(name).then_some((name, all))
+}
+
+/// Expand an array's elements: with `@` each is its own field even inside
+/// double quotes, like `"$@"`; with `*` they are joined by spaces.
+fn push_array(elements: &[String], all: bool, fields: &mut Fields) {
+    match all {
+        true => fields.push_positional(elements),
+        false => fields.push_expansion(&elements.join(" ")),
+    }
+}
+
+fn split_parameter_name(expansion: &str) -> (&str, &str) {
+    let end = match expansion.chars().next() {
+        Some(c) if c.is_ascii_digit() => expansion
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(expansion.len()),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => expansion
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(expansion.len()),
+        Some('?' | '$' | '!' | '#' | '*' | '@' | '-') => 1,
+        _ => 0,
+    };
+    expansion.split_at(end)
+}
+
+/// Expand the word of a `${name:-word}` style operator into `fields`.
+///
+/// Quotes in the word are removed and protect their contents from
+/// splitting. When the whole expansion is double-quoted (`fields` does not
+/// split), single quotes are ordinary characters, as in bash.
+fn expand_operand(word: &str, shell: &mut Shell, fields: &mut Fields) -> Result<(), JshError> {
+    // Pattern quotes are honoured even inside double quotes, as in bash.
+    let in_double_quotes = fields.quoted && !fields.pattern;
+    let word = if in_double_quotes {
+        word.to_string()
+    } else {
+        expand_tilde(word, shell)
+    };
+    // Unquoted text waiting to be expanded.
+    let mut pending = String::new();
+    let mut chars = word.chars();
+
+    // Literal text in an unquoted operand is split like an expansion:
+    // `${x:-a b}` gives two fields.
+    fn flush(pending: &mut String, fields: &mut Fields, shell: &mut Shell) -> Result<(), JshError> {
+        if !pending.is_empty() {
+            let expanded = expand_variable_fields(&std::mem::take(pending), shell, true)?;
+            fields.push_expansion(&expanded.join(" "));
+        }
+        Ok(())
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                flush(&mut pending, fields, shell)?;
+                fields.push_literal(&chars.next().map(String::from).unwrap_or_default());
+            }
+            '\'' if !in_double_quotes => {
+                flush(&mut pending, fields, shell)?;
+                let quoted: String = chars.by_ref().take_while(|&c| c != '\'').collect();
+                fields.push_literal(&quoted);
+            }
+            '"' => {
+                flush(&mut pending, fields, shell)?;
+                let mut quoted = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => quoted.extend(chars.next()),
+                        c => quoted.push(c),
+                    }
+                }
+                let expanded = expand_variable_fields(&quoted, shell, true)?;
+                fields.push_literal(&expanded.join(" "));
+            }
+            c => pending.push(c),
+        }
+    }
+    flush(&mut pending, fields, shell)
+}
+
+/// Value of a special parameter (`$?`, `$$`, `$!`, `$0`, `$#`, `$*`, `$-`), a positional
+/// parameter (`$1`, `${10}`), a dynamic variable like `$RANDOM`, or a
+/// variable. `None` if it is not set.
+fn lookup_parameter(name: &str, shell: &Shell) -> Option<String> {
+    let params = shell.positional_params();
+    match name {
+        "?" => Some(shell.last_exit_code.to_string()),
+        "$" => Some(std::process::id().to_string()),
+        "!" => shell.job_table.last_background_pid().map(|pid| pid.to_string()),
+        "0" => Some(shell.name.clone()),
+        "#" => Some(params.len().to_string()),
+        "-" => Some(shell.option_flags()),
+        "*" | "@" => Some(params.join(" ")),
+        _ if name.chars().all(|c| c.is_ascii_digit()) => {
+            let index: usize = name.parse().ok()?;
+            params.get(index.checked_sub(1)?).cloned()
+        }
+        _ => shell
+            .dynamic
+            .get(name)
+            .or_else(|| shell.variables.get(name).map(str::to_string)),
+    }
+}
+
+/// Like [`lookup_parameter`], but an unset parameter expands to nothing, or
+/// is an error under `set -u`.
+fn lookup_set_parameter(name: &str, shell: &Shell) -> Result<String, JshError> {
+    match lookup_parameter(name, shell) {
+        Some(value) => Ok(value),
+        None if shell.options.nounset => Err(unbound(name, shell)),
+        None => Ok(String::new()),
+    }
+}
+
+/// The `set -u` error for expanding `name`, which also ends a shell that is
+/// not interactive.
+fn unbound(name: &str, shell: &Shell) -> JshError {
+    shell.fatal_expansion.set(true);
+    JshError::Expansion(format!("jsh: {name}: unbound variable"))
+}
+
+// ── Command Substitution ──
+
+/// Run `command` in a child jsh and return its standard output with trailing
+/// newlines removed, as `$(...)` and backticks require.
+///
+/// A separate process gives the substitution subshell semantics: `cd` or
+/// `export` inside it cannot leak into the calling shell.
+fn command_substitution(command: &str, shell: &Shell) -> String {
+    let prelude = shell.subshell_prelude();
+    let spawned = executor::stdin_subshell_command(shell, &prelude)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn();
+
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("jsh: command substitution: {e}");
+            shell.substitution_status.set(Some(1));
+            return String::new();
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = write!(stdin, "{prelude}");
+        let _ = writeln!(stdin, "{command}");
+        // stdin drops here, closing the pipe so the child sees EOF and exits.
+    }
+
+    // The child is in the shell's own process group, so a SIGINT sent to
+    // the shell alone has to be passed on to it.
+    #[cfg(unix)]
+    let _foreground = crate::job_control::ForegroundJob::process(child.id() as libc::pid_t);
+    match child.wait_with_output() {
+        Ok(output) => {
+            shell.substitution_status.set(Some(crate::status::exit_code(output.status)));
+            String::from_utf8_lossy(&output.stdout)
+                .trim_end_matches(['\r', '\n'])
+                .to_string()
+        }
+        Err(e) => {
+            eprintln!("jsh: command substitution: {e}");
+            shell.substitution_status.set(Some(1));
+            String::new()
+        }
+    }
+}
+
 // ── Glob Expansion ──
 
 fn contains_glob_chars(s: &str) -> bool {
     s.contains('*') || s.contains('?') || s.contains('[')
 }
 
-fn expand_globs(pattern: &str) -> Vec<String> {
+/// Most paths one pattern may expand to. The rest are dropped with a
+/// warning instead of building an argument list no command could take.
+const GLOB_LIMIT: usize = 100_000;
+
+/// The paths matching `pattern`, a relative one looked for under `cwd` and
+/// given back relative to it.
+fn expand_globs(pattern: &str, options: &ShellOptions, cwd: &Path) -> Result<Vec<String>, JshError> {
+    use std::path::Component;
+
     if !contains_glob_chars(pattern) {
-        return vec![pattern.to_string()];
+        return Ok(vec![pattern.to_string()]);
     }
 
-    match glob::glob(pattern) {
-        Ok(paths) => {
-            let mut matches: Vec<String> = paths
-                .filter_map(|entry| entry.ok())
-                .map(|path| path.to_string_lossy().into_owned())
-                .collect();
-
-            if matches.is_empty() {
-                // No matches — bash keeps the pattern literal
-                vec![pattern.to_string()]
-            } else {
-                matches.sort();
-                matches
+    let match_options = glob::MatchOptions {
+        require_literal_leading_dot: !options.dotglob,
+        ..glob::MatchOptions::new()
+    };
+    let relative = matches!(
+        Path::new(pattern).components().next(),
+        Some(Component::Normal(_) | Component::CurDir | Component::ParentDir)
+    );
+    let root = cwd.to_string_lossy();
+    let (full, root) = match relative {
+        true if root.ends_with(['/', '\\']) => (format!("{}{pattern}", glob::Pattern::escape(&root)), &root[..]),
+        true => (format!("{}/{pattern}", glob::Pattern::escape(&root)), &root[..]),
+        false => (pattern.to_string(), ""),
+    };
+    let Ok(paths) = glob::glob_with(&full, match_options) else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let mut matches = collect_matches(pattern, paths, GLOB_LIMIT)?;
+    if !root.is_empty() {
+        for path in &mut matches {
+            if let Some(rest) = path.strip_prefix(root) {
+                *path = rest.trim_start_matches(['/', '\\']).to_string();
             }
         }
-        Err(_) => vec![pattern.to_string()],
+    }
+
+    if matches.is_empty() {
+        if options.failglob {
+            Err(JshError::Expansion(format!("jsh: no match: {pattern}")))
+        } else if options.nullglob {
+            Ok(Vec::new())
+        } else {
+            // No matches — bash keeps the pattern literal
+            Ok(vec![pattern.to_string()])
+        }
+    } else {
+        Ok(matches)
     }
 }
 
+/// Gather up to `limit` matches of `pattern`. `glob` yields them already
+/// sorted, so they are kept in that order rather than sorted again. A
+/// Ctrl-C abandons a pattern that is taking too long, e.g. `/**/*`.
+fn collect_matches(
+    pattern: &str,
+    paths: impl Iterator<Item = glob::GlobResult>,
+    limit: usize,
+) -> Result<Vec<String>, JshError> {
+    let mut matches = Vec::new();
+    for entry in paths {
+        if traps::interrupted() {
+            return Err(JshError::Expansion(format!("jsh: {pattern}: expansion interrupted")));
+        }
+        let Ok(path) = entry else { continue };
+        if matches.len() == limit {
+            eprintln!("jsh: warning: {pattern}: more than {limit} matches, using the first {limit}");
+            break;
+        }
+        matches.push(path.into_os_string().into_string().unwrap_or_else(|path| path.to_string_lossy().into_owned()));
+    }
+    Ok(matches)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn tilde_alone() {
-        let expanded = expand_tilde("~");
+        let expanded = expand_tilde("~", &Shell::new());
         assert!(!expanded.is_empty());
         assert_ne!(expanded, "~");
     }
 
     #[test]
     fn tilde_with_path() {
-        let expanded = expand_tilde("~/projects");
+        let expanded = expand_tilde("~/projects", &Shell::new());
         assert!(expanded.ends_with("/projects") || expanded.ends_with("\\projects"));
         assert!(!expanded.starts_with('~'));
     }
 
     #[test]
     fn tilde_in_middle_not_expanded() {
-        assert_eq!(expand_tilde("foo~bar"), "foo~bar");
+        assert_eq!(expand_tilde("foo~bar", &Shell::new()), "foo~bar");
     }
 
     #[test]
     fn variable_simple() {
         // Set a test variable
-        unsafe { std::env::set_var("JSH_TEST_VAR", "hello") };
-        let result = expand_variables("$JSH_TEST_VAR", 0);
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_TEST_VAR", "hello");
+        let result = expand_variables("$JSH_TEST_VAR", &mut shell).unwrap();
         assert_eq!(result, "hello");
-        unsafe { std::env::remove_var("JSH_TEST_VAR") };
     }
 
     #[test]
     fn variable_braced() {
-        unsafe { std::env::set_var("JSH_TEST_VAR2", "world") };
-        let result = expand_variables("${JSH_TEST_VAR2}!", 0);
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_TEST_VAR2", "world");
+        let result = expand_variables("${JSH_TEST_VAR2}!", &mut shell).unwrap();
         assert_eq!(result, "world!");
-        unsafe { std::env::remove_var("JSH_TEST_VAR2") };
     }
 
     #[test]
     fn variable_braced_missing_close_is_literal() {
-        let result = expand_variables("${JSH_MISSING", 0);
+        let result = expand_variables("${JSH_MISSING", &mut Shell::new()).unwrap();
         assert_eq!(result, "${JSH_MISSING");
     }
 
     #[test]
     fn variable_exit_code() {
-        assert_eq!(expand_variables("$?", 42), "42");
-        assert_eq!(expand_variables("$?", 0), "0");
+        let mut shell = Shell::new();
+        shell.last_exit_code = 42;
+        assert_eq!(expand_variables("$?", &mut shell).unwrap(), "42");
+        shell.last_exit_code = 0;
+        assert_eq!(expand_variables("$?", &mut shell).unwrap(), "0");
     }
 
     #[test]
     fn variable_pid() {
-        let result = expand_variables("$$", 0);
+        let result = expand_variables("$$", &mut Shell::new()).unwrap();
         let pid: u32 = result.parse().expect("$$ should be a number");
         assert!(pid > 0);
     }
 
     #[test]
     fn variable_shell_name() {
-        assert_eq!(expand_variables("$0", 0), "jsh");
+        assert_eq!(expand_variables("$0", &mut Shell::new()).unwrap(), "jsh");
     }
 
     #[test]
     fn variable_undefined_is_empty() {
-        let result = expand_variables("$DEFINITELY_NOT_SET_XYZ123", 0);
+        let result = expand_variables("$DEFINITELY_NOT_SET_XYZ123", &mut Shell::new()).unwrap();
         assert_eq!(result, "");
     }
 
     #[test]
     fn trailing_dollar_literal() {
-        assert_eq!(expand_variables("price$", 0), "price$");
+        assert_eq!(expand_variables("price$", &mut Shell::new()).unwrap(), "price$");
     }
 
     #[test]
     fn dollar_followed_by_non_var_char() {
-        assert_eq!(expand_variables("$+foo", 0), "$+foo");
+        assert_eq!(expand_variables("$+foo", &mut Shell::new()).unwrap(), "$+foo");
     }
 
     #[test]
     fn single_quoted_no_expansion() {
         let word = vec![WordSegment::SingleQuoted("$HOME".into())];
-        let result = expand_word(&word, 0);
+        let result = expand_word(&word, &mut Shell::new()).unwrap();
         assert_eq!(result, vec!["$HOME"]);
     }
 
     #[test]
     fn double_quoted_expands_vars() {
-        unsafe { std::env::set_var("JSH_DQ_TEST", "expanded") };
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_DQ_TEST", "expanded");
         let word = vec![WordSegment::DoubleQuoted("$JSH_DQ_TEST".into())];
-        let result = expand_word(&word, 0);
+        let result = expand_word(&word, &mut shell).unwrap();
         assert_eq!(result, vec!["expanded"]);
-        unsafe { std::env::remove_var("JSH_DQ_TEST") };
     }
 
     #[test]
     fn double_quoted_no_glob() {
         let word = vec![WordSegment::DoubleQuoted("*.rs".into())];
-        let result = expand_word(&word, 0);
+        let result = expand_word(&word, &mut Shell::new()).unwrap();
         assert_eq!(result, vec!["*.rs"]);
     }
 
     #[test]
     fn no_glob_matches_keeps_literal() {
-        let result = expand_globs("*.definitely_not_a_real_extension_xyz");
-        assert_eq!(result, vec!["*.definitely_not_a_real_extension_xyz"]);
+        let cwd = std::env::current_dir().unwrap();
+        let result = expand_globs("*.definitely_not_a_real_extension_xyz", &ShellOptions::new(), &cwd);
+        assert_eq!(result.unwrap(), vec!["*.definitely_not_a_real_extension_xyz"]);
+    }
+
+    #[test]
+    fn nullglob_and_failglob_change_no_match_result() {
+        let pattern = "*.definitely_not_a_real_extension_xyz";
+        let cwd = std::env::current_dir().unwrap();
+        let mut options = ShellOptions::new();
+        options.nullglob = true;
+        assert!(expand_globs(pattern, &options, &cwd).unwrap().is_empty());
+        options.failglob = true;
+        assert_eq!(
+            expand_globs(pattern, &options, &cwd).unwrap_err(),
+            JshError::Expansion(format!("jsh: no match: {pattern}"))
+        );
+    }
+
+    #[test]
+    fn glob_matches_stay_sorted_and_stop_at_the_limit() {
+        let dir = std::env::temp_dir().join(format!("jsh_glob_limit_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["c.txt", "a.txt", "b.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let pattern = format!("{}/*.txt", dir.display());
+        let all = expand_globs(&pattern, &ShellOptions::new(), &dir).unwrap();
+        let first = collect_matches(&pattern, glob::glob(&pattern).unwrap(), 2).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let names: Vec<&str> = all.iter().map(|path| &path[path.len() - 5..]).collect();
+        assert_eq!(names, ["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(first, all[..2]);
     }
 
     #[test]
     fn word_split_for_unquoted_variable() {
-        unsafe { std::env::set_var("JSH_SPLIT_TEST", "alpha beta") };
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_SPLIT_TEST", "alpha beta");
         let word = vec![WordSegment::Unquoted("$JSH_SPLIT_TEST".into())];
-        let result = expand_word(&word, 0);
+        let result = expand_word(&word, &mut shell).unwrap();
         assert_eq!(result, vec!["alpha", "beta"]);
-        unsafe { std::env::remove_var("JSH_SPLIT_TEST") };
     }
 
     #[test]
     fn no_word_split_in_quotes() {
-        unsafe { std::env::set_var("JSH_SPLIT_TEST", "alpha beta") };
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_SPLIT_TEST", "alpha beta");
         let word = vec![WordSegment::DoubleQuoted("$JSH_SPLIT_TEST".into())];
-        let result = expand_word(&word, 0);
+        let result = expand_word(&word, &mut shell).unwrap();
         assert_eq!(result, vec!["alpha beta"]);
-        unsafe { std::env::remove_var("JSH_SPLIT_TEST") };
     }
 
     #[test]
@@ -370,17 +1039,188 @@ mod tests {
         std::fs::create_dir_all(&temp_dir).unwrap();
         std::fs::write(temp_dir.join("pre_match.rs"), "test").unwrap();
 
-        let original = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
+        let mut shell = Shell::new();
+        shell.cwd = temp_dir.clone();
 
         let word = vec![
             WordSegment::Unquoted("pre".into()),
             WordSegment::DoubleQuoted("*.rs".into()),
         ];
-        let result = expand_word(&word, 0);
+        let result = expand_word(&word, &mut shell).unwrap();
         assert_eq!(result, vec!["pre*.rs"]);
+        let word = vec![WordSegment::Unquoted("pre*.rs".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["pre_match.rs"]);
 
-        std::env::set_current_dir(original).unwrap();
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
+
+    #[test]
+    fn assignment_value_is_not_split_or_globbed() {
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_ASSIGN_TEST", "alpha beta");
+        let value = vec![
+            WordSegment::Unquoted("$JSH_ASSIGN_TEST*".into()),
+            WordSegment::SingleQuoted(" $x".into()),
+        ];
+        assert_eq!(expand_assignment_value(&value, &mut shell).unwrap(), "alpha beta* $x");
+
+        shell.variables.set("JSH_ASSIGN_TEST", " two  spaces\t");
+        let value = vec![WordSegment::Unquoted("[$JSH_ASSIGN_TEST]".into())];
+        assert_eq!(expand_assignment_value(&value, &mut shell).unwrap(), "[ two  spaces\t]");
+    }
+
+    #[test]
+    fn associative_array_elements_keys_and_counts() {
+        let mut shell = Shell::new();
+        shell.variables.make_assoc("JSH_ASSOC").unwrap();
+        shell.variables.assign_element("JSH_ASSOC", "one", "1 x").unwrap();
+        shell.variables.assign_element("JSH_ASSOC", "two", "2").unwrap();
+        shell.variables.set("JSH_ASSOC_KEY", "two");
+        assert_eq!(
+            expand_variables("${JSH_ASSOC[one]}/${JSH_ASSOC[$JSH_ASSOC_KEY]}/${JSH_ASSOC[no]:-none}", &mut shell).unwrap(),
+            "1 x/2/none"
+        );
+        assert_eq!(expand_variables("${!JSH_ASSOC[*]} ${#JSH_ASSOC[@]}", &mut shell).unwrap(), "one two 2");
+        let word = vec![WordSegment::DoubleQuoted("${JSH_ASSOC[@]}".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["1 x", "2"]);
+        assert_eq!(expand_variables("${JSH_ASSOC[three]=3}", &mut shell).unwrap(), "3");
+        assert_eq!(shell.variables.element("JSH_ASSOC", "three"), Some("3"));
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let mut shell = Shell::new();
+        shell.set_positional_params((1..=10).map(|n| format!("p{n}")).collect());
+        assert_eq!(expand_variables("$1 $9 ${10} $10 $#", &mut shell).unwrap(), "p1 p9 p10 p10 10");
+        assert_eq!(expand_variables("${11}", &mut shell).unwrap(), "");
+        assert!(shell.shift_positional_params(8));
+        assert_eq!(expand_variables("$*", &mut shell).unwrap(), "p9 p10");
+    }
+
+    #[test]
+    fn quoted_at_keeps_parameters_separate() {
+        let mut shell = Shell::new();
+        shell.set_positional_params(vec!["a b".into(), "c".into()]);
+        let word = vec![WordSegment::DoubleQuoted("<$@>".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["<a b", "c>"]);
+        let word = vec![WordSegment::DoubleQuoted("$*".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["a b c"]);
+        let word = vec![WordSegment::Unquoted("$@".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["a", "b", "c"]);
+
+        shell.set_positional_params(Vec::new());
+        let word = vec![WordSegment::DoubleQuoted("$@".into())];
+        assert!(expand_word(&word, &mut shell).unwrap().is_empty());
+        let word = vec![WordSegment::Unquoted("x".into()), WordSegment::DoubleQuoted("$@".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["x"]);
+    }
+
+    #[test]
+    fn default_value_operators() {
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_PE_EMPTY", "");
+        shell.variables.set("JSH_PE_SET", "value");
+        assert_eq!(
+            expand_variables("${JSH_PE_UNSET:-a} ${JSH_PE_EMPTY:-b} [${JSH_PE_EMPTY-c}] ${JSH_PE_SET:-d}", &mut shell).unwrap(),
+            "a b [] value"
+        );
+        assert_eq!(
+            expand_variables("[${JSH_PE_UNSET:+a}] [${JSH_PE_EMPTY:+b}] ${JSH_PE_EMPTY+c} ${JSH_PE_SET:+d}", &mut shell).unwrap(),
+            "[] [] c d"
+        );
+        assert_eq!(expand_variables("${JSH_PE_UNSET:-${JSH_PE_SET}}", &mut shell).unwrap(), "value");
+    }
+
+    #[test]
+    fn assign_default_sets_the_variable() {
+        let mut shell = Shell::new();
+        assert_eq!(expand_variables("${JSH_PE_ASSIGN:=first}", &mut shell).unwrap(), "first");
+        assert_eq!(expand_variables("${JSH_PE_ASSIGN:=second}", &mut shell).unwrap(), "first");
+        assert_eq!(shell.variables.get("JSH_PE_ASSIGN"), Some("first"));
+        assert!(expand_variables("${1:=x}", &mut shell).is_err());
+    }
+
+    #[test]
+    fn error_if_unset() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            expand_variables("${JSH_PE_MISSING:?not here}", &mut shell).unwrap_err(),
+            JshError::Expansion("jsh: JSH_PE_MISSING: not here".into())
+        );
+        assert_eq!(
+            expand_variables("${JSH_PE_MISSING?}", &mut shell).unwrap_err(),
+            JshError::Expansion("jsh: JSH_PE_MISSING: parameter not set".into())
+        );
+        assert_eq!(
+            expand_variables("${JSH_PE_MISSING!}", &mut shell).unwrap_err(),
+            JshError::Expansion("jsh: ${JSH_PE_MISSING!}: bad substitution".into())
+        );
+    }
+
+    #[test]
+    fn quoted_operand_is_not_split() {
+        let mut shell = Shell::new();
+        let word = vec![WordSegment::Unquoted("${JSH_PE_NONE:-a b}".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["a", "b"]);
+        let word = vec![WordSegment::Unquoted("${JSH_PE_NONE:-'a b'}".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["a b"]);
+        let word = vec![WordSegment::DoubleQuoted("${JSH_PE_NONE:-'a b'}".into())];
+        assert_eq!(expand_word(&word, &mut shell).unwrap(), vec!["'a b'"]);
+    }
+
+    #[test]
+    fn pattern_removal() {
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_PE_PATH", "/usr/lib/archive.tar.gz");
+        assert_eq!(
+            expand_variables("${JSH_PE_PATH#*/} ${JSH_PE_PATH##*/}", &mut shell).unwrap(),
+            "usr/lib/archive.tar.gz archive.tar.gz"
+        );
+        assert_eq!(
+            expand_variables("${JSH_PE_PATH%.*} ${JSH_PE_PATH%%.*} ${JSH_PE_PATH%.txt}", &mut shell).unwrap(),
+            "/usr/lib/archive.tar /usr/lib/archive /usr/lib/archive.tar.gz"
+        );
+    }
+
+    #[test]
+    fn quoted_pattern_matches_literally() {
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_PE_STARS", "a*b*c");
+        assert_eq!(expand_variables("${JSH_PE_STARS#*'*'}", &mut shell).unwrap(), "b*c");
+        assert_eq!(expand_variables("${JSH_PE_STARS%\\**}", &mut shell).unwrap(), "a*b");
+        shell.variables.set("JSH_PE_BRACKETS", "x[1][2]");
+        assert_eq!(expand_variables("${JSH_PE_BRACKETS#*[}", &mut shell).unwrap(), "1][2]");
+        assert_eq!(expand_variables("${JSH_PE_BRACKETS%[*}", &mut shell).unwrap(), "x[1]");
+        assert_eq!(expand_variables("${JSH_PE_BRACKETS##*[[0-9]}", &mut shell).unwrap(), "]");
+    }
+
+    #[test]
+    fn pattern_substitution() {
+        let mut shell = Shell::new();
+        shell.variables.set("JSH_PE_TEXT", "hello world, hello moon");
+        assert_eq!(
+            expand_variables("${JSH_PE_TEXT/hello/bye}", &mut shell).unwrap(),
+            "bye world, hello moon"
+        );
+        assert_eq!(
+            expand_variables("${JSH_PE_TEXT//hello/bye}", &mut shell).unwrap(),
+            "bye world, bye moon"
+        );
+        assert_eq!(expand_variables("${JSH_PE_TEXT//o}", &mut shell).unwrap(), "hell wrld, hell mn");
+        assert_eq!(expand_variables("${JSH_PE_TEXT/l*o/X}", &mut shell).unwrap(), "heXn");
+        shell.variables.set("JSH_PE_DIR", "/a/b");
+        assert_eq!(expand_variables("${JSH_PE_DIR//'/'/:}", &mut shell).unwrap(), ":a:b");
+    }
+
+    #[test]
+    fn nounset_rejects_unset_parameters() {
+        let mut shell = Shell::new();
+        shell.options.nounset = true;
+        assert_eq!(
+            expand_variables("$JSH_PE_UNBOUND", &mut shell).unwrap_err(),
+            JshError::Expansion("jsh: JSH_PE_UNBOUND: unbound variable".into())
+        );
+        assert!(expand_variables("${1}", &mut shell).is_err());
+        assert_eq!(expand_variables("${JSH_PE_UNBOUND:-ok} $# [$*]", &mut shell).unwrap(), "ok 0 []");
+    }
 }
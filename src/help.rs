@@ -0,0 +1,719 @@
+/// The `help` builtin.
+///
+/// Every builtin carries its own [`BuiltinHelp`], given where it is
+/// registered, so the overview and `help NAME` are built from the table
+/// that runs the builtins and a new one cannot be left out. The reference
+/// topics that are not builtins are kept here as well.
+use std::io::Write;
+
+use crate::builtins::{BuiltinHelp, BuiltinTable};
+
+/// Width of the usage column in the overview.
+const USAGE_WIDTH: usize = 19;
+
+/// `help [topic|builtin]`: list the builtins and topics, or show one.
+pub(crate) fn builtin_help(
+    args: &[String],
+    builtins: &BuiltinTable,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let Some(name) = args.first() else {
+        overview(builtins, stdout);
+        return 0;
+    };
+    if let Some(help) = builtins.get(name).and_then(|builtin| builtin.help()) {
+        let _ = writeln!(stdout, "{}", help.usage);
+        if help.details.is_empty() {
+            let _ = writeln!(stdout, "  {}", help.summary);
+        }
+        for line in help.details {
+            let _ = writeln!(stdout, "{line}");
+        }
+        return 0;
+    }
+    if let Some((_, lines)) = TOPICS.iter().find(|(topic, _)| topic == name) {
+        for line in *lines {
+            let _ = writeln!(stdout, "{line}");
+        }
+        return 0;
+    }
+    let _ = writeln!(stderr, "help: no help for '{name}'");
+    1
+}
+
+/// Each builtin's usage and summary, then the topics.
+fn overview(builtins: &BuiltinTable, stdout: &mut dyn Write) {
+    let _ = writeln!(stdout, "jsh — James Shell  (type 'help <topic>' for details)");
+    let _ = writeln!(stdout);
+    let _ = writeln!(stdout, "Builtins:");
+    let mut listed: Vec<&BuiltinHelp> = Vec::new();
+    for help in builtins.iter().filter_map(|builtin| builtin.help()) {
+        // `source` and `.` share theirs.
+        if listed.iter().any(|seen| std::ptr::eq(*seen, help)) {
+            continue;
+        }
+        listed.push(help);
+        if help.usage.chars().count() <= USAGE_WIDTH {
+            let _ = writeln!(stdout, "  {:<USAGE_WIDTH$} {}", help.usage, help.summary);
+        } else {
+            let _ = writeln!(stdout, "  {}", help.usage);
+            let _ = writeln!(stdout, "  {:USAGE_WIDTH$} {}", "", help.summary);
+        }
+    }
+    let _ = writeln!(stdout, "  Stateful builtins (cd/export/unset/fg/bg)");
+    let _ = writeln!(stdout, "    run in a subshell in a pipeline (see shopt lastpipe)");
+    let _ = writeln!(stdout, "  time [-p] pipeline  Report how long a pipeline took ($TIMEFORMAT)");
+    let _ = writeln!(stdout);
+    let topics: Vec<&str> = TOPICS.iter().map(|(topic, _)| *topic).collect();
+    let _ = writeln!(stdout, "Topics: {}", topics.join("  "));
+}
+
+/// Reference sections that are not about one builtin.
+static TOPICS: &[(&str, &[&str])] = &[
+    ("variables", &[
+        "Special variables:",
+        "  $?        Exit code of the last command",
+        "  $$        PID of the shell process",
+        "  $!        PID of the most recent background job",
+        "  $0        Shell name (always 'jsh')",
+        "  $1..$9    Positional parameters; ${10} and up need braces",
+        "  $#        Number of positional parameters",
+        "  $@ $*     All positional parameters (\"$@\" keeps them separate)",
+        "  $-        Letters of the set flags that are on, plus i if interactive",
+        "  $HOME     Home directory",
+        "  $PATH     Command search path",
+        "  $PWD      Current directory (logical; set by cd)",
+        "  $OLDPWD   Previous directory (set by cd)",
+        "  $CDPATH   Directories cd searches for relative names",
+        "  $USER     Current user name",
+        "  $SECONDS  Seconds since the shell started; SECONDS=0 restarts the count",
+        "  $RANDOM   A new random number from 0 to 32767 each time; RANDOM=n seeds it",
+        "  $LINENO   Line of the script or session the current command starts on",
+        "  $EPOCHSECONDS  Seconds since 1970-01-01 UTC",
+        "  $IGNOREEOF  Ctrl-Ds in a row to ignore before exiting (10 if not a number)",
+        "  $VAR      Value of a shell or environment variable",
+        "  ${VAR}    Same as $VAR (brace form)",
+        "",
+        "  NAME=value sets a shell variable; 'export NAME' passes it",
+        "  to child processes.  NAME=value cmd sets it for cmd only.",
+        "",
+        "Associative arrays ('declare -A map' first):",
+        "  map[key]=value     Set an element ('unset map[key]' removes it)",
+        "  ${map[key]}        One element; the operators above work on it too",
+        "  ${map[@]}          All values (\"${map[@]}\" keeps them separate)",
+        "  ${!map[@]}         All keys      ${#map[@]}  Number of elements",
+        "",
+        "Prompt:",
+        "  $PS1      Prompt template (default 'jsh> '); $JSH_PROMPT overrides it",
+        "            \\u user  \\h host  \\w cwd (~ for $HOME)  \\W its last part",
+        "            \\? last exit code  \\j job count  \\t \\T \\@ \\A time",
+        "            \\$ '#' for root, else '$'  \\n newline  \\[ \\] wrap colour codes",
+        "            \\D how long the last command took, if $JSH_DURATION_THRESHOLD",
+        "            seconds or more (default 2); $CMD_DURATION holds it in ms",
+        "            Interactive, the default shows a failed command's status, 'jsh [1]> ',",
+        "            in red at a terminal; 'shopt -u promptstatus' turns that off",
+        "  $PS2      Prompt while a command is unfinished (default '> ')",
+        "  $JSH_NOTIFY_AFTER  Seconds after which a finished command sends a desktop",
+        "            notification (notify-send, osascript, or OSC 9); unset: never",
+        "  $PS4      Prefix of set -x trace lines (default '+ '); its first character",
+        "            is repeated once more for each level of subshell",
+        "  $JSH_XTRACEFD  Descriptor set -x writes to, e.g. 3 after 'exec 3>trace.log'",
+        "",
+        "History:",
+        "  $HISTSIZE      Commands kept in memory (default 10000; negative: no limit)",
+        "  $HISTFILESIZE  Lines kept in ~/.jsh_history (default $HISTSIZE)",
+        "  $HISTCONTROL   Colon-separated: ignoredups, ignorespace, ignoreboth, erasedups",
+        "                 (unset: ignoredups)",
+        "",
+        "Timing:",
+        "  $TIMEFORMAT    Report format for 'time': %R real, %U user, %S system",
+        "                 seconds (%2R: 2 decimals, %lR: 1m2.500s), %P CPU percent;",
+        "                 empty: no report (unset: real/user/sys on three lines)",
+        "",
+        "Startup file:",
+        "  ~/.jshrc is run at startup, so exports and aliases persist.",
+        "  $JSH_RC   Run this file instead (empty: skip the startup file)",
+    ]),
+    ("redirection", &[
+        "Redirection operators:",
+        "  cmd > file      Write stdout to file (truncate)",
+        "  cmd >> file     Append stdout to file",
+        "  cmd >| file     Write stdout to file even with 'set -o noclobber'",
+        "  cmd < file      Read stdin from file",
+        "  cmd 2> file     Write stderr to file",
+        "  cmd 2>> file    Append stderr to file",
+        "  cmd 2>&1        Merge stderr into stdout",
+        "  cmd 1>&2        Merge stdout into stderr",
+        "  cmd 3> file     Open fd 3 (any digit) on file, for later N>&3",
+        "  cmd >&N         Write stdout to fd N (>&$fd also works)",
+        "  cmd <&N         Read stdin from fd N",
+        "  cmd 3>&-        Close fd 3 (closing 0-2 leaves them empty or discarding)",
+        "  cmd &> file     Write stdout and stderr to file (same as > file 2>&1)",
+        "  cmd &>> file    Append stdout and stderr to file",
+        "  cmd <<< word    Feed word as stdin (here-string)",
+        "  cmd << EOF      Feed following lines up to EOF as stdin (heredoc)",
+        "  cmd <<- EOF     Heredoc with leading tabs stripped from each line",
+        "  exec > file     Redirect the shell itself from here on (see 'help exec')",
+        "  Redirections apply left to right: > f 2>&1 sends both streams to f,",
+        "  2>&1 > f sends only stdout there.",
+        "  A target may be a glob matching one file; with 'set -o posix' scripts",
+        "  use it literally. Here-strings are never split or globbed.",
+        "  /dev/stdin, /dev/stdout, /dev/stderr, and /dev/fd/0-2 name the current",
+        "  streams in redirections and 'source', on every platform.",
+        "",
+        "Pipelines:",
+        "  a | b           Connect a's stdout to b's stdin",
+        "  a |& b          Connect a's stdout and stderr (same as a 2>&1 | b)",
+        "  Builtins that change the shell (cd, export, ...) run in a subshell in a",
+        "  pipeline; with 'shopt -s lastpipe' the last stage runs in the shell itself.",
+        "  'shopt -s pipewarn' says when a builtin's changes are lost that way.",
+    ]),
+    ("expansion", &[
+        "Word expansion (applied in order):",
+        "  ~               Expands to $HOME",
+        "  ~/path          Expands to $HOME/path",
+        "  $VAR            Variable substitution",
+        "  ${VAR}          Braced variable substitution",
+        "  ${VAR:-word}    word if VAR is unset or empty",
+        "  ${VAR:=word}    Same, and also assign word to VAR",
+        "  ${VAR:?msg}     Fail with msg if VAR is unset or empty",
+        "  ${VAR:+word}    word if VAR is set and not empty",
+        "                  Without the colon, only unset counts",
+        "  ${VAR#pat}      Remove shortest prefix matching pat (## longest)",
+        "  ${VAR%pat}      Remove shortest suffix matching pat (%% longest)",
+        "  ${VAR/pat/rep}  Replace first match of pat with rep (// all)",
+        "  $(cmd)          Output of cmd (command substitution)",
+        "  `cmd`           Legacy form of $(cmd)",
+        "  *               Matches any string of characters",
+        "  ?               Matches any single character",
+        "  [abc]           Matches any character in the set",
+        "  Globs that match nothing are kept as literals.",
+        "  shopt -s nullglob   ...expand to nothing instead",
+        "  shopt -s failglob   ...are an error and the command is not run",
+        "  shopt -s dotglob    Globs also match names starting with '.'",
+        "  Globs inside quotes are not expanded.",
+        "",
+        "History expansion (interactive lines, before everything else):",
+        "  !!              The previous command",
+        "  !N  !-N         History entry N, or the Nth most recent",
+        "  !prefix         The last command starting with prefix",
+        "  ^old^new        The previous command with old replaced by new",
+    ]),
+    ("quotes", &[
+        "Quoting:",
+        "  'text'    Single quotes: no expansion of any kind",
+        "  \"text\"    Double quotes: $VAR expanded, globs suppressed",
+        "  \\c        Backslash: treat next character literally",
+        "  $'text'   ANSI-C quotes: like '...' but \\n, \\t, \\xHH etc. are decoded",
+        "  Mixing quote styles in one word is allowed.",
+        "  # starts a comment when it begins a word: echo hi  # note",
+    ]),
+    ("exit-codes", &[
+        "Exit codes:",
+        "  0          Success",
+        "  1          General error",
+        "  2          Bad usage (wrong arguments)",
+        "  124        Command timed out (see help timeout)",
+        "  126        Command found but not executable",
+        "  127        Command not found (with a close match suggested)",
+        "  128+N      Command killed by signal N",
+        "  130        Interrupted by Ctrl-C; the rest of the line is skipped",
+        "  $?         Holds the exit code of the last command",
+        "  A pipeline's code is its last command's; with 'set -o pipefail'",
+        "  it is the last non-zero code of any command in it.",
+        "",
+        "Options (set -x turns on, set +x turns off):",
+        "  set -C     Do not let > overwrite an existing file (set -o noclobber)",
+        "  set -e     Exit when a command fails (not in if tests or before && ||)",
+        "  set -n     Read commands in a script without running them (jsh --check FILE...)",
+        "  set -u     Expanding an unset variable is an error",
+        "  set -x     Print each command, prefixed with $PS4, to stderr or $JSH_XTRACEFD",
+        "  set -o posix  Scripts do not glob redirection targets (> *.log is literal)",
+        "  set -o bglog  Send each background job's output to a log file (jobs -o)",
+        "  set -o bglog=DIR  ... kept in DIR rather than removed when the shell exits",
+    ]),
+    ("control-flow", &[
+        "Control flow:",
+        "  a && b          Run b only if a succeeds",
+        "  a || b          Run b only if a fails",
+        "  a ; b           Run a, then b",
+        "  if cmd; then ...; elif cmd; then ...; else ...; fi",
+        "                  Run the first branch whose cmd exits 0",
+        "  { a; b; } > f   Group commands in this shell; redirections apply to all",
+        "  Unfinished commands continue on the next line at the '> ' prompt.",
+        "{usage}",
+    ]),
+];
+
+// ── the shell's own builtins, as registered in `builtins::CORE` ──────────────
+
+pub(crate) static CD: BuiltinHelp = BuiltinHelp {
+    usage: "cd [-L|-P] [dir|-]",
+    summary: "Change directory (- goes to previous)",
+    details: &[
+        "  Change the current directory.",
+        "  No argument: go to $HOME.",
+        "  '-': go to the previous directory ($OLDPWD) and print it.",
+        "  A relative dir is also looked up in the :-separated $CDPATH;",
+        "  the new directory is printed when found that way.",
+        "  -L: follow symlinks logically, so '..' undoes the last step (default).",
+        "  -P: resolve symlinks and use the physical directory.",
+        "  Sets $PWD, and $OLDPWD to the directory you came from.",
+        "  shopt -s cdspell: offer to fix a one-letter typo in dir (interactive).",
+        "  shopt -s autocd: typing a directory's name alone runs 'cd' on it (interactive).",
+        "  Windows: 'cd D:' returns to where you last were on drive D, as cmd.exe",
+        "  does; UNC paths (\\\\server\\share) work, and $PWD uses backslashes.",
+    ],
+};
+
+pub(crate) static PWD: BuiltinHelp = BuiltinHelp {
+    usage: "pwd [-LP]",
+    summary: "Print working directory",
+    details: &[
+        "  Print the absolute path of the current directory.",
+        "  -L: the logical path in $PWD, symlinks included (default).",
+        "  -P: the physical path, with symlinks resolved.",
+    ],
+};
+
+pub(crate) static EXIT: BuiltinHelp = BuiltinHelp {
+    usage: "exit [code]",
+    summary: "Exit the shell",
+    details: &[
+        "  Exit the shell with the given numeric exit code.",
+        "  No argument: exit 0.  Non-numeric argument: exit 2.",
+        "  At a terminal with stopped or running jobs, the first exit (or",
+        "  Ctrl-D) only warns, listing the jobs that will be sent SIGHUP;",
+        "  exiting again straight away goes through.  Stopped jobs are always",
+        "  sent SIGHUP; running ones only under 'shopt -s huponexit', on by",
+        "  default in a login shell (jsh -l).  'disown -h' spares a job.",
+        "  On Windows such jobs are terminated, with every process they started.",
+    ],
+};
+
+pub(crate) static ECHO: BuiltinHelp = BuiltinHelp {
+    usage: "echo [-neE] [args...]",
+    summary: "Print arguments",
+    details: &[
+        "  Print arguments separated by spaces, followed by a newline.",
+        "  -n: no trailing newline.",
+        "  -e: decode escapes: \\n \\t \\\\ \\xHH \\0nnn \\uHHHH, and \\c stops output.",
+        "  -E: print backslashes as they are (the default, unless shopt xpg_echo).",
+    ],
+};
+
+pub(crate) static PRINTF: BuiltinHelp = BuiltinHelp {
+    usage: "printf format [arguments...]",
+    summary: "Print the arguments as format directs (%s %d %x %f %q %b)",
+    details: &[
+        "  Print the arguments as format directs, reusing format until they",
+        "  run out.  Conversions: %s %d %i %u %o %x %X %c %e %f %g %q %b %%,",
+        "  with flags, width, and precision.  There is no -v.",
+    ],
+};
+
+pub(crate) static EXPORT: BuiltinHelp = BuiltinHelp {
+    usage: "export [-n] NAME[=value]...   or   export [-p]",
+    summary: "Export variable to child processes",
+    details: &[
+        "  Mark each NAME for export to child processes, assigning",
+        "  value first if given.  NAME=value without export sets a",
+        "  shell variable that child processes do not see.",
+        "  -n: stop exporting NAME, keeping it as a shell variable.",
+        "  No NAMEs, or -p: list exported variables in a reusable form.",
+    ],
+};
+
+pub(crate) static UNSET: BuiltinHelp = BuiltinHelp {
+    usage: "unset NAME...",
+    summary: "Remove variable",
+    details: &[
+        "  Remove one or more shell variables, exported or not.",
+        "  Readonly variables cannot be unset.",
+    ],
+};
+
+pub(crate) static DECLARE: BuiltinHelp = BuiltinHelp {
+    usage: "declare [-Airxp] [+ix] [NAME[=value]...]",
+    summary: "Set variable attributes (array, integer, readonly, export)",
+    details: &[
+        "  Set attributes ('-' turns on, '+' turns off), assigning value if given:",
+        "  -A: an associative array (see 'help variables')",
+        "  -i: values are integer expressions (+ - * / %, names read as numbers)",
+        "  -r: readonly; assigning or unsetting it is an error",
+        "  -x: exported to child processes",
+        "  -p, or no NAMEs: print variables as declare commands.",
+    ],
+};
+
+pub(crate) static LOCAL: BuiltinHelp = BuiltinHelp {
+    usage: "local [-irx] [NAME[=value]...]",
+    summary: "Make a variable local to the running function",
+    details: &[
+        "  Like declare, but NAME gets its old value back when the running",
+        "  function returns.  Outside a function it is an error.",
+    ],
+};
+
+pub(crate) static READONLY: BuiltinHelp = BuiltinHelp {
+    usage: "readonly [-p] [NAME[=value]...]",
+    summary: "Make variables readonly",
+    details: &[
+        "  Make each NAME readonly, assigning value first if given.",
+        "  With no NAMEs, or -p: list readonly variables.",
+    ],
+};
+
+pub(crate) static TYPE: BuiltinHelp = BuiltinHelp {
+    usage: "type [-aptP] name...",
+    summary: "Show whether name is alias, keyword, builtin, or file",
+    details: &[
+        "  For each name, report whether it is an alias, a shell keyword,",
+        "  a shell builtin, or the full path of the external executable.",
+        "  -t: print only alias, keyword, builtin, or file.",
+        "  -a: list every match, including each executable along $PATH.",
+        "  -p: print the path if name is a file.  -P: search $PATH regardless.",
+        "  Exit code 1 if any name is not found.",
+    ],
+};
+
+pub(crate) static COMMAND: BuiltinHelp = BuiltinHelp {
+    usage: "command [-vV] name [args...]",
+    summary: "Run name skipping aliases, or say how it would run",
+    details: &[
+        "  Run name as a builtin or external command, ignoring any alias.",
+        "  -v: print the path, builtin name, or alias definition name would use.",
+        "  -V: describe name as 'type' does.",
+        "  Exit code 1 with -v or -V if any name is not found.",
+    ],
+};
+
+pub(crate) static HASH: BuiltinHelp = BuiltinHelp {
+    usage: "hash [-r] [-d|-t] [name ...]",
+    summary: "Remember or list where commands are on $PATH",
+    details: &[
+        "  External commands are looked up on $PATH once and remembered.",
+        "  No arguments: list remembered commands and how often each ran.",
+        "  name: look name up now and remember it.",
+        "  -r: forget everything.  -d: forget the names.  -t: print their paths.",
+        "  Changing $PATH forgets everything too.",
+    ],
+};
+
+pub(crate) static HISTORY: BuiltinHelp = BuiltinHelp {
+    usage: "history [n] | history --import|--export bash|fish file",
+    summary: "List history, or copy it to or from another shell's history file",
+    details: &[
+        "  With no options, list the commands in ~/.jsh_history, numbered from",
+        "  the oldest; with n, only the last n.",
+        "  --import: add the commands in a bash or fish history file to",
+        "  ~/.jsh_history, before jsh's own. Bash timestamp lines are skipped;",
+        "  multi-line fish commands are joined onto one line.",
+        "  --export: append ~/.jsh_history to file in bash or fish format.",
+        "  Imported commands can be recalled from the next prompt on.",
+    ],
+};
+
+pub(crate) static JOBS: BuiltinHelp = BuiltinHelp {
+    usage: "jobs [-lprs] [--json] | jobs -o [%N]",
+    summary: "List background jobs; 'help jobs' also covers job control",
+    details: &[
+        "  List background and stopped jobs with their IDs.",
+        "  Status column: Running | Stopped | Done",
+        "  A finished job is reported as Done, Exit N, or how it was killed",
+        "  (Terminated, Killed, ...); with 'set -o pipefail' a pipeline reports",
+        "  its last failing stage.  A finished job stays known to 'wait %N'",
+        "  until 'jobs' or 'wait' has shown or collected it.",
+        "  + marks the current job and - the previous one, here and in the",
+        "  Stopped and Done notices.  Running jobs are listed with a trailing &.",
+        "  -l  Also show each job's PID, process group ID, and run time",
+        "  -p  Print only process group IDs, e.g. kill $(jobs -p)",
+        "  -r  Running jobs only",
+        "  -s  Stopped jobs only",
+        "  --json  Print a JSON array of {id, pid, pgid, status, command, started_at}",
+        "          objects; started_at is in seconds since the Unix epoch",
+        "  -o  Print the log file of job %N (default: current) under 'set -o bglog'",
+        "",
+        "Job control summary:",
+        "  cmd &           Run command in background (builtins run in a subshell)",
+        "  fg [%N]         Bring job to foreground",
+        "  bg [%N]         Resume stopped job in background",
+        "  wait [%N]       Wait for job(s) to finish",
+        "  kill [-SIG] %N  Signal every process in a job",
+        "  disown [-h] %N  Forget a job, or (-h) spare it from SIGHUP on exit",
+        "  shopt -s huponexit  Send running jobs SIGHUP on exit (on in login shells)",
+        "  Ctrl-Z          Suspend foreground job (Unix only)",
+        "                  At the prompt (or Alt-Q): put the command being typed",
+        "                  aside; it comes back once the next one has run",
+        "  shopt -s jobtimes  Show real and CPU time in Done notifications",
+        "  A foreground job that crashes, fails, or stops gets the terminal back",
+        "  in the modes it had before; one that succeeds (e.g. stty) keeps its own.",
+        "  On Windows Ctrl-C reaches the foreground job as Ctrl-Break, and",
+        "  'kill -STOP %N' suspends a job's processes for bg or fg to resume.",
+        "",
+        "Job specs:",
+        "  %N              Job number N",
+        "  %% or %+        The current job",
+        "  %-              The previous job",
+        "  %str            The job whose command starts with str",
+        "  %?str           The job whose command contains str",
+    ],
+};
+
+pub(crate) static FG: BuiltinHelp = BuiltinHelp {
+    usage: "fg [%N]",
+    summary: "Bring job to foreground",
+    details: &[
+        "  Bring job %N to the foreground and wait for it.",
+        "  No argument: use the current job (see 'help jobs' for job specs).",
+    ],
+};
+
+pub(crate) static BG: BuiltinHelp = BuiltinHelp {
+    usage: "bg [%N]",
+    summary: "Resume stopped job in background",
+    details: &[
+        "  Resume stopped job %N in the background.",
+        "  No argument: use the most recently stopped job.",
+    ],
+};
+
+pub(crate) static WAIT: BuiltinHelp = BuiltinHelp {
+    usage: "wait [%N]",
+    summary: "Wait for background job(s)",
+    details: &[
+        "  Wait for background job %N to finish.",
+        "  No argument: wait for all background jobs.",
+        "  Sets $? to the exit code of the waited job, even one that has",
+        "  already finished and been reported Done.",
+    ],
+};
+
+pub(crate) static DISOWN: BuiltinHelp = BuiltinHelp {
+    usage: "disown [-h] [-ar] [%N...]",
+    summary: "Forget a job, or (-h) keep it running after exit",
+    details: &[
+        "  Remove jobs from the job table; they keep running, unreported.",
+        "  No job: the current job.  -a: every job.  -r: every running job.",
+        "  -h: keep the jobs in the table, but do not send them SIGHUP when",
+        "  the shell exits under 'shopt -s huponexit'.",
+    ],
+};
+
+pub(crate) static SUSPEND: BuiltinHelp = BuiltinHelp {
+    usage: "suspend [-f]",
+    summary: "Stop this shell until it is continued (Unix only)",
+    details: &[
+        "  Stop the shell with SIGTSTP, as Ctrl-Z stops a job, until it is",
+        "  continued, e.g. by fg in the shell that started jsh.  A login shell",
+        "  is only suspended with -f.  Unix only.",
+    ],
+};
+
+pub(crate) static KILL: BuiltinHelp = BuiltinHelp {
+    usage: "kill [-s SIG | -n NUM | -SIG] pid|%N...",
+    summary: "Send a signal (default TERM) to a job or process",
+    details: &[
+        "kill -l [SIG|status...]",
+        "  Send a signal (default TERM) to each process.  %N signals",
+        "  every process in job N; a stopped job is also continued so",
+        "  it can act on the signal.  SIG is a name (TERM, SIGKILL)",
+        "  or number.  -l lists signals, or converts names and numbers.",
+    ],
+};
+
+pub(crate) static HELP: BuiltinHelp = BuiltinHelp {
+    usage: "help [topic|builtin]",
+    summary: "Show this help or a topic reference",
+    details: &[
+        "  No argument: list all builtins and topics.",
+        "  Builtin name: show usage for that builtin.",
+        "  Topic name: show a reference section.",
+        "  Topics: listed at the end of 'help'; 'help jobs' covers job control.",
+    ],
+};
+
+pub(crate) static ALIAS: BuiltinHelp = BuiltinHelp {
+    usage: "alias [-p] [name[=value] ...]",
+    summary: "Define or list aliases",
+    details: &[
+        "  Define name as an alias for value, or print the named aliases.",
+        "  No argument or -p: list all aliases in reusable form.",
+        "  Aliases expand in the first word of a command; a value ending",
+        "  in a space also expands aliases in the word that follows.",
+    ],
+};
+
+pub(crate) static UNALIAS: BuiltinHelp = BuiltinHelp {
+    usage: "unalias [-a] name...",
+    summary: "Remove aliases",
+    details: &[
+        "  Remove each named alias.  -a removes all aliases.",
+    ],
+};
+
+pub(crate) static SOURCE: BuiltinHelp = BuiltinHelp {
+    usage: "source file [arguments...]",
+    summary: "Run file in the current shell (also '.')",
+    details: &[
+        ". file [arguments...]",
+        "  Read and run the commands in file in the current shell, so",
+        "  exports, cd, and aliases it makes persist afterwards.",
+        "  Arguments become the positional parameters while it runs.",
+        "  Exit code is that of the last command run from the file.",
+    ],
+};
+
+pub(crate) static RETURN: BuiltinHelp = BuiltinHelp {
+    usage: "return [n]",
+    summary: "Stop a sourced file with status n",
+    details: &[
+        "  Stop running the file being read by source or '.', which exits",
+        "  with status n (default: $?).  Outside a sourced file, return is",
+        "  an error.",
+    ],
+};
+
+pub(crate) static EVAL: BuiltinHelp = BuiltinHelp {
+    usage: "eval [args...]",
+    summary: "Run args as a command in the current shell",
+    details: &[
+        "  Join the arguments with spaces and run the result as commands in",
+        "  the current shell.  Exit code is that of the last command run.",
+    ],
+};
+
+pub(crate) static EXEC: BuiltinHelp = BuiltinHelp {
+    usage: "exec [command [args...]]",
+    summary: "Run command in place of the shell, or redirect the shell",
+    details: &[
+        "  No command: apply the redirections to the shell itself for the",
+        "  rest of the session, e.g. exec > log 2>&1 to log a script.",
+        "  exec 3> file opens fd 3 for later >&3; exec 3>&- closes it.",
+        "  With a command: run it in place of the shell, then exit with its code.",
+    ],
+};
+
+pub(crate) static COPROC: BuiltinHelp = BuiltinHelp {
+    usage: "coproc command [args...]",
+    summary: "Run command in the background with pipes to and from it",
+    details: &[
+        "  Start command as a background job whose stdin and stdout are pipes",
+        "  the shell keeps open:",
+        "    ${COPROC[1]}  fd that writes to its stdin:    echo hi >&${COPROC[1]}",
+        "    ${COPROC[0]}  fd that reads its stdout:       cat <&${COPROC[0]}",
+        "    $COPROC_PID   its process ID",
+        "  Close its input so it sees EOF with: eval \"exec ${COPROC[1]}>&-\"",
+    ],
+};
+
+pub(crate) static TIMEOUT: BuiltinHelp = BuiltinHelp {
+    usage: "timeout [-s signal] [-k duration] duration command [args...]",
+    summary: "Run command, signalling it after duration (1.5, 30s, 2m, 1h)",
+    details: &[
+        "  Run command in the foreground; if it is still running after duration,",
+        "  send it signal (default TERM).  -k: send KILL if it is still running",
+        "  that long after.  Durations are seconds, or end in s, m, h, or d;",
+        "  0 means no limit.  On Windows the command is always terminated.",
+        "  Exit code: 124 if it timed out, 137 if it was killed, 125 if timeout",
+        "  itself failed, else the command's own.  Like coreutils timeout, the",
+        "  command must be an external program, not a builtin or function.",
+    ],
+};
+
+pub(crate) static SHIFT: BuiltinHelp = BuiltinHelp {
+    usage: "shift [n]",
+    summary: "Drop the first n positional parameters",
+    details: &[
+        "  Drop the first n positional parameters (default 1), so $2",
+        "  becomes $1.  Fails without shifting if n is greater than $#.",
+    ],
+};
+
+pub(crate) static SET: BuiltinHelp = BuiltinHelp {
+    usage: "set [-Ceux] [-o name] [--] [args...]",
+    summary: "Set shell options (+ unsets) or $1, $2, ...",
+    details: &[
+        "  Turn shell options on (-) or off (+), then make any remaining",
+        "  args the positional parameters $1, $2, ...",
+        "  -e errexit  -u nounset  -x xtrace  -C noclobber  -n noexec",
+        "  -o name: set an option by name (pipefail, posix, bglog, ...).",
+        "  -o bglog=DIR: log background jobs in DIR and keep the logs.",
+        "  No arguments: print every shell variable; set -o or +o lists options.",
+    ],
+};
+
+pub(crate) static SHOPT: BuiltinHelp = BuiltinHelp {
+    usage: "shopt [-pqsu] [name...]",
+    summary: "Set, unset, or show shell options",
+    details: &[
+        "  Set (-s), unset (-u), or show shell options.  Showing fails if any",
+        "  named option is off; -q shows nothing, -p prints reusable commands.",
+        "  Options: autocd cdspell dotglob failglob huponexit jobtimes lastpipe",
+        "  nullglob pipewarn promptstatus wslpath xpg_echo",
+    ],
+};
+
+pub(crate) static TRAP: BuiltinHelp = BuiltinHelp {
+    usage: "trap [-lp] [action] signal...",
+    summary: "Run action on a signal or EXIT ('' ignores, - resets)",
+    details: &[
+        "  Run action when a signal arrives, or for EXIT when the shell exits.",
+        "  An empty action ignores the signal; '-' or a lone signal restores",
+        "  the default.  No arguments or -p: list the traps as reusable",
+        "  commands.  -l lists the signals.",
+    ],
+};
+
+pub(crate) static COMPLETE: BuiltinHelp = BuiltinHelp {
+    usage: "complete [-fdc] [-W words] [-C command] name...",
+    summary: "Set what Tab completes in each name's arguments",
+    details: &[
+        "complete -p [name...] | complete -r [name...]",
+        "  Set what Tab completes in the arguments of each name:",
+        "  -f files, -d directories, -c commands, -W the given words,",
+        "  -C the lines printed by command, which gets the command name,",
+        "  the word being completed, and the word before it as $1 $2 $3.",
+        "  -p prints specs in reusable form; -r removes them.",
+        "  Without a spec, the first word completes commands and later",
+        "  words complete file names.  After $ or ${, Tab completes",
+        "  variable names.",
+    ],
+};
+
+pub(crate) static WSLPATH: BuiltinHelp = BuiltinHelp {
+    usage: "wslpath [-u|-w|-m] path...",
+    summary: "Translate paths between WSL/MSYS and Windows",
+    details: &[
+        "  Print each path as the other side of WSL or MSYS names it:",
+        "  -u: Unix, C:\\Users -> /mnt/c/Users (the default)",
+        "  -w: Windows, /mnt/c/Users -> C:\\Users; other Linux paths go through",
+        "      \\\\wsl.localhost\\$WSL_DISTRO_NAME",
+        "  -m: Windows with forward slashes, C:/Users",
+        "  Under MSYS ($MSYSTEM set) drives are /c, /d, ... instead of /mnt/c.",
+        "  shopt -s wslpath: translate path arguments automatically for a program",
+        "  on the other side, e.g. a Windows .exe run from WSL, or wsl.exe run",
+        "  from Windows.",
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_builtin_is_in_the_overview() {
+        let builtins = BuiltinTable::new();
+        let mut out = Vec::new();
+        overview(&builtins, &mut out);
+        let out = String::from_utf8(out).unwrap();
+        for builtin in builtins.iter() {
+            let help = builtin.help().unwrap_or_else(|| panic!("{} has no help", builtin.name()));
+            assert!(out.contains(&format!("\n  {}", help.usage)), "{} is missing: {out}", builtin.name());
+            assert!(out.contains(&format!(" {}\n", help.summary)), "{} is missing: {out}", builtin.name());
+        }
+        assert!(out.contains("\n  shift [n]           Drop the first n positional parameters\n"), "{out}");
+        assert!(out.ends_with("Topics: variables  redirection  expansion  quotes  exit-codes  control-flow\n"));
+    }
+}
@@ -0,0 +1,366 @@
+use std::path::PathBuf;
+
+use crate::variables::Variables;
+
+/// History entries kept in memory, and lines kept in the history file, when
+/// `HISTSIZE` and `HISTFILESIZE` are unset.
+pub const DEFAULT_HISTORY_SIZE: usize = 10_000;
+
+/// Which commands are recorded and how many are kept, from `HISTSIZE`,
+/// `HISTFILESIZE`, and `HISTCONTROL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryPolicy {
+    /// Entries kept in memory (`HISTSIZE`).
+    pub size: usize,
+    /// Lines kept in the history file (`HISTFILESIZE`).
+    pub file_size: usize,
+    /// Skip a command that repeats the previous entry (`ignoredups`).
+    pub ignore_dups: bool,
+    /// Skip a command typed with a leading space (`ignorespace`).
+    pub ignore_space: bool,
+    /// Drop earlier copies of a command when it is recorded (`erasedups`).
+    pub erase_dups: bool,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_HISTORY_SIZE,
+            file_size: DEFAULT_HISTORY_SIZE,
+            ignore_dups: true,
+            ignore_space: false,
+            erase_dups: false,
+        }
+    }
+}
+
+impl HistoryPolicy {
+    /// Read the policy from shell variables, as bash does: a negative size
+    /// means no limit, and an unset or non-numeric one the default.
+    /// `HISTFILESIZE` defaults to `HISTSIZE`. `HISTCONTROL` is a
+    /// colon-separated list of `ignoredups`, `ignorespace`, `ignoreboth`, and
+    /// `erasedups`; while it is unset, consecutive duplicates are skipped.
+    pub fn from_variables(variables: &Variables) -> Self {
+        let size = parse_size(variables.get("HISTSIZE")).unwrap_or(DEFAULT_HISTORY_SIZE);
+        let file_size = parse_size(variables.get("HISTFILESIZE")).unwrap_or(size);
+        let mut policy = Self {
+            size,
+            file_size,
+            ..Self::default()
+        };
+        if let Some(control) = variables.get("HISTCONTROL") {
+            policy.ignore_dups = false;
+            for setting in control.split(':') {
+                match setting {
+                    "ignoredups" => policy.ignore_dups = true,
+                    "ignorespace" => policy.ignore_space = true,
+                    "ignoreboth" => {
+                        policy.ignore_dups = true;
+                        policy.ignore_space = true;
+                    }
+                    "erasedups" => policy.erase_dups = true,
+                    _ => {}
+                }
+            }
+        }
+        policy
+    }
+}
+
+fn parse_size(value: Option<&str>) -> Option<usize> {
+    let size: i64 = value?.trim().parse().ok()?;
+    Some(usize::try_from(size).unwrap_or(usize::MAX))
+}
+
+/// Whether `line` has anything [`expand`] might act on, so callers can skip
+/// fetching the full history for ordinary lines.
+pub fn needs_expansion(line: &str) -> bool {
+    line.starts_with('^') || line.contains('!')
+}
+
+/// Bash-style history expansion of an interactive line.
+///
+/// `!!` is the previous command, `!N` entry N (numbered from 1, as the
+/// history file is), `!-N` the Nth most recent, and `!prefix` the most recent
+/// command starting with `prefix`. A line starting with `^old^new` is the
+/// previous command with the first `old` replaced by `new`.
+///
+/// `history` is oldest first. Returns `None` when the line contains nothing
+/// to expand, so callers only echo lines that changed.
+pub fn expand(line: &str, history: &[String]) -> Result<Option<String>, String> {
+    if let Some(rest) = line.strip_prefix('^') {
+        return quick_substitution(rest, history).map(Some);
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut in_single_quote = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' => in_single_quote = !in_single_quote,
+            '\\' if !in_single_quote && i + 1 < chars.len() => {
+                expanded.push(c);
+                expanded.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '!' if !in_single_quote && !follows_dollar(&chars, i) => {
+                if let Some((event, len)) = event_spec(&chars[i + 1..]) {
+                    expanded.push_str(find_event(&event, history)?);
+                    changed = true;
+                    i += 1 + len;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        expanded.push(c);
+        i += 1;
+    }
+    Ok(changed.then_some(expanded))
+}
+
+/// `$!` and `${!name}` are parameter expansions, not history references.
+fn follows_dollar(chars: &[char], i: usize) -> bool {
+    match i {
+        0 => false,
+        1 => chars[0] == '$',
+        _ => chars[i - 1] == '$' || (chars[i - 1] == '{' && chars[i - 2] == '$'),
+    }
+}
+
+/// The event designator after a `!`, and how many chars it spans, or `None`
+/// if the `!` is literal (followed by a blank, `=`, `(`, `"`, or nothing).
+fn event_spec(chars: &[char]) -> Option<(String, usize)> {
+    match chars.first()? {
+        '!' => Some(("!".to_string(), 1)),
+        c if c.is_whitespace() || matches!(c, '=' | '(' | '"') => None,
+        '-' | '0'..='9' => {
+            let digits = chars[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let len = 1 + digits;
+            (chars[0] != '-' || digits > 0).then(|| (chars[..len].iter().collect(), len))
+        }
+        _ => {
+            let len = chars
+                .iter()
+                .take_while(|c| !c.is_whitespace() && !matches!(c, ';' | '&' | '|' | '(' | ')' | '<' | '>' | '"' | '\''))
+                .count();
+            (len > 0).then(|| (chars[..len].iter().collect(), len))
+        }
+    }
+}
+
+/// Look up the command an event designator refers to.
+fn find_event<'a>(event: &str, history: &'a [String]) -> Result<&'a str, String> {
+    let not_found = || format!("jsh: !{event}: event not found");
+    let entry = if event == "!" {
+        history.last()
+    } else if let Some(back) = event.strip_prefix('-') {
+        let back: usize = back.parse().map_err(|_| not_found())?;
+        history.len().checked_sub(back).and_then(|index| history.get(index))
+    } else if let Ok(number) = event.parse::<usize>() {
+        number.checked_sub(1).and_then(|index| history.get(index))
+    } else {
+        history.iter().rev().find(|entry| entry.starts_with(event))
+    };
+    entry.map(String::as_str).ok_or_else(not_found)
+}
+
+/// `^old^new[^]`: the previous command with the first `old` replaced.
+fn quick_substitution(spec: &str, history: &[String]) -> Result<String, String> {
+    let (old, new) = spec.split_once('^').unwrap_or((spec, ""));
+    let new = new.strip_suffix('^').unwrap_or(new);
+    let failed = || format!("jsh: ^{spec}: substitution failed");
+    let previous = history.last().ok_or_else(failed)?;
+    if old.is_empty() || !previous.contains(old) {
+        return Err(failed());
+    }
+    Ok(previous.replacen(old, new, 1))
+}
+
+/// Path to `~/.jsh_history`, or `None` when HOME is not set.
+pub fn history_file_path() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .map(|home| PathBuf::from(home).join(".jsh_history"))
+}
+
+/// Another shell's history file format, for `history --import` and
+/// `history --export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignHistory {
+    /// `~/.bash_history`: one command per line, each optionally preceded by
+    /// a `#<epoch>` timestamp line.
+    Bash,
+    /// `~/.local/share/fish/fish_history`: a YAML-like list of `- cmd:`
+    /// entries with backslash and newline escaped.
+    Fish,
+}
+
+impl ForeignHistory {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Self::Bash),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+
+    /// The commands in a history file of this format, oldest first, each
+    /// on one line as `~/.jsh_history` stores them.
+    pub fn import(self, text: &str) -> Vec<String> {
+        match self {
+            Self::Bash => text
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.is_empty() && !is_bash_timestamp(line))
+                .map(str::to_string)
+                .collect(),
+            Self::Fish => text
+                .lines()
+                .filter_map(|line| line.strip_prefix("- cmd: "))
+                .map(|cmd| one_line(&unescape_fish(cmd)))
+                .filter(|cmd| !cmd.is_empty())
+                .collect(),
+        }
+    }
+
+    /// `entries` written in this format. Fish entries are stamped `when`,
+    /// in seconds since the epoch, since jsh keeps no times of its own.
+    pub fn export(self, entries: &[String], when: u64) -> String {
+        let mut out = String::new();
+        for entry in entries {
+            match self {
+                Self::Bash => out.push_str(&format!("{entry}\n")),
+                Self::Fish => out.push_str(&format!(
+                    "- cmd: {}\n  when: {when}\n",
+                    entry.replace('\\', "\\\\")
+                )),
+            }
+        }
+        out
+    }
+}
+
+fn is_bash_timestamp(line: &str) -> bool {
+    line.strip_prefix('#')
+        .is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Undo fish's escaping of backslashes and newlines in a `cmd:` value.
+fn unescape_fish(cmd: &str) -> String {
+    let mut out = String::with_capacity(cmd.len());
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                out.push('\\');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                out.push('\n');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Join a multi-line command into one history line, as the reader does
+/// for commands typed over several lines.
+fn one_line(cmd: &str) -> String {
+    let lines: Vec<String> = cmd
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    crate::reader::history_entry(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vec<String> {
+        ["ls -l", "ssh host", "echo one two"].iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn policy_follows_hist_variables() {
+        let mut variables = Variables::new();
+        assert_eq!(HistoryPolicy::from_variables(&variables), HistoryPolicy::default());
+
+        variables.set("HISTSIZE", "5");
+        variables.set("HISTCONTROL", "ignorespace:erasedups");
+        let policy = HistoryPolicy::from_variables(&variables);
+        assert_eq!((policy.size, policy.file_size), (5, 5));
+        assert!(!policy.ignore_dups && policy.ignore_space && policy.erase_dups);
+
+        variables.set("HISTSIZE", "-1");
+        variables.set("HISTFILESIZE", "junk");
+        variables.set("HISTCONTROL", "ignoreboth");
+        let policy = HistoryPolicy::from_variables(&variables);
+        assert_eq!((policy.size, policy.file_size), (usize::MAX, usize::MAX));
+        assert!(policy.ignore_dups && policy.ignore_space && !policy.erase_dups);
+    }
+
+    #[test]
+    fn event_designators() {
+        let history = history();
+        assert_eq!(expand("!!", &history).unwrap().as_deref(), Some("echo one two"));
+        assert_eq!(expand("sudo !!", &history).unwrap().as_deref(), Some("sudo echo one two"));
+        assert_eq!(expand("!1", &history).unwrap().as_deref(), Some("ls -l"));
+        assert_eq!(expand("!-2", &history).unwrap().as_deref(), Some("ssh host"));
+        assert_eq!(expand("!ss; pwd", &history).unwrap().as_deref(), Some("ssh host; pwd"));
+        assert_eq!(expand("!nope", &history), Err("jsh: !nope: event not found".to_string()));
+        assert_eq!(expand("!9", &history), Err("jsh: !9: event not found".to_string()));
+    }
+
+    #[test]
+    fn literal_exclamation_marks_are_left_alone() {
+        let history = history();
+        for line in ["echo hi!", "[ ! -f x ]", "echo '!!'", r"echo \!!", "echo $!", "echo ${!name}", "a != b"] {
+            assert_eq!(expand(line, &history).unwrap(), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn quick_substitution_edits_previous_command() {
+        let history = history();
+        assert_eq!(expand("^one^three", &history).unwrap().as_deref(), Some("echo three two"));
+        assert_eq!(expand("^two^^", &history).unwrap().as_deref(), Some("echo one "));
+        assert!(expand("^zzz^y", &history).unwrap_err().contains("substitution failed"));
+    }
+
+    #[test]
+    fn bash_and_fish_histories_import_as_single_lines() {
+        let bash = "#1600000000\nls -la\n\n#1600000001\ngit status  \n#not-a-time\n";
+        assert_eq!(ForeignHistory::Bash.import(bash), vec!["ls -la", "git status", "#not-a-time"]);
+
+        let fish = "- cmd: echo a\\\\b\n  when: 1\n- cmd: if true\\n  echo x\\nend\n  when: 2\n  paths:\n    - x\n";
+        assert_eq!(
+            ForeignHistory::Fish.import(fish),
+            vec![r"echo a\b", "if true; echo x; end"]
+        );
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let entries = vec![r"printf 'a\n'".to_string(), "ls".to_string()];
+        for format in [ForeignHistory::Bash, ForeignHistory::Fish] {
+            let text = format.export(&entries, 42);
+            assert_eq!(format.import(&text), entries, "{format:?}");
+        }
+        assert_eq!(
+            ForeignHistory::Fish.export(&entries[..1], 42),
+            "- cmd: printf 'a\\\\n'\n  when: 42\n"
+        );
+        assert_eq!(ForeignHistory::from_name("zsh"), None);
+    }
+}
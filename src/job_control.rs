@@ -1,5 +1,81 @@
-#[cfg(unix)]
+#[cfg(any(unix, windows))]
 use std::io;
+#[cfg(any(unix, windows))]
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// What the shell is waiting on, as a `kill` target: minus the process
+/// group of a foreground job, the pid of a lone child such as a command
+/// substitution, or 0 at the prompt.
+#[cfg(unix)]
+static FOREGROUND: AtomicI32 = AtomicI32::new(0);
+
+/// Pass a Ctrl-C on to the foreground job. The terminal already does this
+/// when the job owns it, but not when jsh has no controlling terminal, is
+/// not the session leader, or got the SIGINT from `kill` rather than the
+/// keyboard. Safe to call from a signal handler.
+#[cfg(unix)]
+pub fn interrupt_foreground() {
+    let target = FOREGROUND.load(Ordering::SeqCst);
+    if target != 0 {
+        // SAFETY: kill has no memory-safety preconditions; a process that
+        // has already exited just gives ESRCH.
+        unsafe {
+            libc::kill(target, libc::SIGINT);
+        }
+    }
+}
+
+/// Signals an external command must start with at their default action,
+/// even though the shell itself survives them.
+#[cfg(unix)]
+pub(crate) const CHILD_DEFAULT_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTSTP, libc::SIGQUIT];
+
+/// Does nothing. The shell catches SIGTSTP and SIGQUIT with this rather than
+/// ignoring them: exec() keeps ignored signals ignored but resets caught
+/// ones to their defaults, so children need no fixing up between fork and
+/// exec, and can be started with posix_spawn.
+#[cfg(unix)]
+extern "C" fn discard_signal(_signal: libc::c_int) {}
+
+/// The disposition that makes the shell survive a signal without passing
+/// that on to the commands it runs.
+#[cfg(unix)]
+pub fn discarding_handler() -> libc::sighandler_t {
+    discard_signal as extern "C" fn(libc::c_int) as libc::sighandler_t
+}
+
+/// Whether the shell currently ignores `signal`, e.g. after `trap '' QUIT`.
+#[cfg(unix)]
+pub(crate) fn is_ignored(signal: libc::c_int) -> bool {
+    // SAFETY: a null new action only reads the current one.
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    unsafe { libc::sigaction(signal, std::ptr::null(), &mut action) == 0 && action.sa_sigaction == libc::SIG_IGN }
+}
+
+/// Stop the shell itself with SIGTSTP, for `suspend`, and return once
+/// something sends it SIGCONT.
+#[cfg(unix)]
+pub(crate) fn suspend_self() -> io::Result<()> {
+    // The shell catches SIGTSTP so Ctrl-Z at the prompt cannot stop it; take
+    // the default action for this one signal.
+    let previous = unsafe { libc::signal(libc::SIGTSTP, libc::SIG_DFL) };
+    if previous == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: raise has no memory-safety preconditions. The whole process
+    // stops here, and carries on from here when continued.
+    let rc = unsafe { libc::raise(libc::SIGTSTP) };
+    let err = io::Error::last_os_error();
+    unsafe {
+        libc::signal(libc::SIGTSTP, previous);
+    }
+    if rc != 0 {
+        return Err(err);
+    }
+    Ok(())
+}
 
 #[cfg(unix)]
 pub(crate) enum WaitOutcome {
@@ -50,8 +126,14 @@ pub(crate) fn send_continue_to_group(pgid: libc::pid_t) -> io::Result<()> {
         ));
     }
 
+    send_signal(-pgid, libc::SIGCONT)
+}
+
+/// Send `signal` to `pid`, or to the process group `-pid` when negative.
+#[cfg(unix)]
+pub(crate) fn send_signal(pid: libc::pid_t, signal: libc::c_int) -> io::Result<()> {
     loop {
-        let rc = unsafe { libc::kill(-pgid, libc::SIGCONT) };
+        let rc = unsafe { libc::kill(pid, signal) };
         if rc == 0 {
             return Ok(());
         }
@@ -64,6 +146,41 @@ pub(crate) fn send_continue_to_group(pgid: libc::pid_t) -> io::Result<()> {
     }
 }
 
+/// Marks a process group as the foreground job, for [`interrupt_foreground`],
+/// until dropped. A Ctrl-C that arrived before the job was marked, while it
+/// was still being started, is passed on as soon as it is.
+#[cfg(unix)]
+pub(crate) struct ForegroundJob;
+
+#[cfg(unix)]
+impl ForegroundJob {
+    pub(crate) fn new(pgid: libc::pid_t) -> Self {
+        FOREGROUND.store(-pgid, Ordering::SeqCst);
+        Self::deliver_missed_interrupt()
+    }
+
+    /// Mark a child that shares the shell's process group, which must be
+    /// signalled on its own.
+    pub(crate) fn process(pid: libc::pid_t) -> Self {
+        FOREGROUND.store(pid, Ordering::SeqCst);
+        Self::deliver_missed_interrupt()
+    }
+
+    fn deliver_missed_interrupt() -> Self {
+        if crate::traps::interrupted() {
+            interrupt_foreground();
+        }
+        Self
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ForegroundJob {
+    fn drop(&mut self) {
+        FOREGROUND.store(0, Ordering::SeqCst);
+    }
+}
+
 #[cfg(unix)]
 pub(crate) struct ForegroundTerminalGuard {
     tty_fd: Option<libc::c_int>,
@@ -113,7 +230,7 @@ pub(crate) fn wait_for_pid(pid: libc::pid_t) -> io::Result<WaitOutcome> {
             return Err(err);
         }
 
-        if unsafe { libc::WIFSTOPPED(raw_status) } {
+        if libc::WIFSTOPPED(raw_status) {
             return Ok(WaitOutcome::Stopped);
         }
 
@@ -172,3 +289,130 @@ fn set_terminal_foreground(fd: libc::c_int, pgid: libc::pid_t) -> io::Result<()>
         return Err(err);
     }
 }
+
+/// The terminal modes the prompt expects: saved when an interactive shell
+/// starts, and again after each foreground job that exits successfully.
+#[cfg(unix)]
+static TERMINAL_MODES: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Remember the current modes of the terminal on stdin, if it is one, as
+/// the ones to come back to after a foreground job.
+#[cfg(unix)]
+pub fn save_terminal_modes() {
+    // SAFETY: tcgetattr only writes into the termios it is given.
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut modes) } == 0 {
+        *TERMINAL_MODES.lock().unwrap_or_else(|e| e.into_inner()) = Some(modes);
+    }
+}
+
+/// Put the saved terminal modes back. Returns false if there are none or
+/// they could not be set.
+#[cfg(unix)]
+pub(crate) fn restore_terminal_modes() -> bool {
+    let Some(modes) = *TERMINAL_MODES.lock().unwrap_or_else(|e| e.into_inner()) else {
+        return false;
+    };
+    // SAFETY: modes came from tcgetattr on the same descriptor.
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &modes) == 0 }
+}
+
+/// Settle the terminal once a foreground job has given it back. A job that
+/// exited successfully may have changed the modes on purpose, as `stty`
+/// does, so they are kept from then on. Any other job, one that crashed or
+/// was stopped, may have left the terminal raw or without echo, so the
+/// saved modes are restored. Does nothing unless modes were saved.
+#[cfg(unix)]
+pub(crate) fn settle_terminal_modes(succeeded: bool) {
+    if TERMINAL_MODES.lock().unwrap_or_else(|e| e.into_inner()).is_none() {
+        return;
+    }
+    if succeeded {
+        save_terminal_modes();
+    } else {
+        restore_terminal_modes();
+    }
+}
+
+// ── Windows ──
+//
+// Console processes have no process groups to join and no signals. Each
+// external command is started as the leader of its own console process
+// group instead, which keeps a console Ctrl-C away from background jobs.
+// That also switches Ctrl-C off in the command, and a Ctrl-C cannot be
+// aimed at one group, so the shell passes it on as a Ctrl-Break, which
+// can. Stopping a job is approximated by suspending its processes.
+
+/// The console process groups of the foreground job, one per pipeline
+/// stage, for [`interrupt_foreground`].
+#[cfg(windows)]
+static FOREGROUND_GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Pass a Ctrl-C or Ctrl-Break on to the foreground job as a Ctrl-Break.
+/// The console handler runs on a thread of its own, not as a signal
+/// handler, so taking a lock here is fine.
+#[cfg(windows)]
+pub fn interrupt_foreground() {
+    use windows_sys::Win32::System::Console::{CTRL_BREAK_EVENT, GenerateConsoleCtrlEvent};
+
+    for &group in FOREGROUND_GROUPS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        // SAFETY: no pointers are involved; a group that has already gone
+        // just makes the call fail.
+        unsafe {
+            GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, group);
+        }
+    }
+}
+
+/// Marks console process groups as the foreground job, for
+/// [`interrupt_foreground`], until dropped. A Ctrl-C that arrived while the
+/// job was still being started is passed on as soon as it is marked.
+#[cfg(windows)]
+pub(crate) struct ForegroundJob;
+
+#[cfg(windows)]
+impl ForegroundJob {
+    /// `groups` are the ids of the job's processes, each of which leads
+    /// its own group.
+    pub(crate) fn new(groups: Vec<u32>) -> Self {
+        *FOREGROUND_GROUPS.lock().unwrap_or_else(|e| e.into_inner()) = groups;
+        if crate::traps::interrupted() {
+            interrupt_foreground();
+        }
+        Self
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ForegroundJob {
+    fn drop(&mut self) {
+        FOREGROUND_GROUPS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[cfg(windows)]
+#[link(name = "ntdll")]
+unsafe extern "system" {
+    fn NtSuspendProcess(process: windows_sys::Win32::Foundation::HANDLE) -> i32;
+    fn NtResumeProcess(process: windows_sys::Win32::Foundation::HANDLE) -> i32;
+}
+
+/// Suspend every thread of `child`, or resume them, the stand-in for
+/// SIGSTOP and SIGCONT. Suspensions nest: each needs its own resume.
+#[cfg(windows)]
+pub(crate) fn set_suspended(child: &std::process::Child, suspended: bool) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+
+    let handle = child.as_raw_handle() as windows_sys::Win32::Foundation::HANDLE;
+    // SAFETY: the handle belongs to `child` and stays open for the call.
+    let status = unsafe {
+        match suspended {
+            true => NtSuspendProcess(handle),
+            false => NtResumeProcess(handle),
+        }
+    };
+    match status {
+        0.. => Ok(()),
+        _ => Err(io::Error::other(format!("NTSTATUS {:#010x}", status as u32))),
+    }
+}
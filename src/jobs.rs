@@ -1,10 +1,65 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::Child;
+use std::time::{Duration, Instant};
 
 use crate::status;
 
+/// How one process of a job ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Termination {
+    Exited(i32),
+    /// Killed by this signal.
+    Signaled(i32),
+}
+
+impl Termination {
+    /// The exit status the shell reports: `128 + signal` for a signal.
+    pub fn code(self) -> i32 {
+        match self {
+            Termination::Exited(code) => code,
+            Termination::Signaled(signal) => 128 + signal,
+        }
+    }
+
+    /// What a Done notification calls it: `Done`, `Exit 3`, or the signal's
+    /// description, like `Terminated` or `Killed`.
+    pub fn describe(self) -> String {
+        match self {
+            Termination::Exited(0) => "Done".to_string(),
+            Termination::Exited(code) => format!("Exit {code}"),
+            Termination::Signaled(signal) => signal_description(signal),
+        }
+    }
+}
+
+/// bash's wording for a job killed by `signal`.
+fn signal_description(signal: i32) -> String {
+    let name = crate::traps::ALL_SIGNALS
+        .iter()
+        .find(|&&(_, number)| number == signal)
+        .map(|&(name, _)| name);
+    match name {
+        Some("HUP") => "Hangup".to_string(),
+        Some("INT") => "Interrupt".to_string(),
+        Some("QUIT") => "Quit".to_string(),
+        Some("ILL") => "Illegal instruction".to_string(),
+        Some("ABRT") => "Aborted".to_string(),
+        Some("FPE") => "Floating point exception".to_string(),
+        Some("KILL") => "Killed".to_string(),
+        Some("SEGV") => "Segmentation fault".to_string(),
+        Some("PIPE") => "Broken pipe".to_string(),
+        Some("ALRM") => "Alarm clock".to_string(),
+        Some("TERM") => "Terminated".to_string(),
+        Some(name) => format!("SIG{name}"),
+        None => format!("Signal {signal}"),
+    }
+}
+
 /// The lifecycle state of a tracked job.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum JobStatus {
     Running,
     Stopped,
@@ -19,12 +74,332 @@ pub struct Job {
     pub command: String,
     pub status: JobStatus,
     pub child: Child,
+    /// When the job was started, for its elapsed time.
+    pub started: Instant,
+    /// User plus system CPU time, known once the job has been reaped (Unix).
+    pub cpu_time: Option<Duration>,
+    /// The file the job's output goes to under `set -o bglog`.
+    pub log: Option<PathBuf>,
+    /// `disown -h`: left running when the shell hangs up its jobs on exit.
+    pub no_hup: bool,
+    /// The job has finished and its `Done` notice has been printed. It stays
+    /// in the table, so `wait %N` can still collect its status, until `jobs`
+    /// or `wait` forgets it.
+    pub reported: bool,
+    /// The earlier stages of a pipeline job; `child` is the last.
+    upstream: Vec<Child>,
+    /// Under `set -o pipefail` the job's status is that of its last stage
+    /// to fail rather than of its last stage.
+    pipefail: bool,
+    /// How `child` ended, once it has been reaped.
+    last_ended: Option<Termination>,
+    /// How each `upstream` stage ended, once reaped.
+    upstream_ended: Vec<Option<Termination>>,
+    /// The Job Object holding the job's processes, so that ending the job
+    /// also ends whatever they started (Windows).
+    #[cfg(windows)]
+    process_tree: Option<ProcessTree>,
+}
+
+impl Job {
+    /// The job's exit status if it has finished, without blocking.
+    pub fn poll_exit(&mut self) -> io::Result<Option<i32>> {
+        self.wait_exit(false)
+    }
+
+    /// Block until the job finishes and return its exit status.
+    pub fn wait_for_exit(&mut self) -> io::Result<i32> {
+        self.wait_exit(true).map(|code| code.unwrap_or(1))
+    }
+
+    /// Record that the last stage, already reaped elsewhere (by `fg`), exited
+    /// with `code`, and wait for the rest of the pipeline. Returns the job's
+    /// exit status.
+    pub fn finish(&mut self, code: i32) -> io::Result<i32> {
+        self.last_ended = Some(Termination::Exited(code));
+        self.wait_for_exit()
+    }
+
+    /// How the job ended, once every stage has: the last stage, or under
+    /// `pipefail` the last stage to fail.
+    pub fn termination(&self) -> Option<Termination> {
+        let last = self.last_ended?;
+        let upstream = self.upstream_ended.iter().copied().collect::<Option<Vec<_>>>()?;
+        if !self.pipefail {
+            return Some(last);
+        }
+        let failed = upstream.into_iter().chain([last]).rev().find(|ended| ended.code() != 0);
+        Some(failed.unwrap_or(last))
+    }
+
+    /// How a finished job ended, as its notice puts it: `Done`, `Exit 3`,
+    /// `Killed`, and so on.
+    pub fn done_state(&self) -> String {
+        self.termination().map_or_else(|| "Done".to_string(), Termination::describe)
+    }
+
+    /// `[1]+  Done    cmd`, `Killed`, and so on, for a finished job that
+    /// was marked `marker` in the job table.
+    pub fn done_message(&self, marker: char, show_times: bool) -> String {
+        let line = status_line(self.id, marker, &self.done_state(), &self.command);
+        match show_times {
+            true => format!("{line}  {}", self.times()),
+            false => line,
+        }
+    }
+
+    /// Reap whichever stages have ended, blocking for the rest if `block`,
+    /// and return the job's exit status once all of them have.
+    fn wait_exit(&mut self, block: bool) -> io::Result<Option<i32>> {
+        if self.last_ended.is_none() {
+            self.last_ended = self.reap_stage(None, block)?;
+        }
+        for stage in 0..self.upstream.len() {
+            if self.upstream_ended[stage].is_none() {
+                self.upstream_ended[stage] = self.reap_stage(Some(stage), block)?;
+            }
+        }
+        Ok(self.termination().map(Termination::code))
+    }
+
+    /// On Unix, `wait4` reaps the stage (`None` for `child`) and reports its
+    /// CPU usage too, which is added to the job's.
+    #[cfg(unix)]
+    fn reap_stage(&mut self, stage: Option<usize>, block: bool) -> io::Result<Option<Termination>> {
+        let pid = match stage {
+            Some(stage) => self.upstream[stage].id(),
+            None => self.pid,
+        };
+        let flags = if block { 0 } else { libc::WNOHANG };
+        let mut raw_status: libc::c_int = 0;
+        // SAFETY: rusage is plain data that wait4 fills in.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        loop {
+            // SAFETY: both out-pointers are valid for the duration of the call.
+            let rc = unsafe { libc::wait4(pid as libc::pid_t, &mut raw_status, flags, &mut usage) };
+            if rc < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                return Err(err);
+            }
+            if rc == 0 {
+                return Ok(None);
+            }
+            let seconds = |time: libc::timeval| {
+                Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+            };
+            let cpu = seconds(usage.ru_utime) + seconds(usage.ru_stime);
+            self.cpu_time = Some(self.cpu_time.unwrap_or_default() + cpu);
+            if libc::WIFSIGNALED(raw_status) {
+                return Ok(Some(Termination::Signaled(libc::WTERMSIG(raw_status))));
+            }
+            let code = status::exit_code_from_wait_status(raw_status).unwrap_or(1);
+            return Ok(Some(Termination::Exited(code)));
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn reap_stage(&mut self, stage: Option<usize>, block: bool) -> io::Result<Option<Termination>> {
+        let child = match stage {
+            Some(stage) => &mut self.upstream[stage],
+            None => &mut self.child,
+        };
+        let status = if block { Some(child.wait()?) } else { child.try_wait()? };
+        Ok(status.map(|status| Termination::Exited(status::exit_code(status))))
+    }
+
+    /// Suspend the job's processes, the stand-in for SIGSTOP on Windows.
+    /// Processes they started themselves keep running.
+    #[cfg(windows)]
+    pub fn suspend(&mut self) -> io::Result<()> {
+        if self.status == JobStatus::Running {
+            for stage in self.upstream.iter().chain([&self.child]) {
+                crate::job_control::set_suspended(stage, true)?;
+            }
+            self.status = JobStatus::Stopped;
+        }
+        Ok(())
+    }
+
+    /// Resume a job stopped with [`Job::suspend`], the stand-in for SIGCONT.
+    #[cfg(windows)]
+    pub fn resume(&mut self) -> io::Result<()> {
+        if self.status == JobStatus::Stopped {
+            for stage in self.upstream.iter().chain([&self.child]) {
+                crate::job_control::set_suspended(stage, false)?;
+            }
+            self.status = JobStatus::Running;
+        }
+        Ok(())
+    }
+
+    /// The ids of the job's processes, first stage first. On Windows each
+    /// leads its own console process group.
+    pub fn process_ids(&self) -> Vec<u32> {
+        self.upstream.iter().chain([&self.child]).map(Child::id).collect()
+    }
+
+    /// End the job, the stand-in for a signal where there are none. On
+    /// Windows this terminates its whole process tree, with `128 + signal`
+    /// as the exit status; failing that, only the job's own processes are
+    /// killed.
+    #[cfg(not(unix))]
+    pub fn terminate(&mut self, signal: i32) -> io::Result<()> {
+        #[cfg(windows)]
+        if let Some(tree) = &self.process_tree {
+            return tree.terminate((128 + signal) as u32);
+        }
+        #[cfg(not(windows))]
+        let _ = signal;
+        for stage in &mut self.upstream {
+            let _ = stage.kill();
+        }
+        self.child.kill()
+    }
+
+    /// `(real 2.01s, cpu 0.35s)`, for a Done notification. CPU time is left
+    /// out where it is not known.
+    pub fn times(&self) -> String {
+        let real = format_duration(self.started.elapsed());
+        match self.cpu_time {
+            Some(cpu) => format!("(real {real}, cpu {})", format_duration(cpu)),
+            None => format!("(real {real})"),
+        }
+    }
+}
+
+/// A duration as `0.42s`, `3m05s`, or `1h02m`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{:.2}s", duration.as_secs_f64()),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+/// A job's line in `jobs` and in notifications, laid out as bash does:
+/// `[1]+  Stopped                 vim`.
+pub fn status_line(id: usize, marker: char, state: &str, command: &str) -> String {
+    format!("[{id}]{marker}  {state:<22}  {command}")
+}
+
+fn marker_for(id: usize, current: Option<usize>, previous: Option<usize>) -> char {
+    match Some(id) {
+        id if id == current => '+',
+        id if id == previous => '-',
+        _ => ' ',
+    }
+}
+
+/// The environment variable a command substitution's child jsh finds its
+/// parent's jobs in, as [`JobTable::snapshot`] writes them.
+pub const JOBS_ENV: &str = "JSH_PARENT_JOBS";
+
+/// A job as `jobs` lists it, apart from the processes behind it. A subshell
+/// cannot own its parent's children, so it lists the parent's jobs from
+/// these.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobListing {
+    pub id: usize,
+    /// `+`, `-`, or a space, as the job was marked where it was started.
+    pub marker: char,
+    pub pid: u32,
+    pub pgid: u32,
+    pub status: JobStatus,
+    /// `Running`, `Stopped`, or how the job ended: `Done`, `Exit 3`, ...
+    pub state: String,
+    pub command: String,
+    pub started: Instant,
+    pub log: Option<PathBuf>,
+}
+
+impl JobListing {
+    /// One tab-separated line, read back by [`JobListing::parse`].
+    fn format(&self) -> String {
+        let status = match self.status {
+            JobStatus::Running => "running".to_string(),
+            JobStatus::Stopped => "stopped".to_string(),
+            JobStatus::Done(code) => format!("done:{code}"),
+        };
+        let log = self.log.as_ref().map(|path| path.to_string_lossy().into_owned()).unwrap_or_default();
+        format!(
+            "{}\t{}\t{}\t{}\t{status}\t{}\t{}\t{}\t{}",
+            self.id,
+            self.marker,
+            self.pid,
+            self.pgid,
+            escape_field(&self.state),
+            self.started.elapsed().as_millis(),
+            escape_field(&log),
+            escape_field(&self.command),
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [id, marker, pid, pgid, status, state, elapsed, log, command] = fields[..] else {
+            return None;
+        };
+        let status = match status {
+            "running" => JobStatus::Running,
+            "stopped" => JobStatus::Stopped,
+            _ => JobStatus::Done(status.strip_prefix("done:")?.parse().ok()?),
+        };
+        let elapsed = Duration::from_millis(elapsed.parse().ok()?);
+        let log = unescape_field(log);
+        Some(Self {
+            id: id.parse().ok()?,
+            marker: marker.chars().next()?,
+            pid: pid.parse().ok()?,
+            pgid: pgid.parse().ok()?,
+            status,
+            state: unescape_field(state),
+            command: unescape_field(command),
+            started: Instant::now().checked_sub(elapsed).unwrap_or_else(Instant::now),
+            log: (!log.is_empty()).then(|| PathBuf::from(log)),
+        })
+    }
+}
+
+/// `text` with backslashes, tabs, and newlines escaped, so it fits in one
+/// field of a [`JobListing`] line.
+fn escape_field(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape_field(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
 }
 
 /// The shell's job table — tracks all background and stopped jobs.
 pub struct JobTable {
     jobs: HashMap<usize, Job>,
     next_id: usize,
+    /// PID of the most recently started background job, for `$!`.
+    last_background_pid: Option<u32>,
+    /// Job IDs, least recently started, stopped, or resumed first; the
+    /// current and previous jobs are picked from the end.
+    recency: Vec<usize>,
+    /// In a subshell, the jobs of the shell it was started from. `jobs`
+    /// lists them, but they are not this shell's to wait for or control.
+    inherited: Vec<JobListing>,
 }
 
 impl Default for JobTable {
@@ -38,9 +413,63 @@ impl JobTable {
         Self {
             jobs: HashMap::new(),
             next_id: 1,
+            last_background_pid: None,
+            recency: Vec::new(),
+            inherited: Vec::new(),
         }
     }
 
+    /// A table for a subshell of the shell holding `listings`, which lists
+    /// those jobs and numbers its own after them.
+    pub fn inheriting(listings: Vec<JobListing>) -> Self {
+        let next_id = listings.iter().map(|listing| listing.id + 1).max().unwrap_or(1);
+        Self {
+            next_id,
+            inherited: listings,
+            ..Self::new()
+        }
+    }
+
+    /// [`JobTable::inheriting`] the listings in `snapshot`, as
+    /// [`JobTable::snapshot`] wrote them.
+    pub fn from_snapshot(snapshot: &str) -> Self {
+        Self::inheriting(snapshot.lines().filter_map(JobListing::parse).collect())
+    }
+
+    /// This table's listings, one job a line, for passing to a child jsh.
+    pub fn snapshot(&self) -> String {
+        self.listings().iter().map(|listing| listing.format() + "\n").collect()
+    }
+
+    /// Every job `jobs` lists, by ID: this shell's, less those finished and
+    /// already reported, and any inherited from its parent.
+    pub fn listings(&self) -> Vec<JobListing> {
+        let (current, previous) = self.current_and_previous();
+        let own = self.jobs.values().filter(|job| !job.reported).map(|job| JobListing {
+            id: job.id,
+            marker: marker_for(job.id, current, previous),
+            pid: job.pid,
+            pgid: job.pgid,
+            status: job.status,
+            state: match job.status {
+                JobStatus::Running => "Running".to_string(),
+                JobStatus::Stopped => "Stopped".to_string(),
+                JobStatus::Done(_) => job.done_state(),
+            },
+            command: job.command.clone(),
+            started: job.started,
+            log: job.log.clone(),
+        });
+        let mut listings: Vec<JobListing> = self.inherited.iter().cloned().chain(own).collect();
+        listings.sort_by_key(|listing| listing.id);
+        listings
+    }
+
+    /// The ID the next job added will get.
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
+
     /// Add a running background job. Returns `(job_id, pid)`.
     pub fn add(&mut self, child: Child, command: String) -> (usize, u32) {
         let pgid = child.id();
@@ -51,6 +480,8 @@ impl JobTable {
     pub fn add_with_pgid(&mut self, child: Child, command: String, pgid: u32) -> (usize, u32) {
         let id = self.next_id;
         let pid = child.id();
+        #[cfg(windows)]
+        let process_tree = ProcessTree::containing(&child);
         self.jobs.insert(
             id,
             Job {
@@ -60,12 +491,31 @@ impl JobTable {
                 command,
                 status: JobStatus::Running,
                 child,
+                started: Instant::now(),
+                cpu_time: None,
+                log: None,
+                no_hup: false,
+                reported: false,
+                upstream: Vec::new(),
+                pipefail: false,
+                last_ended: None,
+                upstream_ended: Vec::new(),
+                #[cfg(windows)]
+                process_tree,
             },
         );
         self.next_id += 1;
+        self.last_background_pid = Some(pid);
+        self.recency.push(id);
         (id, pid)
     }
 
+    /// PID of the most recently started background job (`$!`). For a
+    /// pipeline this is its last process. It outlives the job itself.
+    pub fn last_background_pid(&self) -> Option<u32> {
+        self.last_background_pid
+    }
+
     /// Add a job that has already been stopped (e.g. via Ctrl-Z). Returns `(job_id, pid)`.
     pub fn add_stopped(&mut self, child: Child, command: String) -> (usize, u32) {
         let pgid = child.id();
@@ -86,42 +536,96 @@ impl JobTable {
         (id, pid)
     }
 
-    /// Non-blocking poll of all running jobs. Prints `[N]  Done  cmd` for
-    /// any that have finished and removes them from the table.
-    pub fn reap(&mut self) {
-        let mut done_ids = Vec::new();
-
+    /// Non-blocking poll of all running jobs, marking those that have
+    /// finished `Done` with their exit status.
+    pub fn poll(&mut self) {
         for (id, job) in self.jobs.iter_mut() {
             if job.status != JobStatus::Running {
                 continue;
             }
-            match job.child.try_wait() {
-                Ok(Some(status)) => {
-                    let code = status::exit_code(status);
-                    job.status = JobStatus::Done(code);
-                    println!("[{}]  Done  {}", job.id, job.command);
-                    done_ids.push(*id);
-                }
+            match job.poll_exit() {
+                Ok(Some(code)) => job.status = JobStatus::Done(code),
                 Ok(None) => {} // still running
                 Err(e) => {
                     eprintln!("jsh: error checking job {}: {}", id, e);
                 }
             }
         }
+    }
 
-        for id in done_ids {
-            self.jobs.remove(&id);
+    /// [`JobTable::poll`], then print `[N]  Done  cmd` for each finished job
+    /// not yet reported, followed by its run times if `show_times`. As in
+    /// bash the jobs stay in the table with their status until `jobs` has
+    /// listed them or `wait` has collected them.
+    pub fn reap(&mut self, show_times: bool) {
+        self.poll();
+        let (current, previous) = self.current_and_previous();
+        let unreported: Vec<usize> = self
+            .jobs_sorted()
+            .into_iter()
+            .filter(|job| matches!(job.status, JobStatus::Done(_)) && !job.reported)
+            .map(|job| job.id)
+            .collect();
+        for id in unreported {
+            if let Some(job) = self.jobs.get_mut(&id) {
+                println!("{}", job.done_message(marker_for(id, current, previous), show_times));
+                job.reported = true;
+            }
+        }
+    }
+
+    /// Remove every finished job, once `jobs` or `wait` has dealt with it.
+    pub fn forget_done(&mut self) {
+        let done: Vec<usize> = self
+            .jobs
+            .values()
+            .filter(|job| matches!(job.status, JobStatus::Done(_)))
+            .map(|job| job.id)
+            .collect();
+        for id in done {
+            self.remove(id);
         }
     }
 
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.get(&id)
+    }
+
     pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
         self.jobs.get_mut(&id)
     }
 
+    /// Attach the earlier stages of a pipeline to job `id`, whose `child` is
+    /// its last stage, so they are reaped and counted in its status.
+    pub fn set_upstream(&mut self, id: usize, upstream: Vec<Child>, pipefail: bool) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            #[cfg(windows)]
+            if let Some(tree) = &job.process_tree
+                && !upstream.iter().all(|stage| tree.add(stage))
+            {
+                // Kill the stages one by one rather than miss one.
+                job.process_tree = None;
+            }
+            job.upstream_ended = vec![None; upstream.len()];
+            job.upstream = upstream;
+            job.pipefail = pipefail;
+        }
+    }
+
     pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recency.retain(|&other| other != id);
         self.jobs.remove(&id)
     }
 
+    /// Make `id` the most recently used job, as when it stops or is resumed
+    /// with `fg` or `bg`, so it becomes the current job.
+    pub fn mark_current(&mut self, id: usize) {
+        if self.jobs.contains_key(&id) {
+            self.recency.retain(|&other| other != id);
+            self.recency.push(id);
+        }
+    }
+
     /// All jobs sorted by job ID (ascending).
     pub fn jobs_sorted(&self) -> Vec<&Job> {
         let mut list: Vec<&Job> = self.jobs.values().collect();
@@ -129,28 +633,303 @@ impl JobTable {
         list
     }
 
-    /// Job ID of the most recently added job (any status), for use as the
-    /// `fg` / `bg` default when no argument is given.
-    pub fn most_recent_id(&self) -> Option<usize> {
-        self.jobs.keys().copied().max()
-    }
-
-    /// Job ID of the most recently added *stopped* job, for use as the
+    /// Job ID of the most recently used *stopped* job, for use as the
     /// default target when `bg` is called with no arguments.
     pub fn most_recent_stopped_id(&self) -> Option<usize> {
-        self.jobs
+        self.recency
             .iter()
-            .filter(|(_, j)| j.status == JobStatus::Stopped)
-            .map(|(id, _)| *id)
-            .max()
+            .rev()
+            .copied()
+            .find(|id| self.jobs.get(id).is_some_and(|j| j.status == JobStatus::Stopped))
     }
 
-    /// IDs of all currently running (not stopped/done) jobs, for `wait`.
-    pub fn running_ids(&self) -> Vec<usize> {
-        self.jobs
+    /// The current (`+`) and previous (`-`) jobs, as bash marks them: stopped
+    /// jobs come before running ones, and among each the most recently
+    /// started, stopped, or resumed comes first. A finished job keeps its
+    /// mark until it has been reported.
+    pub fn current_and_previous(&self) -> (Option<usize>, Option<usize>) {
+        let (stopped, running): (Vec<&Job>, Vec<&Job>) = self
+            .recency
             .iter()
-            .filter(|(_, j)| j.status == JobStatus::Running)
-            .map(|(id, _)| *id)
+            .rev()
+            .filter_map(|id| self.jobs.get(id))
+            .filter(|j| !j.reported)
+            .partition(|j| j.status == JobStatus::Stopped);
+        let mut order = stopped.into_iter().chain(running).map(|j| j.id);
+        (order.next(), order.next())
+    }
+
+    /// `+` for the current job, `-` for the previous one, else a space.
+    pub fn marker(&self, id: usize) -> char {
+        let (current, previous) = self.current_and_previous();
+        marker_for(id, current, previous)
+    }
+
+    /// Resolve a job spec as `fg`, `bg`, `wait`, and `kill` accept it: `%N`
+    /// or `N` for job N, `%%` or `%+` for the current job, `%-` for the
+    /// previous one, `%str` for the job whose command starts with `str`, and
+    /// `%?str` for the one whose command contains it. A job ID must be in
+    /// the table.
+    pub fn resolve_spec(&self, spec: &str) -> Result<usize, String> {
+        let (current, previous) = self.current_and_previous();
+        let matches = |found: Option<usize>| found.ok_or_else(|| format!("{spec}: no such job"));
+        let Some(name) = spec.strip_prefix('%') else {
+            let id = spec.parse().map_err(|_| format!("{spec}: invalid job id"))?;
+            return matches(Some(id).filter(|id| self.jobs.contains_key(id)));
+        };
+        match name {
+            "" | "%" | "+" => return matches(current),
+            "-" => return matches(previous),
+            _ => {}
+        }
+        if let Ok(id) = name.parse() {
+            return matches(Some(id).filter(|id| self.jobs.contains_key(id)));
+        }
+        let mut found = self.jobs_sorted().into_iter().filter(|job| !job.reported).filter(|job| {
+            match name.strip_prefix('?') {
+                Some(text) => job.command.contains(text),
+                None => job.command.starts_with(name),
+            }
+        });
+        match (found.next(), found.next()) {
+            (Some(job), None) => Ok(job.id),
+            (Some(_), Some(_)) => Err(format!("{spec}: ambiguous job spec")),
+            (None, _) => Err(format!("{spec}: no such job")),
+        }
+    }
+
+    /// Resolve a job spec as `jobs` accepts it: as [`JobTable::resolve_spec`]
+    /// does, except that in a subshell `%N` or `N` may also name one of the
+    /// parent's jobs, which it can list but not control.
+    pub fn resolve_listed_spec(&self, spec: &str) -> Result<usize, String> {
+        let inherited = spec
+            .strip_prefix('%')
+            .unwrap_or(spec)
+            .parse()
+            .ok()
+            .filter(|id| self.inherited.iter().any(|job| job.id == *id));
+        match inherited {
+            Some(id) => Ok(id),
+            None => self.resolve_spec(spec),
+        }
+    }
+
+    /// The jobs [`JobTable::hang_up`] would signal, sorted by job ID: the
+    /// stopped ones, which nothing could resume once the shell has gone, and
+    /// the running ones too if `running`. Jobs marked with `disown -h` are
+    /// left out.
+    pub fn hang_up_targets(&self, running: bool) -> Vec<&Job> {
+        self.jobs_sorted()
+            .into_iter()
+            .filter(|job| !job.no_hup)
+            .filter(|job| job.status == JobStatus::Stopped || (running && job.status == JobStatus::Running))
             .collect()
     }
+
+    /// Send SIGHUP (and SIGCONT so stopped jobs can receive it) to the
+    /// process group of each of [`JobTable::hang_up_targets`] as the shell
+    /// exits. Errors, such as ESRCH for a job that has already gone, are
+    /// ignored: this is best-effort cleanup.
+    #[cfg(unix)]
+    pub fn hang_up(&self, running: bool) {
+        for job in self.hang_up_targets(running) {
+            // SAFETY: kill has no memory-safety preconditions.
+            unsafe {
+                libc::kill(-(job.pgid as libc::pid_t), libc::SIGHUP);
+                libc::kill(-(job.pgid as libc::pid_t), libc::SIGCONT);
+            }
+        }
+    }
+
+    /// Without signals to send, hanging up a job ends it: see
+    /// [`Job::terminate`]. Errors are ignored here too.
+    #[cfg(not(unix))]
+    pub fn hang_up(&mut self, running: bool) {
+        const HUP: i32 = 1;
+        let ids: Vec<usize> = self.hang_up_targets(running).iter().map(|job| job.id).collect();
+        for id in ids {
+            if let Some(job) = self.jobs.get_mut(&id) {
+                let _ = job.terminate(HUP);
+            }
+        }
+    }
+
+    /// IDs of the jobs a bare `wait` collects, in ID order: those still
+    /// running and those finished but not yet forgotten. Stopped jobs are
+    /// left alone.
+    pub fn waitable_ids(&self) -> Vec<usize> {
+        self.jobs_sorted()
+            .into_iter()
+            .filter(|job| job.status != JobStatus::Stopped)
+            .map(|job| job.id)
+            .collect()
+    }
+}
+
+/// The directory for `set -o bglog` logs when none is given: `jsh-UID`
+/// in the temp directory, made if missing and refused unless it is a
+/// directory of the user's own that nobody else may enter.
+pub(crate) fn private_log_dir() -> io::Result<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+        // SAFETY: getuid cannot fail.
+        let uid = unsafe { libc::getuid() };
+        let dir = std::env::temp_dir().join(format!("jsh-{uid}"));
+        match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        let meta = std::fs::symlink_metadata(&dir)?;
+        if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+            return Err(io::Error::other(format!("{}: not a private directory", dir.display())));
+        }
+        Ok(dir)
+    }
+    // %TEMP% is already the user's own.
+    #[cfg(not(unix))]
+    {
+        let dir = std::env::temp_dir().join("jsh");
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// A new log file in `dir` for job `job_id`, readable only by the user.
+/// It is always a file made here: an existing name, a leftover from a
+/// reused job number or a planted symlink, is skipped for the next one.
+pub(crate) fn create_log(dir: &Path, job_id: usize) -> io::Result<(File, PathBuf)> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let pid = std::process::id();
+    for attempt in 0..100 {
+        let name = match attempt {
+            0 => format!("{pid}-job{job_id}.log"),
+            n => format!("{pid}-job{job_id}-{n}.log"),
+        };
+        let path = dir.join(name);
+        match options.open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{}: no free log name", dir.display())))
+}
+
+/// A Win32 Job Object: the processes put in it, and every process they
+/// start, can be terminated together, as `kill(-pgid)` does on Unix.
+///
+/// It is not made kill-on-close, so jobs that are disowned or left running
+/// when the shell exits carry on. A process can start children before it is
+/// added, and those are missed.
+#[cfg(windows)]
+struct ProcessTree(windows_sys::Win32::Foundation::HANDLE);
+
+// SAFETY: a Job Object handle is a kernel handle, not tied to the thread
+// that made it: any thread may use or close it. The wrapper owns it
+// outright, so moving it cannot leave it shared. This keeps `Shell` `Send`,
+// as running a builtin pipeline stage on its own thread needs.
+#[cfg(windows)]
+unsafe impl Send for ProcessTree {}
+
+#[cfg(windows)]
+impl ProcessTree {
+    /// A new Job Object holding `child`, or `None` if one could not be made.
+    fn containing(child: &Child) -> Option<Self> {
+        use windows_sys::Win32::System::JobObjects::CreateJobObjectW;
+
+        // SAFETY: null attributes and name ask for a default, unnamed object.
+        let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if handle.is_null() {
+            return None;
+        }
+        let tree = ProcessTree(handle);
+        tree.add(child).then_some(tree)
+    }
+
+    /// Put `child` in the Job Object. Returns whether it went in.
+    fn add(&self, child: &Child) -> bool {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::JobObjects::AssignProcessToJobObject;
+
+        // SAFETY: both handles are open for the duration of the call.
+        unsafe { AssignProcessToJobObject(self.0, child.as_raw_handle() as _) != 0 }
+    }
+
+    fn terminate(&self, exit_code: u32) -> io::Result<()> {
+        use windows_sys::Win32::System::JobObjects::TerminateJobObject;
+
+        // SAFETY: the handle stays open until the object is dropped.
+        match unsafe { TerminateJobObject(self.0, exit_code) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        // SAFETY: the handle is closed exactly once, here.
+        unsafe { windows_sys::Win32::Foundation::CloseHandle(self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminations_describe_themselves_as_bash_does() {
+        assert_eq!(Termination::Exited(0).describe(), "Done");
+        assert_eq!(Termination::Exited(3).describe(), "Exit 3");
+        assert_eq!(Termination::Signaled(9).describe(), "Killed");
+        assert_eq!(Termination::Signaled(15).describe(), "Terminated");
+        assert_eq!(Termination::Signaled(15).code(), 143);
+    }
+
+    #[test]
+    fn status_lines_line_up_as_in_bash() {
+        assert_eq!(status_line(1, '+', "Stopped", "vim"), "[1]+  Stopped                 vim");
+        assert_eq!(status_line(12, ' ', "Done", "make"), "[12]   Done                    make");
+        assert_eq!(marker_for(2, Some(2), Some(1)), '+');
+        assert_eq!(marker_for(1, Some(2), Some(1)), '-');
+        assert_eq!(marker_for(3, Some(2), None), ' ');
+    }
+
+    #[test]
+    fn durations_are_formatted_by_size() {
+        assert_eq!(format_duration(Duration::from_millis(420)), "0.42s");
+        assert_eq!(format_duration(Duration::from_secs(185)), "3m05s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h02m");
+    }
+
+    #[test]
+    fn listings_survive_a_snapshot() {
+        let listing = JobListing {
+            id: 3,
+            marker: ' ',
+            pid: 120,
+            pgid: 118,
+            status: JobStatus::Done(2),
+            state: "Exit 2".to_string(),
+            command: "printf 'a\\tb\\n' | grep \"x\ty\"".to_string(),
+            started: Instant::now(),
+            log: Some(PathBuf::from("/tmp/jsh 3.log")),
+        };
+        let table = JobTable::inheriting(vec![listing.clone()]);
+        let [read] = &JobTable::from_snapshot(&table.snapshot()).listings()[..] else {
+            panic!("expected one listing");
+        };
+        assert_eq!(read.command, listing.command);
+        assert_eq!(read.state, listing.state);
+        assert_eq!(read.log, listing.log);
+        assert_eq!((read.id, read.marker, read.pid, read.pgid, read.status), (3, ' ', 120, 118, JobStatus::Done(2)));
+        assert_eq!(table.next_id(), 4);
+    }
 }
@@ -1,11 +1,35 @@
+pub mod aliases;
 pub mod ast;
 pub mod builtins;
+pub mod cli;
+pub mod command_hash;
+pub mod completion;
+#[cfg(feature = "coreutils")]
+pub mod coreutils;
+pub mod dynamic;
 pub mod editor;
+pub mod error;
 pub mod executor;
 pub mod expander;
+pub mod help;
+pub mod history;
 pub mod job_control;
 pub mod jobs;
+pub mod notify;
+pub mod options;
 pub mod parser;
+pub mod printf;
+pub mod prompt;
+pub mod reader;
 pub mod redirect;
 pub mod script_parser;
+pub mod shell;
 pub mod status;
+pub mod times;
+pub mod timeout;
+pub mod traps;
+pub mod variables;
+pub mod wslpath;
+
+pub use error::JshError;
+pub use shell::{ExitStatus, Shell};
@@ -1,32 +1,140 @@
 use james_shell::{
-    ast::Connector,
-    editor::{LineEditor, EDITOR_ACTIVE},
-    executor, expander,
-    jobs::JobTable,
-    parser, redirect, script_parser,
+    cli::{self, Mode},
+    completion::Completer,
+    editor::{self, LineEditor, EDITOR_ACTIVE},
+    executor,
+    job_control,
+    jobs,
+    notify,
+    history::{self, HistoryPolicy},
+    prompt,
+    reader::{CommandReader, Input, LineSource, ScriptLines},
+    Shell,
+    traps,
 };
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
 use std::sync::atomic::Ordering;
+use std::time::Instant;
 
-/// Send SIGHUP (and SIGCONT so stopped jobs can receive it) to every tracked
-/// job's process group when the shell exits.
-/// Errors (e.g. ESRCH for already-exited jobs) are silently ignored —
-/// this is best-effort cleanup and must not disrupt the shell's exit path.
-#[cfg(unix)]
-fn send_sighup_to_jobs(job_table: &james_shell::jobs::JobTable) {
-    for job in job_table.jobs_sorted() {
-        // Skip jobs that have already finished — kill(-pgid, …) would return
-        // ESRCH and is harmless, but filtering avoids unnecessary syscalls.
-        if matches!(job.status, james_shell::jobs::JobStatus::Done(_)) {
-            continue;
+/// Feeds the REPL from the line editor, showing the primary prompt for a new
+/// command and the continuation prompt while one is unfinished.
+struct EditorInput<'a> {
+    editor: &'a mut LineEditor,
+    prompt: &'a str,
+    continuation_prompt: &'a str,
+    /// Apply `!!`-style history expansion to each line read.
+    expand_history: bool,
+    /// Answers Tab completion requests.
+    completer: &'a dyn Completer,
+    /// Lines read so far of the command being entered, which Ctrl-Z stashes
+    /// along with the current one.
+    lines: Vec<String>,
+}
+
+impl LineSource for EditorInput<'_> {
+    fn read_line(&mut self, continuation: bool) -> io::Result<Option<String>> {
+        let prompt = if continuation {
+            self.continuation_prompt
+        } else {
+            self.lines.clear();
+            self.prompt
+        };
+        self.editor.set_pending_lines(&self.lines);
+        let line = self.editor.read_line(prompt, self.completer)?;
+        if let Some(line) = &line {
+            self.lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+        if !self.expand_history {
+            return Ok(line);
+        }
+        let Some(line) = line else {
+            return Ok(None);
+        };
+        if !history::needs_expansion(&line) {
+            return Ok(Some(line));
+        }
+        match history::expand(line.trim_end_matches(['\n', '\r']), self.editor.history()) {
+            Ok(Some(expanded)) => {
+                // Show what is about to run, as bash does.
+                eprintln!("{expanded}");
+                Ok(Some(expanded))
+            }
+            Ok(None) => Ok(Some(line)),
+            Err(message) => {
+                // The line is dropped; a blank line reads as no command.
+                eprintln!("{message}");
+                Ok(Some(String::new()))
+            }
+        }
+    }
+}
+
+/// `name` in the user's home directory.
+fn home_file(name: &str) -> Option<PathBuf> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .map(|home| PathBuf::from(home).join(name))
+}
+
+/// Path of the startup file: `$JSH_RC` if set (an empty value disables the
+/// startup file), otherwise `~/.jshrc`.
+fn rc_file_path() -> Option<(PathBuf, bool)> {
+    if let Some(path) = std::env::var_os("JSH_RC") {
+        return (!path.is_empty()).then(|| (PathBuf::from(path), true));
+    }
+    home_file(".jshrc").map(|path| (path, false))
+}
+
+/// Source a startup file so exports, aliases, and options persist across
+/// sessions. A missing file is silently skipped unless `explicit`; errors
+/// inside the file are reported by the shell but do not stop it from
+/// starting.
+fn load_startup_file(shell: &mut Shell, file: Option<(PathBuf, bool)>) -> executor::ExecutionAction {
+    match file {
+        Some((path, explicit)) if explicit || path.is_file() => {
+            let action = shell.source_file(&path);
+            // A failing rc file should not leave the first prompt with $? set.
+            if let executor::ExecutionAction::Continue(_) = action {
+                shell.last_exit_code = 0;
+            }
+            action
         }
-        // SAFETY: pgid is valid, signals are standard values, return ignored intentionally.
-        unsafe {
-            // Ignore return values: ESRCH means the process group is already gone.
-            libc::kill(-(job.pgid as libc::pid_t), libc::SIGHUP);
-            libc::kill(-(job.pgid as libc::pid_t), libc::SIGCONT);
+        _ => executor::ExecutionAction::Continue(0),
+    }
+}
+
+/// `jsh --check [file...]`: parse each script (stdin if none) without
+/// running it and report every syntax error. The status is 2 if any script
+/// has a syntax error, 1 if one could not be read, and 0 otherwise.
+fn check_scripts(shell: &Shell, paths: &[String]) -> i32 {
+    let scripts: Vec<(&str, io::Result<String>)> = if paths.is_empty() {
+        let mut script = String::new();
+        let read = io::stdin().read_to_string(&mut script).map(|_| script);
+        vec![("stdin", read)]
+    } else {
+        paths
+            .iter()
+            .map(|path| (path.as_str(), std::fs::read_to_string(path)))
+            .collect()
+    };
+
+    let mut status = 0;
+    for (name, script) in scripts {
+        match script {
+            Ok(script) => {
+                if shell.check_source(&mut ScriptLines::new(&script), name) > 0 {
+                    status = 2;
+                }
+            }
+            Err(e) => {
+                eprintln!("jsh: {name}: {e}");
+                status = status.max(1);
+            }
         }
     }
+    status
 }
 
 fn main() {
@@ -38,6 +146,9 @@ fn main() {
             println!();
             let _ = io::stdout().flush();
         }
+        traps::record_interrupt();
+        #[cfg(any(unix, windows))]
+        job_control::interrupt_foreground();
     })
     .expect("Failed to set Ctrl-C handler");
 
@@ -47,38 +158,200 @@ fn main() {
         // Shell must survive Ctrl-Z, Ctrl-\, and broken pipes at the prompt.
         // SIGINT is already handled by the ctrlc crate above (prints newline, EINTR).
         //
-        // IMPORTANT: SIG_IGN is inherited by forked children AND survives exec().
-        // That means every child spawned after this point would also ignore these
-        // signals — which is wrong. The pre_exec block in executor.rs explicitly
-        // resets them back to SIG_DFL before exec() to restore correct child behavior.
-        libc::signal(libc::SIGTSTP, libc::SIG_IGN);
-        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+        // SIGTSTP and SIGQUIT are caught and discarded rather than ignored:
+        // SIG_IGN would survive exec() into every child, while a handler is
+        // reset to SIG_DFL there. The standard library resets SIGPIPE itself.
+        libc::signal(libc::SIGTSTP, job_control::discarding_handler());
+        libc::signal(libc::SIGQUIT, job_control::discarding_handler());
         libc::signal(libc::SIGPIPE, libc::SIG_IGN);
     }
 
-    let mut last_exit_code: i32 = 0;
-    let mut job_table = JobTable::new();
-    let mut editor = LineEditor::new();
+    let mut shell = Shell::new();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let invocation = match cli::parse(&args) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("{message}");
+            eprintln!("{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    for (name, on) in &invocation.options {
+        shell.options.set(name, *on);
+    }
+    shell.set_positional_params(invocation.args.clone());
+    if let Some(name) = &invocation.name {
+        shell.name = name.clone();
+    }
+
+    // Child shells spawned for command substitution and whole-chain background
+    // jobs are fed their script on stdin. They must not print prompts into the
+    // captured output or record the parent's commands in history a second time.
+    let subshell = std::env::var_os(executor::SUBSHELL_ENV).is_some();
+    // The parent's `$0` and jobs, for `jobs` to list; the variables that
+    // carry them are not the script's business.
+    if subshell && let Some(name) = shell.variables.get(executor::NAME_ENV) {
+        shell.name = name.to_string();
+        shell.variables.unset(executor::NAME_ENV);
+    }
+    if subshell && let Some(snapshot) = shell.variables.get(jobs::JOBS_ENV) {
+        shell.job_table = jobs::JobTable::from_snapshot(snapshot);
+        shell.variables.unset(jobs::JOBS_ENV);
+    }
+    // Applied once the prelude on stdin has run, but for `$SECONDS`.
+    let mut parent = None;
+    if subshell && let Some(state) = shell.variables.get(executor::STATE_ENV) {
+        parent = executor::ParentState::parse(state);
+        shell.variables.unset(executor::STATE_ENV);
+    }
+    if let Some(state) = &parent {
+        shell.resume_seconds(state.seconds_millis);
+    }
+    // Line numbers past the prelude continue from the parent's.
+    let mut lines_from = None;
+
+    let startup = |shell: &mut Shell, file: Option<(PathBuf, bool)>| {
+        if !subshell && let executor::ExecutionAction::Exit(_) = load_startup_file(shell, file) {
+            shell.run_exit_trap();
+            std::process::exit(shell.last_exit_code);
+        }
+    };
+    if invocation.login && !matches!(invocation.mode, Mode::Help | Mode::Version | Mode::Check(_)) {
+        shell.login = true;
+        // A login shell's jobs die with it unless its profile says otherwise.
+        shell.options.huponexit = true;
+        startup(&mut shell, home_file(".jsh_profile").map(|path| (path, false)));
+    }
+
+    match &invocation.mode {
+        Mode::Help => {
+            println!("{}", cli::help_text());
+            std::process::exit(0);
+        }
+        Mode::Version => {
+            println!("{}", cli::version_text());
+            std::process::exit(0);
+        }
+        Mode::Check(paths) => std::process::exit(check_scripts(&shell, paths)),
+        // `jsh -c 'string'` runs the string like a typed line and `jsh file`
+        // runs the script; either way the shell exits with its status.
+        Mode::Command(command) => {
+            shell.interactive = invocation.interactive;
+            shell.invocation_flags = "c".to_string();
+            shell.eval_line(command);
+            std::process::exit(shell.finish());
+        }
+        Mode::Script(path) => {
+            shell.interactive = invocation.interactive;
+            // As in bash, a script that is not there is a command not found.
+            if let Err(e) = std::fs::File::open(shell.current_dir().join(path)) {
+                eprintln!("jsh: {path}: {e}");
+                std::process::exit(if e.kind() == io::ErrorKind::NotFound { 127 } else { 126 });
+            }
+            shell.run_script(path);
+            std::process::exit(shell.finish());
+        }
+        Mode::Stdin => {}
+    }
+
+    let mut editor = if subshell { LineEditor::without_history() } else { LineEditor::new() };
+
+    if !invocation.norc {
+        startup(&mut shell, rc_file_path());
+    }
+
+    use std::io::IsTerminal;
+    shell.interactive = invocation.interactive || (!subshell && io::stdin().is_terminal());
+    if !subshell {
+        shell.invocation_flags = "s".to_string();
+    }
+    // `jsh -s` fed from a pipe runs a script, which has no use for prompts.
+    let prompts = !subshell && (shell.interactive || !invocation.from_stdin);
+    #[cfg(unix)]
+    if shell.interactive {
+        // Foreground jobs that crash get the terminal back in these modes.
+        job_control::save_terminal_modes();
+    }
+
+    let mut reader = CommandReader::new();
+    // Ctrl-Ds ignored in a row so far under $IGNOREEOF.
+    let mut eofs = 0;
 
     loop {
         // Reap any completed background jobs and print "[N] Done cmd" before
         // showing the prompt — this is how bash notifies you that a background
         // job finished.
-        job_table.reap();
+        shell.job_table.reap(shell.options.jobtimes);
 
-        let input = match editor.read_line("jsh> ") {
-            Ok(Some(line)) => line,
-            Ok(None) => {
+        // A warning about jobs left running lasts for one command.
+        let exit_warned = shell.exit_warned;
+
+        // Traps for signals that arrived while waiting at the prompt.
+        if let executor::ExecutionAction::Exit(_) = shell.run_pending_traps() {
+            break;
+        }
+
+        // PS1 and PS2 are expanded afresh for every command so `\w`, `\?`,
+        // and the time stay current.
+        let (prompt, continuation_prompt) = if !prompts {
+            (String::new(), String::new())
+        } else {
+            (
+                prompt::render(prompt::primary_template(&shell), &shell),
+                prompt::render(prompt::secondary_template(&shell), &shell),
+            )
+        };
+        let mut input = EditorInput {
+            editor: &mut editor,
+            prompt: &prompt,
+            continuation_prompt: &continuation_prompt,
+            expand_history: !subshell,
+            completer: &shell,
+            lines: Vec::new(),
+        };
+        let (list, history) = match reader.read_command(&mut input, &shell.aliases) {
+            Ok(Input::Command { list, history, line }) => {
+                if let Some(state) = parent.take_if(|state| line > state.prelude_lines) {
+                    shell.last_exit_code = state.status;
+                    lines_from = Some(state);
+                }
+                shell.set_line_number(lines_from.map_or(line, |state| state.line_number(line)));
+                (Some(list), history)
+            }
+            Ok(Input::SyntaxError { error, history, .. }) => {
+                eprintln!("{error}");
+                if let Some(caret) = error.caret() {
+                    eprintln!("{caret}");
+                }
+                shell.last_exit_code = 2;
+                (None, history)
+            }
+            Ok(Input::Eof) => {
+                if eofs < shell.ignored_eofs() {
+                    eofs += 1;
+                    eprintln!("Use \"exit\" to leave the shell.");
+                    continue;
+                }
+                if !shell.may_exit(&mut io::stderr()) {
+                    continue;
+                }
                 // Only print the goodbye message for interactive sessions.
                 // Child shells spawned for whole-chain background execution read
                 // from a pipe, not a TTY, and must not print to the terminal.
-                use std::io::IsTerminal;
                 if std::io::stdin().is_terminal() {
                     println!("Goodbye!");
                 }
                 break;
             }
+            // Ctrl-Z put an unfinished command aside; start a new one.
+            Err(e) if editor::is_line_stashed(&e) => continue,
             Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                // Ctrl-C abandons a partially entered command and sets $? to
+                // 130. The editor reads it as a key, so note it for an INT
+                // trap here.
+                traps::record_interrupt();
+                shell.last_exit_code = 130;
                 continue;
             }
             Err(e) => {
@@ -86,223 +359,44 @@ fn main() {
                 break;
             }
         };
+        eofs = 0;
 
-        let trimmed = input.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-
-        // Add to history before parsing so even malformed commands are recorded,
-        // consistent with bash behaviour.
-        editor.add_to_history(trimmed);
-
-        // Parse into quote-aware words.
-        let mut words = match parser::parse_words(trimmed) {
-            Ok(words) => words,
-            Err(msg) => {
-                eprintln!("{msg}");
-                last_exit_code = 2;
-                continue;
-            }
-        };
-
-        // Detect a trailing `&` background operator and strip it.
-        // When present, the last pipeline in the chain runs in the background.
-        // The command text (for display in `jobs`) is the line without `&`.
-        let background = words
-            .last()
-            .map(|w| parser::is_background_word(w))
-            .unwrap_or(false);
-        if background {
-            words.pop();
-        }
-        let command_text = trimmed
-            .trim_end_matches(|c: char| c == '&' || c == ' ')
-            .to_string();
-
-        // Split by chain operators (&&, ||, ;) into ordered entries.
-        let chain = match script_parser::parse_chain(words) {
-            Ok(chain) => chain,
-            Err(msg) => {
-                eprintln!("{msg}");
-                last_exit_code = 2;
-                continue;
-            }
-        };
-
-        if chain.is_empty() {
-            continue;
-        }
-
-        // Phase 1 — Pre-validate pipeline structure for every chain entry up-front.
-        //
-        // This separates STATIC structural validation (pipeline token layout: does
-        // every `|` have a command on each side?) from DYNAMIC evaluation (word
-        // expansion and redirect resolution, which depend on runtime state like $?).
-        //
-        // Validating all entries now means a syntax error in a branch that would be
-        // short-circuited by && / || is still reported, rather than silently ignored
-        // because the branch happened not to run.
-        let mut pre_validated: Vec<(Vec<Vec<parser::Word>>, Connector)> = Vec::new();
-        let mut syntax_ok = true;
-
-        for entry in &chain {
-            match parser::split_pipeline(&entry.words) {
-                Ok(pipeline_words) => {
-                    pre_validated.push((pipeline_words, entry.connector.clone()));
-                }
-                Err(msg) => {
-                    eprintln!("{msg}");
-                    last_exit_code = 2;
-                    syntax_ok = false;
-                    break;
-                }
-            }
-        }
-
-        if !syntax_ok {
-            continue;
+        // Add to history before running so even failing or malformed commands
+        // are recorded, consistent with bash behaviour.
+        if !subshell && !history.is_empty() {
+            editor.set_history_policy(HistoryPolicy::from_variables(&shell.variables));
+            editor.add_to_history(&history);
         }
 
-        // Phase 2 — Whole-chain background.
-        //
-        // When the line ends with `&` and the chain has more than one entry, the
-        // entire chain must run as a single background job so that && / || exit-code
-        // semantics work correctly inside it.  A simple per-entry background flag
-        // cannot achieve this: backgrounding an early entry returns immediately with
-        // an unknown exit code, so && / || gates become meaningless.
-        //
-        // The solution: spawn a child james-shell process and feed it the command
-        // text on stdin.  The child executes the full chain in its foreground while
-        // the parent shell registers it as a background job and returns the prompt.
-        // (Single-entry chains continue to use the per-command background path below.)
-        if background && pre_validated.len() > 1 {
-            let exe = std::env::current_exe()
-                .unwrap_or_else(|_| std::path::PathBuf::from("james-shell"));
-            match std::process::Command::new(&exe)
-                .stdin(std::process::Stdio::piped())
-                .stdout(std::process::Stdio::inherit())
-                .stderr(std::process::Stdio::inherit())
-                .spawn()
-            {
-                Ok(mut child) => {
-                    // Write the command text and signal EOF so the child shell
-                    // executes the chain and exits cleanly.
-                    if let Some(mut stdin) = child.stdin.take() {
-                        let _ = writeln!(stdin, "{command_text}");
-                        // stdin drops here, closing the pipe and triggering EOF
-                    }
-                    let (job_id, pid) = job_table.add(child, command_text.clone());
-                    println!("[{job_id}] {pid}");
-                    last_exit_code = 0;
-                }
-                Err(e) => {
-                    eprintln!("jsh: failed to spawn background shell: {e}");
-                    last_exit_code = 1;
-                }
-            }
-            continue; // prompt is ready; the chain runs in the child
+        // A Ctrl-C typed before this line must not cut it short. A child
+        // shell runs one command for its parent, and stops at one instead.
+        if !subshell {
+            traps::clear_interrupted();
+        } else if shell.interrupted() {
+            shell.last_exit_code = 130;
+            break;
         }
-
-        // Phase 3 — Execute chain entries with short-circuit logic.
-        //
-        // We iterate the pre-validated pipeline segments (so split_pipeline is not
-        // called a second time).  Word expansion and redirect resolution happen here
-        // because they depend on the runtime value of $? after each entry runs.
-        let mut should_exit = false;
-
-        for (i, (pipeline_words, connector)) in pre_validated.into_iter().enumerate() {
-            // Decide whether this entry should run based on the connector and
-            // the exit code left by the previous entry.
-            let should_run = match connector {
-                Connector::Sequence => true,
-                Connector::And => last_exit_code == 0,
-                Connector::Or => last_exit_code != 0,
-            };
-            if !should_run {
-                continue;
+        if let Some(list) = list
+            && !shell.noexec()
+        {
+            let started = Instant::now();
+            let action = shell.run_list(&list);
+            let elapsed = started.elapsed();
+            shell.record_duration(elapsed);
+            if let Some(notification) = notify::long_command(&shell, &history, shell.last_exit_code, elapsed) {
+                notify::send(&notification);
             }
-
-            // For single-entry chains, the background flag is passed through to the
-            // executor as normal.  Multi-entry chains with & are handled in Phase 2
-            // above, so this path is only reached when entry_count == 1.
-            let entry_background = background && (i == 0);
-
-            let mut commands = Vec::new();
-            let mut had_parse_error = false;
-
-            for segment_words in pipeline_words {
-                let (seg_words, redirections) = match
-                    redirect::extract_redirections_from_words(&segment_words, last_exit_code)
-                {
-                    Ok(pair) => pair,
-                    Err(msg) => {
-                        eprintln!("{msg}");
-                        last_exit_code = 2;
-                        had_parse_error = true;
-                        break;
-                    }
-                };
-
-                let args = expander::expand_words(&seg_words, last_exit_code);
-                if args.is_empty() {
-                    eprintln!("jsh: syntax error: empty command");
-                    last_exit_code = 2;
-                    had_parse_error = true;
-                    break;
-                }
-
-                let command = parser::Command {
-                    program: args[0].clone(),
-                    args: args[1..].to_vec(),
-                };
-                commands.push(executor::PipelineCommand { command, redirections });
-            }
-
-            if had_parse_error || commands.is_empty() {
-                if commands.is_empty() && !had_parse_error {
-                    last_exit_code = 2;
-                }
+            if let executor::ExecutionAction::Exit(_) = action {
                 break;
             }
-
-            let action = if commands.len() == 1 {
-                let command = commands.swap_remove(0);
-                executor::execute(
-                    &command.command,
-                    &command.redirections,
-                    entry_background,
-                    &mut job_table,
-                    &command_text,
-                )
-            } else {
-                executor::execute_pipeline(
-                    commands,
-                    entry_background,
-                    &mut job_table,
-                    &command_text,
-                )
-            };
-
-            match action {
-                executor::ExecutionAction::Continue(code) => {
-                    last_exit_code = code;
-                }
-                executor::ExecutionAction::Exit(code) => {
-                    last_exit_code = code;
-                    should_exit = true;
-                    break;
-                }
-            }
         }
-
-        if should_exit {
-            break;
+        if exit_warned {
+            shell.exit_warned = false;
+        }
+        if std::mem::take(&mut shell.history_rewritten) {
+            editor.reload_history();
         }
     }
 
-    #[cfg(unix)]
-    send_sighup_to_jobs(&job_table);
-
-    std::process::exit(last_exit_code);
+    std::process::exit(shell.finish());
 }
@@ -0,0 +1,136 @@
+/// Desktop notifications when a slow command finishes.
+///
+/// Opt-in: set `$JSH_NOTIFY_AFTER` to a number of seconds, and an
+/// interactive shell announces each command typed at the prompt that ran at
+/// least that long, unless the terminal is known to have focus. The
+/// notification goes through `notify-send` (or `osascript` on macOS) when
+/// available, else as an OSC 9 escape, which many terminals turn into one.
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+#[cfg(unix)]
+use crate::builtins;
+use crate::prompt;
+use crate::shell::Shell;
+
+/// The text of a notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+/// The notification for `command`, which exited with `status` after
+/// `elapsed`, if `shell` is configured to send one.
+pub fn long_command(shell: &Shell, command: &str, status: i32, elapsed: Duration) -> Option<Notification> {
+    let threshold: f64 = shell.variables.get("JSH_NOTIFY_AFTER")?.trim().parse().ok()?;
+    if !shell.interactive || elapsed.as_secs_f64() < threshold {
+        return None;
+    }
+    let command = match command.chars().count() > 60 {
+        true => format!("{}...", command.chars().take(57).collect::<String>()),
+        false => command.to_string(),
+    };
+    let outcome = match status {
+        0 => "finished".to_string(),
+        code => format!("failed (exit {code})"),
+    };
+    Some(Notification {
+        title: "jsh".to_string(),
+        body: format!("{command} {outcome} after {}", prompt::format_duration(elapsed.as_millis() as u64)),
+    })
+}
+
+/// Show `notification` unless the terminal is known to have focus.
+pub fn send(notification: &Notification) {
+    if terminal_focused() == Some(true) {
+        return;
+    }
+    if let Some(mut command) = desktop_command(notification)
+        && let Ok(mut child) = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()
+    {
+        // Reap it off the prompt's path; notify-send can be slow to return.
+        std::thread::spawn(move || child.wait());
+        return;
+    }
+    let mut stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        let text = format!("{}: {}", notification.title, notification.body).replace(['\x07', '\x1b'], "");
+        let _ = write!(stderr, "\x1b]9;{text}\x07");
+        let _ = stderr.flush();
+    }
+}
+
+/// `name` on the process's own `$PATH`: the notifier belongs to the
+/// terminal jsh runs in, not to whatever a script has set.
+#[cfg(unix)]
+fn find_tool(name: &str) -> Option<std::path::PathBuf> {
+    builtins::find_in_path(name, &std::env::var("PATH").unwrap_or_default())
+}
+
+#[cfg(target_os = "macos")]
+fn desktop_command(notification: &Notification) -> Option<Command> {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new(find_tool("osascript")?);
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        quote(&notification.body),
+        quote(&notification.title)
+    ));
+    Some(command)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn desktop_command(notification: &Notification) -> Option<Command> {
+    let mut command = Command::new(find_tool("notify-send")?);
+    command.arg("--").arg(&notification.title).arg(&notification.body);
+    Some(command)
+}
+
+#[cfg(not(unix))]
+fn desktop_command(_notification: &Notification) -> Option<Command> {
+    None
+}
+
+/// Whether the terminal's window has focus, where that can be told: under
+/// X11 with `xdotool`, by comparing the active window with `$WINDOWID`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn terminal_focused() -> Option<bool> {
+    let window: u64 = std::env::var("WINDOWID").ok()?.parse().ok()?;
+    let output = Command::new(find_tool("xdotool")?)
+        .arg("getactivewindow")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let active: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Some(active == window)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn terminal_focused() -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_slow_commands_in_an_interactive_shell_notify() {
+        let mut shell = Shell::new();
+        shell.interactive = true;
+        let slow = Duration::from_secs(75);
+        assert_eq!(long_command(&shell, "make", 0, slow), None);
+
+        shell.variables.set("JSH_NOTIFY_AFTER", "10");
+        assert_eq!(long_command(&shell, "make", 0, Duration::from_secs(9)), None);
+        let notification = long_command(&shell, "make", 0, slow).unwrap();
+        assert_eq!(notification.body, "make finished after 1m15s");
+        let notification = long_command(&shell, &"x".repeat(80), 2, slow).unwrap();
+        assert_eq!(notification.body, format!("{}... failed (exit 2) after 1m15s", "x".repeat(57)));
+
+        shell.interactive = false;
+        assert_eq!(long_command(&shell, "make", 0, slow), None);
+    }
+}
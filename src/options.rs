@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+/// Shell options that change how commands are expanded and run.
+///
+/// Options are toggled by name with the `set` and `shopt` builtins and
+/// consulted wherever the behaviour they control lives, e.g. the expander
+/// for the glob options.
+#[derive(Debug, Clone, Default)]
+pub struct ShellOptions {
+    /// `set -e`: exit when a command fails outside a condition.
+    pub errexit: bool,
+    /// `set -u`: expanding an unset parameter is an error.
+    pub nounset: bool,
+    /// `set -x`: print each expanded command to stderr before running it.
+    pub xtrace: bool,
+    /// `set -o pipefail`: a pipeline fails if any stage fails.
+    pub pipefail: bool,
+    /// `set -C`: `>` refuses to overwrite an existing file; `>|` still does.
+    pub noclobber: bool,
+    /// `set -n`: read and parse commands without running them. Ignored by
+    /// an interactive shell, as in bash.
+    pub noexec: bool,
+    /// `set -o bglog`: background jobs write their output to a log file of
+    /// their own (see `jobs -o`) instead of the terminal.
+    pub bglog: bool,
+    /// `set -o bglog=DIR`: the directory those logs go in, where they are
+    /// kept. Without one they go in a private directory and are removed
+    /// when the shell exits.
+    pub bglog_dir: Option<PathBuf>,
+    /// `set -o posix`: follow POSIX where bash's defaults differ. A
+    /// non-interactive shell does not glob redirection targets.
+    pub posix: bool,
+    /// Globs match names starting with `.` (never `.` and `..`).
+    pub dotglob: bool,
+    /// A glob that matches nothing expands to no words at all.
+    pub nullglob: bool,
+    /// A glob that matches nothing is an error and the command is not run.
+    pub failglob: bool,
+    /// The last stage of a pipeline, if a builtin, runs in this shell so
+    /// its changes (`cd`, assignments) persist.
+    pub lastpipe: bool,
+    /// Say when a builtin that changes the shell runs in a pipeline's
+    /// subshell, where its changes are lost.
+    pub pipewarn: bool,
+    /// Running jobs are sent SIGHUP when the shell exits, as stopped ones
+    /// always are. On by default in a login shell only.
+    pub huponexit: bool,
+    /// Done notifications show how long each background job ran.
+    pub jobtimes: bool,
+    /// In an interactive shell, a command that names a directory changes
+    /// into it, as if it were the argument to `cd`.
+    pub autocd: bool,
+    /// In an interactive shell, `cd` offers to fix a one-letter typo in a
+    /// directory name that does not exist.
+    pub cdspell: bool,
+    /// `echo` decodes backslash escapes without `-e`, as POSIX echo does.
+    pub xpg_echo: bool,
+    /// An interactive shell's default prompt shows the last exit status
+    /// when it is not zero, as `jsh [1]> `. On in a new shell.
+    pub promptstatus: bool,
+    /// Path arguments to a program on the other side of WSL or MSYS, such
+    /// as a Windows `.exe` run from WSL, are translated for it.
+    pub wslpath: bool,
+}
+
+/// Names accepted by `shopt`, in listing order.
+pub const SHOPT_NAMES: &[&str] = &[
+    "autocd", "cdspell", "dotglob", "failglob", "huponexit", "jobtimes", "lastpipe", "nullglob",
+    "pipewarn", "promptstatus", "wslpath", "xpg_echo",
+];
+
+/// Names accepted by `set -o`, in listing order.
+pub const SET_NAMES: &[&str] = &[
+    "bglog", "errexit", "noclobber", "noexec", "nounset", "pipefail", "posix", "xtrace",
+];
+
+/// The `set -o` name for a single-letter `set` flag such as `-e`.
+pub fn flag_name(flag: char) -> Option<&'static str> {
+    match flag {
+        'C' => Some("noclobber"),
+        'e' => Some("errexit"),
+        'n' => Some("noexec"),
+        'u' => Some("nounset"),
+        'x' => Some("xtrace"),
+        _ => None,
+    }
+}
+
+impl ShellOptions {
+    /// The options a new shell starts with.
+    pub fn new() -> Self {
+        Self {
+            promptstatus: true,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the option called `name` is on, or `None` if there is no
+    /// such option.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "errexit" => Some(self.errexit),
+            "nounset" => Some(self.nounset),
+            "xtrace" => Some(self.xtrace),
+            "pipefail" => Some(self.pipefail),
+            "noclobber" => Some(self.noclobber),
+            "noexec" => Some(self.noexec),
+            "posix" => Some(self.posix),
+            "bglog" => Some(self.bglog),
+            "autocd" => Some(self.autocd),
+            "cdspell" => Some(self.cdspell),
+            "dotglob" => Some(self.dotglob),
+            "failglob" => Some(self.failglob),
+            "huponexit" => Some(self.huponexit),
+            "jobtimes" => Some(self.jobtimes),
+            "lastpipe" => Some(self.lastpipe),
+            "nullglob" => Some(self.nullglob),
+            "pipewarn" => Some(self.pipewarn),
+            "xpg_echo" => Some(self.xpg_echo),
+            "wslpath" => Some(self.wslpath),
+            "promptstatus" => Some(self.promptstatus),
+            _ => None,
+        }
+    }
+
+    /// Turn the option called `name` on or off, returning false if there is
+    /// no such option.
+    pub fn set(&mut self, name: &str, on: bool) -> bool {
+        let option = match name {
+            "errexit" => &mut self.errexit,
+            "nounset" => &mut self.nounset,
+            "xtrace" => &mut self.xtrace,
+            "pipefail" => &mut self.pipefail,
+            "noclobber" => &mut self.noclobber,
+            "noexec" => &mut self.noexec,
+            "posix" => &mut self.posix,
+            "bglog" => &mut self.bglog,
+            "autocd" => &mut self.autocd,
+            "cdspell" => &mut self.cdspell,
+            "dotglob" => &mut self.dotglob,
+            "failglob" => &mut self.failglob,
+            "huponexit" => &mut self.huponexit,
+            "jobtimes" => &mut self.jobtimes,
+            "lastpipe" => &mut self.lastpipe,
+            "nullglob" => &mut self.nullglob,
+            "pipewarn" => &mut self.pipewarn,
+            "xpg_echo" => &mut self.xpg_echo,
+            "wslpath" => &mut self.wslpath,
+            "promptstatus" => &mut self.promptstatus,
+            _ => return false,
+        };
+        *option = on;
+        true
+    }
+
+    /// The `set -o` argument that turns on the option called `name` as it
+    /// is now: `bglog=DIR` when the logs have a directory of their own.
+    pub fn set_argument(&self, name: &str) -> String {
+        match (name, &self.bglog_dir) {
+            ("bglog", Some(dir)) => format!("bglog={}", dir.display()),
+            _ => name.to_string(),
+        }
+    }
+
+    /// The single-letter `set` flags that are on, as `$-` lists them.
+    pub fn flags(&self) -> String {
+        "Cenux"
+            .chars()
+            .filter(|&flag| flag_name(flag).and_then(|name| self.get(name)) == Some(true))
+            .collect()
+    }
+
+    /// The options called `names` (e.g. [`SHOPT_NAMES`]) as `(name, on)`
+    /// pairs.
+    pub fn list<'a>(&'a self, names: &'a [&'static str]) -> impl Iterator<Item = (&'static str, bool)> + 'a {
+        names
+            .iter()
+            .map(|&name| (name, self.get(name).unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_get_by_name() {
+        let mut options = ShellOptions::new();
+        assert_eq!(options.get("nullglob"), Some(false));
+        assert!(options.set("nullglob", true));
+        assert!(options.nullglob);
+        assert_eq!(options.get("nullglob"), Some(true));
+        assert!(!options.set("nosuchopt", true));
+        assert_eq!(options.get("nosuchopt"), None);
+    }
+
+    #[test]
+    fn set_flags_map_to_names() {
+        let mut options = ShellOptions::new();
+        assert!(options.set(flag_name('e').unwrap(), true));
+        assert!(options.errexit);
+        assert_eq!(flag_name('n'), Some("noexec"));
+        assert_eq!(flag_name('q'), None);
+        let on: Vec<_> = options.list(SET_NAMES).filter(|(_, on)| *on).collect();
+        assert_eq!(on, vec![("errexit", true)]);
+        options.set("xtrace", true);
+        options.set("pipefail", true);
+        assert_eq!(options.flags(), "ex");
+    }
+}
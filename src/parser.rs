@@ -1,13 +1,20 @@
+use std::rc::Rc;
+
+use crate::error::JshError;
+
 /// A segment of a word, tagged with its quote context.
 /// The expander uses this to decide what expansions to apply.
+///
+/// The text is shared, so a word is copied between the reader, the script
+/// parser, and loop bodies without copying what it says.
 #[derive(Debug, Clone, PartialEq)]
 pub enum WordSegment {
     /// Unquoted text — all expansions apply (tilde, variable, glob, word split)
-    Unquoted(String),
+    Unquoted(Rc<str>),
     /// Double-quoted text — variable expansion only, no glob or word split
-    DoubleQuoted(String),
+    DoubleQuoted(Rc<str>),
     /// Single-quoted text — no expansion at all, everything literal
-    SingleQuoted(String),
+    SingleQuoted(Rc<str>),
 }
 
 /// A single word (argument) made up of one or more segments.
@@ -19,6 +26,56 @@ pub type Word = Vec<WordSegment>;
 pub struct Command {
     pub program: String,
     pub args: Vec<String>,
+    /// Variables set in this command's environment only (`NAME=value cmd`).
+    pub assignments: Vec<(String, String)>,
+}
+
+/// A peekable iterator over the characters of a string, like
+/// `Peekable<Chars>`, that can also tell where it is without rescanning
+/// the rest of the input. The tokenizer asks that for every token.
+#[derive(Clone)]
+pub(crate) struct Cursor<'a> {
+    /// The next character, if any.
+    head: Option<char>,
+    /// The input after `head`.
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        let mut chars = input.chars();
+        Cursor {
+            head: chars.next(),
+            rest: chars.as_str(),
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<&char> {
+        self.head.as_ref()
+    }
+
+    /// Consume the next character if `accept` returns true for it.
+    pub(crate) fn next_if(&mut self, accept: impl FnOnce(&char) -> bool) -> Option<char> {
+        match self.head {
+            Some(c) if accept(&c) => self.next(),
+            _ => None,
+        }
+    }
+
+    /// How many bytes of input are left, including the peeked character.
+    fn remaining(&self) -> usize {
+        self.rest.len() + self.head.map_or(0, char::len_utf8)
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let head = self.head?;
+        *self = Cursor::new(self.rest);
+        Some(head)
+    }
 }
 
 /// States for the tokenizer state machine.
@@ -33,9 +90,28 @@ enum State {
     InSingleQuote,
 }
 
+/// Consume an operator starting with `|` or `&`: `|`, `||`, `|&` (pipe
+/// stdout and stderr), `&`, `&&`, and the redirections `&>` / `&>>`.
+fn consume_control_op(first: char, chars: &mut Cursor) -> String {
+    let mut op = String::new();
+    op.push(first);
+
+    match (first, chars.peek()) {
+        ('|', Some('|' | '&')) | ('&', Some('&')) => op.push(chars.next().unwrap()),
+        ('&', Some('>')) => {
+            op.push(chars.next().unwrap()); // &>
+            if chars.peek() == Some(&'>') {
+                op.push(chars.next().unwrap()); // &>>
+            }
+        }
+        _ => {}
+    }
+    op
+}
+
 /// Consume a redirect operator starting with `>` or `<`.
-/// Handles multi-character operators: >>, <<<, >&N
-fn consume_redirect_op(first: char, chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+/// Handles multi-character operators: >>, >|, <<, <<-, <<<, >&N
+fn consume_redirect_op(first: char, chars: &mut Cursor) -> String {
     let mut op = String::new();
     op.push(first);
 
@@ -43,22 +119,23 @@ fn consume_redirect_op(first: char, chars: &mut std::iter::Peekable<std::str::Ch
         '>' => {
             if chars.peek() == Some(&'>') {
                 op.push(chars.next().unwrap()); // >>
+            } else if chars.peek() == Some(&'|') {
+                op.push(chars.next().unwrap()); // >|
             } else if chars.peek() == Some(&'&') {
                 op.push(chars.next().unwrap()); // >&
-                // Consume the fd number (e.g., >&1, >&2)
-                if let Some(&c) = chars.peek()
-                    && c.is_ascii_digit()
-                {
-                    op.push(chars.next().unwrap());
-                }
+                consume_dup_source(&mut op, chars);
             }
         }
-        '<' => {
+        '<' if chars.peek() == Some(&'&') => {
+            op.push(chars.next().unwrap()); // <&
+            consume_dup_source(&mut op, chars);
+        }
+        '<' if chars.peek() == Some(&'<') => {
+            op.push(chars.next().unwrap()); // <<
             if chars.peek() == Some(&'<') {
-                op.push(chars.next().unwrap()); // <<
-                if chars.peek() == Some(&'<') {
-                    op.push(chars.next().unwrap()); // <<<
-                }
+                op.push(chars.next().unwrap()); // <<<
+            } else if chars.peek() == Some(&'-') {
+                op.push(chars.next().unwrap()); // <<-
             }
         }
         _ => {}
@@ -66,59 +143,382 @@ fn consume_redirect_op(first: char, chars: &mut std::iter::Peekable<std::str::Ch
     op
 }
 
+/// Consume what follows `>&` or `<&`: an fd number (`>&12`) or `-` to close
+/// the descriptor. Anything else, like `>&$fd`, is left for the next word.
+fn consume_dup_source(op: &mut String, chars: &mut Cursor) {
+    if chars.peek() == Some(&'-') {
+        op.push(chars.next().unwrap());
+        return;
+    }
+    while let Some(&c) = chars.peek()
+        && c.is_ascii_digit()
+    {
+        op.push(chars.next().unwrap());
+    }
+}
+
+/// Consume the body of a `$(...)` command substitution whose `$(` has already
+/// been consumed, up to and including the matching `)`.
+///
+/// Nested parentheses, quotes, and backslash escapes are skipped over so that
+/// `$(echo ")")` ends at the right place. Returns the body without the closing
+/// `)`, or `None` if the input ends first.
+pub(crate) fn scan_command_substitution(
+    chars: &mut Cursor,
+) -> Option<String> {
+    let mut body = String::new();
+    let mut depth = 0usize;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                body.push(c);
+                if let Some(next) = chars.next() {
+                    body.push(next);
+                }
+            }
+            '\'' => {
+                body.push(c);
+                loop {
+                    let next = chars.next()?;
+                    body.push(next);
+                    if next == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                body.push(c);
+                loop {
+                    let next = chars.next()?;
+                    body.push(next);
+                    if next == '\\' {
+                        body.push(chars.next()?);
+                    } else if next == '"' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                depth += 1;
+                body.push(c);
+            }
+            ')' if depth == 0 => return Some(body),
+            ')' => {
+                depth -= 1;
+                body.push(c);
+            }
+            _ => body.push(c),
+        }
+    }
+
+    None
+}
+
+/// Consume the body of a `${...}` parameter expansion whose `${` has already
+/// been consumed, up to and including the matching `}`.
+///
+/// Quotes, backslash escapes, and nested `${...}` are skipped over so that
+/// `${x:-"}"}` ends at the right place. Inside double quotes a single quote
+/// is an ordinary character. Returns the body without the closing `}`, or
+/// `None` if the input ends first.
+pub(crate) fn scan_parameter_expansion(
+    chars: &mut Cursor,
+    in_double_quotes: bool,
+) -> Option<String> {
+    let mut body = String::new();
+    let mut depth = 0usize;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                body.push(c);
+                body.push(chars.next()?);
+            }
+            '\'' if !in_double_quotes => {
+                body.push(c);
+                loop {
+                    let next = chars.next()?;
+                    body.push(next);
+                    if next == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                body.push(c);
+                loop {
+                    let next = chars.next()?;
+                    body.push(next);
+                    if next == '\\' {
+                        body.push(chars.next()?);
+                    } else if next == '"' {
+                        break;
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                depth += 1;
+                body.push(c);
+                body.push(chars.next().unwrap());
+            }
+            '}' if depth == 0 => return Some(body),
+            '}' => {
+                depth -= 1;
+                body.push(c);
+            }
+            _ => body.push(c),
+        }
+    }
+
+    None
+}
+
+/// Consume a legacy `` `cmd` `` substitution whose opening backtick has already
+/// been consumed, up to and including the closing backtick.
+///
+/// Inside backticks a backslash only escapes `$`, `` ` ``, and `\` (plus `"`
+/// when the backticks sit inside double quotes); any other backslash is kept
+/// literally. Returns the unescaped command text, or `None` if unterminated.
+fn scan_backtick_body(
+    chars: &mut Cursor,
+    in_double_quotes: bool,
+) -> Option<String> {
+    let mut body = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => return Some(body),
+            '\\' => match chars.peek() {
+                Some(&('$' | '`' | '\\')) => body.push(chars.next().unwrap()),
+                Some(&'"') if in_double_quotes => body.push(chars.next().unwrap()),
+                _ => body.push('\\'),
+            },
+            _ => body.push(c),
+        }
+    }
+
+    None
+}
+
+/// Consume the body of a `$'...'` string whose opening `$'` has already been
+/// consumed, up to and including the closing quote, decoding C-style escapes.
+///
+/// Supports `\a \b \e \E \f \n \r \t \v \\ \' \" \?`, `\cX` control
+/// characters, `\nnn` octal (one to three digits), `\xHH`, `\uHHHH`, and
+/// `\UHHHHHHHH`. Unknown escapes are kept with their backslash. As in bash, a
+/// NUL character ends the string. Returns `None` if unterminated.
+fn scan_ansi_c_quote(chars: &mut Cursor) -> Option<String> {
+    let mut text = String::new();
+    let mut truncated = false;
+
+    loop {
+        let decoded = match chars.next()? {
+            '\'' => return Some(text),
+            '\\' => match chars.next()? {
+                'a' => Some('\x07'),
+                'b' => Some('\x08'),
+                'e' | 'E' => Some('\x1b'),
+                'f' => Some('\x0c'),
+                'n' => Some('\n'),
+                'r' => Some('\r'),
+                't' => Some('\t'),
+                'v' => Some('\x0b'),
+                c @ ('\\' | '\'' | '"' | '?') => Some(c),
+                'c' => chars.next().map(|c| char::from(c.to_ascii_uppercase() as u8 ^ 0x40)),
+                c @ '0'..='7' => {
+                    let mut value = c.to_digit(8).unwrap();
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(digit) => {
+                                value = value * 8 + digit;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    char::from_u32(value & 0xff)
+                }
+                c @ ('x' | 'u' | 'U') => {
+                    let max_digits = match c {
+                        'x' => 2,
+                        'u' => 4,
+                        _ => 8,
+                    };
+                    let mut digits = String::new();
+                    while digits.len() < max_digits
+                        && let Some(&d) = chars.peek().filter(|d| d.is_ascii_hexdigit())
+                    {
+                        digits.push(d);
+                        chars.next();
+                    }
+                    if digits.is_empty() {
+                        text.push('\\');
+                        Some(c)
+                    } else {
+                        u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+                    }
+                }
+                c => {
+                    text.push('\\');
+                    Some(c)
+                }
+            },
+            c => Some(c),
+        };
+        match decoded {
+            Some('\0') => truncated = true,
+            Some(c) if !truncated => text.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Read a `$(...)` or backtick substitution and append it to `segment` in the
+/// canonical `$(...)` form understood by the expander.
+///
+/// `opener` is the character that triggered the scan: `(` (after a `$`) or
+/// `` ` ``. Backtick bodies are rewritten as `$(body)` so both syntaxes share
+/// one expansion path.
+fn push_command_substitution(
+    opener: char,
+    input: &str,
+    chars: &mut Cursor,
+    segment: &mut String,
+    in_double_quotes: bool,
+) -> Result<(), JshError> {
+    let opened = chars.clone();
+    let body = if opener == '`' {
+        scan_backtick_body(chars, in_double_quotes)
+            .ok_or_else(|| unterminated("backtick substitution", input, &opened))?
+    } else {
+        chars.next(); // consume '('
+        scan_command_substitution(chars)
+            .ok_or_else(|| unterminated("command substitution", input, &opened))?
+    };
+
+    segment.push_str("$(");
+    segment.push_str(&body);
+    segment.push(')');
+    Ok(())
+}
+
+/// A syntax error for a construct left open at the end of `input`. `opened`
+/// is the unread input just after its opening character, so the error can
+/// point at where it began.
+fn unterminated(what: &str, input: &str, opened: &Cursor) -> JshError {
+    let start = unread_offset(input, opened).saturating_sub(1);
+    JshError::syntax_at(format!("jsh: syntax error: unterminated {what}"), input, start)
+}
+
+/// The byte offset in `input` of the next character `rest` will yield.
+fn unread_offset(input: &str, rest: &Cursor) -> usize {
+    input.len() - rest.remaining()
+}
+
 /// Tokenize input into a list of words, each preserving quote context.
-pub fn tokenize(input: &str) -> Result<Vec<Word>, String> {
-    let mut words: Vec<Word> = Vec::new();
+pub fn tokenize(input: &str) -> Result<Vec<Word>, JshError> {
+    let words = tokenize_with_offsets(input)?;
+    Ok(words.into_iter().map(|(word, _)| word).collect())
+}
+
+/// Like [`tokenize`], pairing each word with the byte offset in `input`
+/// where it starts, so later syntax errors can point at it.
+pub fn tokenize_with_offsets(input: &str) -> Result<Vec<(Word, usize)>, JshError> {
+    let mut words: Vec<(Word, usize)> = Vec::new();
     let mut current_segment = String::new();
     let mut current_word: Word = Vec::new();
     let mut state = State::Normal;
-    let mut chars = input.chars().peekable();
+    let mut chars = Cursor::new(input);
+    // Input just after the quote that opened the current quoted section.
+    let mut quote_opened = chars.clone();
+    // Where the word or operator being built starts.
+    let mut word_start = 0;
 
     while let Some(ch) = chars.next() {
+        if matches!(state, State::Normal) && !matches!(ch, ' ' | '\t') {
+            word_start = unread_offset(input, &chars) - ch.len_utf8();
+        }
         match (&state, ch) {
             // ── Normal state: between tokens ──
             (State::Normal, ' ' | '\t') => {}
+            (State::Normal, '\n') => {
+                // Newline ends a command like `;`, but the script parser
+                // also needs to see it to allow `then` on its own line.
+                words.push((vec![WordSegment::Unquoted("\n".into())], word_start));
+            }
+            (State::Normal | State::InWord, '\\') if chars.peek() == Some(&'\n') => {
+                // Backslash-newline is a line continuation: both vanish.
+                chars.next();
+            }
+            (State::Normal, '#') => {
+                // A `#` starting a word begins a comment. The newline stays
+                // so it still ends the command.
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
             (State::Normal, '"') => {
+                quote_opened = chars.clone();
                 state = State::InDoubleQuote;
             }
             (State::Normal, '\'') => {
+                quote_opened = chars.clone();
                 state = State::InSingleQuote;
             }
             (State::Normal, '\\') => {
                 // Escaped char is literal — emit as SingleQuoted so expander
                 // won't touch it (e.g., \$VAR stays as $VAR, not expanded)
                 if let Some(next) = chars.next() {
-                    current_word.push(WordSegment::SingleQuoted(next.to_string()));
+                    current_word.push(WordSegment::SingleQuoted(next.to_string().into()));
                 } else {
-                    current_word.push(WordSegment::SingleQuoted("\\".to_string()));
+                    current_word.push(WordSegment::SingleQuoted("\\".into()));
                 }
                 state = State::InWord;
             }
-            (State::Normal, '|') => {
-                // `||` is a chain-or operator; bare `|` is a pipe.
-                if chars.peek() == Some(&'|') {
-                    chars.next();
-                    words.push(vec![WordSegment::Unquoted("||".to_string())]);
-                } else {
-                    words.push(vec![WordSegment::Unquoted("|".to_string())]);
-                }
-            }
-            (State::Normal, '&') => {
-                // `&&` is a chain-and operator; bare `&` is background.
-                if chars.peek() == Some(&'&') {
-                    chars.next();
-                    words.push(vec![WordSegment::Unquoted("&&".to_string())]);
-                } else {
-                    words.push(vec![WordSegment::Unquoted("&".to_string())]);
-                }
+            (State::Normal, '|' | '&') => {
+                // `||`, `&&`, `|`, `&`, and the combined forms `|&`, `&>`, `&>>`.
+                words.push((vec![WordSegment::Unquoted(consume_control_op(ch, &mut chars).into())], word_start));
             }
             (State::Normal, ';') => {
-                words.push(vec![WordSegment::Unquoted(";".to_string())]);
+                words.push((vec![WordSegment::Unquoted(";".into())], word_start));
             }
             (State::Normal, '>' | '<') => {
                 // Redirect operator — emit as its own token
                 let op = consume_redirect_op(ch, &mut chars);
-                words.push(vec![WordSegment::Unquoted(op)]);
+                words.push((vec![WordSegment::Unquoted(op.into())], word_start));
+            }
+            (State::Normal | State::InWord, '`') => {
+                // Command substitution — whitespace inside must not end the word.
+                push_command_substitution('`', input, &mut chars, &mut current_segment, false)?;
+                state = State::InWord;
+            }
+            (State::Normal | State::InWord, '$') if chars.peek() == Some(&'\'') => {
+                // ANSI-C quoting: escapes are decoded here, and the result is
+                // as literal as a single-quoted string.
+                let opened = chars.clone();
+                chars.next(); // consume '\''
+                if !current_segment.is_empty() {
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
+                }
+                let text = scan_ansi_c_quote(&mut chars)
+                    .ok_or_else(|| unterminated("ANSI-C quote", input, &opened))?;
+                current_word.push(WordSegment::SingleQuoted(text.into()));
+                state = State::InWord;
+            }
+            (State::Normal | State::InWord, '$') if chars.peek() == Some(&'(') => {
+                push_command_substitution('(', input, &mut chars, &mut current_segment, false)?;
+                state = State::InWord;
+            }
+            (State::Normal | State::InWord, '$') if chars.peek() == Some(&'{') => {
+                // Parameter expansion — `${x:-a b}` is one word, and quotes
+                // inside are handled by the expander.
+                let opened = chars.clone();
+                chars.next(); // consume '{'
+                let body = scan_parameter_expansion(&mut chars, false)
+                    .ok_or_else(|| unterminated("parameter expansion", input, &opened))?;
+                current_segment.push_str("${");
+                current_segment.push_str(&body);
+                current_segment.push('}');
+                state = State::InWord;
             }
             (State::Normal, c) => {
                 current_segment.push(c);
@@ -129,97 +529,84 @@ pub fn tokenize(input: &str) -> Result<Vec<Word>, String> {
             (State::InWord, ' ' | '\t') => {
                 // Finish current unquoted segment and word
                 if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                 }
                 if !current_word.is_empty() {
-                    words.push(std::mem::take(&mut current_word));
+                    words.push((std::mem::take(&mut current_word), word_start));
                 }
                 state = State::Normal;
             }
             (State::InWord, '"') => {
                 // Flush unquoted segment, switch to double quotes
                 if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                 }
+                quote_opened = chars.clone();
                 state = State::InDoubleQuote;
             }
             (State::InWord, '\'') => {
                 if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                 }
+                quote_opened = chars.clone();
                 state = State::InSingleQuote;
             }
             (State::InWord, '\\') => {
                 // Flush current unquoted segment, emit escaped char as literal
                 if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                 }
                 if let Some(next) = chars.next() {
-                    current_word.push(WordSegment::SingleQuoted(next.to_string()));
+                    current_word.push(WordSegment::SingleQuoted(next.to_string().into()));
                 } else {
-                    current_word.push(WordSegment::SingleQuoted("\\".to_string()));
+                    current_word.push(WordSegment::SingleQuoted("\\".into()));
                 }
             }
-            (State::InWord, '|') => {
-                // `||` is chain-or; bare `|` is a pipe. Both break a word.
-                if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
-                }
-                if !current_word.is_empty() {
-                    words.push(std::mem::take(&mut current_word));
-                }
-                if chars.peek() == Some(&'|') {
-                    chars.next();
-                    words.push(vec![WordSegment::Unquoted("||".to_string())]);
-                } else {
-                    words.push(vec![WordSegment::Unquoted("|".to_string())]);
-                }
-                state = State::Normal;
-            }
-            (State::InWord, '&') => {
-                // `&&` is chain-and; bare `&` is background. Both break a word.
+            (State::InWord, '|' | '&') => {
+                // Pipe, chain, and background operators all break a word.
+                let op_start = unread_offset(input, &chars) - 1;
                 if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                 }
                 if !current_word.is_empty() {
-                    words.push(std::mem::take(&mut current_word));
-                }
-                if chars.peek() == Some(&'&') {
-                    chars.next();
-                    words.push(vec![WordSegment::Unquoted("&&".to_string())]);
-                } else {
-                    words.push(vec![WordSegment::Unquoted("&".to_string())]);
+                    words.push((std::mem::take(&mut current_word), word_start));
                 }
+                words.push((vec![WordSegment::Unquoted(consume_control_op(ch, &mut chars).into())], op_start));
                 state = State::Normal;
             }
-            (State::InWord, ';') => {
-                // Semicolon breaks a word and acts as a sequence separator.
+            (State::InWord, ';' | '\n') => {
+                // Semicolon and newline break a word and act as sequence separators.
+                let op_start = unread_offset(input, &chars) - 1;
                 if !current_segment.is_empty() {
-                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                    current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                 }
                 if !current_word.is_empty() {
-                    words.push(std::mem::take(&mut current_word));
+                    words.push((std::mem::take(&mut current_word), word_start));
                 }
-                words.push(vec![WordSegment::Unquoted(";".to_string())]);
+                words.push((vec![WordSegment::Unquoted(ch.to_string().into())], op_start));
                 state = State::Normal;
             }
             (State::InWord, '>' | '<') => {
-                // Check if the current segment is a lone fd digit (e.g. "2" in "2>&1").
-                // If so, merge it into the operator token instead of emitting as a word.
-                let fd_prefix = if ch == '>'
+                // Check if the current segment is a lone fd digit (e.g. "2" in "2>&1"
+                // or "3" in "3<file"). If so, merge it into the operator token instead
+                // of emitting as a word. Here-documents take no prefix.
+                let mut op_start = unread_offset(input, &chars) - 1;
+                let heredoc = ch == '<' && chars.peek() == Some(&'<') && chars.clone().nth(1) != Some('<');
+                let fd_prefix = if !heredoc
                     && current_word.is_empty()
                     && current_segment.len() == 1
                     && current_segment.chars().next().unwrap().is_ascii_digit()
                 {
                     let prefix = std::mem::take(&mut current_segment);
+                    op_start = word_start;
                     Some(prefix)
                 } else {
                     // Flush the current segment/word normally
                     if !current_segment.is_empty() {
-                        current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                        current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
                     }
                     if !current_word.is_empty() {
-                        words.push(std::mem::take(&mut current_word));
+                        words.push((std::mem::take(&mut current_word), word_start));
                     }
                     None
                 };
@@ -228,7 +615,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Word>, String> {
                 if let Some(prefix) = fd_prefix {
                     op = format!("{prefix}{op}");
                 }
-                words.push(vec![WordSegment::Unquoted(op)]);
+                words.push((vec![WordSegment::Unquoted(op.into())], op_start));
                 state = State::Normal;
             }
             (State::InWord, c) => {
@@ -238,19 +625,45 @@ pub fn tokenize(input: &str) -> Result<Vec<Word>, String> {
             // ── InDoubleQuote state: inside "..." ──
             (State::InDoubleQuote, '"') => {
                 // Flush double-quoted segment (even if empty — "" is a valid empty arg)
-                current_word.push(WordSegment::DoubleQuoted(std::mem::take(&mut current_segment)));
+                current_word.push(WordSegment::DoubleQuoted(std::mem::take(&mut current_segment).into()));
                 state = State::InWord;
             }
             (State::InDoubleQuote, '\\') => {
                 match chars.peek() {
-                    Some(&'"' | &'\\' | &'$' | &'`') => {
+                    Some(&'"' | &'\\') => {
                         current_segment.push(chars.next().unwrap());
                     }
+                    Some(&'\n') => {
+                        chars.next(); // line continuation
+                    }
+                    Some(&'$' | &'`') => {
+                        // An escaped `$` or backtick must stay literal, but the
+                        // expander would treat it as an expansion inside a
+                        // DoubleQuoted segment — emit it as a literal segment.
+                        current_word.push(WordSegment::DoubleQuoted(std::mem::take(&mut current_segment).into()));
+                        current_word.push(WordSegment::SingleQuoted(chars.next().unwrap().to_string().into()));
+                    }
                     _ => {
                         current_segment.push('\\');
                     }
                 }
             }
+            (State::InDoubleQuote, '`') => {
+                push_command_substitution('`', input, &mut chars, &mut current_segment, true)?;
+            }
+            (State::InDoubleQuote, '$') if chars.peek() == Some(&'(') => {
+                push_command_substitution('(', input, &mut chars, &mut current_segment, true)?;
+            }
+            (State::InDoubleQuote, '$') if chars.peek() == Some(&'{') => {
+                // Quotes inside `"${x:-"a b"}"` nest rather than closing the string.
+                let opened = chars.clone();
+                chars.next(); // consume '{'
+                let body = scan_parameter_expansion(&mut chars, true)
+                    .ok_or_else(|| unterminated("parameter expansion", input, &opened))?;
+                current_segment.push_str("${");
+                current_segment.push_str(&body);
+                current_segment.push('}');
+            }
             (State::InDoubleQuote, c) => {
                 current_segment.push(c);
             }
@@ -258,7 +671,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Word>, String> {
             // ── InSingleQuote state: inside '...' ──
             (State::InSingleQuote, '\'') => {
                 // Flush single-quoted segment (even if empty)
-                current_word.push(WordSegment::SingleQuoted(std::mem::take(&mut current_segment)));
+                current_word.push(WordSegment::SingleQuoted(std::mem::take(&mut current_segment).into()));
                 state = State::InWord;
             }
             (State::InSingleQuote, c) => {
@@ -271,18 +684,18 @@ pub fn tokenize(input: &str) -> Result<Vec<Word>, String> {
     match state {
         State::InWord => {
             if !current_segment.is_empty() {
-                current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment)));
+                current_word.push(WordSegment::Unquoted(std::mem::take(&mut current_segment).into()));
             }
             // Push word even if segments produced empty text (e.g. trailing "")
             if !current_word.is_empty() {
-                words.push(current_word);
+                words.push((current_word, word_start));
             }
         }
         State::InDoubleQuote => {
-            return Err("jsh: syntax error: unterminated double quote".to_string());
+            return Err(unterminated("double quote", input, &quote_opened));
         }
         State::InSingleQuote => {
-            return Err("jsh: syntax error: unterminated single quote".to_string());
+            return Err(unterminated("single quote", input, &quote_opened));
         }
         State::Normal => {}
     }
@@ -300,7 +713,7 @@ pub fn words_to_strings(words: &[Word]) -> Vec<String> {
                 .map(|seg| match seg {
                     WordSegment::Unquoted(s)
                     | WordSegment::DoubleQuoted(s)
-                    | WordSegment::SingleQuoted(s) => s.as_str(),
+                    | WordSegment::SingleQuoted(s) => &**s,
                 })
                 .collect()
         })
@@ -320,12 +733,13 @@ pub fn parse(input: &str) -> Option<Command> {
     Some(Command {
         program: strings[0].clone(),
         args: strings[1..].to_vec(),
+        assignments: Vec::new(),
     })
 }
 
 /// Parse input into raw words with quote context preserved.
 /// Used by the expander pipeline.
-pub fn parse_words(input: &str) -> Result<Vec<Word>, String> {
+pub fn parse_words(input: &str) -> Result<Vec<Word>, JshError> {
     tokenize(input)
 }
 
@@ -333,24 +747,25 @@ pub fn parse_words(input: &str) -> Result<Vec<Word>, String> {
 ///
 /// Pipe separators are returned as standalone unquoted `|` words.
 /// Returns a vector of commands (`Vec<Word>`), one per pipeline segment.
-pub fn split_pipeline(words: &[Word]) -> Result<Vec<Vec<Word>>, String> {
+/// The words are moved into the segments rather than copied.
+pub fn split_pipeline(words: Vec<Word>) -> Result<Vec<Vec<Word>>, JshError> {
     let mut commands = Vec::new();
     let mut current: Vec<Word> = Vec::new();
 
     for word in words {
-        if is_pipe_word(word) {
+        if is_pipe_word(&word) {
             if current.is_empty() {
-                return Err("jsh: syntax error: missing command before '|'".to_string());
+                return Err(JshError::syntax("jsh: syntax error: missing command before '|'"));
             }
             commands.push(std::mem::take(&mut current));
             continue;
         }
 
-        current.push(word.clone());
+        current.push(word);
     }
 
     if current.is_empty() {
-        return Err("jsh: syntax error: expected command after '|'".to_string());
+        return Err(JshError::syntax("jsh: syntax error: expected command after '|'"));
     }
 
     commands.push(current);
@@ -359,13 +774,267 @@ pub fn split_pipeline(words: &[Word]) -> Result<Vec<Vec<Word>>, String> {
 
 fn is_pipe_word(word: &Word) -> bool {
     word.len() == 1
-        && matches!(&word[0], WordSegment::Unquoted(token) if token == "|")
+        && matches!(&word[0], WordSegment::Unquoted(token) if &**token == "|")
 }
 
 /// Returns true if this word is a bare `&` background operator.
 pub fn is_background_word(word: &Word) -> bool {
     word.len() == 1
-        && matches!(&word[0], WordSegment::Unquoted(token) if token == "&")
+        && matches!(&word[0], WordSegment::Unquoted(token) if &**token == "&")
+}
+
+// ── Assignments ──
+
+/// Split a `NAME=value` assignment word into the name and the segments of
+/// the value. The name and `=` must be unquoted, so `"FOO"=bar` is an
+/// ordinary word. The name may be an array element, `NAME[key]`.
+pub fn split_assignment(word: &Word) -> Option<(String, Word)> {
+    let (name, rest) = assignment_parts(word)?;
+
+    let mut value = Vec::with_capacity(word.len());
+    if !rest.is_empty() {
+        value.push(WordSegment::Unquoted(rest.into()));
+    }
+    value.extend(word[1..].iter().cloned());
+    Some((name.to_string(), value))
+}
+
+/// Whether `word` is a `NAME=value` assignment, without copying it apart
+/// like [`split_assignment`].
+pub fn is_assignment(word: &Word) -> bool {
+    assignment_parts(word).is_some()
+}
+
+/// The name and the text after `=` in the first segment of an assignment.
+fn assignment_parts(word: &Word) -> Option<(&str, &str)> {
+    let Some(WordSegment::Unquoted(first)) = word.first() else {
+        return None;
+    };
+    let (name, rest) = first.split_once('=')?;
+    if !crate::variables::is_valid_name(name) && crate::variables::split_subscript(name).is_none() {
+        return None;
+    }
+    Some((name, rest))
+}
+
+// ── Heredocs ──
+
+/// A `<< DELIM` or `<<- DELIM` operator whose body has not been read yet.
+///
+/// Heredoc bodies live on the lines *after* the command, so the caller reads
+/// them once the command line has been tokenized and hands each body back to
+/// [`attach_heredoc_body`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeredocRequest {
+    /// Index of the delimiter word (the word after `<<`).
+    pub word_index: usize,
+    /// Delimiter text with quotes removed.
+    pub delimiter: String,
+    /// True when any part of the delimiter was quoted or escaped, which
+    /// disables expansion inside the body.
+    pub quoted: bool,
+    /// True for `<<-`: leading tabs are stripped from every body line and
+    /// from the delimiter line.
+    pub strip_tabs: bool,
+}
+
+/// Find every heredoc operator in `words`, in source order.
+pub fn heredoc_requests(words: &[Word]) -> Result<Vec<HeredocRequest>, JshError> {
+    let mut requests = Vec::new();
+
+    for (idx, word) in words.iter().enumerate() {
+        let op = match word.as_slice() {
+            [WordSegment::Unquoted(token)] if matches!(&**token, "<<" | "<<-") => token,
+            _ => continue,
+        };
+
+        let delimiter_word = match words.get(idx + 1) {
+            Some(word) if !is_operator_word(word) => word,
+            _ => {
+                return Err(JshError::syntax(format!(
+                    "jsh: syntax error: expected delimiter after '{op}'"
+                )));
+            }
+        };
+
+        let mut delimiter = String::new();
+        let mut quoted = false;
+        for segment in delimiter_word {
+            match segment {
+                WordSegment::Unquoted(s) => delimiter.push_str(s),
+                WordSegment::DoubleQuoted(s) | WordSegment::SingleQuoted(s) => {
+                    delimiter.push_str(s);
+                    quoted = true;
+                }
+            }
+        }
+
+        requests.push(HeredocRequest {
+            word_index: idx + 1,
+            delimiter,
+            quoted,
+            strip_tabs: &**op == "<<-",
+        });
+    }
+
+    Ok(requests)
+}
+
+/// Replace the delimiter word of `request` with a word holding `body`.
+///
+/// A quoted delimiter makes the body fully literal. Otherwise the body
+/// behaves like double-quoted text whose `"` characters are ordinary:
+/// `$VAR`, `$(...)`, and backticks expand, and backslash escapes only `$`,
+/// `` ` ``, `\`, and newline.
+pub fn attach_heredoc_body(
+    words: &mut [Word],
+    request: &HeredocRequest,
+    body: &str,
+) -> Result<(), JshError> {
+    let word = if request.quoted {
+        vec![WordSegment::SingleQuoted(body.into())]
+    } else {
+        heredoc_body_word(body)?
+    };
+
+    if let Some(slot) = words.get_mut(request.word_index) {
+        *slot = word;
+    }
+    Ok(())
+}
+
+fn heredoc_body_word(body: &str) -> Result<Word, JshError> {
+    let mut word: Word = Vec::new();
+    let mut segment = String::new();
+    let mut chars = Cursor::new(body);
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.peek() {
+                Some(&('$' | '`' | '\\')) => {
+                    word.push(WordSegment::DoubleQuoted(std::mem::take(&mut segment).into()));
+                    word.push(WordSegment::SingleQuoted(chars.next().unwrap().to_string().into()));
+                }
+                Some(&'\n') => {
+                    // Line continuation: drop both the backslash and the newline.
+                    chars.next();
+                }
+                _ => segment.push('\\'),
+            },
+            '`' => push_command_substitution('`', body, &mut chars, &mut segment, false)?,
+            '$' if chars.peek() == Some(&'(') => {
+                push_command_substitution('(', body, &mut chars, &mut segment, false)?;
+            }
+            c => segment.push(c),
+        }
+    }
+
+    word.push(WordSegment::DoubleQuoted(segment.into()));
+    Ok(word)
+}
+
+// ── Source rendering ──
+
+/// Render words back into shell source that tokenizes to the same words.
+///
+/// Used to hand a parsed command to a child shell and to label jobs. Heredoc
+/// bodies cannot be re-read from following lines, so `<< DELIM` with an
+/// attached body is rendered as an equivalent `<<<` here-string.
+pub fn words_to_source(words: &[Word]) -> String {
+    let mut out = String::new();
+    let mut heredoc_body_next = false;
+
+    for word in words {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+
+        if heredoc_body_next {
+            // A here-string appends the newline the heredoc body already ends with.
+            let mut body = word.clone();
+            if let Some(
+                WordSegment::Unquoted(text)
+                | WordSegment::DoubleQuoted(text)
+                | WordSegment::SingleQuoted(text),
+            ) = body.last_mut()
+                && let Some(rest) = text.strip_suffix('\n')
+            {
+                *text = rest.into();
+            }
+            out.push_str(&word_to_source(&body));
+            heredoc_body_next = false;
+            continue;
+        }
+
+        match word.as_slice() {
+            [WordSegment::Unquoted(op)] if matches!(&**op, "<<" | "<<-") => {
+                out.push_str("<<<");
+                heredoc_body_next = true;
+            }
+            _ => out.push_str(&word_to_source(word)),
+        }
+    }
+
+    out
+}
+
+/// Render a single word, quoting each segment the way it was quoted.
+pub fn word_to_source(word: &Word) -> String {
+    let mut out = String::new();
+
+    for segment in word {
+        match segment {
+            WordSegment::Unquoted(text) => out.push_str(text),
+            // Backslash escapes tokenize to one-character literal segments.
+            WordSegment::SingleQuoted(text) if text.chars().count() == 1 && &**text != "\n" => {
+                out.push('\\');
+                out.push_str(text);
+            }
+            WordSegment::SingleQuoted(text) => {
+                out.push('\'');
+                out.push_str(&text.replace('\'', "'\\''"));
+                out.push('\'');
+            }
+            WordSegment::DoubleQuoted(text) => {
+                out.push('"');
+                let mut chars = Cursor::new(text);
+                while let Some(c) = chars.next() {
+                    match c {
+                        // Substitution bodies are re-scanned verbatim by the
+                        // tokenizer, so their quotes must not be escaped.
+                        '$' if chars.peek() == Some(&'(') => {
+                            chars.next();
+                            let body = scan_command_substitution(&mut chars).unwrap_or_default();
+                            out.push_str("$(");
+                            out.push_str(&body);
+                            out.push(')');
+                        }
+                        '"' | '\\' => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+        }
+    }
+
+    out
+}
+
+/// True for the bare operator tokens the tokenizer emits on its own.
+fn is_operator_word(word: &Word) -> bool {
+    word.len() == 1
+        && matches!(
+            &word[0],
+            WordSegment::Unquoted(token)
+                if matches!(&**token, "|" | "||" | "|&" | "&" | "&&" | ";" | "\n")
+                    || token.starts_with('<')
+                    || token.starts_with('>')
+                    || token.starts_with("&>")
+        )
 }
 
 #[cfg(test)]
@@ -504,14 +1173,14 @@ mod tests {
         let words = tokenize(r"\$HOME").unwrap();
         assert_eq!(words.len(), 1);
         // The $ should be in a SingleQuoted segment (literal)
-        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if s == "$")));
+        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if &**s == "$")));
     }
 
     #[test]
     fn escaped_tilde_is_literal() {
         let words = tokenize(r"\~").unwrap();
         assert_eq!(words.len(), 1);
-        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if s == "~")));
+        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if &**s == "~")));
     }
 
     #[test]
@@ -519,7 +1188,7 @@ mod tests {
         let words = tokenize(r"\*.rs").unwrap();
         assert_eq!(words.len(), 1);
         // The * should be SingleQuoted (literal), not Unquoted (expandable)
-        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if s == "*")));
+        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if &**s == "*")));
     }
 
     #[test]
@@ -528,7 +1197,7 @@ mod tests {
         let words = tokenize(r"echo foo\$BAR").unwrap();
         assert_eq!(words.len(), 2);
         let second = &words[1];
-        assert!(second.iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if s == "$")));
+        assert!(second.iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if &**s == "$")));
     }
 
     // ── Unterminated quote tests ──
@@ -569,6 +1238,12 @@ mod tests {
         assert_eq!(strings, vec!["ls", "2>&1"]);
     }
 
+    #[test]
+    fn fd_prefix_merged_with_input_redirects() {
+        let strings = words_to_strings(&tokenize("cat 3<data <&3 4<<<text <<EOF").unwrap());
+        assert_eq!(strings, vec!["cat", "3<", "data", "<&3", "4<<<", "text", "<<", "EOF"]);
+    }
+
     #[test]
     fn fd_prefix_1_merged_with_dup() {
         let strings = words_to_strings(&tokenize("echo err 1>&2").unwrap());
@@ -598,7 +1273,7 @@ mod tests {
     #[test]
     fn split_simple_pipeline() {
         let words = tokenize("echo hello | tr h H").unwrap();
-        let segments = split_pipeline(&words).unwrap();
+        let segments = split_pipeline(words).unwrap();
         let strings = segments
             .iter()
             .map(|segment| words_to_strings(segment))
@@ -609,14 +1284,14 @@ mod tests {
     #[test]
     fn split_pipeline_errors_on_leading_pipe() {
         let words = tokenize("| echo hi").unwrap();
-        assert!(split_pipeline(&words).is_err());
+        assert!(split_pipeline(words).is_err());
     }
 
     #[test]
     fn split_pipeline_errors_on_consecutive_pipes() {
         // Two pipe characters separated by whitespace — still a syntax error.
         let words = tokenize("echo hi | | tr").unwrap();
-        assert!(split_pipeline(&words).is_err());
+        assert!(split_pipeline(words).is_err());
     }
 
     #[test]
@@ -630,7 +1305,7 @@ mod tests {
                     .map(|seg| match seg {
                         WordSegment::Unquoted(s)
                         | WordSegment::DoubleQuoted(s)
-                        | WordSegment::SingleQuoted(s) => s.as_str(),
+                        | WordSegment::SingleQuoted(s) => &**s,
                     })
                     .collect::<String>()
             })
@@ -649,7 +1324,7 @@ mod tests {
                     .map(|seg| match seg {
                         WordSegment::Unquoted(s)
                         | WordSegment::DoubleQuoted(s)
-                        | WordSegment::SingleQuoted(s) => s.as_str(),
+                        | WordSegment::SingleQuoted(s) => &**s,
                     })
                     .collect::<String>()
             })
@@ -667,7 +1342,7 @@ mod tests {
                     .map(|seg| match seg {
                         WordSegment::Unquoted(s)
                         | WordSegment::DoubleQuoted(s)
-                        | WordSegment::SingleQuoted(s) => s.as_str(),
+                        | WordSegment::SingleQuoted(s) => &**s,
                     })
                     .collect::<String>()
             })
@@ -675,9 +1350,266 @@ mod tests {
         assert_eq!(strings, vec!["echo", "a", ";", "echo", "b"]);
     }
 
+    #[test]
+    fn combined_stream_operators_are_single_tokens() {
+        let words = tokenize("cmd&>out |& cat &>> log").unwrap();
+        let tokens: Vec<_> = words
+            .iter()
+            .map(|word| match word.as_slice() {
+                [WordSegment::Unquoted(s)] => &**s,
+                _ => panic!("unexpected word {word:?}"),
+            })
+            .collect();
+        assert_eq!(tokens, vec!["cmd", "&>", "out", "|&", "cat", "&>>", "log"]);
+    }
+
+    // ── Command substitution tests ──
+
+    #[test]
+    fn dollar_paren_substitution_is_one_word() {
+        let words = tokenize("echo $(date +%s) done").unwrap();
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[1], vec![WordSegment::Unquoted("$(date +%s)".into())]);
+    }
+
+    #[test]
+    fn backticks_become_dollar_paren() {
+        let words = tokenize("echo `date +%s`").unwrap();
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[1], vec![WordSegment::Unquoted("$(date +%s)".into())]);
+    }
+
+    #[test]
+    fn backtick_escapes_are_unescaped() {
+        // \` nests a substitution; \$ and \\ lose their backslash; others keep it.
+        let words = tokenize(r"echo `echo \`pwd\` \$HOME \\ \n`").unwrap();
+        assert_eq!(
+            words[1],
+            vec![WordSegment::Unquoted(r"$(echo `pwd` $HOME \ \n)".into())]
+        );
+    }
+
+    #[test]
+    fn backticks_inside_double_quotes() {
+        let words = tokenize(r#"echo "[`echo \"hi\"`]""#).unwrap();
+        assert_eq!(words[1], vec![WordSegment::DoubleQuoted(r#"[$(echo "hi")]"#.into())]);
+    }
+
+    #[test]
+    fn quotes_nest_inside_quoted_parameter_expansion() {
+        let words = tokenize(r#"echo "<${x/ /"  "}>""#).unwrap();
+        assert_eq!(words[1], vec![WordSegment::DoubleQuoted(r#"<${x/ /"  "}>"#.into())]);
+    }
+
+    #[test]
+    fn ansi_c_quotes_decode_escapes() {
+        let words = tokenize(r"echo a$'\tb\n\x41\101é\'\q' $'x\0y'").unwrap();
+        assert_eq!(
+            words[1],
+            vec![
+                WordSegment::Unquoted("a".into()),
+                WordSegment::SingleQuoted("\tb\nAAé'\\q".into()),
+            ]
+        );
+        assert_eq!(words[2], vec![WordSegment::SingleQuoted("x".into())]);
+        assert!(tokenize("echo $'open").unwrap_err().to_string().contains("unterminated"));
+        // Inside double quotes `$'` is not special.
+        let words = tokenize(r#"echo "$'x'""#).unwrap();
+        assert_eq!(words[1], vec![WordSegment::DoubleQuoted("$'x'".into())]);
+    }
+
+    #[test]
+    fn hash_starting_a_word_begins_a_comment() {
+        let words = words_to_strings(&tokenize("echo a#b '#c' # rest 'unclosed\necho $#").unwrap());
+        assert_eq!(words, vec!["echo", "a#b", "#c", "\n", "echo", "$#"]);
+        assert!(tokenize("# only a comment").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quoted_paren_does_not_close_substitution() {
+        let words = tokenize(r#"echo $(echo ")")x"#).unwrap();
+        assert_eq!(words[1], vec![WordSegment::Unquoted(r#"$(echo ")")x"#.into())]);
+    }
+
+    #[test]
+    fn escaped_backtick_in_double_quotes_is_literal() {
+        let words = tokenize(r#""a\`b\$c""#).unwrap();
+        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if &**s == "`")));
+        assert!(words[0].iter().any(|seg| matches!(seg, WordSegment::SingleQuoted(s) if &**s == "$")));
+        assert_eq!(words_to_strings(&words), vec!["a`b$c"]);
+    }
+
+    #[test]
+    fn unterminated_substitutions_are_errors() {
+        assert!(tokenize("echo `date").is_err());
+        assert!(tokenize("echo $(date").is_err());
+    }
+
+    #[test]
+    fn unterminated_errors_point_at_their_opener() {
+        for (input, line, column) in [
+            ("echo \"a\" 'b", 0, 9),
+            ("é \"$(date", 0, 3),
+            ("echo ok\nécho ${x:-y", 1, 5),
+            ("echo `date\nmore", 0, 5),
+            ("printf $'\\n", 0, 7),
+            ("echo \"one\ntwo", 0, 5),
+        ] {
+            let err = tokenize(input).unwrap_err();
+            let span = err.span().unwrap_or_else(|| panic!("no span for {input:?}"));
+            assert_eq!((span.line, span.column), (line, column), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn newline_is_separator_token() {
+        let strings = words_to_strings(&tokenize("echo a\necho b\n").unwrap());
+        assert_eq!(strings, vec!["echo", "a", "\n", "echo", "b", "\n"]);
+    }
+
+    #[test]
+    fn newline_inside_quotes_is_literal() {
+        let strings = words_to_strings(&tokenize("echo 'a\nb' \"c\nd\"").unwrap());
+        assert_eq!(strings, vec!["echo", "a\nb", "c\nd"]);
+    }
+
+    #[test]
+    fn backslash_newline_continues_line() {
+        let strings = words_to_strings(&tokenize("echo ab\\\ncd \\\n\"x\\\ny\"").unwrap());
+        assert_eq!(strings, vec!["echo", "abcd", "xy"]);
+    }
+
+    #[test]
+    fn words_render_back_to_equivalent_source() {
+        for input in [
+            r#"echo plain 'sq $x' "dq \"$x\" \\" a\ b"#,
+            r#"echo "$(echo "inner")" 2>&1 | wc -l"#,
+            "echo 'it'\\''s'",
+        ] {
+            let words = tokenize(input).unwrap();
+            let source = words_to_source(&words);
+            assert_eq!(tokenize(&source).unwrap(), words, "{input} -> {source}");
+        }
+    }
+
+    #[test]
+    fn heredoc_renders_as_here_string() {
+        let mut words = tokenize("cat << EOF").unwrap();
+        let request = heredoc_requests(&words).unwrap().remove(0);
+        attach_heredoc_body(&mut words, &request, "one\ntwo\n").unwrap();
+        assert_eq!(words_to_source(&words), "cat <<< \"one\ntwo\"");
+    }
+
+    // ── Heredoc tests ──
+
+    #[test]
+    fn heredoc_request_found_after_operator() {
+        let words = tokenize("cat << EOF > out.txt").unwrap();
+        let requests = heredoc_requests(&words).unwrap();
+        assert_eq!(
+            requests,
+            vec![HeredocRequest {
+                word_index: 2,
+                delimiter: "EOF".into(),
+                quoted: false,
+                strip_tabs: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn dash_heredoc_requests_tab_stripping() {
+        let words = tokenize("cat <<-EOF").unwrap();
+        assert_eq!(words[1], vec![WordSegment::Unquoted("<<-".into())]);
+        let requests = heredoc_requests(&words).unwrap();
+        assert_eq!(requests[0].delimiter, "EOF");
+        assert!(requests[0].strip_tabs);
+    }
+
+    #[test]
+    fn quoted_heredoc_delimiter_disables_expansion() {
+        let words = tokenize("cat <<'END'").unwrap();
+        let requests = heredoc_requests(&words).unwrap();
+        assert_eq!(requests[0].delimiter, "END");
+        assert!(requests[0].quoted);
+
+        let words = tokenize(r"cat <<\END").unwrap();
+        assert!(heredoc_requests(&words).unwrap()[0].quoted);
+    }
+
+    #[test]
+    fn heredoc_without_delimiter_is_error() {
+        let words = tokenize("cat <<").unwrap();
+        assert!(heredoc_requests(&words).is_err());
+        let words = tokenize("cat << | wc").unwrap();
+        assert!(heredoc_requests(&words).is_err());
+    }
+
+    #[test]
+    fn attached_heredoc_body_replaces_delimiter() {
+        let mut words = tokenize("cat << EOF").unwrap();
+        let request = heredoc_requests(&words).unwrap().remove(0);
+        attach_heredoc_body(&mut words, &request, "a \"$X\" \\$Y\n").unwrap();
+        assert_eq!(
+            words[2],
+            vec![
+                WordSegment::DoubleQuoted("a \"$X\" ".into()),
+                WordSegment::SingleQuoted("$".into()),
+                WordSegment::DoubleQuoted("Y\n".into()),
+            ]
+        );
+
+        let mut words = tokenize("cat << 'EOF'").unwrap();
+        let request = heredoc_requests(&words).unwrap().remove(0);
+        attach_heredoc_body(&mut words, &request, "$X\n").unwrap();
+        assert_eq!(words[2], vec![WordSegment::SingleQuoted("$X\n".into())]);
+    }
+
     #[test]
     fn split_pipeline_errors_on_trailing_pipe() {
         let words = tokenize("echo hi |").unwrap();
-        assert!(split_pipeline(&words).is_err());
+        assert!(split_pipeline(words).is_err());
+    }
+
+    #[test]
+    fn split_assignment_requires_unquoted_name() {
+        let words = tokenize(r#"FOO="a b"c x=y=z "BAR"=1 1x=2 =3"#).unwrap();
+        assert_eq!(
+            split_assignment(&words[0]),
+            Some((
+                "FOO".to_string(),
+                vec![
+                    WordSegment::DoubleQuoted("a b".into()),
+                    WordSegment::Unquoted("c".into()),
+                ]
+            ))
+        );
+        assert_eq!(
+            split_assignment(&words[1]),
+            Some(("x".to_string(), vec![WordSegment::Unquoted("y=z".into())]))
+        );
+        assert_eq!(split_assignment(&words[2]), None);
+        assert_eq!(split_assignment(&words[3]), None);
+        assert_eq!(split_assignment(&words[4]), None);
+        let assignments: Vec<bool> = words.iter().map(is_assignment).collect();
+        assert_eq!(assignments, [true, true, false, false, false]);
+    }
+
+    #[test]
+    fn offsets_count_bytes_on_long_lines() {
+        let line = vec!["é \"ü\" ;"; 5000].concat();
+        let words = tokenize_with_offsets(&line).unwrap();
+        assert_eq!(words.len(), 15000);
+        let (last, offset) = words.last().unwrap();
+        assert_eq!(last, &vec![WordSegment::Unquoted(";".into())]);
+        assert_eq!(&line[*offset..], ";");
+
+        let mut cursor = Cursor::new("aé");
+        assert_eq!(cursor.next_if(|&c| c == 'é'), None);
+        assert_eq!(cursor.next(), Some('a'));
+        assert_eq!(cursor.peek(), Some(&'é'));
+        assert_eq!(unread_offset("aé", &cursor), 1);
+        assert_eq!(cursor.next_if(|&c| c == 'é'), Some('é'));
+        assert_eq!(unread_offset("aé", &cursor), 3);
     }
 }
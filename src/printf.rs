@@ -0,0 +1,561 @@
+/// The result of formatting: the bytes to print, plus any complaints about
+/// arguments, which make `printf` exit with status 1.
+#[derive(Debug, Default)]
+pub struct Formatted {
+    pub output: Vec<u8>,
+    pub errors: Vec<String>,
+}
+
+/// Format `args` according to `format` the way `printf(1)` does.
+///
+/// The format is reused until every argument has been consumed; conversions
+/// left without an argument get an empty string or zero.
+pub fn format(format: &str, args: &[String]) -> Formatted {
+    let mut formatted = Formatted::default();
+    let mut args = Args { args, next: 0 };
+    loop {
+        let stop = format_once(format, &mut args, &mut formatted);
+        if stop || args.next == 0 || args.next >= args.args.len() {
+            break;
+        }
+    }
+    formatted
+}
+
+struct Args<'a> {
+    args: &'a [String],
+    next: usize,
+}
+
+impl Args<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let arg = self.args.get(self.next)?;
+        self.next += 1;
+        Some(arg)
+    }
+
+    fn next_str(&mut self) -> &str {
+        self.next().unwrap_or_default()
+    }
+
+    /// The next argument as an integer. Like C `printf`, `'c` gives the code
+    /// of the character `c`; bad numbers are reported and count as zero.
+    fn next_int(&mut self, errors: &mut Vec<String>) -> i64 {
+        let Some(arg) = self.next() else { return 0 };
+        parse_int(arg).unwrap_or_else(|| {
+            errors.push(format!("jsh: printf: {arg}: invalid number"));
+            0
+        })
+    }
+
+    fn next_float(&mut self, errors: &mut Vec<String>) -> f64 {
+        let Some(arg) = self.next() else { return 0.0 };
+        if let Some(c) = char_code(arg) {
+            return c as f64;
+        }
+        arg.trim().parse().unwrap_or_else(|_| {
+            errors.push(format!("jsh: printf: {arg}: invalid number"));
+            0.0
+        })
+    }
+}
+
+/// Format one pass over `format`. Returns true if `\c` asked for output to
+/// stop.
+fn format_once(format: &str, args: &mut Args, out: &mut Formatted) -> bool {
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if decode_escape(&mut chars, false, &mut out.output) {
+                    return true;
+                }
+            }
+            '%' if chars.peek() == Some(&'%') => {
+                chars.next();
+                out.output.push(b'%');
+            }
+            '%' => {
+                let spec = match parse_spec(&mut chars, args, &mut out.errors) {
+                    Some(spec) => spec,
+                    None => {
+                        out.output.push(b'%');
+                        continue;
+                    }
+                };
+                if spec.convert(args, out) {
+                    return true;
+                }
+            }
+            c => push_char(&mut out.output, c),
+        }
+    }
+    false
+}
+
+/// A parsed `%` conversion such as `%-08.3f`.
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+fn parse_spec(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    args: &mut Args,
+    errors: &mut Vec<String>,
+) -> Option<Spec> {
+    let mut spec = Spec {
+        left: false,
+        zero: false,
+        plus: false,
+        space: false,
+        alternate: false,
+        width: 0,
+        precision: None,
+        conversion: 's',
+    };
+    while let Some(&c) = chars.peek() {
+        match c {
+            '-' => spec.left = true,
+            '0' => spec.zero = true,
+            '+' => spec.plus = true,
+            ' ' => spec.space = true,
+            '#' => spec.alternate = true,
+            _ => break,
+        }
+        chars.next();
+    }
+
+    if chars.peek() == Some(&'*') {
+        chars.next();
+        let width = args.next_int(errors);
+        spec.left |= width < 0;
+        spec.width = width.unsigned_abs() as usize;
+    } else {
+        spec.width = read_number(chars);
+    }
+
+    if chars.peek() == Some(&'.') {
+        chars.next();
+        spec.precision = Some(if chars.peek() == Some(&'*') {
+            chars.next();
+            args.next_int(errors).max(0) as usize
+        } else {
+            read_number(chars)
+        });
+    }
+
+    // Length modifiers mean nothing here; accept and ignore them.
+    while chars.next_if(|c| matches!(c, 'h' | 'l' | 'L' | 'j' | 'z' | 't')).is_some() {}
+
+    let conversion = chars.next()?;
+    if !"sbqcdiouxXeEfFgG".contains(conversion) {
+        errors.push(format!("jsh: printf: %{conversion}: invalid format character"));
+        return None;
+    }
+    spec.conversion = conversion;
+    Some(spec)
+}
+
+fn read_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> usize {
+    let mut number = 0usize;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        number = number.saturating_mul(10).saturating_add(digit as usize);
+        chars.next();
+    }
+    number
+}
+
+impl Spec {
+    /// Format one argument into `out`. Returns true if a `%b` argument
+    /// contained `\c`.
+    fn convert(&self, args: &mut Args, out: &mut Formatted) -> bool {
+        match self.conversion {
+            's' => {
+                let text = self.truncate(args.next_str());
+                self.pad(text.as_bytes(), &mut out.output);
+            }
+            'b' => {
+                let mut text = Vec::new();
+                let stop = expand_escapes(args.next_str(), &mut text);
+                if let Some(precision) = self.precision {
+                    text.truncate(precision);
+                }
+                self.pad(&text, &mut out.output);
+                return stop;
+            }
+            'q' => {
+                let quoted = quote(args.next_str());
+                self.pad(quoted.as_bytes(), &mut out.output);
+            }
+            'c' => {
+                let text: String = args.next_str().chars().take(1).collect();
+                self.pad(text.as_bytes(), &mut out.output);
+            }
+            'd' | 'i' => {
+                let value = args.next_int(&mut out.errors);
+                let sign = if value < 0 {
+                    "-"
+                } else if self.plus {
+                    "+"
+                } else if self.space {
+                    " "
+                } else {
+                    ""
+                };
+                self.pad_number(sign, "", &value.unsigned_abs().to_string(), &mut out.output);
+            }
+            'o' | 'u' | 'x' | 'X' => {
+                // Negative values wrap around, as in C.
+                let value = args.next_int(&mut out.errors) as u64;
+                let (digits, prefix) = match self.conversion {
+                    'o' => (format!("{value:o}"), if self.alternate && value != 0 { "0" } else { "" }),
+                    'u' => (value.to_string(), ""),
+                    'x' => (format!("{value:x}"), if self.alternate && value != 0 { "0x" } else { "" }),
+                    _ => (format!("{value:X}"), if self.alternate && value != 0 { "0X" } else { "" }),
+                };
+                self.pad_number("", prefix, &digits, &mut out.output);
+            }
+            _ => {
+                let value = args.next_float(&mut out.errors);
+                let sign = if value.is_sign_negative() && value != 0.0 {
+                    "-"
+                } else if self.plus {
+                    "+"
+                } else if self.space {
+                    " "
+                } else {
+                    ""
+                };
+                let digits = format_float(value.abs(), self.conversion, self.precision.unwrap_or(6), self.alternate);
+                // Zero padding does not apply to `inf` and `nan`.
+                if value.is_finite() {
+                    self.pad_number(sign, "", &digits, &mut out.output);
+                } else {
+                    self.pad(format!("{sign}{digits}").as_bytes(), &mut out.output);
+                }
+            }
+        }
+        false
+    }
+
+    fn truncate<'a>(&self, text: &'a str) -> &'a str {
+        match self.precision {
+            Some(precision) => match text.char_indices().nth(precision) {
+                Some((idx, _)) => &text[..idx],
+                None => text,
+            },
+            None => text,
+        }
+    }
+
+    /// Pad `text` with spaces to the field width.
+    fn pad(&self, text: &[u8], out: &mut Vec<u8>) {
+        let fill = self.width.saturating_sub(String::from_utf8_lossy(text).chars().count());
+        if !self.left {
+            out.extend(std::iter::repeat_n(b' ', fill));
+        }
+        out.extend_from_slice(text);
+        if self.left {
+            out.extend(std::iter::repeat_n(b' ', fill));
+        }
+    }
+
+    /// Pad a number to the field width, with zeros between the sign and the
+    /// digits for the `0` flag. An integer precision is a minimum number of
+    /// digits.
+    fn pad_number(&self, sign: &str, prefix: &str, digits: &str, out: &mut Vec<u8>) {
+        let mut digits = digits.to_string();
+        let integer = !"eEfFgG".contains(self.conversion);
+        if integer && let Some(precision) = self.precision {
+            if precision == 0 && digits == "0" {
+                digits.clear();
+            }
+            digits = format!("{digits:0>precision$}");
+        }
+        let len = sign.len() + prefix.len() + digits.len();
+        let fill = self.width.saturating_sub(len);
+        // With a precision, integers ignore the `0` flag, as in C.
+        let zero = self.zero && !self.left && !(integer && self.precision.is_some());
+        if zero {
+            out.extend_from_slice(sign.as_bytes());
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend(std::iter::repeat_n(b'0', fill));
+            out.extend_from_slice(digits.as_bytes());
+        } else {
+            let number = format!("{sign}{prefix}{digits}");
+            self.pad(number.as_bytes(), out);
+        }
+    }
+}
+
+/// Format a non-negative float for `%f`, `%e`, or `%g` (and their upper-case
+/// forms) with C semantics.
+fn format_float(value: f64, conversion: char, precision: usize, alternate: bool) -> String {
+    let upper = conversion.is_ascii_uppercase();
+    let text = if !value.is_finite() {
+        if value.is_nan() { "nan".to_string() } else { "inf".to_string() }
+    } else {
+        match conversion.to_ascii_lowercase() {
+            'f' => format!("{value:.precision$}"),
+            'e' => format_exponent(value, precision),
+            _ => {
+                // %g picks %e or %f by the exponent, then drops trailing zeros.
+                let precision = precision.max(1);
+                let exponent = if value == 0.0 {
+                    0
+                } else {
+                    format_exponent(value, precision - 1)
+                        .rsplit('e')
+                        .next()
+                        .and_then(|exp| exp.parse::<i32>().ok())
+                        .unwrap_or(0)
+                };
+                let text = if exponent < -4 || exponent >= precision as i32 {
+                    format_exponent(value, precision - 1)
+                } else {
+                    let decimals = (precision as i32 - 1 - exponent).max(0) as usize;
+                    format!("{value:.decimals$}")
+                };
+                if alternate {
+                    text
+                } else {
+                    strip_trailing_zeros(&text)
+                }
+            }
+        }
+    };
+    if upper { text.to_ascii_uppercase() } else { text }
+}
+
+/// `%e` formatting: Rust writes `1.5e2` where C writes `1.500000e+02`.
+fn format_exponent(value: f64, precision: usize) -> String {
+    let text = format!("{value:.precision$e}");
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.unsigned_abs())
+}
+
+fn strip_trailing_zeros(text: &str) -> String {
+    let (mantissa, exponent) = match text.find('e') {
+        Some(idx) => text.split_at(idx),
+        None => (text, ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{mantissa}{exponent}")
+}
+
+/// Parse a `printf` integer argument: decimal, `0x` hex, `0` octal, or a
+/// quote followed by a character.
+fn parse_int(arg: &str) -> Option<i64> {
+    if let Some(c) = char_code(arg) {
+        return Some(c as i64);
+    }
+    let text = arg.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// For `'c` or `"c`, the code of the character `c`.
+fn char_code(arg: &str) -> Option<u32> {
+    let rest = arg.strip_prefix('\'').or_else(|| arg.strip_prefix('"'))?;
+    rest.chars().next().map(|c| c as u32)
+}
+
+/// Write `text` to `out` with backslash escapes decoded as `%b` and
+/// `echo -e` do. Returns true if `\c` asked for output to stop there.
+pub fn expand_escapes(text: &str, out: &mut Vec<u8>) -> bool {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if decode_escape(&mut chars, true, out) {
+                return true;
+            }
+        } else {
+            push_char(out, c);
+        }
+    }
+    false
+}
+
+/// Decode the escape after a backslash into `out`. In `%b` arguments octal
+/// escapes are written `\0nnn` and `\c` stops all output (returning true);
+/// in the format they are `\nnn`.
+fn decode_escape(chars: &mut std::iter::Peekable<std::str::Chars>, in_argument: bool, out: &mut Vec<u8>) -> bool {
+    let Some(c) = chars.next() else {
+        out.push(b'\\');
+        return false;
+    };
+    let byte = match c {
+        'a' => 0x07,
+        'b' => 0x08,
+        'e' | 'E' => 0x1b,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        '\\' => b'\\',
+        '"' if !in_argument => b'"',
+        '\'' if !in_argument => b'\'',
+        'c' if in_argument => return true,
+        '0'..='7' => {
+            // `%b` takes up to three digits after a leading 0.
+            let (mut value, max_digits) = match c {
+                '0' if in_argument => (0, 3),
+                _ => (c.to_digit(8).unwrap(), 2),
+            };
+            for _ in 0..max_digits {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(digit) => {
+                        value = value * 8 + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            value as u8
+        }
+        'x' => {
+            let mut value = 0;
+            let mut digits = 0;
+            while digits < 2
+                && let Some(digit) = chars.peek().and_then(|c| c.to_digit(16))
+            {
+                value = value * 16 + digit;
+                digits += 1;
+                chars.next();
+            }
+            if digits == 0 {
+                out.extend_from_slice(b"\\x");
+                return false;
+            }
+            value as u8
+        }
+        'u' | 'U' => {
+            let max_digits = if c == 'u' { 4 } else { 8 };
+            let mut value = 0;
+            let mut digits = 0;
+            while digits < max_digits
+                && let Some(digit) = chars.peek().and_then(|c| c.to_digit(16))
+            {
+                value = value * 16 + digit;
+                digits += 1;
+                chars.next();
+            }
+            match char::from_u32(value).filter(|_| digits > 0) {
+                Some(decoded) => push_char(out, decoded),
+                None => {
+                    out.push(b'\\');
+                    push_char(out, c);
+                }
+            }
+            return false;
+        }
+        c => {
+            out.push(b'\\');
+            push_char(out, c);
+            return false;
+        }
+    };
+    out.push(byte);
+    false
+}
+
+fn push_char(out: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Quote `text` for `%q` so the shell reads it back as one word.
+fn quote(text: &str) -> String {
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || "_-./:=,@%+".contains(c)) {
+        return text.to_string();
+    }
+    if text.chars().any(|c| c.is_control()) {
+        let mut quoted = String::from("$'");
+        for c in text.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\\' | '\'' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf(fmt: &str, args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        String::from_utf8(format(fmt, &args).output).unwrap()
+    }
+
+    #[test]
+    fn strings_and_escapes() {
+        assert_eq!(printf("[%s]\\n", &["a b"]), "[a b]\n");
+        assert_eq!(printf("[%5s][%-5s][%.2s]", &["ab", "cd", "efgh"]), "[   ab][cd   ][ef]");
+        assert_eq!(printf("100%%\\t\\101", &[]), "100%\tA");
+        assert_eq!(printf("%b|", &["x\\ty", "\\0101\\c", "never"]), "x\ty|A");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(printf("%d %i %+d %05d", &["42", "-7", "3", "-12"]), "42 -7 +3 -0012");
+        assert_eq!(printf("%x %X %#x %o %#o", &["255", "255", "255", "8", "8"]), "ff FF 0xff 10 010");
+        assert_eq!(printf("%d %d", &["0x10", "'A"]), "16 65");
+        assert_eq!(printf("%.2f %8.3f %e %g %g", &["3.14159", "2.5", "1500", "0.0001", "1234567"]), "3.14    2.500 1.500000e+03 0.0001 1.23457e+06");
+        assert_eq!(printf("%*d|%.*f", &["4", "7", "1", "2.25"]), "   7|2.2");
+    }
+
+    #[test]
+    fn bad_numbers_are_reported() {
+        let result = format("%d", &["abc".to_string()]);
+        assert_eq!(result.output, b"0");
+        assert_eq!(result.errors, vec!["jsh: printf: abc: invalid number"]);
+    }
+
+    #[test]
+    fn format_is_reused_for_remaining_arguments() {
+        assert_eq!(printf("<%s=%s>", &["a", "1", "b"]), "<a=1><b=>");
+        assert_eq!(printf("x\\n", &["ignored"]), "x\n");
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(printf("%q %q %q", &["plain", "a b", "it's"]), r"plain 'a b' 'it'\''s'");
+        assert_eq!(printf("%q", &["a\nb"]), r"$'a\nb'");
+    }
+}
@@ -0,0 +1,278 @@
+use std::io::IsTerminal;
+use std::path::is_separator;
+
+use crate::builtins;
+use crate::jobs::JobStatus;
+use crate::shell::Shell;
+
+/// The prompt shown when neither `JSH_PROMPT` nor `PS1` is set.
+pub const DEFAULT_PS1: &str = "jsh> ";
+
+/// [`DEFAULT_PS1`] after a command failed in an interactive shell, under
+/// `shopt -s promptstatus`: `jsh [1]> `.
+const STATUS_PS1: &str = "jsh [\\?]> ";
+
+/// [`STATUS_PS1`] with the status in red, for a terminal.
+const COLOR_STATUS_PS1: &str = "jsh \\[\\e[31m\\][\\?]\\[\\e[0m\\]> ";
+
+/// The primary prompt template: `$JSH_PROMPT`, else `$PS1`, else
+/// [`DEFAULT_PS1`], which may show the last exit status.
+pub fn primary_template(shell: &Shell) -> &str {
+    shell
+        .variables
+        .get("JSH_PROMPT")
+        .or_else(|| shell.variables.get("PS1"))
+        .unwrap_or_else(|| default_template(shell))
+}
+
+fn default_template(shell: &Shell) -> &'static str {
+    if !shell.interactive || !shell.options.promptstatus || shell.last_exit_code == 0 {
+        DEFAULT_PS1
+    } else if std::io::stdout().is_terminal() {
+        COLOR_STATUS_PS1
+    } else {
+        STATUS_PS1
+    }
+}
+
+/// The continuation prompt shown while a command is unfinished, when `PS2`
+/// is not set.
+pub const DEFAULT_PS2: &str = "> ";
+
+/// The continuation prompt template: `$PS2`, else [`DEFAULT_PS2`].
+pub fn secondary_template(shell: &Shell) -> &str {
+    shell.variables.get("PS2").unwrap_or(DEFAULT_PS2)
+}
+
+/// Expand the backslash escapes of a prompt template, bash style:
+///
+/// | escape | expands to |
+/// |--------|------------|
+/// | `\u` | user name |
+/// | `\h` `\H` | host name up to the first `.`, or in full |
+/// | `\w` `\W` | current directory with `$HOME` shown as `~`, or its last component |
+/// | `\$` | `#` for root, otherwise `$` |
+/// | `\?` | exit status of the last command |
+/// | `\j` | number of jobs |
+/// | `\D` | how long the last command took, if at least `$JSH_DURATION_THRESHOLD` seconds (default 2) |
+/// | `\t` `\T` `\@` `\A` | time as 24-hour `HH:MM:SS`, 12-hour `HH:MM:SS`, 12-hour `HH:MM AM`, 24-hour `HH:MM` |
+/// | `\s` | shell name |
+/// | `\n` `\e` `\\` | newline, escape, backslash |
+/// | `\[` `\]` | bracket non-printing text such as colour codes; dropped |
+///
+/// Unknown escapes are kept as written.
+pub fn render(template: &str, shell: &Shell) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => prompt.push_str(&user_name(shell)),
+            Some('h') => prompt.push_str(host_name().split('.').next().unwrap_or_default()),
+            Some('H') => prompt.push_str(&host_name()),
+            Some('w') => prompt.push_str(&working_directory(shell)),
+            Some('W') => {
+                // The root, `C:\` or `/`, is shown whole.
+                let cwd = working_directory(shell);
+                let base = cwd
+                    .trim_end_matches(is_separator)
+                    .rsplit(is_separator)
+                    .next()
+                    .filter(|base| !base.is_empty() && !base.ends_with(':'))
+                    .unwrap_or(&cwd);
+                prompt.push_str(base);
+            }
+            Some('$') => prompt.push(if is_root() { '#' } else { '$' }),
+            Some('?') => prompt.push_str(&shell.last_exit_code.to_string()),
+            Some('j') => {
+                let jobs = shell.job_table.jobs_sorted().into_iter();
+                let live = jobs.filter(|job| !matches!(job.status, JobStatus::Done(_))).count();
+                prompt.push_str(&live.to_string())
+            }
+            Some('D') => prompt.push_str(&command_duration(shell)),
+            Some('s') => prompt.push_str("jsh"),
+            Some(format @ ('t' | 'T' | '@' | 'A')) => prompt.push_str(&format_time(format, local_time())),
+            Some('n') => prompt.push('\n'),
+            Some('e') => prompt.push('\x1b'),
+            Some('\\') => prompt.push('\\'),
+            Some('[' | ']') => {}
+            Some(other) => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+            None => prompt.push('\\'),
+        }
+    }
+    prompt
+}
+
+fn user_name(shell: &Shell) -> String {
+    shell
+        .variables
+        .get("USER")
+        .or_else(|| shell.variables.get("USERNAME"))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The logical working directory, so a path entered through a symlink is
+/// shown as typed.
+fn working_directory(shell: &Shell) -> String {
+    let Ok(cwd) = builtins::logical_pwd(&shell.variables, &shell.cwd) else {
+        return String::new();
+    };
+    let cwd = cwd.to_string_lossy().into_owned();
+    match shell.variables.get("HOME").filter(|home| !home.is_empty() && *home != "/") {
+        Some(home) if cwd == home => "~".to_string(),
+        Some(home) if cwd.strip_prefix(home).is_some_and(|rest| rest.starts_with(is_separator)) => {
+            format!("~{}", &cwd[home.len()..])
+        }
+        _ => cwd,
+    }
+}
+
+#[cfg(unix)]
+fn host_name() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its length; gethostname NUL-terminates
+    // names that fit.
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn host_name() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid cannot fail.
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// The local time of day as `(hours, minutes, seconds)`.
+#[cfg(unix)]
+fn local_time() -> (u32, u32, u32) {
+    // SAFETY: time(NULL) is always valid, and localtime_r only writes to the
+    // tm we pass it.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return (0, 0, 0);
+        }
+        (tm.tm_hour as u32, tm.tm_min as u32, tm.tm_sec as u32)
+    }
+}
+
+/// Without a portable time zone lookup, other platforms show UTC.
+#[cfg(not(unix))]
+fn local_time() -> (u32, u32, u32) {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let of_day = (seconds % 86_400) as u32;
+    (of_day / 3600, of_day / 60 % 60, of_day % 60)
+}
+
+/// The threshold `\D` uses when `$JSH_DURATION_THRESHOLD` is unset.
+const DEFAULT_DURATION_THRESHOLD: f64 = 2.0;
+
+/// `$CMD_DURATION`, or nothing if the last command was quicker than
+/// `$JSH_DURATION_THRESHOLD` seconds.
+fn command_duration(shell: &Shell) -> String {
+    let Some(millis) = shell.variables.get("CMD_DURATION").and_then(|ms| ms.parse::<u64>().ok()) else {
+        return String::new();
+    };
+    let threshold = shell
+        .variables
+        .get("JSH_DURATION_THRESHOLD")
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_DURATION_THRESHOLD);
+    if (millis as f64) < threshold * 1000.0 {
+        return String::new();
+    }
+    format_duration(millis)
+}
+
+/// A duration as `4.2s`, `1m05s`, or `2h03m`.
+pub(crate) fn format_duration(millis: u64) -> String {
+    let seconds = millis / 1000;
+    match seconds {
+        0..60 => format!("{seconds}.{}s", millis % 1000 / 100),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+fn format_time(format: char, (hours, minutes, seconds): (u32, u32, u32)) -> String {
+    let twelve = match hours % 12 {
+        0 => 12,
+        h => h,
+    };
+    match format {
+        't' => format!("{hours:02}:{minutes:02}:{seconds:02}"),
+        'T' => format!("{twelve:02}:{minutes:02}:{seconds:02}"),
+        '@' => format!("{twelve:02}:{minutes:02} {}", if hours < 12 { "AM" } else { "PM" }),
+        _ => format!("{hours:02}:{minutes:02}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_expand_from_shell_state() {
+        let mut shell = Shell::new();
+        shell.variables.set("USER", "alice");
+        shell.last_exit_code = 3;
+        assert_eq!(render(r"\u [\?] \j\\ \q", &shell), r"alice [3] 0\ \q");
+        assert_eq!(render(r"\[\e[1m\]\s\[\e[0m\]\n", &shell), "\x1b[1mjsh\x1b[0m\n");
+        let dollar = render(r"\$", &shell);
+        assert!(dollar == "$" || dollar == "#");
+    }
+
+    #[test]
+    fn working_directory_abbreviates_home() {
+        let mut shell = Shell::new();
+        let cwd = std::env::current_dir().unwrap().to_string_lossy().into_owned();
+        shell.variables.set("HOME", &cwd);
+        assert_eq!(render(r"\w \W", &shell), "~ ~");
+    }
+
+    #[test]
+    fn duration_shows_only_above_the_threshold() {
+        let mut shell = Shell::new();
+        assert_eq!(render(r"[\D]", &shell), "[]");
+        shell.variables.set("CMD_DURATION", "1999");
+        assert_eq!(render(r"[\D]", &shell), "[]");
+        shell.variables.set("CMD_DURATION", "4250");
+        assert_eq!(render(r"[\D]", &shell), "[4.2s]");
+        shell.variables.set("JSH_DURATION_THRESHOLD", "0");
+        shell.variables.set("CMD_DURATION", "12");
+        assert_eq!(render(r"[\D]", &shell), "[0.0s]");
+        assert_eq!(format_duration(65_000), "1m05s");
+        assert_eq!(format_duration(7_380_000), "2h03m");
+    }
+
+    #[test]
+    fn times_are_formatted() {
+        assert_eq!(format_time('t', (13, 5, 9)), "13:05:09");
+        assert_eq!(format_time('T', (13, 5, 9)), "01:05:09");
+        assert_eq!(format_time('@', (0, 30, 0)), "12:30 AM");
+        assert_eq!(format_time('A', (23, 59, 1)), "23:59");
+    }
+}
@@ -0,0 +1,406 @@
+use std::io;
+
+use crate::aliases::AliasTable;
+use crate::ast::CommandList;
+use crate::error::{JshError, Span};
+use crate::parser::{self, Word, WordSegment};
+use crate::script_parser::{self, ParseError};
+
+/// Where command lines come from: the interactive line editor, a script
+/// file, or an rc file.
+pub trait LineSource {
+    /// Read one line, or `None` at end of input.
+    ///
+    /// `continuation` is true while a command is unfinished — an open quote,
+    /// an `if` without `fi`, a heredoc body — so interactive sources can show
+    /// the secondary prompt instead of the primary one.
+    fn read_line(&mut self, continuation: bool) -> io::Result<Option<String>>;
+}
+
+/// Lines of an in-memory script, such as the contents of a sourced file.
+pub struct ScriptLines<'a> {
+    lines: std::str::Lines<'a>,
+}
+
+impl<'a> ScriptLines<'a> {
+    pub fn new(script: &'a str) -> Self {
+        Self {
+            lines: script.lines(),
+        }
+    }
+}
+
+impl LineSource for ScriptLines<'_> {
+    fn read_line(&mut self, _continuation: bool) -> io::Result<Option<String>> {
+        Ok(self.lines.next().map(str::to_string))
+    }
+}
+
+/// One complete unit of input.
+#[derive(Debug)]
+pub enum Input {
+    /// A command ready to run, with the text to record in history. `line`
+    /// is the 1-based line it starts on.
+    Command {
+        list: CommandList,
+        history: String,
+        line: usize,
+    },
+    /// Input that failed to parse. `line` is the 1-based line, counted from
+    /// the start of the source, of the offending token when the error has a
+    /// span, and otherwise where the error was detected.
+    SyntaxError {
+        error: JshError,
+        line: usize,
+        history: String,
+    },
+    /// End of input between commands.
+    Eof,
+}
+
+/// Reads complete commands from a [`LineSource`].
+///
+/// A command may span several lines: an open quote, a trailing `&&`, `|`,
+/// or `\`, or an `if` without its `fi`. Lines accumulate until the script
+/// parser stops reporting the input as incomplete. Heredoc bodies are read from the
+/// lines that follow the line containing their `<<` operator.
+#[derive(Debug, Default)]
+pub struct CommandReader {
+    /// Lines consumed so far, for error positions.
+    line_number: usize,
+}
+
+impl CommandReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the next complete command, expanding `aliases` as it is parsed.
+    ///
+    /// I/O errors (including an interrupted read) abandon the partially read
+    /// command; the next call starts fresh.
+    pub fn read_command(
+        &mut self,
+        source: &mut dyn LineSource,
+        aliases: &AliasTable,
+    ) -> io::Result<Input> {
+        // `text` holds input whose tokenization is still unfinished (an open
+        // quote or substitution); `words` holds the tokens of completed lines.
+        let mut text = String::new();
+        let mut words: Vec<Word> = Vec::new();
+        // Where each of `words` came from: an index into `texts`, which
+        // holds each tokenized text and the source line it starts on, and a
+        // byte offset into it. Spans are only built for a syntax error.
+        let mut offsets: Vec<(usize, usize)> = Vec::new();
+        let mut texts: Vec<(String, usize)> = Vec::new();
+        // The 0-based source line that `text` starts on.
+        let mut text_line = 0;
+        // The 1-based line the command starts on.
+        let mut first_line = 0;
+        let mut lines: Vec<String> = Vec::new();
+        // A line ended by `\`, minus the backslash, waiting to be joined to
+        // the next one.
+        let mut continued = String::new();
+
+        loop {
+            let continuing = !text.is_empty() || !words.is_empty() || !continued.is_empty();
+
+            let input = match source.read_line(continuing)? {
+                Some(input) => input,
+                // A backslash on the last line continues onto nothing.
+                None if !continued.is_empty() => String::new(),
+                None => {
+                    if !continuing {
+                        return Ok(Input::Eof);
+                    }
+                    // Input ended partway through a command. Re-running the
+                    // tokenizer on the open text reproduces its specific
+                    // complaint (e.g. "unterminated double quote").
+                    let error = match parser::parse_words(&text) {
+                        Err(error) if !text.is_empty() => rebase(error, text_line),
+                        _ => JshError::syntax("jsh: syntax error: unexpected end of file"),
+                    };
+                    return Ok(self.syntax_error(error, &lines));
+                }
+            };
+            self.line_number += 1;
+
+            let line = input.trim_end_matches(['\n', '\r']);
+            if !continuing && line.trim().is_empty() {
+                continue;
+            }
+            if !continuing {
+                first_line = self.line_number;
+            }
+            if text.is_empty() && continued.is_empty() {
+                text_line = self.line_number - 1;
+            }
+            let line = format!("{}{}", std::mem::take(&mut continued), line);
+            let line = line.as_str();
+
+            // The tokenizer only fails on input that ends inside a quote or
+            // substitution, so keep reading lines until it closes.
+            text.push_str(line);
+            let tokens = match parser::tokenize_with_offsets(&text) {
+                Ok(_) if ends_with_line_continuation(line) => {
+                    // Backslash-newline joins the next line onto this one.
+                    text.truncate(text.len() - line.len());
+                    continued = line[..line.len() - 1].to_string();
+                    continue;
+                }
+                Ok(tokens) => {
+                    lines.push(history_line(line, lines.is_empty()));
+                    tokens
+                }
+                Err(_) => {
+                    lines.push(history_line(line, lines.is_empty()));
+                    text.push('\n');
+                    continue;
+                }
+            };
+            let (mut line_words, line_offsets): (Vec<Word>, Vec<usize>) = tokens.into_iter().unzip();
+            let chunk = texts.len();
+            offsets.extend(line_offsets.into_iter().map(|offset| (chunk, offset)));
+            offsets.push((chunk, text.len()));
+            texts.push((std::mem::take(&mut text), text_line));
+
+            // Heredoc bodies follow the command line, so read them before the
+            // words are handed to the script parser.
+            if let Err(error) = self.collect_heredocs(&mut line_words, source) {
+                return Ok(self.syntax_error(error, &lines));
+            }
+
+            words.append(&mut line_words);
+            words.push(vec![WordSegment::Unquoted("\n".into())]);
+
+            match script_parser::parse_script_with_aliases(words.clone(), aliases) {
+                Ok(list) => {
+                    return Ok(Input::Command {
+                        list,
+                        history: history_entry(&lines),
+                        line: first_line,
+                    });
+                }
+                Err(ParseError::Incomplete) => continue,
+                Err(ParseError::Syntax(error)) => {
+                    // Parse again with spans, which are too costly to build
+                    // for every command, to point at the bad token.
+                    let spans = offsets
+                        .iter()
+                        .map(|&(chunk, offset)| {
+                            let (text, text_line) = &texts[chunk];
+                            rebase_span(Span::at(text, offset), *text_line)
+                        })
+                        .collect();
+                    let error = match script_parser::parse_script_spanned(words, spans, aliases) {
+                        Err(ParseError::Syntax(located)) => located,
+                        _ => error,
+                    };
+                    return Ok(self.syntax_error(error, &lines));
+                }
+            }
+        }
+    }
+
+    /// The [`Input`] for `error`, reported on its span's line if it has one.
+    fn syntax_error(&self, error: JshError, lines: &[String]) -> Input {
+        Input::SyntaxError {
+            line: error.span().map_or(self.line_number, |span| span.line + 1),
+            error,
+            history: history_entry(lines),
+        }
+    }
+
+    /// Read heredoc body lines for every `<<` operator in `words` and splice
+    /// each body in place of its delimiter word.
+    fn collect_heredocs(
+        &mut self,
+        words: &mut [Word],
+        source: &mut dyn LineSource,
+    ) -> Result<(), JshError> {
+        for request in parser::heredoc_requests(words)? {
+            let body = self
+                .read_heredoc_body(source, &request.delimiter, request.strip_tabs)
+                .map_err(|e| JshError::syntax(format!("jsh: error reading here-document: {e}")))?;
+            parser::attach_heredoc_body(words, &request, &body)?;
+        }
+        Ok(())
+    }
+
+    /// Read lines until one matches `delimiter` exactly; the delimiter line
+    /// itself is not part of the body. EOF ends the body early with a warning,
+    /// as in bash. With `strip_tabs` (`<<-`), leading tabs are removed before
+    /// the comparison and from each body line.
+    fn read_heredoc_body(
+        &mut self,
+        source: &mut dyn LineSource,
+        delimiter: &str,
+        strip_tabs: bool,
+    ) -> io::Result<String> {
+        let mut body = String::new();
+
+        while let Some(line) = source.read_line(true)? {
+            self.line_number += 1;
+            let mut line = line.trim_end_matches(['\n', '\r']);
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
+            }
+            if line == delimiter {
+                return Ok(body);
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        eprintln!("jsh: warning: here-document delimited by end-of-file (wanted `{delimiter}')");
+        Ok(body)
+    }
+}
+
+/// Whether `line` ends in an unescaped backslash. Callers check that the
+/// line is not inside quotes, where the backslash would be literal.
+fn ends_with_line_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// Shift a span found in text starting on source line `first_line` so its
+/// line counts from the start of the source.
+fn rebase_span(mut span: Span, first_line: usize) -> Span {
+    span.line += first_line;
+    span
+}
+
+fn rebase(error: JshError, first_line: usize) -> JshError {
+    match error {
+        JshError::Syntax { message, span } => JshError::Syntax {
+            message,
+            span: span.map(|span| rebase_span(span, first_line)),
+        },
+        other => other,
+    }
+}
+
+/// A line as recorded in history. The first line keeps its leading
+/// whitespace so `HISTCONTROL=ignorespace` can see it.
+fn history_line(line: &str, first: bool) -> String {
+    match first {
+        true => line.trim_end().to_string(),
+        false => line.trim().to_string(),
+    }
+}
+
+/// Join the lines of a multi-line command into one history entry.
+///
+/// Lines are joined with `; ` the way bash's `cmdhist` does, except after
+/// words that must be followed by more command text, where `;` would be a
+/// syntax error (`if; true` or `echo a &&; echo b`).
+pub(crate) fn history_entry(lines: &[String]) -> String {
+    let mut entry = String::new();
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        if !entry.is_empty() {
+            let last_word = entry.split_whitespace().last().unwrap_or("");
+            let joiner = match last_word {
+                "if" | "then" | "elif" | "else" | "{" | "|" | "||" | "&&" | "&" | ";" => " ",
+                _ => "; ",
+            };
+            entry.push_str(joiner);
+        }
+        entry.push_str(line);
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(script: &str) -> Vec<Input> {
+        let mut source = ScriptLines::new(script);
+        let mut reader = CommandReader::new();
+        let mut inputs = Vec::new();
+        loop {
+            match reader.read_command(&mut source, &AliasTable::new()).unwrap() {
+                Input::Eof => return inputs,
+                input => inputs.push(input),
+            }
+        }
+    }
+
+    #[test]
+    fn one_command_per_line() {
+        let inputs = read_all("echo a\n\necho b; echo c\n");
+        assert_eq!(inputs.len(), 2);
+        assert!(matches!(&inputs[1], Input::Command { list, .. } if list.len() == 2));
+    }
+
+    #[test]
+    fn multi_line_command_is_one_input() {
+        let inputs = read_all("if true\nthen\n  echo yes\nfi\necho after\n");
+        assert_eq!(inputs.len(), 2);
+        match &inputs[0] {
+            Input::Command { history, .. } => assert_eq!(history, "if true; then echo yes; fi"),
+            other => panic!("expected command, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn backslash_newline_joins_lines() {
+        let inputs = read_all("echo one \\\ntwo\necho 'three \\'\necho four \\");
+        assert_eq!(inputs.len(), 3);
+        match &inputs[0] {
+            Input::Command { list, history, .. } => {
+                assert_eq!(history, "echo one two");
+                assert_eq!(list[0].to_string(), "echo one two");
+            }
+            other => panic!("expected command, got {other:?}"),
+        }
+        // Inside single quotes the backslash is literal.
+        assert!(matches!(&inputs[1], Input::Command { history, .. } if history == "echo 'three \\'"));
+        // A backslash on the last line continues onto nothing.
+        assert!(matches!(&inputs[2], Input::Command { history, .. } if history == "echo four"));
+    }
+
+    #[test]
+    fn heredoc_body_lines_are_consumed() {
+        let inputs = read_all("cat <<EOF\nnot a command\nEOF\necho after\n");
+        assert_eq!(inputs.len(), 2);
+        match &inputs[0] {
+            Input::Command { list, .. } => {
+                assert_eq!(list[0].to_string(), "cat <<< \"not a command\"");
+            }
+            other => panic!("expected command, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn syntax_errors_report_their_line() {
+        let inputs = read_all("echo ok\n\nfi\necho after\n");
+        assert_eq!(inputs.len(), 3);
+        match &inputs[1] {
+            Input::SyntaxError { line, error, .. } => {
+                assert_eq!(*line, 3);
+                assert!(error.to_string().contains("`fi'"), "{error}");
+            }
+            other => panic!("expected syntax error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn eof_inside_command_is_syntax_error() {
+        let inputs = read_all("if true; then\necho x\n");
+        assert!(matches!(
+            &inputs[..],
+            [Input::SyntaxError { error, .. }] if error.to_string().contains("unexpected end of file")
+        ));
+
+        let inputs = read_all("echo ok\necho \"open\nstill open\n");
+        match &inputs[1] {
+            Input::SyntaxError { error, line, .. } => {
+                assert!(error.to_string().contains("unterminated double quote"), "{error}");
+                assert_eq!(*line, 2);
+                assert_eq!(error.caret().as_deref(), Some("echo \"open\n     ^ column 6"));
+            }
+            other => panic!("expected syntax error, got {other:?}"),
+        }
+    }
+}
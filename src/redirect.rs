@@ -1,19 +1,28 @@
+use crate::error::JshError;
 use crate::expander;
-use crate::parser::{Word, WordSegment};
+use crate::parser::{self, Word, WordSegment};
+use crate::shell::Shell;
 
 /// What a file descriptor should be connected to.
 #[derive(Debug, Clone)]
 pub enum RedirectTarget {
     /// Write to file (truncate)
     File(String),
+    /// Write to a file that must not already exist, for `>` under
+    /// `set -o noclobber`
+    FileNoClobber(String),
     /// Write to file (append)
     FileAppend(String),
     /// Read from file
     FileRead(String),
     /// Duplicate another fd (e.g., 2>&1)
     Fd(i32),
+    /// Close the fd (e.g., 3>&-)
+    Close,
     /// Feed a string as stdin
     HereString(String),
+    /// Feed a heredoc body (already expanded, newlines included) as stdin
+    HereDoc(String),
 }
 
 /// A single I/O redirection instruction.
@@ -26,9 +35,11 @@ pub struct Redirection {
 /// Separate redirect operators from regular arguments.
 /// Returns (args, redirections) or an error message for syntax errors.
 ///
-/// Handles: >, >>, <, <<<, 2>, 2>>, >&N, N>&M
+/// Handles: >, >>, >|, <, <<<, 2>, 2>>, >&N, N>&M
 /// Also merges a standalone digit before > into a fd-prefixed redirect (e.g., "2" ">" → 2>).
-pub fn extract_redirections(tokens: &[String]) -> Result<(Vec<String>, Vec<Redirection>), String> {
+pub fn extract_redirections(
+    tokens: &[String],
+) -> Result<(Vec<String>, Vec<Redirection>), JshError> {
     let mut args = Vec::new();
     let mut redirections = Vec::new();
     let mut i = 0;
@@ -99,6 +110,13 @@ pub fn extract_redirections(tokens: &[String]) -> Result<(Vec<String>, Vec<Redir
                 fd: 1,
                 target: RedirectTarget::File(path),
             });
+        } else if token == ">|" {
+            i += 1;
+            let path = expect_filename(i, tokens, ">|")?;
+            redirections.push(Redirection {
+                fd: 1,
+                target: RedirectTarget::File(path),
+            });
         } else if token == ">>" {
             i += 1;
             let path = expect_filename(i, tokens, ">>")?;
@@ -117,6 +135,10 @@ pub fn extract_redirections(tokens: &[String]) -> Result<(Vec<String>, Vec<Redir
                 fd: 1,
                 target: RedirectTarget::Fd(2),
             });
+        } else if token == "&>" || token == "&>>" {
+            i += 1;
+            let path = expect_filename(i, tokens, token)?;
+            redirections.extend(both_streams(path, token == "&>>", false));
         } else if token == "<" {
             i += 1;
             let path = expect_filename(i, tokens, "<")?;
@@ -143,10 +165,11 @@ pub fn extract_redirections(tokens: &[String]) -> Result<(Vec<String>, Vec<Redir
 
 /// Separate redirect operators from parsed words.
 /// Quote-aware: operators hidden behind escapes or quotes are not treated as redirections.
-pub fn extract_redirections_from_words(
-    words: &[Word],
-    last_exit_code: i32,
-) -> Result<(Vec<Word>, Vec<Redirection>), String> {
+/// The remaining words are borrowed from `words`, not copied.
+pub fn extract_redirections_from_words<'a>(
+    words: &'a [Word],
+    shell: &mut Shell,
+) -> Result<(Vec<&'a Word>, Vec<Redirection>), JshError> {
     let mut args = Vec::new();
     let mut redirections = Vec::new();
     let mut i = 0;
@@ -154,47 +177,77 @@ pub fn extract_redirections_from_words(
     while i < words.len() {
         let redir = parse_redirect_word(&words[i]);
         if let Some(op) = redir {
-            i = apply_parsed_redirect(&mut redirections, op, words, i, last_exit_code, false)?;
+            i = apply_parsed_redirect(&mut redirections, op, words, i, shell, false)?;
             continue;
         }
 
         // Support spaced fd-prefixed redirects like: `2 > file`, `2 >> file`, `2 >&1`.
-        if let Some(fd) = parse_standalone_fd_prefix(&words[i]) {
-            if let Some(op) = words
+        // Input operators take no spaced prefix, so `head -n 1 < file` keeps its `1`.
+        if let Some(fd) = parse_standalone_fd_prefix(&words[i])
+            && let Some(op) = words
                 .get(i + 1)
-                .and_then(|word| {
-                    if word.len() == 1 {
-                        parse_unprefixed_redirect_word(&word[0])
-                    } else {
-                        None
+                .and_then(|word| match word.as_slice() {
+                    [segment @ WordSegment::Unquoted(op)] if op.starts_with('>') => {
+                        parse_unprefixed_redirect_word(segment)
                     }
+                    _ => None,
                 })
-            {
-                i = apply_spaced_prefixed_redirect(
-                    &mut redirections,
-                    fd,
-                    op,
-                    words,
-                    i,
-                    last_exit_code,
-                )?;
-                continue;
-            }
+        {
+            i = apply_spaced_prefixed_redirect(
+                &mut redirections,
+                fd,
+                op,
+                words,
+                i,
+                shell,
+            )?;
+            continue;
         }
 
-        args.push(words[i].clone());
+        args.push(&words[i]);
         i += 1;
     }
 
     Ok((args, redirections))
 }
 
+/// If `word` is a redirection operator, the number of words it consumes
+/// after itself: 0 for forms that carry their target (`2>&1`, `2>file`),
+/// 1 for forms followed by a target word (`>`, `<`, `<<`).
+///
+/// Lets the script parser pick out the redirections trailing a compound
+/// command without interpreting them.
+pub fn redirect_operand_count(word: &Word) -> Option<usize> {
+    match parse_redirect_word(word)? {
+        ParsedRedirect::Duplicate { .. }
+        | ParsedRedirect::Close { .. }
+        | ParsedRedirect::FileWithAttachedPath { .. } => Some(0),
+        ParsedRedirect::File { .. }
+        | ParsedRedirect::Clobber { .. }
+        | ParsedRedirect::Both { .. }
+        | ParsedRedirect::DuplicateWord { .. }
+        | ParsedRedirect::FileRead { .. }
+        | ParsedRedirect::HereString { .. }
+        | ParsedRedirect::HereDoc => Some(1),
+    }
+}
+
 #[derive(Debug)]
 enum ParsedRedirect {
     File { fd: i32, append: bool },
-    FileRead,
-    HereString,
+    /// `>|`: truncate even under `set -o noclobber`.
+    Clobber { fd: i32 },
+    /// `&>` / `&>>`: stdout and stderr to the same file.
+    Both { append: bool },
+    FileRead { fd: i32 },
+    HereString { fd: i32 },
+    HereDoc,
     Duplicate { fd: i32, target: i32 },
+    /// `>&` / `<&` with the fd to copy (or `-`) in the next word, as in
+    /// `>&$fd`.
+    DuplicateWord { fd: i32 },
+    /// `N>&-` or `<&-`.
+    Close { fd: i32 },
     FileWithAttachedPath {
         fd: i32,
         append: bool,
@@ -208,43 +261,69 @@ fn parse_redirect_word(word: &Word) -> Option<ParsedRedirect> {
     }
 
     let token = match &word[0] {
-        WordSegment::Unquoted(s) => s.as_str(),
+        WordSegment::Unquoted(s) => &**s,
         _ => return None,
     };
 
+    // `&>` takes no fd prefix, so it is not accepted after a spaced `2 `.
+    match token {
+        "&>" => return Some(ParsedRedirect::Both { append: false }),
+        "&>>" => return Some(ParsedRedirect::Both { append: true }),
+        _ => {}
+    }
+
     parse_unprefixed_redirect_word(&word[0])
         .or_else(|| parse_prefixed_redirect(token))
 }
 
 fn parse_unprefixed_redirect_word(segment: &WordSegment) -> Option<ParsedRedirect> {
     let token = match segment {
-        WordSegment::Unquoted(s) => s.as_str(),
+        WordSegment::Unquoted(s) => &**s,
         _ => return None,
     };
 
     match token {
         ">" => Some(ParsedRedirect::File { fd: 1, append: false }),
         ">>" => Some(ParsedRedirect::File { fd: 1, append: true }),
-        "<" => Some(ParsedRedirect::FileRead),
-        "<<<" => Some(ParsedRedirect::HereString),
-        ">&1" => Some(ParsedRedirect::Duplicate { fd: 1, target: 1 }),
-        ">&2" => Some(ParsedRedirect::Duplicate { fd: 1, target: 2 }),
+        ">|" => Some(ParsedRedirect::Clobber { fd: 1 }),
+        "<" => Some(ParsedRedirect::FileRead { fd: 0 }),
+        "<<<" => Some(ParsedRedirect::HereString { fd: 0 }),
+        "<<" | "<<-" => Some(ParsedRedirect::HereDoc),
         _ => None
     }
+    .or_else(|| parse_duplicate(1, token.strip_prefix(">&")?))
+    .or_else(|| parse_duplicate(0, token.strip_prefix("<&")?))
     .or_else(|| parse_prefixed_redirect(token))
 }
 
+/// `fd` duplicated from what follows `>&` or `<&`: an fd number, `-` to
+/// close it, or nothing when the source is the next word.
+fn parse_duplicate(fd: i32, source: &str) -> Option<ParsedRedirect> {
+    match source {
+        "" => Some(ParsedRedirect::DuplicateWord { fd }),
+        "-" => Some(ParsedRedirect::Close { fd }),
+        _ if source.bytes().all(|b| b.is_ascii_digit()) => {
+            let target = source.parse::<i32>().ok()?;
+            Some(ParsedRedirect::Duplicate { fd, target })
+        }
+        _ => None,
+    }
+}
+
 fn parse_prefixed_redirect(token: &str) -> Option<ParsedRedirect> {
-    let (fd_char, rest) = token.chars().next().map(|c| (c, &token[1..]))?;
-    let fd = match fd_char {
-        '1' => 1,
-        '2' => 2,
-        _ => return None,
-    };
+    let mut chars = token.chars();
+    let fd_char = chars.next()?;
+    let rest = chars.as_str();
+    let fd = fd_char.to_digit(10)? as i32;
 
-    if let Some(path) = rest.strip_prefix(">&") {
-        let target = path.parse::<i32>().ok()?;
-        return Some(ParsedRedirect::Duplicate { fd, target });
+    if let Some(source) = rest.strip_prefix(">&").or_else(|| rest.strip_prefix("<&")) {
+        return parse_duplicate(fd, source);
+    }
+    if rest == "<" {
+        return Some(ParsedRedirect::FileRead { fd });
+    }
+    if rest == "<<<" {
+        return Some(ParsedRedirect::HereString { fd });
     }
 
     if rest == ">" {
@@ -253,6 +332,9 @@ fn parse_prefixed_redirect(token: &str) -> Option<ParsedRedirect> {
     if rest == ">>" {
         return Some(ParsedRedirect::File { fd, append: true });
     }
+    if rest == ">|" {
+        return Some(ParsedRedirect::Clobber { fd });
+    }
 
     if let Some(path) = rest.strip_prefix(">") {
         if let Some(path) = path.strip_prefix(">") {
@@ -279,7 +361,7 @@ fn parse_standalone_fd_prefix(word: &Word) -> Option<i32> {
     }
 
     let token = match &word[0] {
-        WordSegment::Unquoted(s) => s.as_str(),
+        WordSegment::Unquoted(s) => &**s,
         _ => return None,
     };
 
@@ -297,8 +379,13 @@ fn normalize_redirection_op(fd: i32, op: ParsedRedirect) -> ParsedRedirect {
             append,
         },
         ParsedRedirect::Duplicate { fd: _, target } => ParsedRedirect::Duplicate { fd, target },
-        ParsedRedirect::FileRead => ParsedRedirect::FileRead,
-        ParsedRedirect::HereString => ParsedRedirect::HereString,
+        ParsedRedirect::Clobber { .. } => ParsedRedirect::Clobber { fd },
+        ParsedRedirect::Both { append } => ParsedRedirect::Both { append },
+        ParsedRedirect::FileRead { .. } => ParsedRedirect::FileRead { fd },
+        ParsedRedirect::DuplicateWord { .. } => ParsedRedirect::DuplicateWord { fd },
+        ParsedRedirect::Close { .. } => ParsedRedirect::Close { fd },
+        ParsedRedirect::HereString { .. } => ParsedRedirect::HereString { fd },
+        ParsedRedirect::HereDoc => ParsedRedirect::HereDoc,
         ParsedRedirect::FileWithAttachedPath { append, path, .. } => ParsedRedirect::FileWithAttachedPath {
             fd,
             append,
@@ -312,14 +399,23 @@ fn apply_parsed_redirect(
     op: ParsedRedirect,
     words: &[Word],
     idx: usize,
-    last_exit_code: i32,
+    shell: &mut Shell,
     spaced: bool,
-) -> Result<usize, String> {
+) -> Result<usize, JshError> {
     let increment = if spaced { 2 } else { 1 };
     let next = if spaced { idx + 2 } else { idx + 1 };
+    let noclobber = shell.options.noclobber;
     match op {
         ParsedRedirect::File { fd, append: false } => {
-            let path = extract_target(words, idx + increment, "redirection target", last_exit_code)?;
+            let path = extract_target(words, idx + increment, "redirection target", shell)?;
+            redirections.push(Redirection {
+                fd,
+                target: truncate(path, noclobber),
+            });
+            Ok(idx + increment + 1)
+        }
+        ParsedRedirect::Clobber { fd } => {
+            let path = extract_target(words, idx + increment, "redirection target", shell)?;
             redirections.push(Redirection {
                 fd,
                 target: RedirectTarget::File(path),
@@ -327,38 +423,71 @@ fn apply_parsed_redirect(
             Ok(idx + increment + 1)
         }
         ParsedRedirect::File { fd, append: true } => {
-            let path = extract_target(words, idx + increment, "redirection target", last_exit_code)?;
+            let path = extract_target(words, idx + increment, "redirection target", shell)?;
             redirections.push(Redirection {
                 fd,
                 target: RedirectTarget::FileAppend(path),
             });
             Ok(idx + increment + 1)
         }
+        ParsedRedirect::Both { append } => {
+            let path = extract_target(words, idx + increment, "redirection target", shell)?;
+            redirections.extend(both_streams(path, append, noclobber));
+            Ok(idx + increment + 1)
+        }
         ParsedRedirect::Duplicate { fd, target } => {
             redirections.push(Redirection { fd, target: RedirectTarget::Fd(target) });
             Ok(next)
         }
-        ParsedRedirect::FileRead => {
-            let path = extract_target(words, idx + increment, "redirection target", last_exit_code)?;
+        ParsedRedirect::DuplicateWord { fd } => {
+            let source = extract_target(words, idx + increment, "redirection target", shell)?;
+            let target = match source.as_str() {
+                "-" => RedirectTarget::Close,
+                _ => match source.parse::<i32>() {
+                    Ok(source) if source >= 0 => RedirectTarget::Fd(source),
+                    _ => {
+                        return Err(JshError::Redirect(format!("jsh: {source}: bad file descriptor")));
+                    }
+                },
+            };
+            redirections.push(Redirection { fd, target });
+            Ok(idx + increment + 1)
+        }
+        ParsedRedirect::Close { fd } => {
+            redirections.push(Redirection { fd, target: RedirectTarget::Close });
+            Ok(next)
+        }
+        ParsedRedirect::FileRead { fd } => {
+            let path = extract_target(words, idx + increment, "redirection target", shell)?;
             redirections.push(Redirection {
-                fd: 0,
+                fd,
                 target: RedirectTarget::FileRead(path),
             });
             Ok(idx + increment + 1)
         }
-        ParsedRedirect::HereString => {
-            let text = extract_target(words, idx + increment, "here-string target", last_exit_code)?;
+        ParsedRedirect::HereString { fd } => {
+            let text = extract_text(words, idx + increment, "here-string target", shell)?;
             redirections.push(Redirection {
-                fd: 0,
+                fd,
                 target: RedirectTarget::HereString(text),
             });
             Ok(idx + increment + 1)
         }
+        ParsedRedirect::HereDoc => {
+            // The caller has already replaced the delimiter word with the body
+            // (see `parser::attach_heredoc_body`), so expanding it yields the text.
+            let body = extract_text(words, idx + increment, "here-document delimiter", shell)?;
+            redirections.push(Redirection {
+                fd: 0,
+                target: RedirectTarget::HereDoc(body),
+            });
+            Ok(idx + increment + 1)
+        }
         ParsedRedirect::FileWithAttachedPath { fd, append, path } => {
             let target = if append {
                 RedirectTarget::FileAppend(path)
             } else {
-                RedirectTarget::File(path)
+                truncate(path, noclobber)
             };
             redirections.push(Redirection { fd, target });
             Ok(next)
@@ -372,38 +501,90 @@ fn apply_spaced_prefixed_redirect(
     op: ParsedRedirect,
     words: &[Word],
     idx: usize,
-    last_exit_code: i32,
-) -> Result<usize, String> {
+    shell: &mut Shell,
+) -> Result<usize, JshError> {
     let adjusted = normalize_redirection_op(fd, op);
-    apply_parsed_redirect(redirections, adjusted, words, idx, last_exit_code, true)
+    apply_parsed_redirect(redirections, adjusted, words, idx, shell, true)
 }
 
 fn extract_target(
     words: &[Word],
     idx: usize,
     context: &str,
-    last_exit_code: i32,
-) -> Result<String, String> {
-    if idx >= words.len() {
-        return Err(format!(
+    shell: &mut Shell,
+) -> Result<String, JshError> {
+    let Some(word) = words.get(idx) else {
+        return Err(JshError::syntax(format!(
             "jsh: syntax error: expected filename after {context}"
-        ));
-    }
+        )));
+    };
 
-    let expanded = expander::expand_words(&[words[idx].clone()], last_exit_code);
+    // A non-interactive shell in POSIX mode does not glob or split the
+    // target, as in bash; otherwise it must expand to exactly one word.
+    if shell.options.posix && !shell.interactive {
+        return expander::expand_assignment_value(word, shell);
+    }
+    let expanded =
+        expander::expand_words([word], shell)?;
 
     match expanded.as_slice() {
-        [] => Err(format!("jsh: syntax error: expected filename after {context}")),
         [one] => Ok(one.clone()),
-        _ => Err("jsh: ambiguous redirect target".to_string()),
+        _ => Err(JshError::Redirect(format!(
+            "jsh: {}: ambiguous redirect",
+            parser::word_to_source(word)
+        ))),
     }
 }
 
-fn expect_filename(i: usize, tokens: &[String], operator: &str) -> Result<String, String> {
+/// Expand the text of a here-string or here-document. It is never split or
+/// globbed.
+fn extract_text(
+    words: &[Word],
+    idx: usize,
+    context: &str,
+    shell: &mut Shell,
+) -> Result<String, JshError> {
+    let Some(word) = words.get(idx) else {
+        return Err(JshError::syntax(format!(
+            "jsh: syntax error: expected filename after {context}"
+        )));
+    };
+    expander::expand_assignment_value(word, shell)
+}
+
+/// The target for `> path`, which may not overwrite a file under
+/// `set -o noclobber`.
+fn truncate(path: String, noclobber: bool) -> RedirectTarget {
+    if noclobber {
+        RedirectTarget::FileNoClobber(path)
+    } else {
+        RedirectTarget::File(path)
+    }
+}
+
+/// `&> path` is `> path 2>&1`, and `&>> path` is `>> path 2>&1`.
+fn both_streams(path: String, append: bool, noclobber: bool) -> [Redirection; 2] {
+    let target = if append {
+        RedirectTarget::FileAppend(path)
+    } else {
+        truncate(path, noclobber)
+    };
+    [
+        Redirection { fd: 1, target },
+        Redirection {
+            fd: 2,
+            target: RedirectTarget::Fd(1),
+        },
+    ]
+}
+
+fn expect_filename(i: usize, tokens: &[String], operator: &str) -> Result<String, JshError> {
     if i < tokens.len() {
         Ok(tokens[i].clone())
     } else {
-        Err(format!("jsh: syntax error: expected filename after '{operator}'"))
+        Err(JshError::syntax(format!(
+            "jsh: syntax error: expected filename after '{operator}'"
+        )))
     }
 }
 
@@ -416,6 +597,24 @@ pub fn is_null_device(path: &str) -> bool {
     }
 }
 
+/// The descriptor a special path names: 0 for `/dev/stdin`, 1 for
+/// `/dev/stdout`, 2 for `/dev/stderr`, and N for `/dev/fd/N`.
+///
+/// Redirections to these paths use the shell's current descriptors instead
+/// of opening a file, so they work on Windows and inside a redirected group,
+/// as bash emulates them.
+pub fn device_fd(path: &str) -> Option<i32> {
+    match path {
+        "/dev/stdin" => Some(0),
+        "/dev/stdout" => Some(1),
+        "/dev/stderr" => Some(2),
+        _ => path
+            .strip_prefix("/dev/fd/")
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|n| n.parse().ok()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -432,6 +631,13 @@ mod tests {
         assert!(matches!(&redirs[0].target, RedirectTarget::File(p) if p == "out.txt"));
     }
 
+    #[test]
+    fn non_ascii_words_are_not_redirects() {
+        let (args, redirs) = extract_redirections(["echo", "日本", "é>"].map(String::from).as_ref()).unwrap();
+        assert_eq!(args, vec!["echo", "日本", "é>"]);
+        assert!(redirs.is_empty());
+    }
+
     #[test]
     fn append_redirect() {
         let (args, redirs) = extract_redirections(
@@ -479,6 +685,54 @@ mod tests {
         assert!(matches!(&redirs[0].target, RedirectTarget::Fd(1)));
     }
 
+    #[test]
+    fn both_streams_to_a_file() {
+        let parsed = crate::parser::tokenize("make &>> build.log").unwrap();
+        let (args, redirs) =
+            extract_redirections_from_words(&parsed, &mut Shell::new()).expect("parse");
+        assert_eq!(args.len(), 1);
+        assert_eq!(redirs.len(), 2);
+        assert_eq!(redirs[0].fd, 1);
+        assert!(matches!(&redirs[0].target, RedirectTarget::FileAppend(p) if p == "build.log"));
+        assert_eq!(redirs[1].fd, 2);
+        assert!(matches!(&redirs[1].target, RedirectTarget::Fd(1)));
+    }
+
+    #[test]
+    fn duplicates_from_numbers_words_and_closes() {
+        let mut shell = Shell::new();
+        shell.variables.set("fd", "7");
+        let parsed = crate::parser::tokenize("cmd >&12 <&3 >&$fd 2>&- <&-").unwrap();
+        let (args, redirs) = extract_redirections_from_words(&parsed, &mut shell).expect("parse");
+        assert_eq!(args.len(), 1);
+        assert_eq!(redirs.len(), 5);
+        assert!(matches!((redirs[0].fd, &redirs[0].target), (1, RedirectTarget::Fd(12))));
+        assert!(matches!((redirs[1].fd, &redirs[1].target), (0, RedirectTarget::Fd(3))));
+        assert!(matches!((redirs[2].fd, &redirs[2].target), (1, RedirectTarget::Fd(7))));
+        assert!(matches!((redirs[3].fd, &redirs[3].target), (2, RedirectTarget::Close)));
+        assert!(matches!((redirs[4].fd, &redirs[4].target), (0, RedirectTarget::Close)));
+
+        let parsed = crate::parser::tokenize("cmd >& nope").unwrap();
+        let err = extract_redirections_from_words(&parsed, &mut shell).unwrap_err();
+        assert!(
+            matches!(&err, JshError::Redirect(message) if message.contains("bad file descriptor")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn noclobber_protects_plain_truncation_only() {
+        let mut shell = Shell::new();
+        shell.options.noclobber = true;
+        let parsed = crate::parser::tokenize("cmd > a 2>| b &> c >> d").unwrap();
+        let (_, redirs) = extract_redirections_from_words(&parsed, &mut shell).expect("parse");
+        assert!(matches!(&redirs[0].target, RedirectTarget::FileNoClobber(p) if p == "a"));
+        assert_eq!(redirs[1].fd, 2);
+        assert!(matches!(&redirs[1].target, RedirectTarget::File(p) if p == "b"));
+        assert!(matches!(&redirs[2].target, RedirectTarget::FileNoClobber(p) if p == "c"));
+        assert!(matches!(&redirs[4].target, RedirectTarget::FileAppend(p) if p == "d"));
+    }
+
     #[test]
     fn here_string() {
         let (args, redirs) = extract_redirections(
@@ -511,10 +765,10 @@ mod tests {
     fn spaced_stderr_redirect() {
         let parsed = crate::parser::tokenize("printf hi 2 > err.txt").unwrap();
         let (args, redirs) =
-            extract_redirections_from_words(&parsed, 0).expect("parse");
+            extract_redirections_from_words(&parsed, &mut Shell::new()).expect("parse");
         assert_eq!(args.len(), 2);
-        assert_eq!(args[0], vec![WordSegment::Unquoted("printf".into())]);
-        assert_eq!(args[1], vec![WordSegment::Unquoted("hi".into())]);
+        assert_eq!(*args[0], vec![WordSegment::Unquoted("printf".into())]);
+        assert_eq!(*args[1], vec![WordSegment::Unquoted("hi".into())]);
         assert_eq!(redirs[0].fd, 2);
         assert!(matches!(&redirs[0].target, RedirectTarget::File(p) if p == "err.txt"));
     }
@@ -523,10 +777,10 @@ mod tests {
     fn spaced_stderr_append_redirect() {
         let parsed = crate::parser::tokenize("printf hi 2 >> err.txt").unwrap();
         let (args, redirs) =
-            extract_redirections_from_words(&parsed, 0).expect("parse");
+            extract_redirections_from_words(&parsed, &mut Shell::new()).expect("parse");
         assert_eq!(args.len(), 2);
-        assert_eq!(args[0], vec![WordSegment::Unquoted("printf".into())]);
-        assert_eq!(args[1], vec![WordSegment::Unquoted("hi".into())]);
+        assert_eq!(*args[0], vec![WordSegment::Unquoted("printf".into())]);
+        assert_eq!(*args[1], vec![WordSegment::Unquoted("hi".into())]);
         assert_eq!(redirs[0].fd, 2);
         assert!(matches!(
             &redirs[0].target,
@@ -538,13 +792,49 @@ mod tests {
     fn spaced_fd_dup_redirect() {
         let parsed = crate::parser::tokenize("cmd 2 >&1").unwrap();
         let (args, redirs) =
-            extract_redirections_from_words(&parsed, 0).expect("parse");
+            extract_redirections_from_words(&parsed, &mut Shell::new()).expect("parse");
         assert_eq!(args.len(), 1);
-        assert_eq!(args[0], vec![WordSegment::Unquoted("cmd".into())]);
+        assert_eq!(*args[0], vec![WordSegment::Unquoted("cmd".into())]);
         assert_eq!(redirs[0].fd, 2);
         assert!(matches!(&redirs[0].target, RedirectTarget::Fd(1)));
     }
 
+    #[test]
+    fn heredoc_redirect_uses_attached_body() {
+        let mut parsed = crate::parser::tokenize("cat << EOF").unwrap();
+        let request = crate::parser::heredoc_requests(&parsed).unwrap().remove(0);
+        crate::parser::attach_heredoc_body(&mut parsed, &request, "line one\nline two\n").unwrap();
+        let (args, redirs) = extract_redirections_from_words(&parsed, &mut Shell::new()).expect("parse");
+        assert_eq!(args.len(), 1);
+        assert_eq!(redirs[0].fd, 0);
+        assert!(matches!(
+            &redirs[0].target,
+            RedirectTarget::HereDoc(body) if body == "line one\nline two\n"
+        ));
+    }
+
+    #[test]
+    fn redirect_operand_counts() {
+        let words = crate::parser::tokenize("> >> < <<< << 2>&1 >&2 &> word").unwrap();
+        let counts: Vec<_> = words.iter().map(redirect_operand_count).collect();
+        assert_eq!(
+            counts,
+            vec![Some(1), Some(1), Some(1), Some(1), Some(1), Some(0), Some(0), Some(1), None]
+        );
+    }
+
+    #[test]
+    fn standard_stream_paths() {
+        assert_eq!(device_fd("/dev/stdin"), Some(0));
+        assert_eq!(device_fd("/dev/fd/1"), Some(1));
+        assert_eq!(device_fd("/dev/stderr"), Some(2));
+        assert_eq!(device_fd("/dev/fd/3"), Some(3));
+        assert_eq!(device_fd("/dev/fd/12"), Some(12));
+        assert_eq!(device_fd("/dev/fd/"), None);
+        assert_eq!(device_fd("/dev/fd/+1"), None);
+        assert_eq!(device_fd("dev/stdout"), None);
+    }
+
     #[test]
     fn null_device_detection() {
         assert!(is_null_device("/dev/null"));
@@ -558,8 +848,8 @@ mod tests {
     fn escaped_redirect_is_literal() {
         let parsed = crate::parser::tokenize(r"echo \> out.txt").unwrap();
         let (args, redirs) =
-            extract_redirections_from_words(&parsed, 0).expect("parse");
-        let args = crate::expander::expand_words(&args, 0);
+            extract_redirections_from_words(&parsed, &mut Shell::new()).expect("parse");
+        let args = crate::expander::expand_words(args, &mut Shell::new()).unwrap();
         assert!(redirs.is_empty());
         assert_eq!(args, vec!["echo".to_string(), ">".to_string(), "out.txt".to_string()]);
     }
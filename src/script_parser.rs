@@ -1,82 +1,397 @@
-use crate::ast::{ChainEntry, Connector};
-use crate::parser::{Word, WordSegment};
+use std::rc::Rc;
+
+use crate::aliases::AliasTable;
+use crate::ast::{
+    AndOrList, ChainEntry, Command, CommandList, CompoundCommand, Connector, IfBranch, IfClause,
+    Pipeline, Timing,
+};
+use crate::error::{JshError, Span};
+use crate::parser::{self, Word, WordSegment};
+use crate::redirect;
+
+/// Why a token stream could not be turned into a [`CommandList`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The input ended inside an unfinished construct — an `if` without
+    /// `fi`, or a trailing `&&` / `|`. More lines may complete it.
+    Incomplete,
+    /// A genuine syntax error.
+    Syntax(JshError),
+}
+
+/// Reserved words that open or continue a compound command.
+const RESERVED_WORDS: &[&str] = &["if", "then", "elif", "else", "fi", "{", "}"];
 
 /// If `word` is a chain operator token (`&&`, `||`, or `;`), return its
 /// [`Connector`] variant. Returns `None` for all other tokens.
 fn chain_op(word: &Word) -> Option<Connector> {
-    if word.len() != 1 {
-        return None;
-    }
-    match &word[0] {
-        WordSegment::Unquoted(s) => match s.as_str() {
-            "&&" => Some(Connector::And),
-            "||" => Some(Connector::Or),
-            ";" => Some(Connector::Sequence),
-            _ => None,
-        },
+    match operator(word)? {
+        "&&" => Some(Connector::And),
+        "||" => Some(Connector::Or),
+        ";" => Some(Connector::Sequence),
+        _ => None,
+    }
+}
+
+/// The text of a bare operator token (`|`, `&&`, `;`, newline, ...).
+fn operator(word: &Word) -> Option<&str> {
+    match word.as_slice() {
+        [WordSegment::Unquoted(s)]
+            if matches!(&**s, "|" | "||" | "|&" | "&" | "&&" | ";" | "\n") =>
+        {
+            Some(&**s)
+        }
         _ => None,
     }
 }
 
-fn connector_display(c: &Connector) -> &'static str {
-    match c {
-        Connector::Sequence => ";",
-        Connector::And => "&&",
-        Connector::Or => "||",
+/// Whether `name` is a reserved word of the shell's grammar, for `type`.
+/// `time` is one too, although it only counts before a pipeline.
+pub fn is_reserved_word(name: &str) -> bool {
+    RESERVED_WORDS.contains(&name) || name == "time"
+}
+
+/// The reserved word `word` spells, if any. Quoting a reserved word
+/// (`"if"`, `\if`) makes it an ordinary word, as in POSIX shells.
+fn reserved_word(word: &Word) -> Option<&str> {
+    match word.as_slice() {
+        [WordSegment::Unquoted(s)] if RESERVED_WORDS.contains(&&**s) => Some(&**s),
+        _ => None,
     }
 }
 
-/// Split a flat `Vec<Word>` by chain operators (`&&`, `||`, `;`) into a list
-/// of [`ChainEntry`] values, each annotated with the connector that gates it.
+
+/// Parse a token stream into the statement tree the shell executes.
 ///
-/// The first entry always gets [`Connector::Sequence`] (run unconditionally).
-/// Subsequent entries get the connector that appeared before them in the input.
+/// `words` may span several lines: newline tokens separate commands just like
+/// `;`, except that blank lines are allowed and `then`/`else`/`fi` may start
+/// a line of their own. Returns [`ParseError::Incomplete`] when the stream
+/// stops partway through a statement so an interactive caller can read a
+/// continuation line and try again.
+pub fn parse_script(words: Vec<Word>) -> Result<CommandList, ParseError> {
+    parse_script_with_aliases(words, &AliasTable::new())
+}
+
+/// Like [`parse_script`], expanding `aliases` in command position.
 ///
-/// Returns an error for syntax problems such as leading, trailing, or
-/// consecutive chain operators with no command between them.
-pub fn parse_chain(words: Vec<Word>) -> Result<Vec<ChainEntry>, String> {
-    let mut entries: Vec<ChainEntry> = Vec::new();
-    let mut current: Vec<Word> = Vec::new();
-    // Connector that will apply to the *next* entry we collect.
-    // The first entry always runs unconditionally.
-    let mut next_connector = Connector::Sequence;
-
-    for word in words {
-        if let Some(connector) = chain_op(&word) {
-            if current.is_empty() {
-                let op = connector_display(&connector);
-                return Err(format!(
-                    "jsh: syntax error near unexpected token `{op}'"
-                ));
+/// The first word of each simple command is replaced by the tokens of its
+/// alias value, repeatedly, except that an alias is never expanded inside its
+/// own expansion (`alias ls='ls -F'` works). A value ending in a blank also
+/// makes the following word eligible for alias expansion.
+pub fn parse_script_with_aliases(
+    words: Vec<Word>,
+    aliases: &AliasTable,
+) -> Result<CommandList, ParseError> {
+    parse_script_spanned(words, Vec::new(), aliases)
+}
+
+/// Like [`parse_script_with_aliases`], with `spans[i]` giving where
+/// `words[i]` came from so a syntax error can point at the bad token.
+pub fn parse_script_spanned(
+    words: Vec<Word>,
+    spans: Vec<Span>,
+    aliases: &AliasTable,
+) -> Result<CommandList, ParseError> {
+    let mut parser = Parser {
+        words,
+        spans,
+        pos: 0,
+        aliases,
+    };
+    parser.parse_list(&[])
+}
+
+/// Recursive-descent parser over the tokenizer's words.
+struct Parser<'a> {
+    words: Vec<Word>,
+    /// Source positions of `words`, when known.
+    spans: Vec<Span>,
+    pos: usize,
+    aliases: &'a AliasTable,
+}
+
+impl Parser<'_> {
+    /// A syntax error for the token at the current position.
+    fn unexpected(&self) -> ParseError {
+        let token = match self.peek() {
+            Some(word) => operator(word).map_or_else(|| parser::word_to_source(word), str::to_string),
+            None => return ParseError::Incomplete,
+        };
+        let token = if token == "\n" { "newline" } else { &token };
+        ParseError::Syntax(JshError::Syntax {
+            message: format!("jsh: syntax error near unexpected token `{token}'"),
+            span: self.spans.get(self.pos).cloned(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Word> {
+        self.words.get(self.pos)
+    }
+
+    fn peek_operator(&self) -> Option<&str> {
+        self.peek().and_then(operator)
+    }
+
+    fn peek_reserved(&self) -> Option<&str> {
+        self.peek().and_then(reserved_word)
+    }
+
+    /// Move the current word out of the stream and step past it. The parser
+    /// never looks back, so this saves copying every word into the tree.
+    fn take(&mut self) -> Word {
+        let word = std::mem::take(&mut self.words[self.pos]);
+        self.pos += 1;
+        word
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek_operator() == Some("\n") {
+            self.pos += 1;
+        }
+    }
+
+    /// Parse and-or lists until end of input or one of the reserved words in
+    /// `terminators` appears in command position. The terminator is left for
+    /// the caller to consume.
+    ///
+    /// Inside a compound command (`terminators` non-empty) running out of
+    /// input is [`ParseError::Incomplete`], and the list may not be empty.
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<CommandList, ParseError> {
+        let mut list = CommandList::new();
+
+        loop {
+            self.skip_newlines();
+
+            match self.peek() {
+                None if terminators.is_empty() => return Ok(list),
+                None => return Err(ParseError::Incomplete),
+                Some(_) => {}
+            }
+            if let Some(keyword) = self.peek_reserved()
+                && terminators.contains(&keyword)
+            {
+                if list.is_empty() {
+                    return Err(self.unexpected());
+                }
+                return Ok(list);
+            }
+
+            let mut item = self.parse_and_or()?;
+
+            match self.peek() {
+                None => {}
+                Some(_) if matches!(self.peek_operator(), Some(";" | "\n")) => self.pos += 1,
+                Some(_) if self.peek_operator() == Some("&") => {
+                    self.pos += 1;
+                    item.background = true;
+                }
+                // `fi fi` closes two blocks without a separator, as in bash.
+                Some(_) if self.peek_reserved().is_some_and(|kw| terminators.contains(&kw)) => {}
+                // Only a compound command can be followed by a plain word,
+                // e.g. `if true; then :; fi echo`.
+                Some(_) => return Err(self.unexpected()),
+            }
+            list.push(item);
+        }
+    }
+
+    /// Parse pipelines joined by `&&` / `||`.
+    fn parse_and_or(&mut self) -> Result<AndOrList, ParseError> {
+        let mut entries = vec![ChainEntry {
+            pipeline: self.parse_pipeline()?,
+            connector: Connector::Sequence,
+        }];
+
+        while let Some(connector) = self.peek().and_then(chain_op) {
+            if connector == Connector::Sequence {
+                break;
             }
+            self.pos += 1;
+            // A trailing `&&` / `||` continues on the next line.
+            self.skip_newlines();
             entries.push(ChainEntry {
-                words: std::mem::take(&mut current),
-                connector: next_connector,
+                pipeline: self.parse_pipeline()?,
+                connector,
             });
-            next_connector = connector;
-        } else {
-            current.push(word);
         }
+
+        Ok(AndOrList {
+            entries,
+            background: false,
+        })
     }
 
-    if current.is_empty() {
-        if entries.is_empty() {
-            // Completely empty input — callers guard against this, but handle gracefully.
-            return Ok(vec![]);
+    /// Parse commands joined by `|` or `|&`, optionally preceded by `time`
+    /// or `time -p`.
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let time = self.parse_time();
+        let mut commands = vec![self.parse_command()?];
+
+        while let Some(op @ ("|" | "|&")) = self.peek_operator() {
+            if op == "|&" {
+                // `a |& b` is shorthand for `a 2>&1 | b`, applied after a's
+                // own redirections.
+                if let Some(Command::Simple(words) | Command::Compound(_, words)) = commands.last_mut() {
+                    words.push(vec![WordSegment::Unquoted("2>&1".into())]);
+                }
+            }
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
         }
-        // Trailing operator, e.g. `echo hi &&`
-        let op = connector_display(&next_connector);
-        return Err(format!(
-            "jsh: syntax error: expected command after `{op}'"
-        ));
+
+        Ok(Pipeline { commands, time })
+    }
+
+    /// Consume a leading `time` reserved word. Like bash, `time` with no
+    /// pipeline after it is left as an ordinary command name.
+    fn parse_time(&mut self) -> Option<Timing> {
+        let is_word = |word: Option<&Word>, text: &str| {
+            matches!(word.map(Vec::as_slice), Some([WordSegment::Unquoted(s)]) if &**s == text)
+        };
+        if !is_word(self.peek(), "time") {
+            return None;
+        }
+        let mut next = self.pos + 1;
+        let mut timing = Timing::Format;
+        if is_word(self.words.get(next), "-p") {
+            next += 1;
+            timing = Timing::Posix;
+        }
+        match self.words.get(next) {
+            Some(word) if operator(word).is_none() => {
+                self.pos = next;
+                Some(timing)
+            }
+            _ => None,
+        }
+    }
+
+    /// Expand aliases for the word at `index`.
+    ///
+    /// Returns the index of the word after the expansion when the last alias
+    /// value ended in a blank, meaning that word must be checked too.
+    fn expand_aliases_at(&mut self, index: usize) -> Result<Option<usize>, ParseError> {
+        let mut expanded: Vec<Rc<str>> = Vec::new();
+        let mut check_next = None;
+
+        while let Some([WordSegment::Unquoted(name)]) = self.words.get(index).map(Vec::as_slice)
+            && !expanded.contains(name)
+            && let Some(value) = self.aliases.get(name)
+        {
+            let tokens = parser::tokenize(value).map_err(ParseError::Syntax)?;
+            let count = tokens.len();
+            expanded.push(name.clone());
+            self.words.splice(index..=index, tokens);
+            // Tokens from an alias value point back at the alias name.
+            if let Some(span) = self.spans.get(index).cloned() {
+                self.spans.splice(index..=index, std::iter::repeat_n(span, count));
+            }
+
+            check_next = value.ends_with([' ', '\t']).then_some(index + count);
+            if count == 0 {
+                break;
+            }
+        }
+
+        Ok(check_next)
     }
 
-    entries.push(ChainEntry {
-        words: current,
-        connector: next_connector,
-    });
+    /// Parse one pipeline stage: a compound command or a simple command.
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        let mut check_alias = self.expand_aliases_at(self.pos)?;
 
-    Ok(entries)
+        match self.peek() {
+            None => return Err(ParseError::Incomplete),
+            Some(word) => {
+                if operator(word).is_some() {
+                    return Err(self.unexpected());
+                }
+            }
+        }
+
+        let compound = match self.peek_reserved() {
+            Some("if") => {
+                self.pos += 1;
+                Some(CompoundCommand::If(self.parse_if()?))
+            }
+            Some("{") => {
+                self.pos += 1;
+                let body = self.parse_list(&["}"])?;
+                self.pos += 1; // }
+                Some(CompoundCommand::BraceGroup(body))
+            }
+            Some(_) => return Err(self.unexpected()),
+            None => None,
+        };
+        if let Some(compound) = compound {
+            let redirects = self.parse_compound_redirects()?;
+            return Ok(Command::Compound(compound, redirects));
+        }
+
+        let mut words = Vec::new();
+        loop {
+            if check_alias == Some(self.pos) {
+                check_alias = self.expand_aliases_at(self.pos)?;
+            }
+            let Some(word) = self.peek() else { break };
+            if operator(word).is_some() {
+                break;
+            }
+            words.push(self.take());
+        }
+        Ok(Command::Simple(words))
+    }
+
+    /// Collect the redirection words that follow a compound command.
+    fn parse_compound_redirects(&mut self) -> Result<Vec<Word>, ParseError> {
+        let mut redirects = Vec::new();
+
+        while let Some(operands) = self.peek().and_then(redirect::redirect_operand_count) {
+            redirects.push(self.take());
+            for _ in 0..operands {
+                match self.peek() {
+                    None => return Err(ParseError::Incomplete),
+                    Some(word) if operator(word).is_some() => return Err(self.unexpected()),
+                    Some(_) => redirects.push(self.take()),
+                }
+            }
+        }
+
+        Ok(redirects)
+    }
+
+    /// Parse the rest of an `if` statement after the `if` keyword.
+    fn parse_if(&mut self) -> Result<IfClause, ParseError> {
+        let mut branches = Vec::new();
+        let mut else_body = None;
+
+        // `parse_list` only returns once it sits on one of its terminators,
+        // so each keyword below is consumed without re-checking it.
+        loop {
+            let condition = self.parse_list(&["then"])?;
+            self.pos += 1; // then
+            let body = self.parse_list(&["elif", "else", "fi"])?;
+            branches.push(IfBranch { condition, body });
+
+            let keyword = self.peek_reserved().map(str::to_string);
+            self.pos += 1;
+            match keyword.as_deref() {
+                Some("elif") => continue,
+                Some("else") => {
+                    else_body = Some(self.parse_list(&["fi"])?);
+                    self.pos += 1; // fi
+                }
+                _ => {} // fi
+            }
+            break;
+        }
+
+        Ok(IfClause {
+            branches,
+            else_body,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -84,21 +399,31 @@ mod tests {
     use super::*;
     use crate::parser::tokenize;
 
+    fn parse(input: &str) -> Result<CommandList, ParseError> {
+        parse_script(tokenize(input).expect("tokenize failed"))
+    }
+
     fn tokenize_chain(input: &str) -> Vec<ChainEntry> {
-        let words = tokenize(input).expect("tokenize failed");
-        parse_chain(words).expect("parse_chain failed")
+        let mut list = parse(input).expect("parse_script failed");
+        assert_eq!(list.len(), 1, "expected a single and-or list");
+        list.remove(0).entries
     }
 
     fn entry_strings(entry: &ChainEntry) -> Vec<String> {
         entry
-            .words
+            .pipeline
+            .commands
             .iter()
+            .flat_map(|command| match command {
+                Command::Simple(words) => words.clone(),
+                Command::Compound(..) => panic!("expected a simple command"),
+            })
             .map(|word| {
                 word.iter()
                     .map(|seg| match seg {
                         WordSegment::Unquoted(s)
                         | WordSegment::DoubleQuoted(s)
-                        | WordSegment::SingleQuoted(s) => s.as_str(),
+                        | WordSegment::SingleQuoted(s) => &**s,
                     })
                     .collect::<String>()
             })
@@ -133,10 +458,10 @@ mod tests {
     }
 
     #[test]
-    fn semicolon_chain() {
-        let entries = tokenize_chain("echo a ; echo b");
-        assert_eq!(entries.len(), 2);
-        assert_eq!(entries[1].connector, Connector::Sequence);
+    fn semicolon_separates_lists() {
+        let list = parse("echo a ; echo b").unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(entry_strings(&list[1].entries[0]), vec!["echo", "b"]);
     }
 
     #[test]
@@ -151,29 +476,281 @@ mod tests {
 
     #[test]
     fn pipe_inside_chain_segment() {
-        // The | inside a chain entry should pass through un-consumed.
         let entries = tokenize_chain("ls | wc && echo done");
         assert_eq!(entries.len(), 2);
-        // First entry should still have "ls", "|", "wc" as words.
-        let first_words = entry_strings(&entries[0]);
-        assert_eq!(first_words, vec!["ls", "|", "wc"]);
+        assert_eq!(entries[0].pipeline.commands.len(), 2);
+        assert_eq!(entry_strings(&entries[0]), vec!["ls", "wc"]);
+    }
+
+    #[test]
+    fn pipe_ampersand_also_pipes_stderr() {
+        let entries = tokenize_chain("make 2>/dev/null |& grep error");
+        assert_eq!(entries[0].pipeline.commands.len(), 2);
+        assert_eq!(entries[0].pipeline.to_string(), "make 2> /dev/null 2>&1 | grep error");
+    }
+
+    #[test]
+    fn ampersand_backgrounds_only_its_list() {
+        let list = parse("sleep 1 && echo a & echo b").unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list[0].background);
+        assert_eq!(list[0].entries.len(), 2);
+        assert!(!list[1].background);
     }
 
     #[test]
     fn leading_operator_is_error() {
-        let words = tokenize("&& echo hi").unwrap();
-        assert!(parse_chain(words).is_err());
+        assert!(matches!(parse("&& echo hi"), Err(ParseError::Syntax(_))));
+        assert!(matches!(parse("echo a ;; echo b"), Err(ParseError::Syntax(_))));
+        assert!(matches!(parse("| wc"), Err(ParseError::Syntax(_))));
+    }
+
+    #[test]
+    fn trailing_operator_is_incomplete() {
+        assert_eq!(parse("echo hi &&").unwrap_err(), ParseError::Incomplete);
+        assert_eq!(parse("echo hi |\n").unwrap_err(), ParseError::Incomplete);
     }
 
     #[test]
-    fn trailing_operator_is_error() {
-        let words = tokenize("echo hi &&").unwrap();
-        assert!(parse_chain(words).is_err());
+    fn operator_may_continue_on_next_line() {
+        let entries = tokenize_chain("echo hi &&\n\n echo bye");
+        assert_eq!(entries.len(), 2);
     }
 
     #[test]
     fn empty_input_returns_empty() {
-        let entries = parse_chain(vec![]).unwrap();
-        assert!(entries.is_empty());
+        assert!(parse_script(vec![]).unwrap().is_empty());
+        assert!(parse("\n\n").unwrap().is_empty());
+    }
+
+    // ── if statements ──
+
+    fn parse_if(input: &str) -> IfClause {
+        let list = parse(input).expect("parse_script failed");
+        match &list[0].entries[0].pipeline.commands[0] {
+            Command::Compound(CompoundCommand::If(clause), _) => clause.clone(),
+            other => panic!("expected if, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn if_then_fi_on_one_line() {
+        let clause = parse_if("if true; then echo yes; fi");
+        assert_eq!(clause.branches.len(), 1);
+        assert_eq!(clause.branches[0].condition.len(), 1);
+        assert_eq!(clause.branches[0].body.len(), 1);
+        assert!(clause.else_body.is_none());
+    }
+
+    #[test]
+    fn if_elif_else_across_lines() {
+        let clause = parse_if("if false\nthen\n  echo a\nelif true; then echo b\n\nelse\n echo c\n echo d\nfi\n");
+        assert_eq!(clause.branches.len(), 2);
+        assert_eq!(clause.else_body.as_ref().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn nested_if_and_fi_fi() {
+        let clause = parse_if("if true; then if true; then echo x; fi fi");
+        assert!(matches!(
+            clause.branches[0].body[0].entries[0].pipeline.commands[0],
+            Command::Compound(CompoundCommand::If(_), _)
+        ));
+    }
+
+    #[test]
+    fn if_can_be_chained_and_piped() {
+        let list = parse("if true; then echo a; fi && echo b | cat").unwrap();
+        assert_eq!(list[0].entries.len(), 2);
+    }
+
+    #[test]
+    fn time_prefixes_a_pipeline() {
+        let entries = tokenize_chain("time -p a | b && time c");
+        assert_eq!(entries[0].pipeline.time, Some(Timing::Posix));
+        assert_eq!(entry_strings(&entries[0]), vec!["a", "b"]);
+        assert_eq!(entries[1].pipeline.time, Some(Timing::Format));
+        assert_eq!(entries[0].pipeline.to_string(), "time -p a | b");
+
+        // With nothing to time, `time` is an ordinary command name.
+        let list = parse("time; echo time").unwrap();
+        assert_eq!(list[0].entries[0].pipeline.time, None);
+        assert_eq!(entry_strings(&list[1].entries[0]), vec!["echo", "time"]);
+    }
+
+    #[test]
+    fn reserved_words_only_in_command_position() {
+        let entries = tokenize_chain("echo if then fi");
+        assert_eq!(entry_strings(&entries[0]), vec!["echo", "if", "then", "fi"]);
+        let entries = tokenize_chain("'if' true");
+        assert_eq!(entry_strings(&entries[0]), vec!["if", "true"]);
+    }
+
+    #[test]
+    fn unfinished_if_is_incomplete() {
+        for input in ["if true", "if true; then", "if true; then echo\n", "if true; then echo; else"] {
+            assert_eq!(parse(input).unwrap_err(), ParseError::Incomplete, "{input}");
+        }
+    }
+
+    #[test]
+    fn misplaced_reserved_words_are_errors() {
+        for (input, token) in [
+            ("then echo", "then"),
+            ("fi", "fi"),
+            ("if true; then fi", "fi"),
+            ("if; then echo; fi", ";"),
+            ("if true; then echo; fi echo", "echo"),
+        ] {
+            assert_eq!(
+                parse(input).unwrap_err(),
+                ParseError::Syntax(JshError::syntax(format!(
+                    "jsh: syntax error near unexpected token `{token}'"
+                ))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn if_renders_back_to_source() {
+        let list = parse("if a\nthen b &\nelif c; then d 'e f'\nelse g | h; fi").unwrap();
+        assert_eq!(
+            list[0].to_string(),
+            "if a; then b & elif c; then d 'e f'; else g | h; fi"
+        );
+    }
+
+    // ── brace groups ──
+
+    #[test]
+    fn brace_group_with_redirections() {
+        let list = parse("{ echo a; echo b; } > out.txt 2>&1 && echo done").unwrap();
+        let entries = &list[0].entries;
+        assert_eq!(entries.len(), 2);
+        match &entries[0].pipeline.commands[0] {
+            Command::Compound(CompoundCommand::BraceGroup(body), redirects) => {
+                assert_eq!(body.len(), 2);
+                assert_eq!(parser::words_to_source(redirects), "> out.txt 2>&1");
+            }
+            other => panic!("expected brace group, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn braces_are_only_reserved_as_separate_words() {
+        let entries = tokenize_chain("echo {a,b} } {");
+        assert_eq!(entry_strings(&entries[0]), vec!["echo", "{a,b}", "}", "{"]);
+        let entries = tokenize_chain("{echo");
+        assert_eq!(entry_strings(&entries[0]), vec!["{echo"]);
+    }
+
+    #[test]
+    fn brace_group_needs_separator_before_close() {
+        // `}` is an argument to echo here, so the group is still open.
+        assert_eq!(parse("{ echo a }").unwrap_err(), ParseError::Incomplete);
+        assert!(parse("{ echo a }\n}").is_ok());
+        assert_eq!(
+            parse("{ }").unwrap_err(),
+            ParseError::Syntax(JshError::syntax("jsh: syntax error near unexpected token `}'"))
+        );
+    }
+
+    #[test]
+    fn compound_redirect_needs_target() {
+        assert_eq!(
+            parse("{ echo a; } > ;").unwrap_err(),
+            ParseError::Syntax(JshError::syntax("jsh: syntax error near unexpected token `;'"))
+        );
+        assert_eq!(parse("{ echo a; } >").unwrap_err(), ParseError::Incomplete);
+    }
+
+    #[test]
+    fn unexpected_tokens_carry_their_span() {
+        let input = "echo a\nif true; then :; fi fi";
+        let tokens = crate::parser::tokenize_with_offsets(input).unwrap();
+        let (words, spans): (Vec<Word>, Vec<Span>) = tokens
+            .into_iter()
+            .map(|(word, offset)| (word, Span::at(input, offset)))
+            .unzip();
+        let err = parse_script_spanned(words, spans, &AliasTable::new()).unwrap_err();
+        let ParseError::Syntax(err) = err else { panic!("expected a syntax error") };
+        let span = err.span().expect("span");
+        assert_eq!((span.line, span.column), (1, 20));
+        assert_eq!(span.text, "if true; then :; fi fi");
+    }
+
+    #[test]
+    fn brace_group_renders_back_to_source() {
+        let list = parse("{ a\nb & } 2> err.txt").unwrap();
+        assert_eq!(list[0].to_string(), "{ a; b & } 2> err.txt");
+    }
+
+    // ── aliases ──
+
+    fn parse_with(input: &str, aliases: &[(&str, &str)]) -> Result<CommandList, ParseError> {
+        let mut table = AliasTable::new();
+        for (name, value) in aliases {
+            table.set(name, value);
+        }
+        parse_script_with_aliases(tokenize(input).expect("tokenize failed"), &table)
+    }
+
+    fn list_strings(list: &CommandList) -> Vec<Vec<String>> {
+        list.iter()
+            .flat_map(|item| item.entries.iter().map(entry_strings))
+            .collect()
+    }
+
+    #[test]
+    fn alias_expands_in_command_position_only() {
+        let list = parse_with("ll -a; echo ll", &[("ll", "ls -l")]).unwrap();
+        assert_eq!(
+            list_strings(&list),
+            vec![vec!["ls", "-l", "-a"], vec!["echo", "ll"]]
+        );
+    }
+
+    #[test]
+    fn quoted_word_is_not_alias_expanded() {
+        let list = parse_with("'ll' x", &[("ll", "ls -l")]).unwrap();
+        assert_eq!(list_strings(&list), vec![vec!["ll", "x"]]);
+    }
+
+    #[test]
+    fn alias_value_may_contain_operators() {
+        let list = parse_with("up x", &[("up", "echo a | tr a-z A-Z &&")]).unwrap();
+        let entries = &list[0].entries;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].pipeline.commands.len(), 2);
+        assert_eq!(entry_strings(&entries[1]), vec!["x"]);
+    }
+
+    #[test]
+    fn recursive_aliases_stop_at_loops() {
+        let list = parse_with("ls", &[("ls", "ls -F")]).unwrap();
+        assert_eq!(list_strings(&list), vec![vec!["ls", "-F"]]);
+
+        let list = parse_with("a", &[("a", "b x"), ("b", "a y")]).unwrap();
+        assert_eq!(list_strings(&list), vec![vec!["a", "y", "x"]]);
+    }
+
+    #[test]
+    fn trailing_blank_expands_next_word() {
+        let aliases = [("sudo", "sudo "), ("ll", "ls -l"), ("quiet", "nice")];
+        let list = parse_with("sudo ll x", &aliases).unwrap();
+        assert_eq!(list_strings(&list), vec![vec!["sudo", "ls", "-l", "x"]]);
+
+        let list = parse_with("quiet ll", &aliases).unwrap();
+        assert_eq!(list_strings(&list), vec![vec!["nice", "ll"]]);
+    }
+
+    #[test]
+    fn alias_can_open_compound_command() {
+        let list = parse_with("when true; then echo y; fi", &[("when", "if")]).unwrap();
+        assert!(matches!(
+            list[0].entries[0].pipeline.commands[0],
+            Command::Compound(CompoundCommand::If(_), _)
+        ));
     }
 }
@@ -0,0 +1,1030 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::aliases::{self, AliasTable};
+use crate::builtins::{self, BuiltinTable};
+use crate::command_hash::CommandHash;
+use crate::completion::CompletionTable;
+use crate::dynamic::DynamicVariables;
+use crate::error::JshError;
+use crate::ast::{AndOrList, Command, CommandList, CompoundCommand, Connector, IfClause, Pipeline, Timing};
+use crate::executor::{self, ExecutionAction, StdStreams};
+use crate::expander;
+use crate::jobs::{self, JobStatus, JobTable};
+use crate::options::{self, ShellOptions};
+use crate::parser;
+use crate::reader::{CommandReader, Input, LineSource, ScriptLines};
+use crate::redirect;
+use crate::times;
+use crate::traps::{self, TrapTable};
+use crate::variables::{self, Variables};
+
+/// Interpreter state shared by every command the shell runs.
+///
+/// Executes the statement tree produced by
+/// [`script_parser::parse_script`](crate::script_parser::parse_script) by
+/// walking it: compound commands evaluate their condition lists and recurse
+/// into the chosen body, while pipelines of simple commands are expanded and
+/// handed to the [`executor`].
+pub struct Shell {
+    /// Exit status of the most recently completed command (`$?`).
+    pub last_exit_code: i32,
+    pub job_table: JobTable,
+    pub aliases: AliasTable,
+    /// Builtins registered on top of the shell's own.
+    pub builtins: BuiltinTable,
+    /// Where external commands were found on `$PATH`, for `hash`.
+    pub command_hash: CommandHash,
+    pub completions: CompletionTable,
+    pub variables: Variables,
+    pub options: ShellOptions,
+    pub traps: TrapTable,
+    /// `$SECONDS`, `$RANDOM`, `$LINENO`, and `$EPOCHSECONDS`.
+    pub(crate) dynamic: DynamicVariables,
+    /// Commands come from a user at a terminal, so `exit` with jobs left
+    /// must be confirmed.
+    pub interactive: bool,
+    /// Letters `$-` adds for how the shell was started: `c` for `jsh -c`,
+    /// `s` when it reads its commands from stdin.
+    pub invocation_flags: String,
+    /// Started as a login shell (`jsh -l`), which `suspend` refuses to stop
+    /// without `-f`.
+    pub login: bool,
+    /// `$0`: `jsh`, or the script or `-c` name the shell was started with.
+    pub name: String,
+    /// `exit` or end of input was refused because of jobs; another one
+    /// straight away goes through.
+    pub exit_warned: bool,
+    /// `history --import` rewrote `~/.jsh_history`, so the line editor must
+    /// read it again before the next prompt.
+    pub history_rewritten: bool,
+    /// Positional parameter frames. The bottom frame holds the shell's own
+    /// arguments; a sourced file given arguments pushes a frame of its own,
+    /// and `$1`, `$2`, ... always read the top one.
+    positional: Vec<Vec<String>>,
+    /// Nesting depth of contexts where `set -e` does not apply: `if`
+    /// conditions and the parts of an `&&` / `||` list before the last.
+    errexit_ignored: usize,
+    /// How many files `source` is reading, one inside another.
+    pub(crate) source_depth: usize,
+    /// `return` ran in a sourced file: stop reading it.
+    pub(crate) returning: bool,
+    /// The status of the last command substitution expanded, which a
+    /// command made only of assignments exits with.
+    pub(crate) substitution_status: Cell<Option<i32>>,
+    /// An expansion failed in a way that ends a non-interactive shell, as
+    /// `${VAR:?message}` and an unset variable under `set -u` do.
+    pub(crate) fatal_expansion: Cell<bool>,
+    /// Default stdio for commands without their own redirections; replaced
+    /// while a redirected compound command runs.
+    pub(crate) streams: StdStreams,
+    /// The directory last left on each drive, for `cd D:` (Windows).
+    pub(crate) drive_directories: BTreeMap<char, PathBuf>,
+    /// Logs made under `set -o bglog` in the private directory, removed
+    /// when the shell exits.
+    pub(crate) temporary_logs: Vec<PathBuf>,
+    /// The working directory. Kept here rather than in the process so that
+    /// several shells can live in one program; children are started in it.
+    pub(crate) cwd: PathBuf,
+}
+
+/// What running a line or script through [`Shell::eval_line`] or
+/// [`Shell::run_script`] came to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus {
+    /// `$?` afterwards.
+    pub code: i32,
+    /// A command asked the shell to exit; the caller should stop feeding it
+    /// input and call [`Shell::finish`].
+    pub exited: bool,
+}
+
+impl ExitStatus {
+    pub fn success(self) -> bool {
+        self.code == 0
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        let mut variables = Variables::from_env();
+        let mut cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        // An inherited $PWD is kept only while it still names the working
+        // directory, so a symlinked path survives but a stale one does not.
+        if let Ok(pwd) = builtins::logical_pwd(&variables, &cwd) {
+            variables.export("PWD", Some(&pwd.to_string_lossy()));
+            cwd = pwd;
+        }
+        Self {
+            last_exit_code: 0,
+            job_table: JobTable::new(),
+            aliases: AliasTable::new(),
+            builtins: BuiltinTable::new(),
+            command_hash: CommandHash::new(),
+            completions: CompletionTable::new(),
+            variables,
+            options: ShellOptions::new(),
+            traps: TrapTable::new(),
+            dynamic: DynamicVariables::new(),
+            interactive: false,
+            invocation_flags: String::new(),
+            login: false,
+            name: "jsh".to_string(),
+            exit_warned: false,
+            history_rewritten: false,
+            positional: vec![Vec::new()],
+            errexit_ignored: 0,
+            source_depth: 0,
+            returning: false,
+            substitution_status: Cell::new(None),
+            fatal_expansion: Cell::new(false),
+            streams: StdStreams::inherit(),
+            drive_directories: BTreeMap::new(),
+            temporary_logs: Vec::new(),
+            cwd,
+        }
+    }
+
+    /// A copy of the state a builtin can consult from another thread, such
+    /// as a non-final pipeline stage. Changes made to it are discarded, and
+    /// its jobs can be listed but not waited for or controlled.
+    pub(crate) fn detached(&self) -> Shell {
+        Shell {
+            last_exit_code: self.last_exit_code,
+            job_table: JobTable::inheriting(self.job_table.listings()),
+            aliases: self.aliases.clone(),
+            builtins: self.builtins.clone(),
+            command_hash: self.command_hash.clone(),
+            completions: self.completions.clone(),
+            variables: self.variables.clone(),
+            options: self.options.clone(),
+            traps: TrapTable::new(),
+            dynamic: self.dynamic.clone(),
+            interactive: false,
+            invocation_flags: String::new(),
+            login: self.login,
+            name: self.name.clone(),
+            exit_warned: false,
+            history_rewritten: false,
+            positional: vec![self.positional_params().to_vec()],
+            errexit_ignored: 0,
+            source_depth: 0,
+            returning: false,
+            substitution_status: Cell::new(None),
+            fatal_expansion: Cell::new(false),
+            streams: StdStreams::inherit(),
+            drive_directories: self.drive_directories.clone(),
+            temporary_logs: Vec::new(),
+            cwd: self.cwd.clone(),
+        }
+    }
+
+    /// Run `line`, which may hold several commands or a whole script, as
+    /// `jsh -c` does. This is the entry point for programs embedding the
+    /// shell; output goes to the process's own stdout and stderr.
+    pub fn eval_line(&mut self, line: &str) -> ExitStatus {
+        let action = self.run_source(&mut ScriptLines::new(line), "-c");
+        self.exit_status(action)
+    }
+
+    /// Run the script at `path` in this shell, as `source` does.
+    pub fn run_script(&mut self, path: impl AsRef<Path>) -> ExitStatus {
+        let action = self.source_file(path.as_ref());
+        self.exit_status(action)
+    }
+
+    fn exit_status(&self, action: ExecutionAction) -> ExitStatus {
+        ExitStatus {
+            code: self.last_exit_code,
+            exited: matches!(action, ExecutionAction::Exit(_)),
+        }
+    }
+
+    /// Wind the shell down as it exits: run the traps of signals still
+    /// pending, then the `EXIT` trap, and send SIGHUP to the stopped jobs
+    /// left behind, and under `shopt -s huponexit` to the running ones. On
+    /// Windows those jobs are terminated instead. Background logs in the
+    /// private directory are removed. Returns the exit status.
+    pub fn finish(&mut self) -> i32 {
+        // An `exit` in one of these traps sets the status the shell ends with.
+        let _ = self.run_pending_traps();
+        self.run_exit_trap();
+        self.job_table.hang_up(self.options.huponexit);
+        for log in self.temporary_logs.drain(..) {
+            let _ = std::fs::remove_file(log);
+        }
+        self.last_exit_code
+    }
+
+    pub fn job_table(&self) -> &JobTable {
+        &self.job_table
+    }
+
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    pub fn variables_mut(&mut self) -> &mut Variables {
+        &mut self.variables
+    }
+
+    /// The directory `cd` last moved to, which relative paths and started
+    /// programs use. The process's own working directory is left alone.
+    pub fn current_dir(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// `$1`, `$2`, ... of the current frame.
+    pub fn positional_params(&self) -> &[String] {
+        self.positional.last().map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Replace the parameters of the current frame.
+    pub fn set_positional_params(&mut self, params: Vec<String>) {
+        match self.positional.last_mut() {
+            Some(frame) => *frame = params,
+            None => self.positional.push(params),
+        }
+    }
+
+    /// Start a new frame, e.g. for a sourced file given arguments.
+    pub fn push_positional_params(&mut self, params: Vec<String>) {
+        self.positional.push(params);
+    }
+
+    /// Return to the caller's frame. The bottom frame is never removed.
+    pub fn pop_positional_params(&mut self) {
+        if self.positional.len() > 1 {
+            self.positional.pop();
+        }
+    }
+
+    /// Drop the first `n` parameters of the current frame, as `shift n`
+    /// does. Returns false, changing nothing, if there are fewer than `n`.
+    pub fn shift_positional_params(&mut self, n: usize) -> bool {
+        match self.positional.last_mut() {
+            Some(frame) if n <= frame.len() => {
+                frame.drain(..n);
+                true
+            }
+            _ => n == 0,
+        }
+    }
+
+    /// `$-`: the letters of the `set` flags that are on, plus `i` in an
+    /// interactive shell and the [`Shell::invocation_flags`].
+    pub fn option_flags(&self) -> String {
+        let mut flags = self.options.flags();
+        if self.interactive {
+            flags.push('i');
+        }
+        flags.push_str(&self.invocation_flags);
+        flags
+    }
+
+    /// Script text that recreates this shell's unexported variables,
+    /// readonly marks, positional parameters, aliases, and enabled options
+    /// in a child jsh, so command substitutions and background lists see
+    /// them as a forked subshell would. Exported variables arrive via the
+    /// environment, and `$?` via [`executor::ParentState`].
+    pub(crate) fn subshell_prelude(&self) -> String {
+        let assignments = self
+            .variables
+            .iter()
+            .filter(|(_, var)| !var.exported)
+            .map(|(name, var)| match &var.assoc {
+                Some(map) => {
+                    let elements = map.iter().map(|(key, value)| variables::format_element_assignment(name, key, value));
+                    std::iter::once(format!("declare -A {name}")).chain(elements).collect::<Vec<_>>().join("\n") + "\n"
+                }
+                None => variables::format_assignment(name, &var.value) + "\n",
+            });
+        // After every value is in place, exported ones included.
+        let readonly = self
+            .variables
+            .iter()
+            .filter(|(_, var)| var.readonly)
+            .map(|(name, _)| format!("readonly {name}\n"));
+        let set_options = self
+            .options
+            .list(options::SET_NAMES)
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| format!("set -o {}\n", quote_word(&self.options.set_argument(name))));
+        // Some shopt options start on, so replay whichever differ.
+        let defaults = ShellOptions::new();
+        let shopt_options = self
+            .options
+            .list(options::SHOPT_NAMES)
+            .filter(|&(name, on)| defaults.get(name) != Some(on))
+            .map(|(name, on)| format!("shopt {} {name}\n", if on { "-s" } else { "-u" }));
+        let params = self.positional_params();
+        let positional = (!params.is_empty()).then(|| {
+            let words: Vec<String> = params.iter().map(|param| quote_word(param)).collect();
+            format!("set -- {}\n", words.join(" "))
+        });
+        let aliases = self
+            .aliases
+            .iter()
+            .map(|(name, value)| aliases::format_definition(name, value) + "\n");
+        // `set` options last, so `set -e` and `set -x` do not act on the
+        // prelude itself.
+        assignments
+            .chain(readonly)
+            .chain(positional)
+            .chain(aliases)
+            .chain(shopt_options)
+            .chain(set_options)
+            .collect()
+    }
+
+    /// Read and run commands from `source` until it is exhausted, the way a
+    /// script or startup file runs.
+    ///
+    /// `name` labels error messages (`jsh: ~/.jshrc: line 3: ...`). A syntax
+    /// error stops the rest of the input from running and sets `$?` to 2.
+    pub fn run_source(&mut self, source: &mut dyn LineSource, name: &str) -> ExecutionAction {
+        let mut reader = CommandReader::new();
+        let outer_line = self.dynamic.line();
+
+        loop {
+            match reader.read_command(source, &self.aliases) {
+                Ok(Input::Command { list, line, .. }) => {
+                    if self.noexec() {
+                        continue;
+                    }
+                    self.dynamic.set_line(line);
+                    if let ExecutionAction::Exit(code) = self.run_list(&list) {
+                        return ExecutionAction::Exit(code);
+                    }
+                    if self.returning {
+                        break;
+                    }
+                }
+                Ok(Input::SyntaxError { error, line, .. }) => {
+                    report_syntax_error(name, line, &error);
+                    self.last_exit_code = 2;
+                    break;
+                }
+                Ok(Input::Eof) => break,
+                Err(e) => {
+                    eprintln!("jsh: {name}: {e}");
+                    self.last_exit_code = 1;
+                    break;
+                }
+            }
+        }
+
+        self.dynamic.set_line(outer_line);
+        ExecutionAction::Continue(self.last_exit_code)
+    }
+
+    /// Record how long a command typed at the prompt took to run, as
+    /// `$CMD_DURATION` in milliseconds, for the prompt's `\D`.
+    pub fn record_duration(&mut self, elapsed: Duration) {
+        self.variables.set("CMD_DURATION", &elapsed.as_millis().to_string());
+    }
+
+    /// Set `$LINENO` for a command read outside [`Shell::run_source`], like
+    /// one typed at the prompt.
+    pub fn set_line_number(&mut self, line: usize) {
+        self.dynamic.set_line(line);
+    }
+
+    /// Count `$SECONDS` on from the parent's, for a subshell given its
+    /// [`executor::ParentState`].
+    pub fn resume_seconds(&mut self, millis: i64) {
+        self.dynamic.resume_seconds(millis);
+    }
+
+    /// Whether commands are only read, not run: `set -n` in a
+    /// non-interactive shell.
+    pub fn noexec(&self) -> bool {
+        self.options.noexec && !self.interactive
+    }
+
+    /// Parse `source` to the end without running anything, reporting every
+    /// syntax error the way [`Shell::run_source`] reports the first. Returns
+    /// how many errors there were.
+    pub fn check_source(&self, source: &mut dyn LineSource, name: &str) -> usize {
+        let mut reader = CommandReader::new();
+        let mut errors = 0;
+
+        loop {
+            match reader.read_command(source, &self.aliases) {
+                Ok(Input::Command { .. }) => {}
+                Ok(Input::SyntaxError { error, line, .. }) => {
+                    report_syntax_error(name, line, &error);
+                    errors += 1;
+                }
+                Ok(Input::Eof) => return errors,
+                Err(e) => {
+                    eprintln!("jsh: {name}: {e}");
+                    return errors + 1;
+                }
+            }
+        }
+    }
+
+    /// Run the commands in the file at `path`, relative to the shell's
+    /// working directory, in this shell. `/dev/stdin` reads the shell's
+    /// current stdin, on any platform.
+    pub fn source_file(&mut self, path: &Path) -> ExecutionAction {
+        let script = match path.to_str().and_then(redirect::device_fd) {
+            Some(0) => self.streams.stdin_reader().map_err(|e| e.to_string()).and_then(|mut reader| {
+                let mut script = String::new();
+                reader
+                    .read_to_string(&mut script)
+                    .map(|_| script)
+                    .map_err(|e| e.to_string())
+            }),
+            _ => std::fs::read_to_string(self.cwd.join(path)).map_err(|e| e.to_string()),
+        };
+        let script = match script {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("jsh: {}: {e}", path.display());
+                self.last_exit_code = 1;
+                return ExecutionAction::Continue(1);
+            }
+        };
+
+        let name = path.display().to_string();
+        self.run_source(&mut ScriptLines::new(&script), &name)
+    }
+
+    /// Run every and-or list in `list` in order.
+    ///
+    /// Returns [`ExecutionAction::Exit`] as soon as a command asks the shell
+    /// to exit; otherwise `Continue` with the status of the last command.
+    pub fn run_list(&mut self, list: &CommandList) -> ExecutionAction {
+        for item in list {
+            if let ExecutionAction::Exit(code) = self.run_and_or(item) {
+                self.last_exit_code = code;
+                return ExecutionAction::Exit(code);
+            }
+            if let ExecutionAction::Exit(code) = self.run_pending_traps() {
+                return ExecutionAction::Exit(code);
+            }
+            if self.interrupted() || self.returning {
+                break;
+            }
+        }
+        ExecutionAction::Continue(self.last_exit_code)
+    }
+
+    /// Run the traps of signals received since the last check. `$?` is kept
+    /// unless a trap runs `exit`.
+    pub fn run_pending_traps(&mut self) -> ExecutionAction {
+        let actions = self.traps.take_pending();
+        // A trapped Ctrl-C is dealt with by its trap, and must not be passed
+        // on to the next command started.
+        if traps::parse_signal("INT").is_some_and(|signal| self.traps.get(signal).is_some()) {
+            traps::clear_interrupted();
+        }
+        for action in actions {
+            let status = self.last_exit_code;
+            if let ExecutionAction::Exit(code) = self.run_source(&mut ScriptLines::new(&action), "trap") {
+                self.last_exit_code = code;
+                return ExecutionAction::Exit(code);
+            }
+            self.last_exit_code = status;
+        }
+        ExecutionAction::Continue(self.last_exit_code)
+    }
+
+    /// Whether the shell may exit now. As in bash, an interactive shell with
+    /// stopped or running jobs warns instead the first time, listing the
+    /// jobs that will be sent SIGHUP; exiting again right away goes through.
+    pub fn may_exit(&mut self, stderr: &mut dyn Write) -> bool {
+        if !self.interactive || self.exit_warned {
+            return true;
+        }
+        let jobs = self.job_table.jobs_sorted();
+        let kind = if jobs.iter().any(|job| job.status == JobStatus::Stopped) {
+            "stopped"
+        } else if jobs.iter().any(|job| job.status == JobStatus::Running) {
+            "running"
+        } else {
+            return true;
+        };
+        let _ = writeln!(stderr, "There are {kind} jobs.");
+        for job in self.job_table.hang_up_targets(self.options.huponexit) {
+            let _ = writeln!(stderr, "  [{}]  {}  (will be sent SIGHUP)", job.id, job.command);
+        }
+        self.exit_warned = true;
+        false
+    }
+
+    /// How many Ctrl-Ds in a row an interactive shell ignores before it
+    /// exits: `$IGNOREEOF`, or 10 if that is set but not a number, as in
+    /// bash. Unset, or in a non-interactive shell, none are.
+    pub fn ignored_eofs(&self) -> u32 {
+        match self.variables.get("IGNOREEOF") {
+            Some(count) if self.interactive => count.trim().parse().unwrap_or(10),
+            _ => 0,
+        }
+    }
+
+    /// Whether a Ctrl-C should cut the current command line short: one has
+    /// arrived, and no `INT` trap handles it instead.
+    pub fn interrupted(&self) -> bool {
+        traps::interrupted() && traps::parse_signal("INT").and_then(|signal| self.traps.get(signal)).is_none()
+    }
+
+    /// Run the `EXIT` trap, if one is set, as the shell exits. `$?` stays the
+    /// shell's exit status unless the trap runs `exit`.
+    pub fn run_exit_trap(&mut self) {
+        if let Some(action) = self.traps.take_exit() {
+            // The trap runs in full even when a Ctrl-C ended the script.
+            traps::clear_interrupted();
+            let status = self.last_exit_code;
+            self.last_exit_code = match self.run_source(&mut ScriptLines::new(&action), "trap") {
+                ExecutionAction::Exit(code) => code,
+                ExecutionAction::Continue(_) => status,
+            };
+        }
+    }
+
+    /// Run pipelines joined by `&&` / `||`, short-circuiting on `$?`.
+    fn run_and_or(&mut self, item: &AndOrList) -> ExecutionAction {
+        if item.background {
+            return self.run_background(item);
+        }
+
+        let command_text = item.to_string();
+        let last = item.entries.len().saturating_sub(1);
+        for (idx, entry) in item.entries.iter().enumerate() {
+            // Decide whether this entry should run based on the connector and
+            // the exit code left by the previous entry.
+            let should_run = match entry.connector {
+                Connector::Sequence => true,
+                Connector::And => self.last_exit_code == 0,
+                Connector::Or => self.last_exit_code != 0,
+            };
+            if !should_run {
+                continue;
+            }
+
+            // A failure tested by a following `&&` or `||` does not trip
+            // `set -e`.
+            let tested = idx < last;
+            if tested {
+                self.errexit_ignored += 1;
+            }
+            self.fatal_expansion.set(false);
+            let action = match entry.pipeline.time {
+                Some(timing) => self.run_timed(&entry.pipeline, timing, &command_text),
+                None => self.run_pipeline(&entry.pipeline, false, &command_text),
+            };
+            if tested {
+                self.errexit_ignored -= 1;
+            }
+            match action {
+                ExecutionAction::Continue(code) => {
+                    self.last_exit_code = code;
+                    if self.returning {
+                        return ExecutionAction::Continue(code);
+                    }
+                    if self.interrupted() {
+                        // Ctrl-C abandons the rest of the line, as in bash.
+                        self.last_exit_code = 130;
+                        return ExecutionAction::Continue(130);
+                    }
+                    if self.fatal_expansion.take() && !self.interactive {
+                        return ExecutionAction::Exit(code);
+                    }
+                    if code != 0 && !tested && self.options.errexit && self.errexit_ignored == 0 {
+                        return ExecutionAction::Exit(code);
+                    }
+                }
+                ExecutionAction::Exit(_) => return action,
+            }
+        }
+
+        ExecutionAction::Continue(self.last_exit_code)
+    }
+
+    /// Run an and-or list terminated by `&`.
+    ///
+    /// A single pipeline of simple commands is started directly as a
+    /// background job. Anything larger must run as one unit so that `&&` /
+    /// `||` gates and `if` conditions see real exit codes: backgrounding an
+    /// early entry would return immediately with an unknown status. Those run
+    /// in a child jsh fed the list's source text on stdin, which the parent
+    /// registers as a single job.
+    fn run_background(&mut self, item: &AndOrList) -> ExecutionAction {
+        if self.options.bglog {
+            return self.run_logged_background(item);
+        }
+        self.start_background(item)
+    }
+
+    /// Run `item` in the background with its output going to a log file of
+    /// its own, recorded on the job for `jobs -o`. The file is new and
+    /// private to the user, in the `set -o bglog=DIR` directory if there is
+    /// one.
+    fn run_logged_background(&mut self, item: &AndOrList) -> ExecutionAction {
+        let job_id = self.job_table.next_id();
+        let dir = match &self.options.bglog_dir {
+            Some(dir) => log_directory(dir).map(|()| dir.clone()),
+            None => jobs::private_log_dir(),
+        };
+        let created = dir.and_then(|dir| jobs::create_log(&dir, job_id));
+        let (log, path) = match created {
+            Ok(created) => created,
+            Err(e) => {
+                eprintln!("jsh: bglog: {e}");
+                return ExecutionAction::Continue(1);
+            }
+        };
+        let logged = match self.streams.logged_to(&log) {
+            Ok(streams) => streams,
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                eprintln!("{e}");
+                return ExecutionAction::Continue(1);
+            }
+        };
+
+        let saved = std::mem::replace(&mut self.streams, logged);
+        let action = self.start_background(item);
+        self.streams = saved;
+
+        match self.job_table.get_mut(job_id) {
+            Some(job) => {
+                if self.options.bglog_dir.is_none() {
+                    self.temporary_logs.push(path.clone());
+                }
+                job.log = Some(path);
+            }
+            // Nothing went into the background (e.g. `cd dir &`).
+            None => {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+        action
+    }
+
+    /// Start `item` as a background job, as described on [`Self::run_background`].
+    fn start_background(&mut self, item: &AndOrList) -> ExecutionAction {
+        let command_text = item.to_string();
+
+        if let [entry] = item.entries.as_slice()
+            && entry
+                .pipeline
+                .commands
+                .iter()
+                .all(|command| matches!(command, Command::Simple(_)))
+        {
+            return self.run_pipeline(&entry.pipeline, true, &command_text);
+        }
+
+        self.spawn_background_subshell(&command_text)
+    }
+
+    /// Start `command_text` as a background job in a child jsh fed the text
+    /// on stdin, so it runs with a copy of this shell's state and cannot
+    /// change it.
+    pub(crate) fn spawn_background_subshell(&mut self, command_text: &str) -> ExecutionAction {
+        let (stdout, stderr) = match self.streams.output_stdio() {
+            Ok(stdio) => stdio,
+            Err(e) => {
+                eprintln!("{e}");
+                return ExecutionAction::Continue(1);
+            }
+        };
+        let prelude = self.subshell_prelude();
+        match executor::stdin_subshell_command(self, &prelude)
+            .stdin(std::process::Stdio::piped())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+        {
+            Ok(mut child) => {
+                // Write the command text and signal EOF so the child shell
+                // executes the list and exits cleanly.
+                if let Some(mut stdin) = child.stdin.take() {
+                    let _ = write!(stdin, "{prelude}");
+                    let _ = writeln!(stdin, "{command_text}");
+                    // stdin drops here, closing the pipe and triggering EOF
+                }
+                let (job_id, pid) = self.job_table.add(child, command_text.to_string());
+                println!("[{job_id}] {pid}");
+                ExecutionAction::Continue(0)
+            }
+            Err(e) => {
+                eprintln!("jsh: failed to spawn background shell: {e}");
+                ExecutionAction::Continue(1)
+            }
+        }
+    }
+
+    /// Expand and run one pipeline.
+    ///
+    /// Word expansion and redirect resolution happen here, not at parse
+    /// time, because they depend on runtime state like `$?`.
+    fn run_pipeline(
+        &mut self,
+        pipeline: &Pipeline,
+        background: bool,
+        command_text: &str,
+    ) -> ExecutionAction {
+        if let [Command::Compound(compound, redirects)] = pipeline.commands.as_slice() {
+            return self.run_compound(compound, redirects);
+        }
+
+        let mut commands = Vec::new();
+        for command in &pipeline.commands {
+            let words = match command {
+                Command::Simple(words) => words,
+                Command::Compound(..) => {
+                    eprintln!("jsh: compound commands in pipelines are not supported yet");
+                    return ExecutionAction::Continue(1);
+                }
+            };
+
+            let (words, redirections) =
+                match redirect::extract_redirections_from_words(words, self) {
+                    Ok(pair) => pair,
+                    Err(msg) => {
+                        eprintln!("{msg}");
+                        return ExecutionAction::Continue(2);
+                    }
+                };
+
+            // Leading `NAME=value` words apply to this command only.
+            let assignment_count = words
+                .iter()
+                .take_while(|word| parser::is_assignment(word))
+                .count();
+            let (assignment_words, words) = words.split_at(assignment_count);
+
+            let mut args = match expander::expand_words(words.iter().copied(), self) {
+                Ok(args) => args,
+                Err(msg) => {
+                    eprintln!("{msg}");
+                    return ExecutionAction::Continue(1);
+                }
+            };
+            builtins::strip_command_prefix(&mut args);
+            if args.is_empty() {
+                // With no command left, the assignments set shell variables.
+                if assignment_count > 0 && pipeline.commands.len() == 1 {
+                    return self.run_assignments(assignment_words);
+                }
+                eprintln!("jsh: syntax error: empty command");
+                return ExecutionAction::Continue(2);
+            }
+
+            let assignments = match assignment_words
+                .iter()
+                .filter_map(|word| parser::split_assignment(word))
+                .map(|(name, value)| match self.variables.is_readonly(&name) {
+                    true => Err(JshError::Assignment(format!("jsh: {name}: readonly variable"))),
+                    false => Ok((name, expander::expand_assignment_value(&value, self)?)),
+                })
+                .collect::<Result<Vec<_>, JshError>>()
+            {
+                Ok(assignments) => assignments,
+                Err(msg) => {
+                    eprintln!("{msg}");
+                    return ExecutionAction::Continue(1);
+                }
+            };
+            let program = args.remove(0);
+            let command = parser::Command {
+                program,
+                args,
+                assignments,
+            };
+            commands.push(executor::PipelineCommand {
+                command,
+                redirections,
+            });
+        }
+
+        if self.options.xtrace {
+            for command in &commands {
+                let assignments = command
+                    .command
+                    .assignments
+                    .iter()
+                    .map(|(name, value)| format!("{name}={}", quote_word(value)));
+                let words = std::iter::once(&command.command.program)
+                    .chain(&command.command.args)
+                    .map(|word| quote_word(word));
+                self.trace(&assignments.chain(words).collect::<Vec<_>>());
+            }
+        }
+
+        if commands.len() == 1 {
+            let command = commands.swap_remove(0);
+            executor::execute(
+                &command.command,
+                &command.redirections,
+                background,
+                self,
+                command_text,
+            )
+        } else {
+            executor::execute_pipeline(commands, background, self, command_text)
+        }
+    }
+
+    /// Perform a command made only of `NAME=value` words, setting shell
+    /// variables left to right so later values can refer to earlier names.
+    ///
+    /// The command's status is that of the last command substitution in
+    /// the values, or 0 without one.
+    fn run_assignments(&mut self, words: &[&parser::Word]) -> ExecutionAction {
+        self.substitution_status.set(None);
+        for word in words {
+            if let Some((name, value)) = parser::split_assignment(word) {
+                match expander::expand_assignment_value(&value, self) {
+                    Ok(value) => {
+                        if self.options.xtrace {
+                            self.trace(&[format!("{name}={}", quote_word(&value))]);
+                        }
+                        let assigned = match variables::split_subscript(&name) {
+                            Some((array, key)) => {
+                                let key = vec![parser::WordSegment::Unquoted(key.into())];
+                                expander::expand_assignment_value(&key, self).and_then(|key| {
+                                    self.variables.assign_element(array, &key, &value).map_err(JshError::Assignment)
+                                })
+                            }
+                            None if self.dynamic.assign(&name, &value) => Ok(()),
+                            None => self.variables.assign(&name, &value).map_err(JshError::Assignment),
+                        };
+                        if let Err(msg) = assigned {
+                            eprintln!("{msg}");
+                            return ExecutionAction::Continue(1);
+                        }
+                    }
+                    Err(msg) => {
+                        eprintln!("{msg}");
+                        return ExecutionAction::Continue(1);
+                    }
+                }
+            }
+        }
+        ExecutionAction::Continue(self.substitution_status.take().unwrap_or(0))
+    }
+
+    /// Run a compound command in this shell process.
+    ///
+    /// Redirections on the compound command are opened once and become the
+    /// default streams of every command inside it, then the previous streams
+    /// are restored.
+    fn run_compound(&mut self, compound: &CompoundCommand, redirects: &[parser::Word]) -> ExecutionAction {
+        if redirects.is_empty() {
+            return self.run_compound_body(compound);
+        }
+
+        let redirected = redirect::extract_redirections_from_words(redirects, self)
+            .and_then(|(_, redirections)| self.streams.redirected(&redirections, &self.cwd));
+        let streams = match redirected {
+            Ok(streams) => streams,
+            Err(msg) => {
+                eprintln!("{msg}");
+                return ExecutionAction::Continue(1);
+            }
+        };
+
+        let saved = std::mem::replace(&mut self.streams, streams);
+        let action = self.run_compound_body(compound);
+        self.streams = saved;
+        action
+    }
+
+    fn run_compound_body(&mut self, compound: &CompoundCommand) -> ExecutionAction {
+        match compound {
+            CompoundCommand::BraceGroup(body) => self.run_list(body),
+            CompoundCommand::If(clause) => self.run_if(clause),
+        }
+    }
+
+    /// Run `pipeline` and report how long it took on stderr, in the format
+    /// `timing` and `$TIMEFORMAT` call for. An empty `TIMEFORMAT` turns the
+    /// report off.
+    fn run_timed(&mut self, pipeline: &Pipeline, timing: Timing, command_text: &str) -> ExecutionAction {
+        let started = Instant::now();
+        let before = times::cpu_times();
+        let action = self.run_pipeline(pipeline, false, command_text);
+        let cpu = times::cpu_times().since(before);
+        let real = started.elapsed();
+
+        let format = match timing {
+            Timing::Posix => times::POSIX_TIMEFORMAT,
+            Timing::Format => self.variables.get("TIMEFORMAT").unwrap_or(times::DEFAULT_TIMEFORMAT),
+        };
+        if !format.is_empty() {
+            let report = times::format_report(format, real, cpu);
+            match self.streams.stderr_writer() {
+                Ok(mut stderr) => {
+                    let _ = writeln!(stderr, "{report}");
+                }
+                Err(msg) => eprintln!("{msg}"),
+            }
+        }
+        action
+    }
+
+    /// Run the first branch whose condition exits 0, or the `else` body.
+    ///
+    /// As in POSIX shells, the status is that of the body that ran, or 0 when
+    /// no branch was taken.
+    fn run_if(&mut self, clause: &IfClause) -> ExecutionAction {
+        for branch in &clause.branches {
+            self.errexit_ignored += 1;
+            let action = self.run_list(&branch.condition);
+            self.errexit_ignored -= 1;
+            if let ExecutionAction::Exit(code) = action {
+                return ExecutionAction::Exit(code);
+            }
+            if self.returning {
+                return action;
+            }
+            if self.last_exit_code == 0 {
+                return self.run_list(&branch.body);
+            }
+        }
+
+        match &clause.else_body {
+            Some(body) => self.run_list(body),
+            None => ExecutionAction::Continue(0),
+        }
+    }
+
+    /// Print a command about to run for `set -x`, prefixed with `$PS4`.
+    ///
+    /// `$PS4` is expanded like a double-quoted word, and its first
+    /// character is repeated once more for each level of subshell, so
+    /// `$(...)` traces as `++`. The line goes to the descriptor named by
+    /// `$JSH_XTRACEFD`, or the current stderr if that is unset or not open.
+    fn trace(&mut self, words: &[String]) {
+        let ps4 = self.variables.get("PS4").unwrap_or("+ ").to_string();
+        let ps4 = expander::expand_assignment_value(&[parser::WordSegment::DoubleQuoted(ps4.as_str().into())], self)
+            .unwrap_or(ps4);
+        let mut chars = ps4.chars();
+        let prefix = match chars.next() {
+            Some(first) => {
+                let depth = executor::subshell_depth() as usize;
+                format!("{}{}", first.to_string().repeat(depth + 1), chars.as_str())
+            }
+            None => String::new(),
+        };
+
+        let fd = self.variables.get("JSH_XTRACEFD").and_then(|fd| fd.parse().ok());
+        let writer = match fd {
+            Some(fd) => self.streams.output_writer(fd),
+            None => self.streams.stderr_writer(),
+        };
+        match writer.or_else(|_| self.streams.stderr_writer()) {
+            Ok(mut writer) => {
+                let _ = writeln!(writer, "{prefix}{}", words.join(" "));
+                let _ = writer.flush();
+            }
+            Err(_) => eprintln!("{prefix}{}", words.join(" ")),
+        }
+    }
+}
+
+/// Make `dir`, a `set -o bglog=DIR` directory, if it is missing, closed to
+/// other users as the default one is.
+fn log_directory(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Quote `word` for a `set -x` trace line or a subshell's script, unless it
+/// reads back as the same single word without quotes.
+pub(crate) fn quote_word(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "_-./:=,@%+".contains(c)) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Print a syntax error found on `line` of the input called `name`, with a
+/// caret under the offending token when its position is known.
+fn report_syntax_error(name: &str, line: usize, error: &JshError) {
+    let message = error.to_string();
+    let message = message.strip_prefix("jsh: ").unwrap_or(&message);
+    eprintln!("jsh: {name}: line {line}: {message}");
+    if let Some(caret) = error.caret() {
+        eprintln!("{caret}");
+    }
+}
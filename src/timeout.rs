@@ -0,0 +1,236 @@
+/// The `timeout` builtin: run a command, and signal it if it runs too long.
+///
+/// Unlike coreutils `timeout`, the command runs on the shell's own
+/// foreground path, so it keeps the terminal, Ctrl-Z moves it to the job
+/// table, and it works on Windows too. A watchdog thread waits out the
+/// duration and then signals the command's process group (on Windows,
+/// terminates the process).
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::traps;
+
+/// Exit status when the command timed out, as in coreutils.
+pub const TIMED_OUT: i32 = 124;
+
+/// Exit status when `timeout` itself fails, e.g. on bad usage.
+pub const FAILED: i32 = 125;
+
+pub const USAGE: &str = "timeout: usage: timeout [-s signal] [-k duration] duration command [args...]";
+
+#[cfg(unix)]
+const TERM: i32 = libc::SIGTERM;
+#[cfg(not(unix))]
+const TERM: i32 = 15;
+
+#[cfg(unix)]
+const KILL: i32 = libc::SIGKILL;
+#[cfg(not(unix))]
+const KILL: i32 = 9;
+
+/// How long a command may run, and how to stop it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeLimit {
+    /// How long to wait before signalling; zero means no limit.
+    pub duration: Duration,
+    /// The signal to send when it runs out: `-s`, default TERM.
+    pub signal: i32,
+    /// `-k`: send KILL this long after `signal` if the command is still
+    /// running.
+    pub kill_after: Option<Duration>,
+}
+
+/// Parse the arguments of `timeout` into the limit and the command words.
+pub fn parse(args: &[String]) -> Result<(TimeLimit, &[String]), String> {
+    let mut signal = TERM;
+    let mut kill_after = None;
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        match arg.as_str() {
+            "--" => {
+                i += 1;
+                break;
+            }
+            "-s" | "-k" => {
+                let value = args.get(i + 1).ok_or_else(|| format!("timeout: {arg}: option requires an argument"))?;
+                if arg == "-s" {
+                    signal = parse_signal(value)?;
+                } else {
+                    kill_after = Some(parse_duration(value)?);
+                }
+                i += 2;
+            }
+            option if option.starts_with("-s") => {
+                signal = parse_signal(&option[2..])?;
+                i += 1;
+            }
+            option if option.starts_with("-k") => {
+                kill_after = Some(parse_duration(&option[2..])?);
+                i += 1;
+            }
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(format!("timeout: {option}: invalid option"));
+            }
+            _ => break,
+        }
+    }
+    let [duration, command @ ..] = &args[i..] else {
+        return Err(USAGE.to_string());
+    };
+    if command.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let limit = TimeLimit {
+        duration: parse_duration(duration)?,
+        signal,
+        kill_after,
+    };
+    Ok((limit, command))
+}
+
+/// A duration in seconds, which may be fractional and may end in `s`, `m`,
+/// `h`, or `d` for seconds, minutes, hours, or days.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, scale) = match text.char_indices().last() {
+        Some((at, 's')) => (&text[..at], 1.0),
+        Some((at, 'm')) => (&text[..at], 60.0),
+        Some((at, 'h')) => (&text[..at], 3600.0),
+        Some((at, 'd')) => (&text[..at], 86400.0),
+        _ => (text, 1.0),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0 && !number.starts_with('+'))
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds * scale).ok())
+        .ok_or_else(|| format!("timeout: {text}: invalid time interval"))
+}
+
+fn parse_signal(spec: &str) -> Result<i32, String> {
+    traps::parse_kill_signal(spec)
+        .filter(|&signal| signal != 0)
+        .ok_or_else(|| format!("timeout: {spec}: invalid signal"))
+}
+
+/// The exit status of a command that was sent `signal` when it timed out.
+/// KILL cannot be caught, so it is reported as a death by that signal.
+pub fn status(signal: i32) -> i32 {
+    if signal == KILL { 128 + KILL } else { TIMED_OUT }
+}
+
+/// A running watch on one command. Dropping it without [`Watchdog::finish`]
+/// leaves the thread to signal the command when the time runs out.
+pub(crate) struct Watchdog {
+    done: Sender<()>,
+    thread: JoinHandle<Option<i32>>,
+}
+
+impl TimeLimit {
+    /// Start watching the process `pid`, leader of process group `pgid`.
+    pub(crate) fn watch(&self, pid: u32, pgid: u32) -> Watchdog {
+        let (done, finished) = mpsc::channel::<()>();
+        let limit = self.clone();
+        let thread = std::thread::spawn(move || {
+            if limit.duration.is_zero() || finished.recv_timeout(limit.duration) != Err(RecvTimeoutError::Timeout) {
+                return None;
+            }
+            stop(pid, pgid, limit.signal);
+            let Some(grace) = limit.kill_after else {
+                return Some(limit.signal);
+            };
+            if finished.recv_timeout(grace) != Err(RecvTimeoutError::Timeout) {
+                return Some(limit.signal);
+            }
+            stop(pid, pgid, KILL);
+            Some(KILL)
+        });
+        Watchdog { done, thread }
+    }
+}
+
+impl Watchdog {
+    /// Call off the watch once the command has finished. Returns the last
+    /// signal sent to it, if it timed out.
+    pub(crate) fn finish(self) -> Option<i32> {
+        drop(self.done);
+        self.thread.join().ok().flatten()
+    }
+}
+
+/// Send `signal` to the process group, waking it in case it is stopped.
+#[cfg(unix)]
+fn stop(_pid: u32, pgid: u32, signal: i32) {
+    use crate::job_control;
+
+    let pgid = pgid as libc::pid_t;
+    let _ = job_control::send_signal(-pgid, signal);
+    if signal != KILL {
+        let _ = job_control::send_continue_to_group(pgid);
+    }
+}
+
+/// Windows has no signals: whatever was asked for, terminate the process.
+#[cfg(windows)]
+fn stop(pid: u32, _pgid: u32, signal: i32) {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_TERMINATE, TerminateProcess};
+
+    // SAFETY: the handle is checked before use and closed once.
+    unsafe {
+        let process = OpenProcess(PROCESS_TERMINATE, 0, pid);
+        if process.is_null() {
+            return;
+        }
+        TerminateProcess(process, (128 + signal) as u32);
+        CloseHandle(process);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stop(_pid: u32, _pgid: u32, _signal: i32) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn durations_take_a_unit_suffix() {
+        assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("0.5s"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("-1").unwrap_err(), "timeout: -1: invalid time interval");
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn options_come_before_the_duration() {
+        let args = words(&["-s", "INT", "-k5", "1m", "sleep", "-k", "90"]);
+        let (limit, command) = parse(&args).unwrap();
+        assert_eq!(limit.duration, Duration::from_secs(60));
+        assert_eq!(limit.signal, traps::parse_kill_signal("INT").unwrap());
+        assert_eq!(limit.kill_after, Some(Duration::from_secs(5)));
+        assert_eq!(command, ["sleep", "-k", "90"]);
+
+        let args = words(&["3", "true"]);
+        let (limit, _) = parse(&args).unwrap();
+        assert_eq!((limit.signal, limit.kill_after), (TERM, None));
+
+        assert_eq!(parse(&words(&["3"])).unwrap_err(), USAGE);
+        assert_eq!(parse(&words(&["-s", "BOGUS", "3", "true"])).unwrap_err(), "timeout: BOGUS: invalid signal");
+        assert_eq!(parse(&words(&["-q", "3", "true"])).unwrap_err(), "timeout: -q: invalid option");
+    }
+
+    #[test]
+    fn killed_commands_report_the_signal() {
+        assert_eq!(status(TERM), TIMED_OUT);
+        assert_eq!(status(KILL), 137);
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+/// The report format when `TIMEFORMAT` is unset, as in bash.
+pub const DEFAULT_TIMEFORMAT: &str = "\nreal\t%3lR\nuser\t%3lU\nsys\t%3lS";
+
+/// The report format for `time -p`, as POSIX specifies.
+pub const POSIX_TIMEFORMAT: &str = "real %2R\nuser %2U\nsys %2S";
+
+/// CPU time used so far by the shell and the children it has waited for.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuTimes {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+impl CpuTimes {
+    /// Time used since `earlier`.
+    pub fn since(self, earlier: CpuTimes) -> CpuTimes {
+        CpuTimes {
+            user: self.user.saturating_sub(earlier.user),
+            system: self.system.saturating_sub(earlier.system),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn cpu_times() -> CpuTimes {
+    let usage = |who| {
+        // SAFETY: rusage is plain data that getrusage fills in.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        // SAFETY: the pointer is valid for the duration of the call.
+        unsafe { libc::getrusage(who, &mut usage) };
+        usage
+    };
+    let duration = |time: libc::timeval| Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64);
+    let (shell, children) = (usage(libc::RUSAGE_SELF), usage(libc::RUSAGE_CHILDREN));
+    CpuTimes {
+        user: duration(shell.ru_utime) + duration(children.ru_utime),
+        system: duration(shell.ru_stime) + duration(children.ru_stime),
+    }
+}
+
+/// Windows only reports the shell's own time; child processes are not
+/// counted.
+#[cfg(windows)]
+pub fn cpu_times() -> CpuTimes {
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    let zero = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    let (mut creation, mut exit, mut kernel, mut user) = (zero, zero, zero, zero);
+    // SAFETY: the pseudo-handle is always valid and the out-pointers live for
+    // the duration of the call.
+    if unsafe { GetProcessTimes(GetCurrentProcess(), &mut creation, &mut exit, &mut kernel, &mut user) } == 0 {
+        return CpuTimes::default();
+    }
+    // FILETIME counts 100-nanosecond intervals.
+    let duration = |time: FILETIME| {
+        Duration::from_nanos((((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64) * 100)
+    };
+    CpuTimes {
+        user: duration(user),
+        system: duration(kernel),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn cpu_times() -> CpuTimes {
+    CpuTimes::default()
+}
+
+/// Expand a bash `TIMEFORMAT` string. `%R`, `%U`, and `%S` are the real,
+/// user, and system times in seconds, optionally preceded by the number of
+/// decimal places (`%2R`, default 3) and `l` for minutes-and-seconds
+/// (`%lR` gives `0m1.250s`). `%P` is the CPU percentage and `%%` a literal
+/// `%`.
+pub fn format_report(format: &str, real: Duration, cpu: CpuTimes) -> String {
+    let mut report = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            report.push(c);
+            continue;
+        }
+        let mut precision = 3;
+        if let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            precision = digit.min(3) as usize;
+            chars.next();
+        }
+        let long = chars.next_if_eq(&'l').is_some();
+        let value = match chars.next() {
+            Some('R') => real,
+            Some('U') => cpu.user,
+            Some('S') => cpu.system,
+            Some('P') => {
+                let used = (cpu.user + cpu.system).as_secs_f64();
+                let percent = if real.is_zero() { 0.0 } else { used * 100.0 / real.as_secs_f64() };
+                report.push_str(&format!("{percent:.2}"));
+                continue;
+            }
+            Some('%') => {
+                report.push('%');
+                continue;
+            }
+            Some(other) => {
+                report.push('%');
+                report.push(other);
+                continue;
+            }
+            None => {
+                report.push('%');
+                continue;
+            }
+        };
+        let seconds = value.as_secs_f64();
+        if long {
+            let minutes = (seconds / 60.0).floor();
+            report.push_str(&format!("{minutes}m{:.precision$}s", seconds - minutes * 60.0));
+        } else {
+            report.push_str(&format!("{seconds:.precision$}"));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_follow_timeformat() {
+        let cpu = CpuTimes {
+            user: Duration::from_millis(250),
+            system: Duration::from_millis(50),
+        };
+        let real = Duration::from_millis(61_500);
+        assert_eq!(
+            format_report(DEFAULT_TIMEFORMAT, real, cpu),
+            "\nreal\t1m1.500s\nuser\t0m0.250s\nsys\t0m0.050s"
+        );
+        assert_eq!(format_report(POSIX_TIMEFORMAT, real, cpu), "real 61.50\nuser 0.25\nsys 0.05");
+        assert_eq!(format_report("%0R %P%% %q", real, cpu), "62 0.49% %q");
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Signals that can be trapped, as `(name, number)` pairs in listing order.
+#[cfg(unix)]
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+];
+#[cfg(not(unix))]
+pub const SIGNALS: &[(&str, i32)] = &[("INT", 2), ("TERM", 15)];
+
+/// Every signal `kill` can send by name, as `(name, number)` pairs in number
+/// order. [`SIGNALS`] is the trappable subset.
+#[cfg(unix)]
+pub const ALL_SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("SYS", libc::SIGSYS),
+];
+#[cfg(not(unix))]
+pub const ALL_SIGNALS: &[(&str, i32)] = &[
+    ("INT", 2),
+    ("KILL", 9),
+    ("TERM", 15),
+    ("CONT", 18),
+    ("STOP", 19),
+    ("TSTP", 20),
+];
+
+/// The pseudo-signal whose trap runs when the shell exits.
+pub const EXIT: i32 = 0;
+
+/// Signals received but whose traps have not run yet, one bit per number.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// Note that `signal` arrived. Safe to call from a signal handler.
+pub fn record_signal(signal: i32) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+}
+
+/// Set by a Ctrl-C and cleared before each command line, so the rest of an
+/// interrupted line is skipped.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Note a Ctrl-C, whether it arrived as SIGINT or as a key press at the
+/// prompt.
+pub fn record_interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    if let Some(&(_, signal)) = SIGNALS.iter().find(|(name, _)| *name == "INT") {
+        record_signal(signal);
+    }
+}
+
+/// Whether a Ctrl-C arrived since [`clear_interrupted`].
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Forget earlier Ctrl-Cs, before a new command line starts.
+pub fn clear_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Take the signals received since the last call, in ascending order.
+fn take_pending() -> Vec<i32> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    (1..64).filter(|signal| pending & (1 << signal) != 0).collect()
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(signal: libc::c_int) {
+    record_signal(signal);
+}
+
+/// The number of a signal given as a name (`INT`, `SIGINT`, `int`), a number,
+/// or `EXIT`.
+pub fn parse_signal(spec: &str) -> Option<i32> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return (number == EXIT || SIGNALS.iter().any(|&(_, signal)| signal == number)).then_some(number);
+    }
+    let name = spec.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    if name == "EXIT" {
+        return Some(EXIT);
+    }
+    SIGNALS
+        .iter()
+        .find(|(signal_name, _)| *signal_name == name)
+        .map(|&(_, signal)| signal)
+}
+
+/// The name of a signal number, e.g. `EXIT` or `INT`.
+pub fn signal_name(signal: i32) -> &'static str {
+    if signal == EXIT {
+        return "EXIT";
+    }
+    SIGNALS
+        .iter()
+        .find(|&&(_, number)| number == signal)
+        .map(|&(name, _)| name)
+        .unwrap_or("?")
+}
+
+/// The number of any signal in [`ALL_SIGNALS`], given as a name or number.
+/// `0` checks that a process exists without signalling it.
+pub fn parse_kill_signal(spec: &str) -> Option<i32> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return (number == 0 || ALL_SIGNALS.iter().any(|&(_, signal)| signal == number)).then_some(number);
+    }
+    let name = spec.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    ALL_SIGNALS
+        .iter()
+        .find(|(signal_name, _)| *signal_name == name)
+        .map(|&(_, signal)| signal)
+}
+
+/// The name of any signal in [`ALL_SIGNALS`], e.g. `KILL`.
+pub fn kill_signal_name(signal: i32) -> Option<&'static str> {
+    ALL_SIGNALS
+        .iter()
+        .find(|&&(_, number)| number == signal)
+        .map(|&(name, _)| name)
+}
+
+/// Commands installed with the `trap` builtin, keyed by signal number.
+///
+/// An empty action ignores the signal. Traps for real signals only record
+/// that the signal arrived; the shell runs the action between commands.
+#[derive(Debug, Clone, Default)]
+pub struct TrapTable {
+    traps: BTreeMap<i32, String>,
+}
+
+impl TrapTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, signal: i32) -> Option<&str> {
+        self.traps.get(&signal).map(String::as_str)
+    }
+
+    /// Install `action` for `signal`.
+    pub fn set(&mut self, signal: i32, action: &str) {
+        self.traps.insert(signal, action.to_string());
+        if signal != EXIT {
+            // A signal received before the trap existed must not fire it.
+            PENDING.fetch_and(!(1 << signal), Ordering::SeqCst);
+            #[cfg(unix)]
+            // SAFETY: handle_signal only performs an atomic update.
+            unsafe {
+                let handler = if action.is_empty() {
+                    libc::SIG_IGN
+                } else {
+                    handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t
+                };
+                install_handler(signal, handler);
+            }
+        }
+    }
+
+    /// Remove the trap for `signal`, restoring the shell's default handling.
+    pub fn reset(&mut self, signal: i32) {
+        self.traps.remove(&signal);
+        #[cfg(unix)]
+        if signal != EXIT {
+            // SAFETY: restores the disposition the shell starts with.
+            unsafe { install_handler(signal, default_disposition(signal)) };
+        }
+    }
+
+    /// Remove and return the `EXIT` trap, so it runs only once.
+    pub fn take_exit(&mut self) -> Option<String> {
+        self.traps.remove(&EXIT)
+    }
+
+    /// The actions of trapped signals received since the last call.
+    pub fn take_pending(&self) -> Vec<String> {
+        take_pending()
+            .into_iter()
+            .filter_map(|signal| self.get(signal))
+            .filter(|action| !action.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// All traps as `(signal, action)` pairs, in signal order.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &str)> {
+        self.traps.iter().map(|(&signal, action)| (signal, action.as_str()))
+    }
+}
+
+/// How the shell handles `signal` when it is not trapped.
+#[cfg(unix)]
+fn default_disposition(signal: i32) -> libc::sighandler_t {
+    // main() discards SIGQUIT so Ctrl-\ cannot kill the shell. SIGINT is
+    // owned by the Ctrl-C handler, which already records it.
+    match signal {
+        libc::SIGQUIT => crate::job_control::discarding_handler(),
+        _ => libc::SIG_DFL,
+    }
+}
+
+#[cfg(unix)]
+unsafe fn install_handler(signal: i32, handler: libc::sighandler_t) {
+    // SIGINT keeps the Ctrl-C handler installed in main(), which records the
+    // signal for traps itself; replacing it would break line editing.
+    if signal != libc::SIGINT {
+        // SAFETY: the caller passes a valid disposition for a trappable signal.
+        unsafe { libc::signal(signal, handler) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_specs() {
+        assert_eq!(parse_signal("EXIT"), Some(EXIT));
+        assert_eq!(parse_signal("0"), Some(EXIT));
+        assert_eq!(parse_signal("sigint"), parse_signal("INT"));
+        assert_eq!(signal_name(parse_signal("TERM").unwrap()), "TERM");
+        assert_eq!(parse_signal("NOPE"), None);
+        assert_eq!(parse_signal("999"), None);
+    }
+
+    #[test]
+    fn kill_signal_specs_include_untrappable_signals() {
+        assert_eq!(parse_signal("KILL"), None);
+        let kill = parse_kill_signal("sigkill").unwrap();
+        assert_eq!(kill_signal_name(kill), Some("KILL"));
+        assert_eq!(parse_kill_signal("0"), Some(0));
+        assert_eq!(parse_kill_signal("EXIT"), None);
+    }
+}
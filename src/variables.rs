@@ -0,0 +1,545 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// One shell variable and its attributes.
+#[derive(Debug, Clone, Default)]
+pub struct Variable {
+    pub value: String,
+    /// Exported variables are passed to child processes.
+    pub exported: bool,
+    /// `readonly` / `declare -r`: assigning or unsetting it is an error.
+    pub readonly: bool,
+    /// `declare -i`: assigned values are evaluated as integer expressions.
+    pub integer: bool,
+    /// `declare -A`: an associative array, whose elements are read with
+    /// `${name[key]}`. `value` is unused.
+    pub assoc: Option<BTreeMap<String, String>>,
+}
+
+impl Variable {
+    /// The attribute letters `declare -p` shows, e.g. `-rx`, or `--` for
+    /// none.
+    pub fn flags(&self) -> String {
+        let flags: String = [
+            (self.assoc.is_some(), 'A'),
+            (self.integer, 'i'),
+            (self.readonly, 'r'),
+            (self.exported, 'x'),
+        ]
+            .into_iter()
+            .filter_map(|(on, flag)| on.then_some(flag))
+            .collect();
+        if flags.is_empty() { "--".to_string() } else { format!("-{flags}") }
+    }
+}
+
+/// The shell's variable table.
+///
+/// Every variable the shell knows about lives here, exported or not; the
+/// expander reads only this table. The process environment is read once,
+/// by [`from_env`](Self::from_env), and never written: each spawned command
+/// is given the exported variables from [`environment`](Self::environment)
+/// as its whole environment, so several shells can live in one process.
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    vars: HashMap<String, Variable>,
+    /// One frame per active function call: what each name made `local` in
+    /// it held before, put back when the frame is popped.
+    scopes: Vec<HashMap<String, Option<Variable>>>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A table holding the process environment, every entry exported.
+    /// Entries that are not valid Unicode are left out.
+    pub fn from_env() -> Self {
+        let vars = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .map(|(name, value)| {
+                (
+                    name,
+                    Variable {
+                        value,
+                        exported: true,
+                        ..Variable::default()
+                    },
+                )
+            })
+            .collect();
+        Self {
+            vars,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// The exported variables as `(name, value)` pairs: the environment
+    /// of a command the shell runs.
+    pub fn environment(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+    }
+
+    /// The value of `name`; for an associative array, `$name` is its
+    /// element `0`, as in bash.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let var = self.vars.get(name)?;
+        match &var.assoc {
+            Some(map) => map.get("0").map(String::as_str),
+            None => Some(var.value.as_str()),
+        }
+    }
+
+    /// The element `key` of the associative array `name`.
+    pub fn element(&self, name: &str, key: &str) -> Option<&str> {
+        self.vars.get(name)?.assoc.as_ref()?.get(key).map(String::as_str)
+    }
+
+    /// The elements of the associative array `name` as `(key, value)`
+    /// pairs, sorted by key. A plain variable is a one-element array with
+    /// key `0`.
+    pub fn elements(&self, name: &str) -> Vec<(&str, &str)> {
+        match self.vars.get(name) {
+            Some(Variable { assoc: Some(map), .. }) => {
+                map.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect()
+            }
+            Some(var) => vec![("0", var.value.as_str())],
+            None => Vec::new(),
+        }
+    }
+
+    /// Make `name` an associative array (`declare -A`). A plain variable's
+    /// value becomes its element `0`.
+    pub fn make_assoc(&mut self, name: &str) -> Result<(), String> {
+        if self.is_readonly(name) {
+            return Err(format!("jsh: {name}: readonly variable"));
+        }
+        if self.is_exported(name) {
+            return Err(format!("jsh: {name}: cannot make an exported variable an array"));
+        }
+        let var = self.vars.entry(name.to_string()).or_default();
+        if var.assoc.is_none() {
+            let value = std::mem::take(&mut var.value);
+            let old = (!value.is_empty()).then(|| ("0".to_string(), value));
+            var.assoc = Some(old.into_iter().collect());
+        }
+        Ok(())
+    }
+
+    /// `name[key]=value`. `name` must have been declared with `declare -A`.
+    pub fn assign_element(&mut self, name: &str, key: &str, value: &str) -> Result<(), String> {
+        if self.is_readonly(name) {
+            return Err(format!("jsh: {name}: readonly variable"));
+        }
+        let value = match self.vars.get(name).is_some_and(|var| var.integer) {
+            true => self.integer_value(value)?.to_string(),
+            false => value.to_string(),
+        };
+        match self.vars.get_mut(name).and_then(|var| var.assoc.as_mut()) {
+            Some(map) => {
+                map.insert(key.to_string(), value);
+                Ok(())
+            }
+            None => Err(format!("jsh: {name}: not an associative array (declare -A {name} first)")),
+        }
+    }
+
+    /// Remove `name[key]`, returning false if there was no such element.
+    pub fn unset_element(&mut self, name: &str, key: &str) -> bool {
+        self.vars
+            .get_mut(name)
+            .and_then(|var| var.assoc.as_mut())
+            .is_some_and(|map| map.remove(key).is_some())
+    }
+
+    /// The variable with its attributes, e.g. to save it before a
+    /// temporary assignment.
+    pub fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    /// Put back a variable saved with [`lookup`](Self::lookup), removing it
+    /// if it was not set.
+    pub fn restore(&mut self, name: &str, saved: Option<Variable>) {
+        self.vars.remove(name);
+        if let Some(var) = saved {
+            self.vars.insert(name.to_string(), var);
+        }
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.vars.get(name).is_some_and(|var| var.exported)
+    }
+
+    pub fn is_readonly(&self, name: &str) -> bool {
+        self.vars.get(name).is_some_and(|var| var.readonly)
+    }
+
+    /// Assign `value`, keeping the variable's attributes. New variables are
+    /// not exported. This is the shell's own assignment and ignores
+    /// `readonly`; assignments the user writes go through
+    /// [`assign`](Self::assign).
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.vars.entry(name.to_string()).or_default().value = value.to_string();
+    }
+
+    /// Assign `value` as a user assignment: an error for a readonly
+    /// variable, and evaluated as an integer expression for a `declare -i`
+    /// one.
+    pub fn assign(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if self.is_readonly(name) {
+            return Err(format!("jsh: {name}: readonly variable"));
+        }
+        if self.vars.get(name).is_some_and(|var| var.assoc.is_some()) {
+            return self.assign_element(name, "0", value);
+        }
+        match self.vars.get(name).is_some_and(|var| var.integer) {
+            true => {
+                let value = self.integer_value(value)?;
+                self.set(name, &value.to_string());
+            }
+            false => self.set(name, value),
+        }
+        Ok(())
+    }
+
+    /// Mark `name` readonly, creating it empty if it is not set.
+    pub fn set_readonly(&mut self, name: &str) {
+        self.vars.entry(name.to_string()).or_default().readonly = true;
+    }
+
+    /// Turn `declare -i` on or off for `name`, creating it empty if it is
+    /// not set.
+    pub fn set_integer(&mut self, name: &str, on: bool) {
+        self.vars.entry(name.to_string()).or_default().integer = on;
+    }
+
+    /// Evaluate `expr` as `declare -i` does: integers and variable names
+    /// combined with `+ - * / %` and unary minus, `*` `/` `%` binding
+    /// tighter. A name that is unset or not a number counts as 0.
+    pub fn integer_value(&self, expr: &str) -> Result<i64, String> {
+        let syntax_error = || format!("jsh: {}: arithmetic syntax error", expr.trim());
+        let mut tokens = Vec::new();
+        let mut chars = expr.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_alphanumeric() || c == '_' {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    word.push(c);
+                }
+                let number = match word.parse::<i64>() {
+                    Ok(number) => number,
+                    Err(_) if is_valid_name(&word) => {
+                        self.get(&word).and_then(|value| value.trim().parse().ok()).unwrap_or(0)
+                    }
+                    Err(_) => return Err(syntax_error()),
+                };
+                tokens.push(IntegerToken::Number(number));
+            } else if "+-*/%".contains(c) {
+                tokens.push(IntegerToken::Operator(c));
+                chars.next();
+            } else {
+                return Err(syntax_error());
+            }
+        }
+
+        // Sum of terms, each a product of signed numbers.
+        let mut tokens = tokens.into_iter().peekable();
+        let factor = |tokens: &mut std::iter::Peekable<std::vec::IntoIter<IntegerToken>>| {
+            let mut sign = 1;
+            loop {
+                match tokens.next() {
+                    Some(IntegerToken::Operator('-')) => sign = -sign,
+                    Some(IntegerToken::Operator('+')) => {}
+                    Some(IntegerToken::Number(number)) => return Ok(sign * number),
+                    _ => return Err(syntax_error()),
+                }
+            }
+        };
+        let mut total = 0i64;
+        let mut term_sign = 1;
+        loop {
+            let mut term = factor(&mut tokens)?;
+            while let Some(IntegerToken::Operator(op @ ('*' | '/' | '%'))) = tokens.peek().copied() {
+                tokens.next();
+                let rhs = factor(&mut tokens)?;
+                term = match op {
+                    '*' => term.wrapping_mul(rhs),
+                    _ if rhs == 0 => return Err(format!("jsh: {}: division by 0", expr.trim())),
+                    '/' => term.wrapping_div(rhs),
+                    _ => term.wrapping_rem(rhs),
+                };
+            }
+            total = total.wrapping_add(term_sign * term);
+            match tokens.next() {
+                None => return Ok(total),
+                Some(IntegerToken::Operator('+')) => term_sign = 1,
+                Some(IntegerToken::Operator('-')) => term_sign = -1,
+                _ => return Err(syntax_error()),
+            }
+        }
+    }
+
+    /// Mark `name` for export, assigning `value` first when given. Arrays
+    /// are never exported. Exporting
+    /// a name that has no value creates it as an empty exported variable.
+    pub fn export(&mut self, name: &str, value: Option<&str>) {
+        if self.vars.get(name).is_some_and(|var| var.assoc.is_some()) {
+            return;
+        }
+        let value = match value {
+            Some(value) => value.to_string(),
+            None => self.get(name).unwrap_or_default().to_string(),
+        };
+        let var = self.vars.entry(name.to_string()).or_default();
+        var.value = value;
+        var.exported = true;
+    }
+
+    /// Stop exporting `name`, keeping its value in the shell.
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = false;
+        }
+    }
+
+    /// Start a function call's scope for `local` variables.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// End the innermost scope, putting back every variable made local in
+    /// it.
+    pub fn pop_scope(&mut self) {
+        if let Some(saved) = self.scopes.pop() {
+            for (name, var) in saved {
+                self.restore(&name, var);
+            }
+        }
+    }
+
+    /// Make `name` local to the innermost scope: it starts out unset and
+    /// its current value comes back when the scope ends.
+    pub fn make_local(&mut self, name: &str) -> Result<(), String> {
+        let current = self.vars.get(name).cloned();
+        let Some(scope) = self.scopes.last_mut() else {
+            return Err("can only be used in a function".to_string());
+        };
+        if !scope.contains_key(name) {
+            scope.insert(name.to_string(), current);
+            self.restore(name, None);
+        }
+        Ok(())
+    }
+
+    /// Remove a variable, returning false if it was not set.
+    pub fn unset(&mut self, name: &str) -> bool {
+        self.vars.remove(name).is_some()
+    }
+
+    /// All variables as `(name, variable)` pairs, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Variable)> {
+        let mut vars: Vec<_> = self.vars.iter().map(|(name, var)| (name.as_str(), var)).collect();
+        vars.sort_by_key(|(name, _)| *name);
+        vars.into_iter()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum IntegerToken {
+    Number(i64),
+    Operator(char),
+}
+
+/// Variable names are a letter or underscore followed by letters, digits,
+/// and underscores.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split `name[key]` into `name` and `key`, for array element
+/// assignments and `unset`.
+pub fn split_subscript(word: &str) -> Option<(&str, &str)> {
+    let (name, rest) = word.split_once('[')?;
+    let key = rest.strip_suffix(']')?;
+    is_valid_name(name).then_some((name, key))
+}
+
+/// Render an associative array the way `declare -p` shows it:
+/// `name=([key]='value' ...)`.
+pub fn format_assoc(name: &str, elements: &[(&str, &str)]) -> String {
+    let elements: Vec<String> = elements
+        .iter()
+        .map(|(key, value)| format!("[{key}]='{}'", value.replace('\'', r"'\''")))
+        .collect();
+    format!("{name}=({})", elements.join(" "))
+}
+
+/// Render an assignment that recreates one element of an associative array
+/// when run by a shell: `name[key]='value'`. A key the subscript would not
+/// read back as written goes through a temporary variable instead.
+pub fn format_element_assignment(name: &str, key: &str, value: &str) -> String {
+    let value = value.replace('\'', r"'\''");
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || "_-./:,@%+".contains(c)) {
+        return format!("{name}[{key}]='{value}'");
+    }
+    format!(
+        "{KEY_VARIABLE}='{}'; {name}[${KEY_VARIABLE}]='{value}'; unset {KEY_VARIABLE}",
+        key.replace('\'', r"'\''")
+    )
+}
+
+/// The temporary variable [`format_element_assignment`] passes keys in.
+const KEY_VARIABLE: &str = "__jsh_key";
+
+/// Render an assignment that recreates the variable when run by a shell:
+/// `name='value'`.
+pub fn format_assignment(name: &str, value: &str) -> String {
+    format!("{name}='{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_does_not_export_new_variables() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_LOCAL", "one");
+        assert_eq!(vars.get("JSH_VARS_LOCAL"), Some("one"));
+        assert!(!vars.is_exported("JSH_VARS_LOCAL"));
+        assert_eq!(vars.environment().count(), 0);
+    }
+
+    #[test]
+    fn export_promotes_and_assignment_follows() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_PROMOTE", "one");
+        vars.export("JSH_VARS_PROMOTE", None);
+        assert_eq!(vars.environment().collect::<Vec<_>>(), vec![("JSH_VARS_PROMOTE", "one")]);
+
+        vars.set("JSH_VARS_PROMOTE", "two");
+        assert_eq!(vars.environment().collect::<Vec<_>>(), vec![("JSH_VARS_PROMOTE", "two")]);
+
+        assert!(vars.unset("JSH_VARS_PROMOTE"));
+        assert_eq!(vars.environment().count(), 0);
+        assert!(!vars.unset("JSH_VARS_PROMOTE"));
+    }
+
+    #[test]
+    fn restore_undoes_a_temporary_export() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_TEMP", "shell");
+        let saved = vars.lookup("JSH_VARS_TEMP").cloned();
+
+        vars.export("JSH_VARS_TEMP", Some("temporary"));
+        vars.restore("JSH_VARS_TEMP", saved);
+        assert_eq!(vars.get("JSH_VARS_TEMP"), Some("shell"));
+        assert!(!vars.is_exported("JSH_VARS_TEMP"));
+        assert_eq!(vars.environment().count(), 0);
+
+        vars.export("JSH_VARS_TEMP_NEW", Some("temporary"));
+        vars.restore("JSH_VARS_TEMP_NEW", None);
+        assert_eq!(vars.get("JSH_VARS_TEMP_NEW"), None);
+        assert_eq!(vars.environment().count(), 0);
+    }
+
+    #[test]
+    fn exports_leave_the_process_environment_alone() {
+        let mut vars = Variables::from_env();
+        vars.export("JSH_VARS_PROCESS", Some("shell"));
+        assert!(vars.environment().any(|entry| entry == ("JSH_VARS_PROCESS", "shell")));
+        assert!(std::env::var("JSH_VARS_PROCESS").is_err());
+    }
+
+    #[test]
+    fn valid_names() {
+        assert!(is_valid_name("_foo1"));
+        assert!(is_valid_name("PATH"));
+        assert!(!is_valid_name("1foo"));
+        assert!(!is_valid_name("foo-bar"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn assignments_are_quoted() {
+        assert_eq!(format_assignment("x", "it's"), r"x='it'\''s'");
+    }
+
+    #[test]
+    fn readonly_variables_reject_assignment() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_RO", "fixed");
+        vars.set_readonly("JSH_VARS_RO");
+        assert_eq!(
+            vars.assign("JSH_VARS_RO", "changed"),
+            Err("jsh: JSH_VARS_RO: readonly variable".to_string())
+        );
+        assert_eq!(vars.get("JSH_VARS_RO"), Some("fixed"));
+        assert_eq!(vars.lookup("JSH_VARS_RO").map(Variable::flags).as_deref(), Some("-r"));
+    }
+
+    #[test]
+    fn integer_variables_evaluate_assignments() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_STEP", "4");
+        vars.set_integer("JSH_VARS_INT", true);
+        vars.assign("JSH_VARS_INT", "2 + 3 * JSH_VARS_STEP - -1").unwrap();
+        assert_eq!(vars.get("JSH_VARS_INT"), Some("15"));
+        vars.assign("JSH_VARS_INT", "JSH_VARS_INT % 4 / 3").unwrap();
+        assert_eq!(vars.get("JSH_VARS_INT"), Some("1"));
+        assert_eq!(vars.integer_value("nosuchvar"), Ok(0));
+        assert!(vars.integer_value("1 +").is_err());
+        assert!(vars.integer_value("2 / 0").is_err());
+    }
+
+    #[test]
+    fn locals_are_restored_when_the_scope_ends() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_SCOPED", "global");
+        assert!(vars.make_local("JSH_VARS_SCOPED").is_err());
+
+        vars.push_scope();
+        vars.make_local("JSH_VARS_SCOPED").unwrap();
+        vars.make_local("JSH_VARS_FRESH").unwrap();
+        assert_eq!(vars.get("JSH_VARS_SCOPED"), None);
+        vars.set("JSH_VARS_SCOPED", "local");
+        vars.set("JSH_VARS_FRESH", "local");
+        vars.pop_scope();
+
+        assert_eq!(vars.get("JSH_VARS_SCOPED"), Some("global"));
+        assert_eq!(vars.get("JSH_VARS_FRESH"), None);
+    }
+
+    #[test]
+    fn associative_arrays_hold_keyed_elements() {
+        let mut vars = Variables::new();
+        vars.set("JSH_VARS_MAP", "first");
+        assert!(vars.assign_element("JSH_VARS_MAP", "k", "v").is_err());
+
+        vars.make_assoc("JSH_VARS_MAP").unwrap();
+        vars.assign_element("JSH_VARS_MAP", "k", "it's").unwrap();
+        assert_eq!(vars.get("JSH_VARS_MAP"), Some("first"));
+        assert_eq!(vars.element("JSH_VARS_MAP", "k"), Some("it's"));
+        assert_eq!(vars.elements("JSH_VARS_MAP"), vec![("0", "first"), ("k", "it's")]);
+        assert_eq!(
+            format_assoc("JSH_VARS_MAP", &vars.elements("JSH_VARS_MAP")),
+            r"JSH_VARS_MAP=([0]='first' [k]='it'\''s')"
+        );
+
+        assert!(vars.unset_element("JSH_VARS_MAP", "0"));
+        assert!(!vars.unset_element("JSH_VARS_MAP", "0"));
+        assert_eq!(vars.get("JSH_VARS_MAP"), None);
+        assert_eq!(split_subscript("map[a b]"), Some(("map", "a b")));
+        assert_eq!(split_subscript("map"), None);
+    }
+}
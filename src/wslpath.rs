@@ -0,0 +1,188 @@
+/// Translating paths between Windows (`C:\Users`) and the Unix view of the
+/// same files that WSL (`/mnt/c/Users`) or MSYS (`/c/Users`) gives, for the
+/// `wslpath` builtin and `shopt -s wslpath`.
+///
+/// With the option on, a program started from the other side of the
+/// boundary gets its path arguments translated: from WSL, a Windows program
+/// (one under a drive mount, or an `.exe`) is given Windows paths; from
+/// Windows, `wsl.exe` or a program on a `\\wsl$` share is given Unix ones.
+/// Only arguments that are plainly paths are touched, so `cmd.exe /c` keeps
+/// its flag.
+use std::path::Path;
+
+use crate::variables::Variables;
+
+/// Where the two views of the file system meet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mounts {
+    /// What drive letters are mounted under: `/mnt` for WSL, empty for
+    /// MSYS, whose drives are `/c`, `/d`, ...
+    root: String,
+    /// `$WSL_DISTRO_NAME`, for naming this distribution's own files as
+    /// `\\wsl.localhost\<distro>\...` from Windows.
+    distro: Option<String>,
+}
+
+/// The form `wslpath` translates to: `-u`, `-w`, or `-m`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    /// `/mnt/c/Users`
+    Unix,
+    /// `C:\Users`
+    Windows,
+    /// `C:/Users`
+    Mixed,
+}
+
+impl Mounts {
+    pub fn new(root: &str, distro: Option<&str>) -> Self {
+        Self {
+            root: root.trim_end_matches('/').to_string(),
+            distro: distro.filter(|distro| !distro.is_empty()).map(str::to_string),
+        }
+    }
+
+    /// The mounts this shell sees: MSYS's when `$MSYSTEM` is set, else
+    /// WSL's.
+    pub fn from_variables(variables: &Variables) -> Self {
+        let root = if variables.get("MSYSTEM").is_some() { "" } else { "/mnt" };
+        Self::new(root, variables.get("WSL_DISTRO_NAME"))
+    }
+
+    /// `path` in `style`, or `None` if it has no form there, like a Linux
+    /// path when the distribution's name is not known.
+    pub fn translate(&self, path: &str, style: Style) -> Option<String> {
+        match style {
+            Style::Unix => self.to_unix(path),
+            Style::Windows => self.to_windows(path),
+            Style::Mixed => self.to_windows(path).map(|path| path.replace('\\', "/")),
+        }
+    }
+
+    fn to_unix(&self, path: &str) -> Option<String> {
+        if let Some((drive, rest)) = split_drive(path) {
+            return Some(format!("{}/{}{}", self.root, drive.to_ascii_lowercase(), rest.replace('\\', "/")));
+        }
+        if let Some(share) = wsl_share(path) {
+            // Past the distribution's name is that distribution's root.
+            let rest = share.find(['\\', '/']).map_or("", |at| &share[at..]);
+            return Some(if rest.is_empty() { "/".to_string() } else { rest.replace('\\', "/") });
+        }
+        match path.starts_with(['\\', '/']) {
+            true => None,
+            false => Some(path.replace('\\', "/")),
+        }
+    }
+
+    fn to_windows(&self, path: &str) -> Option<String> {
+        if let Some((drive, rest)) = self.mounted_drive(path) {
+            let rest = if rest.is_empty() { "\\".to_string() } else { rest.replace('/', "\\") };
+            return Some(format!("{}:{rest}", drive.to_ascii_uppercase()));
+        }
+        if !path.starts_with('/') {
+            return Some(path.replace('/', "\\"));
+        }
+        let distro = self.distro.as_ref()?;
+        Some(format!("\\\\wsl.localhost\\{distro}{}", path.replace('/', "\\")))
+    }
+
+    /// The drive letter and the rest of a path under a drive mount, like
+    /// `/mnt/c/Users`.
+    fn mounted_drive<'a>(&self, path: &'a str) -> Option<(char, &'a str)> {
+        let rest = path.strip_prefix(self.root.as_str())?.strip_prefix('/')?;
+        let mut chars = rest.chars();
+        let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+        let rest = chars.as_str();
+        (rest.is_empty() || rest.starts_with('/')).then_some((drive, rest))
+    }
+
+    /// `args` translated for `program` when it is on the other side of the
+    /// boundary from this shell, or `None` when it is on the same side.
+    pub fn translate_args(&self, program: &Path, args: &[String]) -> Option<Vec<String>> {
+        let program = program.to_string_lossy();
+        let style = match cfg!(windows) {
+            true => Self::runs_on_unix(&program).then_some(Style::Unix)?,
+            false => self.runs_on_windows(&program).then_some(Style::Windows)?,
+        };
+        let translated = args.iter().map(|arg| match self.is_path_argument(arg, style) {
+            true => self.translate(arg, style).unwrap_or_else(|| arg.clone()),
+            false => arg.clone(),
+        });
+        Some(translated.collect())
+    }
+
+    /// A Windows program as WSL sees it: under a drive mount, or an `.exe`.
+    fn runs_on_windows(&self, program: &str) -> bool {
+        self.mounted_drive(program).is_some() || program.to_ascii_lowercase().ends_with(".exe")
+    }
+
+    /// A program Windows runs in WSL: `wsl.exe` itself, or one on a
+    /// distribution's share.
+    fn runs_on_unix(program: &str) -> bool {
+        let name = program.rsplit(['\\', '/']).next().unwrap_or(program).to_ascii_lowercase();
+        name == "wsl" || name == "wsl.exe" || wsl_share(program).is_some()
+    }
+
+    /// Whether `arg` is plainly a path that needs translating to `style`:
+    /// a drive path for Unix; for Windows, a path under a drive mount or
+    /// any absolute path that exists, which a flag like `/c` does not.
+    fn is_path_argument(&self, arg: &str, style: Style) -> bool {
+        match style {
+            Style::Unix => split_drive(arg).is_some(),
+            _ => self.mounted_drive(arg).is_some() || (arg.starts_with('/') && Path::new(arg).exists()),
+        }
+    }
+}
+
+/// The drive letter and the rest of an absolute Windows path such as
+/// `C:\Users` or `c:/Users`.
+fn split_drive(path: &str) -> Option<(char, &str)> {
+    let mut chars = path.chars();
+    let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+    let rest = chars.as_str().strip_prefix(':')?;
+    (rest.is_empty() || rest.starts_with(['\\', '/'])).then_some((drive, rest))
+}
+
+/// What follows `\\wsl$\` or `\\wsl.localhost\` (either slash, any case)
+/// in a path on a WSL distribution's share: its name and the path in it.
+fn wsl_share(path: &str) -> Option<&str> {
+    ["\\\\wsl$\\", "\\\\wsl.localhost\\"].iter().find_map(|prefix| {
+        let head = path.get(..prefix.len())?;
+        head.replace('/', "\\").eq_ignore_ascii_case(prefix).then(|| &path[prefix.len()..])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drive_paths_cross_both_ways() {
+        let wsl = Mounts::new("/mnt", Some("Ubuntu"));
+        assert_eq!(wsl.translate("C:\\Users\\me", Style::Unix).as_deref(), Some("/mnt/c/Users/me"));
+        assert_eq!(wsl.translate("d:/src", Style::Unix).as_deref(), Some("/mnt/d/src"));
+        assert_eq!(wsl.translate("/mnt/c/Users/me", Style::Windows).as_deref(), Some("C:\\Users\\me"));
+        assert_eq!(wsl.translate("/mnt/c", Style::Windows).as_deref(), Some("C:\\"));
+        assert_eq!(wsl.translate("/mnt/c/Users", Style::Mixed).as_deref(), Some("C:/Users"));
+        assert_eq!(wsl.translate("/home/me", Style::Windows).as_deref(), Some("\\\\wsl.localhost\\Ubuntu\\home\\me"));
+        assert_eq!(wsl.translate("\\\\wsl$\\Ubuntu\\etc\\hosts", Style::Unix).as_deref(), Some("/etc/hosts"));
+        assert_eq!(wsl.translate("src\\main.rs", Style::Unix).as_deref(), Some("src/main.rs"));
+
+        let msys = Mounts::new("", None);
+        assert_eq!(msys.translate("C:\\Users", Style::Unix).as_deref(), Some("/c/Users"));
+        assert_eq!(msys.translate("/c/Users", Style::Windows).as_deref(), Some("C:\\Users"));
+        assert_eq!(msys.translate("/home/me", Style::Windows), None);
+        assert_eq!(msys.translate("/cdrom", Style::Windows), None);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn only_windows_programs_get_windows_paths() {
+        let wsl = Mounts::new("/mnt", Some("Ubuntu"));
+        let args: Vec<String> = ["/c", "/mnt/c/tmp/x.txt", "-v"].iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(wsl.translate_args(Path::new("/usr/bin/cat"), &args), None);
+        let translated = wsl.translate_args(Path::new("/mnt/c/Windows/System32/cmd.exe"), &args).unwrap();
+        assert_eq!(translated, ["/c", "C:\\tmp\\x.txt", "-v"]);
+        assert!(wsl.translate_args(Path::new("notepad.exe"), &args).is_some());
+    }
+}
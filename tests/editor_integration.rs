@@ -38,16 +38,14 @@ fn run_shell_with_home_from_script_file(
     home: &Path,
 ) -> std::process::Output {
     let stdin = File::open(script_path).expect("open script file");
-    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+    Command::new(env!("CARGO_BIN_EXE_james-shell"))
         .stdin(Stdio::from(stdin))
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .env("HOME", home)
         .env("USERPROFILE", home)
         .output()
-        .expect("run james-shell from script file");
-
-    output
+        .expect("run james-shell from script file")
 }
 
 /// RAII temp directory — created on construction, deleted on drop.
@@ -215,3 +213,55 @@ fn empty_commands_not_written_to_history() {
         "blank line found in history file:\n{contents}"
     );
 }
+
+#[test]
+fn history_expansion_reruns_and_edits_previous_commands() {
+    let home = TempHome::new("expansion");
+    let output = run_shell_with_home(
+        &["echo first", "echo second", "!!", "!1", "!echo f", "^first f^third", "!nope", "echo '!!'"],
+        home.path(),
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(stdout.matches("second\n").count(), 2, "stdout was: {stdout}");
+    assert_eq!(stdout.matches("first\n").count(), 2, "stdout was: {stdout}");
+    assert!(stdout.contains("first f\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("third\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("!!\n"), "stdout was: {stdout}");
+    // Expanded lines are echoed before they run.
+    assert!(stderr.contains("echo third\n"), "stderr was: {stderr}");
+    assert!(stderr.contains("!nope: event not found"), "stderr was: {stderr}");
+
+    // History records the expanded commands, not the `!` forms.
+    let contents = std::fs::read_to_string(home.history_path()).expect("read .jsh_history");
+    assert!(contents.contains("echo third"), "history was:\n{contents}");
+    assert!(!contents.contains("^first"), "history was:\n{contents}");
+}
+
+#[test]
+fn imported_history_can_be_recalled_in_the_same_session() {
+    let home = TempHome::new("import");
+    std::fs::write(home.history_path(), "echo mine\n").unwrap();
+    std::fs::write(home.path().join("bash_history"), "printf '%s\\n' imported\n").unwrap();
+    let output = run_shell_with_home(&["history --import bash ~/bash_history", "!printf"], home.path());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("imported\n"), "stdout was: {stdout}");
+    assert!(!stderr.contains("event not found"), "stderr was: {stderr}");
+}
+
+#[test]
+fn hist_variables_control_what_is_recorded_and_kept() {
+    let home = TempHome::new("histcontrol");
+    let _ = run_shell_with_home(
+        &["HISTCONTROL=ignorespace", "HISTFILESIZE=2", " echo secret", "echo one", "echo two"],
+        home.path(),
+    );
+
+    let contents = std::fs::read_to_string(home.history_path()).expect("read .jsh_history");
+    assert!(!contents.contains("secret"), "history was:\n{contents}");
+    assert_eq!(contents.lines().count(), 2, "history was:\n{contents}");
+    assert!(contents.starts_with("echo two\n"), "history was:\n{contents}");
+}
@@ -123,3 +123,376 @@ fn multiple_background_jobs_all_appear_in_jobs_output() {
     assert!(stdout.contains("[1]"), "stdout was: {stdout}");
     assert!(stdout.contains("[2]"), "stdout was: {stdout}");
 }
+
+#[test]
+fn last_background_pid_matches_job_pid() {
+    let output = run_shell(&[
+        "echo BEFORE:[$!]",
+        long_background_command(),
+        "echo BANG:$!",
+        "wait",
+        "echo AFTER:$!",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("BEFORE:[]"), "stdout was: {stdout}");
+
+    // `[1] <pid>` is printed when the job starts; `$!` must be that pid.
+    let pid = stdout
+        .lines()
+        .find_map(|line| line.split("[1] ").nth(1))
+        .map(str::trim)
+        .expect("job start line");
+    assert!(stdout.contains(&format!("BANG:{pid}")), "stdout was: {stdout}");
+    assert!(stdout.contains(&format!("AFTER:{pid}")), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn kill_signals_a_job_by_jobspec() {
+    let output = run_shell(&[
+        "sleep 30 &",
+        "kill %1",
+        "wait",
+        "echo WAIT:$?",
+        "kill -l 143",
+        "kill %99; echo KILL:$?",
+        "kill -BOGUS %1; echo BAD:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("WAIT:143"), "stdout was: {stdout}");
+    assert!(stdout.contains("TERM\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("KILL:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("BAD:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("kill: %99: no such job"), "stderr was: {stderr}");
+    assert!(stderr.contains("BOGUS: invalid signal specification"), "stderr was: {stderr}");
+}
+
+#[test]
+fn job_ids_that_name_no_job_are_rejected() {
+    let output = run_shell(&[
+        "sleep 0.1 &",
+        "disown %9; echo DISOWN:$?",
+        "fg %9; echo FG:$?",
+        "bg 9; echo BG:$?",
+        "wait 9; echo WAIT:$?",
+        "disown %1; echo KNOWN:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for name in ["DISOWN", "FG", "BG", "WAIT"] {
+        assert!(stdout.contains(&format!("{name}:1")), "stdout was: {stdout}");
+    }
+    assert!(stdout.contains("KNOWN:0"), "stdout was: {stdout}");
+    assert!(stderr.contains("disown: %9: no such job"), "stderr was: {stderr}");
+    assert!(stderr.contains("fg: %9: no such job"), "stderr was: {stderr}");
+    assert!(stderr.contains("bg: 9: no such job"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn kill_terminates_a_stopped_job() {
+    let output = run_shell(&["sleep 30 &", "kill -STOP %1", "kill -KILL %1", "wait %1", "echo WAIT:$?"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("WAIT:137"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn jobs_flags_filter_and_mark_current_and_previous() {
+    let output = run_shell(&[
+        "sleep 30 &",
+        "sleep 30 &",
+        "sleep 30 &",
+        "jobs",
+        "jobs -r -l",
+        "echo STOPPED:[$(jobs -s)]",
+        "kill $(jobs -p)",
+        "wait",
+        "echo WAIT:$?",
+        "jobs -x; echo BAD:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("[1]   Running                 sleep 30 &"), "stdout was: {stdout}");
+    assert!(stdout.contains("[2]-  Running                 sleep 30 &"), "stdout was: {stdout}");
+    assert!(stdout.contains("[3]+  Running                 sleep 30 &"), "stdout was: {stdout}");
+
+    // `[3] <pid>` is printed when the job starts; `jobs -l` shows it twice,
+    // as the job's PID and its process group.
+    let pid = stdout
+        .lines()
+        .find_map(|line| line.split("[3] ").nth(1))
+        .map(str::trim)
+        .expect("job start line");
+    assert!(stdout.contains(&format!("[3]+ {pid} {pid} Running")), "stdout was: {stdout}");
+
+    assert!(stdout.contains("STOPPED:[]"), "stdout was: {stdout}");
+    assert!(stdout.contains("WAIT:143"), "stdout was: {stdout}");
+    assert!(stdout.contains("BAD:2"), "stdout was: {stdout}");
+    assert!(stderr.contains("jobs: -x: invalid option"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn subshells_list_the_parents_jobs_however_jobs_is_invoked() {
+    let output = run_shell(&[
+        "sleep 30 &",
+        "sleep 30 &",
+        "echo COUNT:$(jobs -p | wc -l)",
+        "echo QUIET:$(jobs -p 2>/dev/null | wc -l)",
+        "echo NESTED:$(echo $(jobs -p) | wc -w)",
+        "jobs -r | cat",
+        "echo ENV:[$(echo $JSH_PARENT_JOBS)]",
+        "kill $(jobs -p)",
+        "wait",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for expected in ["COUNT:2", "QUIET:2", "NESTED:2", "[2]+  Running                 sleep 30 &", "ENV:[]"] {
+        assert!(stdout.contains(expected), "missing {expected}; stdout was: {stdout}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn job_specs_select_current_previous_and_by_command() {
+    let output = run_shell(&[
+        "sleep 30 &",
+        "sh -c 'sleep 30' &",
+        "sleep 31 &",
+        "kill -STOP %1",
+        "jobs",
+        "kill %sl; echo AMBIGUOUS:$?",
+        "kill %nope; echo MISSING:$?",
+        // Each kill shares a line with its wait so the prompt cannot reap
+        // the job in between.
+        "kill %?31; wait %3; echo SUBSTRING:$?",
+        "kill %sh; wait %-; echo PREVIOUS:$?",
+        "kill -KILL %%; wait %1; echo CURRENT:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // The stopped job becomes current; the newest running job is previous.
+    assert!(stdout.contains("[1]+  Stopped                 sleep 30\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("[3]-  Running                 sleep 31 &"), "stdout was: {stdout}");
+    assert!(stdout.contains("AMBIGUOUS:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("MISSING:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("SUBSTRING:143"), "stdout was: {stdout}");
+    assert!(stdout.contains("PREVIOUS:143"), "stdout was: {stdout}");
+    assert!(stdout.contains("CURRENT:137"), "stdout was: {stdout}");
+    assert!(stderr.contains("kill: %sl: ambiguous job spec"), "stderr was: {stderr}");
+    assert!(stderr.contains("kill: %nope: no such job"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn noninteractive_exit_with_stopped_job_does_not_wait_for_confirmation() {
+    let output = run_shell(&["sleep 30 &", "kill -STOP %1", "exit 3"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "stderr was: {stderr}");
+    assert!(!stderr.contains("There are stopped jobs"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn sigint_interrupts_foreground_job_and_abandons_the_line() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn james-shell");
+
+    let mut stdin = child.stdin.take().expect("stdin");
+    writeln!(stdin, "sleep 30; echo AFTER").expect("write line");
+    writeln!(stdin, "echo STATUS:$?").expect("write line");
+    stdin.flush().expect("flush");
+
+    // Without a terminal nothing else delivers the interrupt to `sleep`; the
+    // shell must forward it to the foreground process group.
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    // SAFETY: signalling our own child process.
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    writeln!(stdin, "exit").expect("write exit");
+    drop(stdin);
+
+    let output = child.wait_with_output().expect("wait output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("AFTER"), "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:130"), "stdout was: {stdout}");
+}
+
+#[test]
+fn jobtimes_adds_run_times_to_done_notifications() {
+    let output = run_shell(&[quick_exit_background_command(), "wait", "shopt -s jobtimes", quick_exit_background_command(), "wait"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let done: Vec<&str> = stdout.lines().filter(|line| line.contains("Done")).collect();
+    assert_eq!(done.len(), 2, "stdout was: {stdout}");
+    assert!(!done[0].contains("(real "), "stdout was: {stdout}");
+    assert!(done[1].contains("(real "), "stdout was: {stdout}");
+    #[cfg(unix)]
+    assert!(done[1].contains(", cpu "), "stdout was: {stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn background_pipelines_report_how_they_ended() {
+    let output = run_shell(&[
+        "sleep 5 | cat &",
+        "kill %1",
+        "wait %1",
+        "sh -c 'exit 3' &",
+        "wait %2",
+        "sh -c 'exit 5' | sleep 0.3 &",
+        "wait %3",
+        "echo PLAIN:$?",
+        "set -o pipefail",
+        "sh -c 'exit 5' | sleep 0.3 &",
+        "wait %4",
+        "echo PIPEFAIL:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[1]+  Terminated              sleep 5 | cat"), "stdout was: {stdout}");
+    assert!(stdout.contains("[2]+  Exit 3                  sh -c 'exit 3'"), "stdout was: {stdout}");
+    assert!(stdout.contains("PLAIN:0"), "stdout was: {stdout}");
+    assert!(stdout.contains("PIPEFAIL:5"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+#[cfg(unix)]
+fn finished_jobs_keep_their_status_until_waited_for() {
+    let output = run_shell(&[
+        "sh -c 'exit 3' &",
+        "sleep 0.5",
+        "wait %1",
+        "echo STATUS:$?",
+        "wait %1",
+        "sh -c 'exit 4' &",
+        "sleep 0.5",
+        "jobs",
+        "wait %2",
+        "echo AFTER_JOBS:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stdout.matches("Exit 3").count(), 1, "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:3"), "stdout was: {stdout}");
+    assert!(stderr.contains("wait: %1: no such job"), "stderr was: {stderr}");
+    assert_eq!(stdout.matches("Exit 4").count(), 1, "stdout was: {stdout}");
+    assert!(stdout.contains("AFTER_JOBS:1"), "stdout was: {stdout}");
+}
+
+#[test]
+fn jobs_json_describes_each_job() {
+    let output = run_shell(&[
+        "sleep 30 &",
+        "sh -c 'sleep 30; echo \"a\\b\"' &",
+        "kill -STOP %1",
+        "jobs --json",
+        "jobs -s --json",
+        "kill -KILL %1 %2; wait",
+        "jobs --json",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let pid = stdout
+        .lines()
+        .find_map(|line| line.split("[1] ").nth(1))
+        .map(str::trim)
+        .expect("job start line");
+
+    let listings: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.trim_start_matches("jsh> "))
+        .filter(|line| line.starts_with("[{") || *line == "[]")
+        .collect();
+    assert_eq!(listings.len(), 3, "stdout was: {stdout}");
+    let first = format!(r#"[{{"id":1,"pid":{pid},"pgid":{pid},"status":"stopped","command":"sleep 30","started_at":"#);
+    assert!(listings[0].starts_with(&first), "stdout was: {stdout}");
+    assert!(
+        listings[0].contains(r#""status":"running","command":"sh -c 'sleep 30; echo \"a\\b\"'","#),
+        "stdout was: {stdout}"
+    );
+    assert!(listings[1].starts_with(&first) && !listings[1].contains(r#""id":2"#), "stdout was: {stdout}");
+    assert_eq!(listings[2], "[]");
+}
+
+#[cfg(unix)]
+#[test]
+fn huponexit_decides_which_jobs_die_with_the_shell() {
+    let dir = std::env::temp_dir().join(format!("jsh_huponexit_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let job = |marker: &str| format!("sh -c 'sleep 1; touch {}' >/dev/null 2>&1 &", dir.join(marker).display());
+
+    // Without huponexit, a running job outlives the shell.
+    run_shell(&[&job("kept")]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .arg("-i")
+        .env("JSH_RC", "")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn james-shell");
+    let lines = [
+        "shopt -s huponexit".to_string(),
+        job("hung_up"),
+        job("spared"),
+        "disown -h %2".to_string(),
+        "sh -c 'sleep 1' &".to_string(),
+        "disown %3; jobs".to_string(),
+        "exit".to_string(),
+        "exit".to_string(),
+    ];
+    child.stdin.take().unwrap().write_all(lines.join("\n").as_bytes()).unwrap();
+    let output = child.wait_with_output().expect("wait output");
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    let exists = |marker: &str| dir.join(marker).exists();
+    let (kept, hung_up, spared) = (exists("kept"), exists("hung_up"), exists("spared"));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(kept, "a job without huponexit was killed");
+    assert!(!hung_up, "stderr was: {stderr}");
+    assert!(spared, "a job marked with disown -h was killed");
+    assert!(stdout.contains("[2]+  Running") && !stdout.contains("sh -c 'sleep 1' &"), "stdout was: {stdout}");
+    assert!(stderr.contains("There are running jobs."), "stderr was: {stderr}");
+    assert!(stderr.contains("hung_up' > /dev/null 2>&1  (will be sent SIGHUP)"), "stderr was: {stderr}");
+    assert!(!stderr.contains("spared' > /dev/null 2>&1  (will be sent SIGHUP)"), "stderr was: {stderr}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn suspend_stops_the_shell_until_it_is_continued() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn james-shell");
+    child.stdin.take().unwrap().write_all(b"suspend\necho resumed:$?\n").unwrap();
+
+    let stat = format!("/proc/{}/stat", child.id());
+    let stopped = (0..100).any(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let stat = std::fs::read_to_string(&stat).unwrap_or_default();
+        stat.rsplit(") ").next().is_some_and(|fields| fields.starts_with('T'))
+    });
+    let _ = Command::new("kill").args(["-CONT", &child.id().to_string()]).status();
+    let output = child.wait_with_output().expect("wait output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stopped, "the shell never stopped; stdout was: {stdout}");
+    assert!(stdout.contains("resumed:0"), "stdout was: {stdout}");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-l", "-c", "suspend; echo refused:$?"])
+        .env("HOME", std::env::temp_dir())
+        .output()
+        .expect("run james-shell -l -c");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "refused:1\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("jsh: suspend: cannot suspend a login shell"));
+}
@@ -0,0 +1,236 @@
+/// Integration tests for embedding the shell as a library through
+/// `james_shell::Shell`, without spawning the binary.
+use james_shell::builtins::{Builtin, BuiltinAction, BuiltinHelp, BuiltinIo, BuiltinKind};
+use james_shell::{ExitStatus, JshError, Shell};
+
+#[test]
+fn eval_line_reports_status_and_keeps_state() {
+    let mut shell = Shell::new();
+    let status = shell.eval_line("greeting=hello; export TARGET=world");
+    assert_eq!(status, ExitStatus { code: 0, exited: false });
+    assert_eq!(shell.variables().get("greeting"), Some("hello"));
+    assert!(shell.variables().is_exported("TARGET"));
+
+    let status = shell.eval_line("false || true && false");
+    assert_eq!(status, ExitStatus { code: 1, exited: false });
+
+    shell.variables_mut().set("count", "2");
+    assert!(shell.eval_line("copy=\"$greeting $count\"").success());
+    assert_eq!(shell.variables().get("copy"), Some("hello 2"));
+}
+
+#[test]
+fn eval_line_reports_exit_requests() {
+    let mut shell = Shell::new();
+    let status = shell.eval_line("exit 4; echo never");
+    assert_eq!(status, ExitStatus { code: 4, exited: true });
+    assert_eq!(shell.finish(), 4);
+}
+
+#[test]
+fn run_script_runs_a_file_in_the_shell() {
+    let root = std::env::temp_dir().join(format!("jsh_library_api_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let script = root.join("setup.jsh");
+    std::fs::write(&script, "name=embedded\nalias ll='ls -l'\nfalse\n").unwrap();
+
+    let mut shell = Shell::new();
+    let status = shell.run_script(&script);
+    let missing = shell.run_script(root.join("missing.jsh"));
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(status, ExitStatus { code: 1, exited: false });
+    assert_eq!(shell.variables().get("name"), Some("embedded"));
+    assert_eq!(shell.aliases.get("ll"), Some("ls -l"));
+    assert_eq!(missing.code, 1);
+    assert!(shell.job_table().jobs_sorted().is_empty());
+}
+
+#[test]
+fn parse_errors_can_be_matched_by_kind() {
+    let err = james_shell::parser::tokenize("echo ok 'unclosed").unwrap_err();
+    match &err {
+        JshError::Syntax { message, span: Some(span) } => {
+            assert_eq!(message, "jsh: syntax error: unterminated single quote");
+            assert_eq!(span.column, 8);
+        }
+        other => panic!("expected a syntax error with a span, got {other:?}"),
+    }
+    assert_eq!(err.caret().as_deref(), Some("echo ok 'unclosed\n        ^ column 9"));
+
+    let words = james_shell::parser::tokenize("cat < $many").unwrap();
+    let mut shell = Shell::new();
+    shell.variables_mut().set("many", "a b");
+    let err = james_shell::redirect::extract_redirections_from_words(&words, &mut shell).unwrap_err();
+    assert!(matches!(err, JshError::Redirect(_)), "{err}");
+
+    let words = james_shell::parser::tokenize("${missing:?not set}").unwrap();
+    let err = james_shell::expander::expand_words(&words, &mut shell).unwrap_err();
+    assert_eq!(err, JshError::Expansion("jsh: missing: not set".to_string()));
+
+    shell.variables_mut().set_readonly("fixed");
+    let words = james_shell::parser::tokenize("${fixed:=1}").unwrap();
+    let err = james_shell::expander::expand_words(&words, &mut shell).unwrap_err();
+    assert!(matches!(err, JshError::Assignment(_)), "{err}");
+}
+
+#[test]
+fn parsed_lists_expand_afresh_each_time_they_run() {
+    let words = james_shell::parser::tokenize("copy=\"$n\"; [ \"$n\" = 2 ] && seen=yes").unwrap();
+    let list = james_shell::script_parser::parse_script(words).unwrap();
+
+    let mut shell = Shell::new();
+    for n in ["1", "2"] {
+        shell.variables_mut().set("n", n);
+        shell.run_list(&list);
+        assert_eq!(shell.variables().get("copy"), Some(n));
+    }
+    assert_eq!(shell.variables().get("seen"), Some("yes"));
+}
+
+/// Upper-cases its input and counts its runs in `$shouts`.
+struct Shout;
+
+impl Builtin for Shout {
+    fn name(&self) -> &str {
+        "shout"
+    }
+
+    fn kind(&self) -> BuiltinKind {
+        BuiltinKind::Stateful
+    }
+
+    fn run(&self, shell: &mut Shell, args: &[String], io: &mut BuiltinIo<'_>) -> BuiltinAction {
+        let mut input = String::new();
+        let _ = io.stdin.read_to_string(&mut input);
+        let _ = write!(io.stdout, "{}", input.to_uppercase());
+        let count = shell.variables().get("shouts").map_or(0, |n| n.parse().unwrap()) + 1;
+        shell.variables_mut().set("shouts", &count.to_string());
+        BuiltinAction::Continue(args.len() as i32)
+    }
+}
+
+#[test]
+fn registered_builtins_run_in_the_shell() {
+    let root = std::env::temp_dir().join(format!("jsh_library_builtin_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let input = root.join("in.txt");
+    let output = root.join("out.txt");
+    std::fs::write(&input, "quiet words\n").unwrap();
+
+    let mut shell = Shell::new();
+    shell.builtins.register(Shout);
+    assert!(shell.builtins.contains("shout"));
+
+    let line = format!("shout a b < '{}' > '{}'", input.display(), output.display());
+    assert_eq!(shell.eval_line(&line).code, 2);
+    let shouted = std::fs::read_to_string(&output).unwrap();
+    let typed = shell.eval_line(&format!("type shout > '{}'", output.display()));
+    let described = std::fs::read_to_string(&output).unwrap();
+    // Stateful builtins get a copy of the shell in a pipeline, as in bash.
+    let piped = shell.eval_line(&format!("echo hi | shout > '{}'", output.display()));
+    let piped_output = std::fs::read_to_string(&output).unwrap();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(shouted, "QUIET WORDS\n");
+    assert_eq!(typed.code, 0);
+    assert_eq!(described, "shout is a shell builtin\n");
+    assert_eq!(piped.code, 0);
+    assert_eq!(piped_output, "HI\n");
+    assert_eq!(shell.variables().get("shouts"), Some("1"));
+
+    assert!(shell.builtins.unregister("shout"));
+    assert!(!shell.builtins.unregister("echo"));
+    assert!(!shell.builtins.contains("shout"));
+    assert!(shell.builtins.contains("echo"));
+}
+
+struct Greet;
+
+static GREET_HELP: BuiltinHelp = BuiltinHelp {
+    usage: "greet [name]",
+    summary: "Say hello",
+    details: &["  Print a greeting for name, or for the world."],
+};
+
+impl Builtin for Greet {
+    fn name(&self) -> &str {
+        "greet"
+    }
+
+    fn run(&self, _shell: &mut Shell, args: &[String], io: &mut BuiltinIo<'_>) -> BuiltinAction {
+        let _ = writeln!(io.stdout, "hello, {}", args.first().map_or("world", String::as_str));
+        BuiltinAction::Continue(0)
+    }
+
+    fn help(&self) -> Option<&BuiltinHelp> {
+        Some(&GREET_HELP)
+    }
+}
+
+#[test]
+fn registered_builtins_can_document_themselves_in_help() {
+    let root = std::env::temp_dir().join(format!("jsh_library_help_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let output = root.join("out.txt");
+
+    let mut shell = Shell::new();
+    shell.builtins.register(Greet);
+    shell.builtins.register(Shout);
+    let mut help = |args: &str| {
+        let status = shell.eval_line(&format!("help {args} > '{}'", output.display()));
+        (status.code, std::fs::read_to_string(&output).unwrap())
+    };
+    let (_, overview) = help("");
+    let (_, greet) = help("greet");
+    let (shout_status, _) = help("shout 2>/dev/null");
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(overview.contains("\n  greet [name]        Say hello\n"), "overview was: {overview}");
+    assert!(!overview.contains("shout"), "overview was: {overview}");
+    assert_eq!(greet, "greet [name]\n  Print a greeting for name, or for the world.\n");
+    assert_eq!(shout_status, 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn shells_in_parallel_keep_their_own_directory_and_exports() {
+    let root = std::env::temp_dir().join(format!("jsh_library_parallel_{}", std::process::id()));
+    let before = std::env::current_dir().unwrap();
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+    let threads: Vec<_> = ["left", "right"]
+        .into_iter()
+        .map(|name| {
+            let dir = root.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                let mut shell = Shell::new();
+                assert!(shell.eval_line(&format!("cd '{}' && export SIDE={name}", dir.display())).success());
+                barrier.wait();
+                for _ in 0..20 {
+                    shell.eval_line("sh -c 'echo \"$(pwd -P) $SIDE\"' >> seen; echo * > listed");
+                }
+                shell.eval_line("pwd > logical");
+                assert_eq!(shell.current_dir(), dir);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let read = |path: std::path::PathBuf| std::fs::read_to_string(path).unwrap();
+    for name in ["left", "right"] {
+        let dir = root.join(name);
+        let physical = dir.canonicalize().unwrap();
+        let seen = read(dir.join("seen"));
+        assert_eq!(seen.lines().count(), 20);
+        assert!(seen.lines().all(|line| line == format!("{} {name}", physical.display())), "{seen}");
+        assert_eq!(read(dir.join("listed")), "listed seen\n");
+        assert_eq!(read(dir.join("logical")).trim_end(), dir.display().to_string());
+    }
+    assert_eq!(std::env::current_dir().unwrap(), before);
+    assert!(std::env::var_os("SIDE").is_none());
+    let _ = std::fs::remove_dir_all(&root);
+}
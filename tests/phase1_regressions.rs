@@ -150,14 +150,14 @@ fn cd_failed_directory_does_not_update_oldpwd() {
 }
 
 #[test]
-fn stateful_builtin_in_nonterminal_pipeline_is_rejected() {
+fn stateful_builtin_in_nonterminal_pipeline_runs_in_subshell() {
     let root = std::env::temp_dir().join(format!("jsh_pipeline_builtin_reg_{}", std::process::id()));
     let valid_dir = root.join("valid");
     let valid = valid_dir.to_string_lossy().to_string();
     std::fs::create_dir_all(&valid_dir).unwrap();
 
     let output = run_shell_with_env(
-        &["cd \"$JSH_VALID_DIR\" | echo DONE", "echo PIPE:$?"],
+        &["cd \"$JSH_VALID_DIR\" | echo DONE", "echo PIPE:$?", "pwd", "shopt -s pipewarn", "cd / | echo DONE"],
         &[("JSH_VALID_DIR", valid.as_str())],
     );
 
@@ -165,26 +165,38 @@ fn stateful_builtin_in_nonterminal_pipeline_is_rejected() {
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
 
-    assert!(
-        stderr.contains("builtin 'cd' is not supported in non-terminal pipeline positions"),
-        "stderr was: {stderr}"
-    );
-    assert!(stdout.contains("PIPE:1"), "stdout was: {stdout}");
-    assert!(!stdout.contains("DONE"), "stdout was: {stdout}");
+    assert!(stdout.contains("DONE"), "stdout was: {stdout}");
+    assert!(stdout.contains("PIPE:0"), "stdout was: {stdout}");
+    // The cd happened in the subshell only.
+    assert!(!stdout.contains(&valid), "stdout was: {stdout}");
+    // The warning only appears under `shopt -s pipewarn`.
+    assert_eq!(stderr.matches("its changes to the shell are discarded").count(), 1, "stderr was: {stderr}");
 }
 
 #[test]
-fn stateful_builtin_export_in_nonterminal_pipeline_is_rejected() {
-    let output = run_shell(&["export FOO=bar | echo DONE", "echo PIPE:$?"]);
+fn stateful_builtin_export_in_nonterminal_pipeline_has_no_lasting_effect() {
+    let output = run_shell(&["export FOO=bar | echo DONE", "echo PIPE:$?:[$FOO]", "alias ll=ls", "alias | cat"]);
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
 
-    assert!(
-        stderr.contains("builtin 'export' is not supported in non-terminal pipeline positions"),
-        "stderr was: {stderr}"
-    );
-    assert!(stdout.contains("PIPE:1"), "stdout was: {stdout}");
-    assert!(!stdout.contains("DONE"), "stdout was: {stdout}");
+    assert!(stdout.contains("DONE"), "stdout was: {stdout}");
+    assert!(stdout.contains("PIPE:0:[]"), "stdout was: {stdout}");
+    // Listing builtins see a copy of the shell's state.
+    assert!(stdout.contains("alias ll='ls'"), "stdout was: {stdout}");
+}
+
+#[test]
+fn last_stage_stateful_builtin_runs_in_shell_only_with_lastpipe() {
+    let output = run_shell(&[
+        "echo x | export FOO=sub",
+        "echo A:[$FOO]",
+        "shopt -s lastpipe",
+        "echo x | export FOO=here",
+        "echo B:[$FOO]",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains("A:[]"), "stdout was: {stdout}");
+    assert!(stdout.contains("B:[here]"), "stdout was: {stdout}");
 }
 
 #[test]
@@ -202,8 +214,8 @@ fn nonterminal_pure_builtins_are_allowed() {
 }
 
 #[test]
-fn background_builtin_warning_is_emitted_once() {
-    let output = run_shell(&["pwd | echo PIPELINE &", "echo PIPE:$?"]);
+fn stateful_background_builtin_warns_and_runs_foreground() {
+    let output = run_shell(&["cd / &", "pwd", "echo CD:$?"]);
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
 
@@ -213,10 +225,8 @@ fn background_builtin_warning_is_emitted_once() {
         1,
         "expected exactly one background builtin warning, saw {warning_count}; stderr={stderr}"
     );
-    assert!(
-        stdout.contains("PIPE:0"),
-        "stdout was: {stdout}"
-    );
+    assert!(stdout.contains("> /\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("CD:0"), "stdout was: {stdout}");
 }
 
 #[test]
@@ -249,6 +259,67 @@ fn pipeline_stdout_redirect_on_nonterminal_command_is_rejected() {
     assert!(stdout.contains("PIPE:1"), "stdout was: {stdout}");
 }
 
+#[cfg(unix)]
+#[test]
+fn pipeline_stage_may_send_only_stderr_down_the_pipe() {
+    let output = run_shell(&[
+        "sh -c 'echo out; echo err >&2' 2>&1 >/dev/null | sed 's/^/first:/'",
+        "sh -c 'echo out; echo err >&2' 3>&1 1>&2 2>&3 | sed 's/^/swapped:/'",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("first:err"), "stdout was: {stdout}");
+    assert!(stdout.contains("swapped:err"), "stdout was: {stdout}");
+    assert!(!stdout.contains(":out"), "stdout was: {stdout}");
+    assert_eq!(stderr.matches("out\n").count(), 1, "stderr was: {stderr}");
+    assert!(!stderr.contains("cannot redirect"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn descriptors_above_two_reach_external_commands() {
+    let dir = std::env::temp_dir().join(format!("jsh_extra_fds_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let three = dir.join("three.txt");
+    let four = dir.join("four.txt");
+    let output = run_shell(&[
+        &format!("sh -c 'echo to3 >&3' 3>'{}'", three.display()),
+        &format!("exec 4>'{}'", four.display()),
+        "sh -c 'echo to4 >&4'",
+        "sh -c 'echo via3 >&3' 3>&1 | tr a-z A-Z",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(std::fs::read_to_string(&three).unwrap_or_default(), "to3\n");
+    assert_eq!(std::fs::read_to_string(&four).unwrap_or_default(), "to4\n");
+    assert!(stdout.contains("VIA3"), "stdout was: {stdout}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn missing_command_is_reported_with_descriptors_above_two() {
+    let dir = std::env::temp_dir().join(format!("jsh_extra_fds_missing_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = run_shell(&[
+        &format!("jsh_no_such_cmd_a 6>'{}'", dir.join("six.txt").display()),
+        "echo status=$?",
+        &format!("exec 7>'{}'", dir.join("seven.txt").display()),
+        "jsh_no_such_cmd_b",
+        "echo status=$?",
+        "coproc cat",
+        "jsh_no_such_cmd_c <&${COPROC[0]}",
+        "echo status=$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stdout.matches("status=127").count(), 3, "stdout was: {stdout}");
+    for name in ["jsh_no_such_cmd_a", "jsh_no_such_cmd_b", "jsh_no_such_cmd_c"] {
+        assert!(stderr.contains(&format!("command not found: {name}")), "stderr was: {stderr}");
+    }
+    assert!(!stderr.contains("fatal runtime error"), "stderr was: {stderr}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 #[test]
 fn cd_minus_ignores_failed_cd_for_oldpwd() {
     let root = std::env::temp_dir().join(format!("jsh_cdminus_reg_{}", std::process::id()));
@@ -296,34 +367,116 @@ fn cd_minus_ignores_failed_cd_for_oldpwd() {
     );
 }
 
+#[cfg(unix)]
 #[test]
-fn builtin_background_prints_warning_and_runs_foreground() {
-    let output = run_shell(&["pwd &", "echo FG:$?"]);
+fn cd_keeps_a_logical_pwd_through_symlinks() {
+    let root = std::env::temp_dir().join(format!("jsh_cd_logical_reg_{}", std::process::id()));
+    let real_dir = root.join("real");
+    std::fs::create_dir_all(real_dir.join("sub")).unwrap();
+    std::os::unix::fs::symlink(&real_dir, root.join("link")).unwrap();
+    let root_str = root.to_string_lossy().to_string();
+
+    let output = run_shell_with_env(
+        &[
+            "cd \"$JSH_ROOT/link/sub\"",
+            "echo L:$(pwd)",
+            "echo P:$(pwd -P)",
+            "cd ..",
+            "echo UP:$PWD",
+            "cd -P ../link",
+            "echo PHYS:$PWD",
+            "cd -x",
+            "echo BAD:$?",
+        ],
+        &[("JSH_ROOT", root_str.as_str())],
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains(&format!("L:{root_str}/link/sub")), "stdout was: {stdout}");
+    assert!(stdout.contains(&format!("P:{root_str}/real/sub")), "stdout was: {stdout}");
+    assert!(stdout.contains(&format!("UP:{root_str}/link\n")), "stdout was: {stdout}");
+    assert!(stdout.contains(&format!("PHYS:{root_str}/real\n")), "stdout was: {stdout}");
+    assert!(stdout.contains("BAD:2"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn startup_keeps_an_inherited_logical_pwd_and_replaces_a_stale_one() {
+    let root = std::env::temp_dir().join(format!("jsh_startup_pwd_reg_{}", std::process::id()));
+    let real_dir = root.join("real");
+    std::fs::create_dir_all(&real_dir).unwrap();
+    std::os::unix::fs::symlink(&real_dir, root.join("link")).unwrap();
+    let root = root.canonicalize().unwrap();
+    let root_str = root.to_string_lossy().to_string();
+
+    let run = |pwd: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(["-c", "echo $PWD; env | grep '^PWD='; cd ..; echo $PWD"])
+            .current_dir(&real_dir)
+            .env("PWD", pwd)
+            .stdin(Stdio::null())
+            .output()
+            .expect("run james-shell -c");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let logical = run(&format!("{root_str}/link"));
+    let stale = run("/nonexistent/elsewhere");
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(logical, format!("{root_str}/link\nPWD={root_str}/link\n{root_str}\n"));
+    assert_eq!(stale, format!("{root_str}/real\nPWD={root_str}/real\n{root_str}\n"));
+}
+
+#[test]
+fn cd_searches_cdpath_and_prints_the_directory() {
+    let root = std::env::temp_dir().join(format!("jsh_cdpath_reg_{}", std::process::id()));
+    let project_dir = root.join("projects").join("app");
+    std::fs::create_dir_all(&project_dir).unwrap();
+    std::fs::create_dir_all(root.join("here")).unwrap();
+    let root_str = root.to_string_lossy().to_string();
+    let separator = if cfg!(windows) { ";" } else { ":" };
+    let cdpath = format!("{}{separator}{}", root.join("missing").display(), root.join("projects").display());
+
+    let output = run_shell_with_env(
+        &["cd \"$JSH_ROOT/here\"", "cd app", "echo NOW:$?"],
+        &[("JSH_ROOT", root_str.as_str()), ("CDPATH", cdpath.as_str())],
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
 
     assert!(
-        stderr.contains("does not support background execution"),
-        "stderr was: {stderr}"
+        stdout.contains(&project_dir.to_string_lossy().to_string()),
+        "stdout was: {stdout}"
     );
+    assert!(stdout.contains("NOW:0"), "stdout was: {stdout}");
+}
+
+#[test]
+fn builtin_background_runs_as_subshell_job() {
+    let output = run_shell(&["X=parent", "echo BG:$X &", "wait", "echo FG:$?"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!stderr.contains("does not support background execution"), "stderr was: {stderr}");
+    assert!(stdout.contains("[1] "), "stdout was: {stdout}");
+    assert!(stdout.contains("BG:parent\n"), "stdout was: {stdout}");
     assert!(stdout.contains("FG:0"), "stdout was: {stdout}");
 }
 
 #[test]
-fn pipeline_with_background_builtin_warns_and_runs_foreground() {
-    let output = run_shell(&["pwd | echo PIPELINE &", "echo PIPE:$?"]);
+fn pipeline_with_background_builtin_runs_as_subshell_job() {
+    let output = run_shell(&["pwd | echo PIPELINE &", "wait", "echo PIPE:$?"]);
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
 
-    assert!(
-        stderr.contains("does not support background execution"),
-        "stderr was: {stderr}"
-    );
-    assert!(
-        stdout.contains("PIPE:0"),
-        "stdout was: {stdout}"
-    );
-    assert!(stdout.contains("PIPELINE"), "stdout was: {stdout}");
+    assert!(!stderr.contains("does not support background execution"), "stderr was: {stderr}");
+    assert!(stdout.contains("[1] "), "stdout was: {stdout}");
+    assert!(stdout.contains("PIPELINE\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("PIPE:0"), "stdout was: {stdout}");
 }
 
 #[cfg(unix)]
@@ -332,6 +485,85 @@ fn builtin_to_external_large_output_does_not_hang_unix() {
     assert_large_payload_roundtrip_returns_quickly("echo $BIG | sort");
 }
 
+#[test]
+#[cfg(unix)]
+fn redirections_apply_left_to_right_unix() {
+    let root = std::env::temp_dir().join(format!("jsh_redir_order_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let both = root.join("both.txt");
+    let only_out = root.join("out.txt");
+    let via_three = root.join("three.txt");
+    let script = "sh -c 'echo out; echo err 1>&2'";
+    let first = format!("{script} > {} 2>&1", both.to_string_lossy());
+    let second = format!("{script} 2>&1 > {}", only_out.to_string_lossy());
+    let third = format!("{script} 3>{} 1>&3 2>&3", via_three.to_string_lossy());
+    let output = run_shell(&[&first, &second, &third, "echo x 2>&5", "echo status:$?"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let read = |path: &std::path::Path| std::fs::read_to_string(path).unwrap_or_default();
+    let (both, only_out, via_three) = (read(&both), read(&only_out), read(&via_three));
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(both, "out\nerr\n");
+    assert_eq!(only_out, "out\n");
+    assert_eq!(via_three, "out\nerr\n");
+    // `2>&1 > f` leaves stderr on the shell's stdout.
+    assert!(stdout.contains("err"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: 5: bad file descriptor"), "stderr was: {stderr}");
+    assert!(stdout.contains("status:1"), "stdout was: {stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn redirect_targets_glob_to_exactly_one_file_unix() {
+    let root = std::env::temp_dir().join(format!("jsh_redir_glob_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    for name in ["a.log", "b.log", "only.txt"] {
+        std::fs::write(root.join(name), "").unwrap();
+    }
+    let dir = root.to_string_lossy();
+    let ambiguous = format!("echo x > {dir}/*.log");
+    let single = format!("echo one > {dir}/*.txt");
+    let literal = format!("echo two > {dir}/*.txt");
+    let output = run_shell(&[
+        &ambiguous,
+        "echo status:$?",
+        &single,
+        "set -o posix",
+        &literal,
+        "spaced='a  b'",
+        "cat <<< $spaced",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let only = std::fs::read_to_string(root.join("only.txt")).unwrap_or_default();
+    let star = std::fs::read_to_string(root.join("*.txt")).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(stderr.contains(&format!("jsh: {dir}/*.log: ambiguous redirect")), "stderr was: {stderr}");
+    assert!(!stdout.contains("status:0"), "stdout was: {stdout}");
+    assert_eq!(only, "one\n");
+    assert_eq!(star, "two\n");
+    assert!(stdout.contains("a  b\n"), "stdout was: {stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn large_here_string_does_not_hang_unix() {
+    // `cat` fills the pipe to `wc` long before it has read the whole
+    // here-string, so the text must be fed while later stages start.
+    let payload_len = 200_000;
+    let assign = format!("BIG={}", "x".repeat(payload_len));
+    let start = std::time::Instant::now();
+    let output = run_shell(&[&assign, "cat <<< $BIG | wc -c"]);
+    let elapsed = start.elapsed();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // The here-string gains a trailing newline.
+    assert!(stdout.contains(&format!("{}", payload_len + 1)), "stdout was: {stdout}");
+    assert!(elapsed < Duration::from_secs(5), "here-string appeared to hang; took {elapsed:.2?}");
+}
+
 #[test]
 #[cfg(unix)]
 fn external_stderr_pipes_into_next_command_unix() {
@@ -343,6 +575,227 @@ fn external_stderr_pipes_into_next_command_unix() {
     assert!(!stderr.contains("err"), "stderr was: {stderr}");
 }
 
+#[test]
+#[cfg(unix)]
+fn combined_stream_operators_unix() {
+    let root = std::env::temp_dir().join(format!("jsh_both_streams_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let log = root.join("both.log");
+    let log = log.to_string_lossy();
+    let first = format!("sh -c 'echo out; echo err 1>&2' &> {log}");
+    let second = format!("sh -c 'echo again 1>&2' &>> {log}");
+    let output = run_shell(&[
+        &first,
+        &second,
+        "sh -c 'echo piped 1>&2' |& tr a-z A-Z",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let contents = std::fs::read_to_string(root.join("both.log")).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(contents, "out\nerr\nagain\n");
+    assert!(stdout.contains("PIPED"), "stdout was: {stdout}");
+    assert!(!stderr.contains("piped"), "stderr was: {stderr}");
+}
+
+#[test]
+fn noclobber_refuses_to_overwrite_files() {
+    let root = std::env::temp_dir().join(format!("jsh_noclobber_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let file = root.join("kept.txt");
+    std::fs::write(&file, "original\n").unwrap();
+    let file = file.to_string_lossy();
+    let overwrite = format!("echo clobbered > {file}");
+    let force = format!("echo forced >| {file}");
+    let output = run_shell(&["set -C", &overwrite, "echo status:$?", "set -o noclobber", &force]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let contents = std::fs::read_to_string(root.join("kept.txt")).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(stderr.contains("cannot overwrite existing file"), "stderr was: {stderr}");
+    assert!(stdout.contains("status:1"), "stdout was: {stdout}");
+    assert_eq!(contents, "forced\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn bglog_sends_background_output_to_a_job_log_unix() {
+    let output = run_shell(&[
+        "set -o bglog",
+        "sh -c 'echo out; echo err >&2; sleep 1' &",
+        "jobs -o %1",
+        "log=$(jobs -o %1)",
+        "wait",
+        "tr a-z A-Z < \"$log\"",
+        "echo fg-done",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let log = stdout
+        .split("jsh> ")
+        .map(str::trim)
+        .find(|line| line.ends_with(".log"))
+        .unwrap_or_else(|| panic!("no log path in stdout: {stdout}"))
+        .to_string();
+
+    assert!(stdout.contains("OUT\nERR\n"), "stdout was: {stdout}");
+    assert!(!stdout.contains("out\n"), "stdout was: {stdout}");
+    assert!(!stderr.contains("err"), "stderr was: {stderr}");
+    assert!(stdout.contains("fg-done"), "stdout was: {stdout}");
+    // The private directory's logs go with the shell.
+    assert!(!std::path::Path::new(&log).exists(), "{log} was left behind");
+}
+
+#[test]
+#[cfg(unix)]
+fn bglog_directory_keeps_private_logs_with_fresh_names_unix() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = std::env::temp_dir().join(format!("jsh_bglog_dir_reg_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let dir = root.join("logs");
+    let set = format!("set -o bglog={}", dir.display());
+    let output = run_shell(&[&set, "set +o | grep bglog", "echo first &", "wait", "echo second &", "wait"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let mut logs: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    logs.sort();
+    let contents: Vec<String> = logs.iter().map(|log| std::fs::read_to_string(log).unwrap()).collect();
+    let modes: Vec<u32> = logs.iter().map(|log| mode(log)).collect();
+    let dir_mode = mode(&dir);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(stdout.contains(&format!("{set}\n")), "stdout was: {stdout}");
+    // Both jobs were %1; the second did not reuse the first one's file.
+    assert_eq!(contents, ["first\n", "second\n"]);
+    assert_eq!(modes, [0o600, 0o600]);
+    assert_eq!(dir_mode, 0o700);
+}
+
+#[test]
+fn exec_redirections_persist_for_the_session() {
+    let root = std::env::temp_dir().join(format!("jsh_exec_redirect_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let log = root.join("session.log");
+    let exec = format!("exec > {} 2>&1", log.to_string_lossy());
+    let output = run_shell(&[
+        "echo before",
+        &exec,
+        "echo first",
+        "echo second | cat",
+        "echo oops >&2",
+        "exec echo last",
+        "echo never",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let contents = std::fs::read_to_string(&log).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(stdout.contains("before"), "stdout was: {stdout}");
+    assert!(!stdout.contains("first"), "stdout was: {stdout}");
+    assert_eq!(contents, "first\nsecond\noops\nlast\n");
+}
+
+#[test]
+fn exec_opens_descriptors_for_reading() {
+    let root = std::env::temp_dir().join(format!("jsh_exec_read_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let input = root.join("input.txt");
+    std::fs::write(&input, "one\ntwo\n").unwrap();
+    let input = input.to_string_lossy();
+    let output = run_shell(&[
+        &format!("exec 3<'{input}'"),
+        "cat <&3",
+        &format!("cat 4<'{input}' <&4"),
+        "cat 5<<<here <&5",
+        &format!("echo \"[$(head -n 1 < '{input}')]\""),
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(stdout.matches("one\ntwo\n").count(), 2, "stdout was: {stdout}");
+    assert!(stdout.contains("here\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("[one]"), "stdout was: {stdout}");
+    assert!(stderr.is_empty(), "stderr was: {stderr}");
+}
+
+#[test]
+#[cfg(unix)]
+fn coproc_talks_to_a_background_command_unix() {
+    let output = run_shell(&[
+        "coproc tr a-z A-Z",
+        "echo pid:$COPROC_PID",
+        "echo hello >&${COPROC[1]}",
+        "echo world >&${COPROC[1]}",
+        "eval \"exec ${COPROC[1]}>&-\"",
+        "cat <&${COPROC[0]}",
+        "wait",
+        "echo unused >&${COPROC[1]}",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("HELLO\nWORLD\n"), "stdout was: {stdout}");
+    assert!(!stdout.contains("pid:\n"), "stdout was: {stdout}");
+    assert!(stderr.contains("bad file descriptor"), "stderr was: {stderr}");
+}
+
+#[test]
+fn dev_std_paths_use_the_current_streams() {
+    let root = std::env::temp_dir().join(format!("jsh_dev_std_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let file = root.join("group.txt");
+    let group = format!(
+        "{{ echo grouped > /dev/stdout; echo also 1> /dev/fd/1; }} > {}",
+        file.to_string_lossy()
+    );
+    let output = run_shell(&[
+        &group,
+        "echo to-stderr > /dev/stderr",
+        "source /dev/stdin <<< 'echo sourced:ok'",
+        "echo piped > /dev/stdout | tr a-z A-Z",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let contents = std::fs::read_to_string(&file).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(contents, "grouped\nalso\n");
+    assert!(stderr.contains("to-stderr"), "stderr was: {stderr}");
+    assert!(!stdout.contains("to-stderr"), "stdout was: {stdout}");
+    assert!(stdout.contains("sourced:"), "stdout was: {stdout}");
+    assert!(stdout.contains("PIPED"), "stdout was: {stdout}");
+}
+
+#[test]
+fn dev_fd_paths_use_the_shells_numbered_descriptors() {
+    let root = std::env::temp_dir().join(format!("jsh_dev_fd_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let file = root.join("four.txt");
+    let output = run_shell(&[
+        &format!("exec 4> {}", file.to_string_lossy()),
+        "echo four > /dev/fd/4",
+        "exec 4>&-",
+        &format!("exec 3< {}", file.to_string_lossy()),
+        "cat < /dev/fd/3",
+        "echo lost > /dev/fd/7",
+        "cat /dev/stdin <<< 'argument:ok'",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let contents = std::fs::read_to_string(&file).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(contents, "four\n");
+    assert!(stdout.contains("four\n"), "stdout was: {stdout}");
+    assert!(stderr.contains("7: bad file descriptor"), "stderr was: {stderr}");
+    assert!(stdout.contains("argument:ok\n"), "stdout was: {stdout}");
+}
+
 #[cfg(windows)]
 #[test]
 fn builtin_to_external_large_output_does_not_hang_windows() {
@@ -397,3 +850,1384 @@ fn help_unknown_topic_exits_nonzero() {
     assert!(stdout.contains("AFTER:1"), "stdout was: {stdout}");
     assert!(stderr.contains("nonexistent_topic_xyzzy"), "stderr was: {stderr}");
 }
+
+#[test]
+fn command_substitution_captures_stdout() {
+    let output = run_shell(&["echo [$(echo inner)]", "echo \"<`echo legacy`>\""]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[inner]"), "stdout was: {stdout}");
+    assert!(stdout.contains("<legacy>"), "stdout was: {stdout}");
+}
+
+#[test]
+fn command_substitution_sees_positional_parameters() {
+    let output = run_shell(&["set -- one \"it's two\"", "echo \"[$(echo $1)|$(echo \"$2\")|$(echo $#)]\""]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[one|it's two|2]"), "stdout was: {stdout}");
+}
+
+#[test]
+fn command_substitution_sees_associative_arrays() {
+    let output = run_shell(&[
+        "declare -A m",
+        "m[k]=v",
+        "key=\"a b\"",
+        "m[$key]=\"it's\"",
+        "echo \"[$(echo ${m[k]})|$(echo ${m[$key]})]\"",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("[v|it's]"), "stdout was: {stdout}");
+    assert!(stderr.is_empty(), "stderr was: {stderr}");
+}
+
+#[test]
+fn command_substitution_keeps_readonly_variables() {
+    let output = run_shell(&["readonly R=1", "export X=1", "readonly X", "echo \"[$(R=2; echo $R)|$(X=2; echo $X)]\""]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("[1|1]"), "stdout was: {stdout}");
+    assert!(stderr.contains("R: readonly variable"), "stderr was: {stderr}");
+}
+
+#[test]
+fn command_substitution_passes_exit_status_both_ways() {
+    let output = run_shell(&[
+        "false",
+        "echo IN:$(echo $?)",
+        "x=$(exit 3); echo OUT:$?",
+        "x=1; echo PLAIN:$?",
+        "set -e",
+        "x=$(false)",
+        "echo SURVIVED",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("IN:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("OUT:3"), "stdout was: {stdout}");
+    assert!(stdout.contains("PLAIN:0"), "stdout was: {stdout}");
+    assert!(!stdout.contains("SURVIVED"), "stdout was: {stdout}");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn parent_status_does_not_trip_errexit_in_subshells() {
+    let output = run_shell(&[
+        "set -e",
+        "false || echo \"[$(echo hi)]\"",
+        "false || echo bg:$? &",
+        "wait",
+        "set -x",
+        "false || echo \"<$(echo $?)>\"",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("[hi]"), "stdout was: {stdout}");
+    assert!(stdout.contains("bg:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("<1>"), "stdout was: {stdout}");
+    assert!(!stderr.contains("return"), "stderr was: {stderr}");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn subshells_carry_on_the_parents_line_number_and_seconds() {
+    let output = run_shell(&[
+        "a=1; b=2; c=3; echo L:$LINENO $(echo $LINENO)",
+        "PS4='+$LINENO: '",
+        "set -x",
+        "x=$(echo $LINENO)",
+        "set +x",
+        "sleep 1.1; echo S:$SECONDS $(echo $SECONDS)",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("L:1 1\n"), "stdout was: {stdout}");
+    assert!(stderr.contains("++4: echo 4\n+4: x=4\n"), "stderr was: {stderr}");
+    assert!(stdout.contains("S:1 1\n"), "stdout was: {stdout}");
+}
+
+#[test]
+fn return_stops_a_sourced_file() {
+    let script = std::env::temp_dir().join(format!("jsh_return_{}.sh", std::process::id()));
+    std::fs::write(&script, "echo BEFORE\nreturn 4\necho NOT_REACHED\n").unwrap();
+    let source = format!("source {}; echo STATUS:$?", script.display());
+    let output = run_shell(&[&source, "return 6; echo TOP:$?"]);
+    let _ = std::fs::remove_file(&script);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("BEFORE"), "stdout was: {stdout}");
+    assert!(!stdout.contains("NOT_REACHED"), "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:4"), "stdout was: {stdout}");
+    assert!(stdout.contains("TOP:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("can only `return' from a sourced script"), "stderr was: {stderr}");
+}
+
+#[test]
+fn backtick_substitution_splits_unless_quoted() {
+    let output = run_shell(&[
+        "printf '<%s>' `echo \"a   b\"`; echo",
+        "printf '<%s>' \"`echo 'a   b'`\"; echo",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("<a><b>"), "stdout was: {stdout}");
+    assert!(stdout.contains("<a   b>"), "stdout was: {stdout}");
+}
+
+#[test]
+fn command_substitution_does_not_leak_prompt_or_history() {
+    let home = std::env::temp_dir().join(format!("jsh_cmdsub_home_{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let home_str = home.to_string_lossy().to_string();
+
+    let output = run_shell_with_env(
+        &["echo X`echo inner_marker`X"],
+        &[("HOME", home_str.as_str()), ("USERPROFILE", home_str.as_str())],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let history = std::fs::read_to_string(home.join(".jsh_history")).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&home);
+
+    assert!(stdout.contains("Xinner_markerX"), "stdout was: {stdout}");
+    assert_eq!(
+        history.lines().filter(|line| line.contains("inner_marker")).count(),
+        1,
+        "history was:\n{history}"
+    );
+}
+
+#[test]
+fn heredoc_feeds_following_lines_to_stdin() {
+    let output = run_shell(&[
+        "export HD_NAME=world",
+        "cat << EOF",
+        "hello $HD_NAME",
+        "  \"kept\" \\$HD_NAME",
+        "EOF",
+        "echo AFTER:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("hello world\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("  \"kept\" $HD_NAME\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("AFTER:0"), "stdout was: {stdout}");
+}
+
+#[test]
+fn quoted_heredoc_delimiter_keeps_body_literal() {
+    let output = run_shell(&["cat <<'EOF' | tr a-z A-Z", "raw $HOME", "EOF"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("RAW $HOME"), "stdout was: {stdout}");
+}
+
+#[test]
+fn dash_heredoc_strips_leading_tabs() {
+    let output = run_shell(&["cat <<-EOF", "\t\tindented", "\t  mixed", "\tEOF", "echo DONE"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("indented\n  mixed\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("DONE"), "stdout was: {stdout}");
+}
+
+#[test]
+fn if_elif_else_picks_first_true_branch() {
+    let output = run_shell(&[
+        "if false; then echo A; elif true; then echo B; else echo C; fi",
+        "if false; then echo A; else echo C; fi",
+        "if false; then echo A; fi; echo STATUS:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("B\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("C\n"), "stdout was: {stdout}");
+    assert!(!stdout.contains("A\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:0"), "stdout was: {stdout}");
+}
+
+#[test]
+fn if_statement_spans_multiple_lines() {
+    let output = run_shell(&[
+        "if false",
+        "then",
+        "  echo WRONG",
+        "elif true; then",
+        "  if true; then echo NESTED; fi",
+        "  echo OK",
+        "fi",
+        "echo \"two",
+        "lines\" &&",
+        "  echo AFTER",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("NESTED\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("OK\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("two\nlines\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("AFTER"), "stdout was: {stdout}");
+    assert!(!stdout.contains("WRONG"), "stdout was: {stdout}");
+}
+
+#[test]
+fn misplaced_fi_is_syntax_error() {
+    let output = run_shell(&["fi", "echo STATUS:$?"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unexpected token `fi'"), "stderr was: {stderr}");
+    assert!(stdout.contains("STATUS:2"), "stdout was: {stdout}");
+}
+
+#[test]
+fn unterminated_if_at_eof_is_syntax_error() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn james-shell");
+    {
+        let stdin = child.stdin.as_mut().expect("stdin");
+        writeln!(stdin, "if true; then").expect("write line");
+        writeln!(stdin, "echo NEVER").expect("write line");
+    }
+    let output = child.wait_with_output().expect("wait output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unexpected end of file"), "stderr was: {stderr}");
+    assert!(!stdout.contains("NEVER"), "stdout was: {stdout}");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn brace_group_shares_redirection() {
+    let dir = std::env::temp_dir().join(format!("jsh-brace-group-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    let out = dir.join("group.txt");
+    let out = out.to_string_lossy();
+
+    let redirect_line = format!("{{ echo one; pwd; echo two; }} > '{out}'");
+    let append_line = format!("if true; then echo three; fi >> '{out}'");
+    let output = run_shell(&[
+        &format!("cd '{}'", dir.to_string_lossy()),
+        redirect_line.as_str(),
+        append_line.as_str(),
+        "{ echo visible; }",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let contents = std::fs::read_to_string(dir.join("group.txt")).expect("read group output");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(contents.starts_with("one\n"), "file was: {contents}");
+    assert!(contents.ends_with("two\nthree\n"), "file was: {contents}");
+    assert!(!stdout.contains("one"), "stdout was: {stdout}");
+    assert!(stdout.contains("visible"), "stdout was: {stdout}");
+}
+
+#[test]
+fn alias_expands_and_unalias_removes() {
+    let output = run_shell(&[
+        "alias greet='echo hello'",
+        "alias shout='greet | tr a-z A-Z'",
+        "greet world",
+        "shout",
+        "alias greet",
+        "unalias greet",
+        "greet again",
+        "echo STATUS:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("hello world\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("HELLO\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("alias greet='echo hello'"), "stdout was: {stdout}");
+    assert!(!stdout.contains("hello again"), "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:127"), "stdout was: {stdout}");
+}
+
+#[test]
+fn source_runs_file_in_current_shell() {
+    let dir = std::env::temp_dir().join(format!("jsh-source-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    let script = dir.join("setup.jsh");
+    std::fs::write(
+        &script,
+        "export SOURCED_VAR=from_file\nalias sourced='echo ALIASED'\necho from-script\ncd ..\nfalse\n",
+    )
+    .expect("write script");
+    let script = script.to_string_lossy();
+
+    let source_line = format!("source '{script}'");
+    let dot_line = format!(". '{script}' > '{}'", dir.join("out.txt").to_string_lossy());
+    let output = run_shell(&[
+        &format!("cd '{}'", dir.to_string_lossy()),
+        source_line.as_str(),
+        "echo STATUS:$?",
+        "echo VAR:$SOURCED_VAR",
+        "sourced",
+        "pwd",
+        dot_line.as_str(),
+        "source",
+        "echo USAGE:$?",
+        "source /nonexistent/jsh-script",
+        "echo MISSING:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let redirected = std::fs::read_to_string(dir.join("out.txt")).expect("read redirected output");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(stdout.matches("from-script").count(), 1, "stdout was: {stdout}");
+    assert_eq!(redirected, "from-script\n");
+    assert!(stdout.contains("STATUS:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("VAR:from_file"), "stdout was: {stdout}");
+    assert!(stdout.contains("ALIASED"), "stdout was: {stdout}");
+    let parent = dir.parent().expect("temp dir parent");
+    let parent = parent.canonicalize().unwrap_or_else(|_| parent.to_path_buf());
+    assert!(stdout.contains(&*parent.to_string_lossy()), "stdout was: {stdout}");
+    assert!(stdout.contains("USAGE:2"), "stdout was: {stdout}");
+    assert!(stdout.contains("MISSING:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("filename argument required"), "stderr was: {stderr}");
+}
+
+#[test]
+fn dash_c_runs_command_string_and_exits_with_its_status() {
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .expect("run james-shell -c")
+    };
+
+    let output = run(&["-c", "echo one && echo two; false"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run(&["-c", "exit 7; echo unreachable"]);
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(7));
+
+    let output = run(&["-c", "if true; then echo x"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("jsh: -c: line 1: syntax error"), "stderr was: {stderr}");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&["-c"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn dollar_zero_is_the_command_name_or_script_path() {
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .expect("run james-shell");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(run(&["-c", "echo $0"]), "jsh\n");
+    assert_eq!(run(&["-c", "echo $0 $(echo $0); echo $0 | cat", "myname", "a"]), "myname myname\nmyname\n");
+
+    let dir = std::env::temp_dir().join(format!("jsh_dollar_zero_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("named.jsh");
+    std::fs::write(&script, "echo \"$0 [$(echo $0)]\"\n").unwrap();
+    let path = script.to_string_lossy();
+    assert_eq!(run(&[&path]), format!("{path} [{path}]\n"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn command_line_options_set_modes_and_dollar_dash() {
+    let run = |args: &[&str], stdin: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(args)
+            .env("JSH_RC", "")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn james-shell");
+        child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+        child.wait_with_output().expect("wait for james-shell")
+    };
+
+    let output = run(&["--version"], "");
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("jsh (james-shell) "));
+    assert_eq!(output.status.code(), Some(0));
+    let output = run(&["--help"], "");
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: jsh [option...]"));
+
+    let output = run(&["-q"], "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), format!("jsh: -q: invalid option\n{}\n", james_shell::cli::USAGE));
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&["-eu", "-o", "pipefail", "-c", "echo \"[$-]\" $1 $#; set +e; echo \"[$-]\"", "name", "a", "b"], "");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[euc] a 2\n[uc]\n");
+
+    let output = run(&["-s", "x", "y"], "echo \"$# $2 [$-]\"\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2 y [s]\n");
+
+    let dir = std::env::temp_dir().join(format!("jsh_cli_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.jsh");
+    std::fs::write(&script, "echo \"script $1 [$-]\"\nexit 4\n").unwrap();
+    let output = run(&["-x", "+x", script.to_str().unwrap(), "arg"], "");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "script arg []\n");
+    assert_eq!(output.status.code(), Some(4));
+    let output = run(&[script.to_str().unwrap()], "");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with(&format!("jsh: {}: ", script.display())));
+    assert_eq!(output.status.code(), Some(127));
+
+    let output = run(&["-i"], "echo \"[$-]\"\n");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[is]"));
+}
+
+#[test]
+fn syntax_errors_point_at_the_offending_token() {
+    let run = |script: &str| {
+        Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(["-c", script])
+            .stdin(Stdio::null())
+            .output()
+            .expect("run james-shell -c")
+    };
+
+    let output = run("echo a | | wc");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "jsh: -c: line 1: syntax error near unexpected token `|'\necho a | | wc\n         ^ column 10\n"
+    );
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run("echo ok\nif true; then\n  echo \"$(date\nfi");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("jsh: -c: line 3: syntax error: unterminated command substitution\n  echo \"$(date\n        ^ column 9\n"),
+        "stderr was: {stderr}"
+    );
+}
+
+#[test]
+fn check_mode_reports_syntax_errors_without_running_anything() {
+    let dir = std::env::temp_dir().join(format!("jsh_check_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let marker = dir.join("ran");
+    let good = dir.join("good.jsh");
+    let bad = dir.join("bad.jsh");
+    std::fs::write(&good, format!("touch {}\nif true; then\n  echo ok\nfi\n", marker.display())).unwrap();
+    std::fs::write(&bad, "echo a | | b\necho fine\necho ;;\n").unwrap();
+
+    let check = |paths: &[&std::path::Path]| {
+        Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .arg("--check")
+            .args(paths)
+            .stdin(Stdio::null())
+            .output()
+            .expect("run james-shell --check")
+    };
+
+    let output = check(&[&good]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    assert!(!marker.exists(), "--check ran the script");
+
+    let output = check(&[&good, &bad]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty(), "stdout was: {}", String::from_utf8_lossy(&output.stdout));
+    let bad = bad.display();
+    assert!(stderr.contains(&format!("jsh: {bad}: line 1: syntax error near unexpected token `|'")), "{stderr}");
+    assert!(stderr.contains(&format!("jsh: {bad}: line 3: syntax error near unexpected token `;'")), "{stderr}");
+
+    let output = run_shell(&["echo before", "set -n", "echo after", "set +n", "echo still"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("before"), "stdout was: {stdout}");
+    assert!(!stdout.contains("after") && !stdout.contains("still"), "stdout was: {stdout}");
+}
+
+#[test]
+fn shell_variables_are_not_exported_until_export() {
+    let output = run_shell(&[
+        "JSH_LOCAL_VAR=secret OTHER=$JSH_LOCAL_VAR",
+        "echo shell:$JSH_LOCAL_VAR:$OTHER",
+        "sh -c 'echo child:$JSH_LOCAL_VAR'",
+        "echo sub:$(echo $JSH_LOCAL_VAR)",
+        "export JSH_LOCAL_VAR",
+        "sh -c 'echo exported:$JSH_LOCAL_VAR'",
+        "JSH_LOCAL_VAR=changed",
+        "sh -c 'echo reassigned:$JSH_LOCAL_VAR'",
+        "unset JSH_LOCAL_VAR",
+        "sh -c 'echo unset:$JSH_LOCAL_VAR'",
+        "export 1bad",
+        "echo STATUS:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("shell:secret:secret"), "stdout was: {stdout}");
+    assert!(stdout.contains("child:\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("sub:secret"), "stdout was: {stdout}");
+    assert!(stdout.contains("exported:secret"), "stdout was: {stdout}");
+    assert!(stdout.contains("reassigned:changed"), "stdout was: {stdout}");
+    assert!(stdout.contains("unset:\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("not a valid identifier"), "stderr was: {stderr}");
+}
+
+#[test]
+fn prefix_assignments_apply_to_one_command() {
+    let output = run_shell(&[
+        "JSH_PREFIX=outer",
+        "JSH_PREFIX=inner JSH_OTHER=2 sh -c 'echo child:$JSH_PREFIX:$JSH_OTHER'",
+        "echo shell:$JSH_PREFIX:[$JSH_OTHER]",
+        "JSH_PIPED=yes env | grep JSH_PIPED",
+        "sh -c 'echo after:[$JSH_PIPED]'",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("child:inner:2"), "stdout was: {stdout}");
+    assert!(stdout.contains("shell:outer:[]"), "stdout was: {stdout}");
+    assert!(stdout.contains("JSH_PIPED=yes"), "stdout was: {stdout}");
+    assert!(stdout.contains("after:[]"), "stdout was: {stdout}");
+}
+
+#[test]
+fn sourced_file_gets_its_own_positional_parameters() {
+    let dir = std::env::temp_dir().join(format!("jsh-positional-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    let script = dir.join("args.jsh");
+    std::fs::write(
+        &script,
+        "echo count:$#:$1\nprintf '[%s]\\n' \"$@\"\nshift\necho shifted:$#:$1\nshift 5\necho overshift:$?\n",
+    )
+    .expect("write script");
+
+    let source_line = format!("source '{}' 'a b' c", script.to_string_lossy());
+    let output = run_shell(&[source_line.as_str(), "echo outer:$#"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(stdout.contains("count:2:a b"), "stdout was: {stdout}");
+    assert!(stdout.contains("[a b]\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("[c]\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("shifted:1:c"), "stdout was: {stdout}");
+    assert!(stdout.contains("overshift:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("outer:0"), "stdout was: {stdout}");
+}
+
+#[test]
+fn default_value_expansions() {
+    let output = run_shell(&[
+        "echo [${JSH_PE_UNSET:-fallback}] [${JSH_PE_UNSET:+alt}]",
+        "echo assigned:${JSH_PE_NEW:=first} then:$JSH_PE_NEW",
+        "printf '<%s>\\n' ${JSH_PE_UNSET:-'one field'}",
+        "echo ${JSH_PE_UNSET:?is required}",
+        "echo survived",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("[fallback] []"), "stdout was: {stdout}");
+    assert!(stdout.contains("assigned:first then:first"), "stdout was: {stdout}");
+    assert!(stdout.contains("<one field>"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: JSH_PE_UNSET: is required"), "stderr was: {stderr}");
+    // A shell that is not interactive gives up on the failed expansion.
+    assert!(!stdout.contains("survived"), "stdout was: {stdout}");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn required_parameter_ends_a_command_string() {
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-c", "x=${JSH_PE_UNSET:?unset}; echo survived"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.stdout.is_empty(), "stdout was: {}", String::from_utf8_lossy(&output.stdout));
+    assert!(stderr.contains("jsh: JSH_PE_UNSET: unset"), "stderr was: {stderr}");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn pattern_removal_expansions() {
+    let output = run_shell(&[
+        "file=notes/report.txt",
+        "echo base:${file##*/} stem:${file%.txt} dir:${file%/*}",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("base:report.txt stem:notes/report dir:notes"), "stdout was: {stdout}");
+}
+
+#[test]
+fn pattern_substitution_expansions() {
+    let output = run_shell(&[
+        "path=/usr/local/bin",
+        "echo first:${path/\\//@} all:${path//\\//:}",
+        "echo \"${path//o/0}\"",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("first:@usr/local/bin all::usr:local:bin"), "stdout was: {stdout}");
+    assert!(stdout.contains("/usr/l0cal/bin"), "stdout was: {stdout}");
+}
+
+#[test]
+fn ansi_c_quoting_passes_newline_as_one_argument() {
+    let output = run_shell(&["printf '[%s]' $'one\\ntwo' $'a\\tb'"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[one\ntwo][a\tb]"), "stdout was: {stdout}");
+}
+
+#[test]
+fn glob_options_control_dotfiles_and_unmatched_patterns() {
+    let dir = std::env::temp_dir().join(format!("jsh-globopts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    std::fs::write(dir.join(".hidden"), "").expect("write dotfile");
+    std::fs::write(dir.join("shown"), "").expect("write file");
+
+    let cd_line = format!("cd '{}'", dir.to_string_lossy());
+    let output = run_shell(&[
+        cd_line.as_str(),
+        "echo default *",
+        "shopt -s dotglob",
+        "echo dotglob *",
+        "shopt -s nullglob",
+        "echo null [*.nomatch]",
+        "shopt -s failglob",
+        "echo never *.nomatch",
+        "echo failglob:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(stdout.contains("default shown\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("dotglob .hidden shown"), "stdout was: {stdout}");
+    assert!(stdout.contains("null\n"), "stdout was: {stdout}");
+    assert!(!stdout.contains("never"), "stdout was: {stdout}");
+    assert!(stdout.contains("failglob:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: no match: *.nomatch"), "stderr was: {stderr}");
+}
+
+#[test]
+fn set_e_exits_on_untested_failure() {
+    let output = run_shell(&[
+        "set -e",
+        "false || echo recovered",
+        "if false; then echo no; fi",
+        "false && echo no",
+        "echo still-running",
+        "sh -c 'exit 3'",
+        "echo not-reached",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("recovered"), "stdout was: {stdout}");
+    assert!(stdout.contains("still-running"), "stdout was: {stdout}");
+    assert!(!stdout.contains("not-reached"), "stdout was: {stdout}");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn set_options_pipefail_xtrace_nounset_and_parameters() {
+    let output = run_shell(&[
+        "false | true; echo plain:$?",
+        "set -o pipefail",
+        "sh -c 'exit 4' | false | true; echo pipefail:$?",
+        "set +o pipefail -x",
+        "echo 'two words'",
+        "set +x -u",
+        "set -- a 'b c'",
+        "echo count:$# second:$2",
+        "echo $JSH_SET_UNBOUND; echo nounset:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("plain:0"), "stdout was: {stdout}");
+    assert!(stdout.contains("pipefail:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("+ echo 'two words'"), "stderr was: {stderr}");
+    assert!(stdout.contains("count:2 second:b c"), "stdout was: {stdout}");
+    // An unbound variable ends a shell that is not interactive.
+    assert!(stderr.contains("jsh: JSH_SET_UNBOUND: unbound variable"), "stderr was: {stderr}");
+    assert!(!stdout.contains("nounset:"), "stdout was: {stdout}");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn xtrace_expands_ps4_repeats_it_in_subshells_and_follows_the_trace_fd() {
+    let dir = std::env::temp_dir().join(format!("jsh_xtrace_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("trace.log");
+    let script = format!(
+        "set -x; echo $(echo inner); PS4='+$#: '; set -- a b; echo two\n\
+         exec 3>'{}'; JSH_XTRACEFD=3; echo three; JSH_XTRACEFD=7; echo four",
+        log.display()
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-c", &script])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    let traced = std::fs::read_to_string(&log).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "inner\ntwo\nthree\nfour\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("++ echo inner\n+ echo inner\n"), "stderr was: {stderr}");
+    assert!(stderr.contains("+2: echo two\n"), "stderr was: {stderr}");
+    assert!(!stderr.contains("echo three"), "stderr was: {stderr}");
+    // An fd that is not open falls back to stderr.
+    assert!(stderr.contains("+2: echo four\n"), "stderr was: {stderr}");
+    assert_eq!(traced, "+2: echo three\n+2: JSH_XTRACEFD=7\n");
+}
+
+#[test]
+fn dynamic_variables_are_computed_on_each_expansion() {
+    let script = "echo seconds:$SECONDS; SECONDS=100; echo seconds:$SECONDS\n\
+                  RANDOM=7; a=$RANDOM; RANDOM=7; b=$RANDOM; [ \"$a\" = \"$b\" ] && echo seeded\n\
+                  [ $RANDOM -le 32767 ] && echo in-range\n\
+                  \n\
+                  echo line:$LINENO; if true; then\n\
+                  echo line:$LINENO; fi\n\
+                  EPOCHSECONDS=5; [ $EPOCHSECONDS -gt 1600000000 ] && echo epoch";
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-c", script])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "seconds:0\nseconds:100\nseeded\nin-range\nline:5\nline:5\nepoch\n",
+        "stderr was: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn exit_trap_runs_when_shell_exits() {
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-c", "trap 'echo cleanup:$?' EXIT; echo body; exit 3"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "body\ncleanup:3\n");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[cfg(unix)]
+#[test]
+fn signal_traps_still_pending_run_before_the_exit_trap() {
+    // `kill` is a builtin, so the signal has been taken by the time `exit`
+    // runs, and its trap is pending rather than racing the exit.
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-c", "trap 'echo cleanup:$?' EXIT; trap 'echo caught usr1' USR1; kill -USR1 $$ && exit 3"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "caught usr1\ncleanup:3\n");
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args(["-c", "trap 'echo cleanup:$?' EXIT; trap 'exit 5' USR1; kill -USR1 $$ && exit 3"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "cleanup:5\n");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn signal_trap_runs_between_commands() {
+    let output = run_shell(&[
+        "trap 'echo caught usr1' USR1",
+        "trap -p USR1",
+        "kill -USR1 $$",
+        "echo after",
+        "trap - USR1",
+        "trap -p USR1",
+        "trap 'echo x' NOSUCHSIG; echo status:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stdout.matches("trap -- 'echo caught usr1' USR1").count(), 1, "stdout was: {stdout}");
+    let caught = stdout.find("caught usr1\n").expect("trap ran");
+    assert!(caught < stdout.find("after").expect("after ran"), "stdout was: {stdout}");
+    assert!(stdout.contains("status:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("NOSUCHSIG: invalid signal specification"), "stderr was: {stderr}");
+}
+
+#[test]
+fn printf_builtin_formats_through_pipes_and_redirections() {
+    let dir = std::env::temp_dir().join(format!("jsh-printf-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    let out = dir.join("out.txt");
+
+    let redirect_line = format!("printf '%-3s|%03d\\n' a 7 bb 42 > '{}'", out.to_string_lossy());
+    let output = run_shell(&[
+        redirect_line.as_str(),
+        "printf '%s\\n' one two | sort -r",
+        "printf '%x %q\\n' 255 'a b'",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let file = std::fs::read_to_string(&out).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(file, "a  |007\nbb |042\n");
+    assert!(stdout.contains("two\none\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("ff 'a b'\n"), "stdout was: {stdout}");
+}
+
+#[test]
+fn eval_runs_arguments_in_current_shell() {
+    let output = run_shell(&[
+        "cmd='echo first; JSH_EVAL_VAR=set'",
+        "eval $cmd",
+        "echo var:$JSH_EVAL_VAR",
+        "false; eval 'echo status:$?'",
+        "eval 'sh -c \"exit 5\"'; echo after:$?",
+        "eval 'if true'; echo syntax:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("first\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("var:set"), "stdout was: {stdout}");
+    assert!(stdout.contains("status:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("after:5"), "stdout was: {stdout}");
+    assert!(stdout.contains("syntax:2"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: eval: line 1: syntax error"), "stderr was: {stderr}");
+}
+
+#[test]
+fn complete_registers_lists_and_removes_specs() {
+    let output = run_shell(&[
+        "complete -W 'start stop' svc",
+        "complete -d -C 'git branch' git",
+        "complete -p",
+        "complete -r svc",
+        "complete -p svc; echo status:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("complete -d -C 'git branch' git\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("complete -W 'start stop' svc\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("status:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("svc: no completion specification"), "stderr was: {stderr}");
+}
+
+#[test]
+fn ps1_escapes_are_expanded_before_each_prompt() {
+    let output = run_shell_with_env(&["false", "PS1='\\u|\\j> '"], &[("PS1", "[\\u:\\?]\\$ "), ("USER", "tester")]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("[tester:0]"), "stdout was: {stdout}");
+    assert!(stdout.contains("[tester:1]"), "stdout was: {stdout}");
+    assert!(stdout.ends_with("tester|0> "), "stdout was: {stdout}");
+}
+
+#[test]
+fn prompt_shows_how_long_a_slow_command_took() {
+    let output = run_shell_with_env(
+        &["sleep 0.3", "echo ms:$CMD_DURATION", "true"],
+        &[("PS1", "<\\D> "), ("JSH_DURATION_THRESHOLD", "0.25")],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let prompts: Vec<&str> = stdout.split("> ").collect();
+    // Before anything runs, after the sleep, after the quick echo, and after true.
+    assert_eq!(prompts[0], "<", "stdout was: {stdout}");
+    assert!(prompts[1].starts_with("<0.") && prompts[1].ends_with('s'), "stdout was: {stdout}");
+    let ms: u64 = prompts[2].trim_start_matches("ms:").split('\n').next().unwrap().parse().unwrap();
+    assert!(ms >= 300, "stdout was: {stdout}");
+    assert!(prompts[2].ends_with("\n<"), "stdout was: {stdout}");
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn slow_commands_send_a_desktop_notification_when_asked() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("jsh_notify_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("notified");
+    let fake = dir.join("notify-send");
+    std::fs::write(&fake, format!("#!/bin/sh\necho \"$@\" >> '{}'\n", log.display())).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+
+    let run = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(args)
+            .env("PATH", &path)
+            .env("JSH_NOTIFY_AFTER", "0.2")
+            .env("JSH_RC", "")
+            .env_remove("WINDOWID")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn james-shell");
+        child.stdin.take().unwrap().write_all(b"true\nsleep 0.3 && false\nexit\n").unwrap();
+        child.wait_with_output().expect("wait for james-shell")
+    };
+
+    // Only interactive shells notify.
+    run(&[]);
+    assert!(!log.exists());
+    run(&["-i"]);
+    // The notifier is reaped on a thread; give it a moment to write.
+    for _ in 0..50 {
+        if log.exists() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let notified = std::fs::read_to_string(&log).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(notified.starts_with("-- jsh sleep 0.3 && false failed (exit 1) after 0."), "notified: {notified}");
+    assert_eq!(notified.lines().count(), 1, "notified: {notified}");
+}
+
+#[test]
+fn ps2_is_shown_while_a_command_is_unfinished() {
+    let output = run_shell(&["PS2='more> '", "echo \"a", "b\" one \\", "two"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("more> more> a\nb one two\n"), "stdout was: {stdout}");
+}
+
+#[test]
+fn time_reports_on_stderr_in_timeformat() {
+    let output = run_shell(&[
+        "time -p true | false; echo STATUS:$?",
+        "TIMEFORMAT='took %2R'",
+        "time echo timed",
+        "{ time echo quiet; } 2>/dev/null",
+        "TIMEFORMAT=",
+        "time echo silent",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("STATUS:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("timed") && stdout.contains("quiet") && stdout.contains("silent"), "stdout was: {stdout}");
+    for line in ["real ", "user ", "sys "] {
+        assert!(stderr.lines().any(|l| l.starts_with(line)), "stderr was: {stderr}");
+    }
+    assert_eq!(stderr.matches("took ").count(), 1, "stderr was: {stderr}");
+}
+
+#[test]
+fn command_not_found_suggests_a_close_builtin() {
+    let output = run_shell(&["ehco hi", "echo STATUS:$?", "zzqqxxyy"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("STATUS:127"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: command not found: ehco\njsh: did you mean 'echo'?"), "stderr was: {stderr}");
+    assert!(!stderr.contains("did you mean 'zz"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn hash_remembers_commands_until_path_changes() {
+    let output = run_shell(&[
+        "hash",
+        "ls >/dev/null",
+        "ls >/dev/null",
+        "hash",
+        "type ls",
+        "PATH=$PATH",
+        "PATH=/bin:$PATH",
+        "hash",
+        "hash nosuchcommandanywhere",
+        "echo STATUS:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let listing = stdout.split("hits\tcommand\n").nth(1).unwrap_or_default();
+    assert!(listing.starts_with("   2\t") && listing.contains("/ls\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("ls is hashed ("), "stdout was: {stdout}");
+    assert_eq!(stdout.matches("hash table empty").count(), 2, "stdout was: {stdout}");
+    assert!(stdout.contains("STATUS:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: hash: nosuchcommandanywhere: not found"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn type_flags_and_command_v_classify_names() {
+    let output = run_shell(&[
+        "alias ll='ls -l'",
+        "echo T:$(type -t ll if cd ls)",
+        "echo A:$(type -a echo)",
+        "echo P:$(type -p cd ls)",
+        "echo V:$(command -v ll cd ls)",
+        "command -v nosuchcommandanywhere",
+        "echo VS:$?",
+        "command ll",
+        "command echo RAN",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("T:alias keyword builtin file\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("A:echo is a shell builtin echo is /"), "stdout was: {stdout}");
+    assert!(stdout.contains("P:/") && stdout.contains("/ls\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("V:alias ll='ls -l' cd /"), "stdout was: {stdout}");
+    assert!(stdout.contains("VS:1"), "stdout was: {stdout}");
+    assert!(stdout.contains("RAN"), "stdout was: {stdout}");
+    assert!(stderr.contains("command not found: ll"), "stderr was: {stderr}");
+}
+
+#[test]
+fn readonly_and_declare_attributes_are_enforced() {
+    let output = run_shell(&[
+        "readonly LOCKED=one",
+        "LOCKED=two",
+        "echo ASSIGN:$?:$LOCKED",
+        "unset LOCKED",
+        "echo UNSET:$?:$LOCKED",
+        "declare -i total=2+3*4",
+        "total=total-4",
+        "echo TOTAL:$total",
+        "declare -p LOCKED total",
+        "local scoped=1",
+        "echo LOCAL:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("ASSIGN:1:one"), "stdout was: {stdout}");
+    assert!(stdout.contains("UNSET:1:one"), "stdout was: {stdout}");
+    assert!(stdout.contains("TOTAL:10"), "stdout was: {stdout}");
+    assert!(stdout.contains("declare -r LOCKED='one'\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("declare -i total='10'\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("LOCAL:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: LOCKED: readonly variable"), "stderr was: {stderr}");
+    assert!(stderr.contains("jsh: local: can only be used in a function"), "stderr was: {stderr}");
+}
+
+#[test]
+fn associative_arrays_store_and_list_elements() {
+    let output = run_shell(&[
+        "declare -A color",
+        "color[apple]=red",
+        "fruit=sky",
+        "color[$fruit]=blue",
+        "echo GET:${color[apple]}:${color[$fruit]}:${color[none]:-unset}",
+        "echo KEYS:${!color[@]}",
+        "echo COUNT:${#color[@]}",
+        "unset color[apple]",
+        "declare -p color",
+        "plain[key]=value",
+        "echo PLAIN:$?",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("GET:red:blue:unset"), "stdout was: {stdout}");
+    assert!(stdout.contains("KEYS:apple sky"), "stdout was: {stdout}");
+    assert!(stdout.contains("COUNT:2"), "stdout was: {stdout}");
+    assert!(stdout.contains("declare -A color=([sky]='blue')"), "stdout was: {stdout}");
+    assert!(stdout.contains("PLAIN:1"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: plain: not an associative array"), "stderr was: {stderr}");
+}
+
+#[test]
+fn echo_flags_control_newline_and_escapes() {
+    let output = run_shell(&[
+        "echo -n A; echo B",
+        "echo -e 'tab\\there\\x21'",
+        "echo -E 'raw\\t'",
+        "echo -e 'cut\\chidden'; echo",
+        "echo -x -- -n",
+        "shopt -s xpg_echo",
+        "echo 'posix\\ttab'",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains("AB\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("tab\there!\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("raw\\t\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("cut\n") && !stdout.contains("hidden"), "stdout was: {stdout}");
+    assert!(stdout.contains("-x -- -n\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("posix\ttab\n"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn export_lists_marks_and_unmarks_variables() {
+    let output = run_shell(&[
+        "JSH_EXPORT_ME=shell",
+        "sh -c 'echo CHILD1:$JSH_EXPORT_ME'",
+        "export JSH_EXPORT_ME",
+        "sh -c 'echo CHILD2:$JSH_EXPORT_ME'",
+        "export",
+        "export -n JSH_EXPORT_ME",
+        "sh -c 'echo CHILD3:$JSH_EXPORT_ME'",
+        "echo SHELL:$JSH_EXPORT_ME",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains("CHILD1:\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("CHILD2:shell\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("declare -x JSH_EXPORT_ME='shell'\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("CHILD3:\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("SHELL:shell\n"), "stdout was: {stdout}");
+}
+
+#[test]
+fn history_imports_and_exports_other_shells_histories() {
+    let home = std::env::temp_dir().join(format!("jsh_history_import_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::write(home.join(".jsh_history"), "echo mine\n").unwrap();
+    std::fs::write(home.join("bash_history"), "#1600000000\nls -la\ngit status\n").unwrap();
+    std::fs::write(home.join("fish_history"), "- cmd: make\n  when: 1600000002\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args([
+            "-c",
+            "history --import bash ~/bash_history && history --import fish ~/fish_history \
+             && history --export bash ~/exported; history --import zsh x; echo status:$?; \
+             history 2; history x; echo count:$?",
+        ])
+        .env("HOME", &home)
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    let jsh_history = std::fs::read_to_string(home.join(".jsh_history")).unwrap();
+    let exported = std::fs::read_to_string(home.join("exported")).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&home);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(jsh_history, "make\nls -la\ngit status\necho mine\n");
+    assert_eq!(exported, jsh_history);
+    assert!(stdout.contains("status:2"), "stdout was: {stdout}");
+    assert!(stderr.contains("jsh: history: zsh: unknown history format"), "stderr was: {stderr}");
+    assert!(stdout.contains("    3  git status\n    4  echo mine\ncount:1\n"), "stdout was: {stdout}");
+    assert!(stderr.contains("history: x: numeric argument required"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn timeout_signals_commands_that_outlive_the_duration() {
+    let started = std::time::Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args([
+            "-c",
+            "timeout 0.2 sleep 10; echo timed:$?; timeout 5 sh -c 'exit 3'; echo own:$?; \
+             timeout -k 0.2 0.2 sh -c 'trap \"\" TERM; sleep 10'; echo killed:$?; \
+             timeout 1 echo hi | cat; timeout 1; echo usage:$?; timeout 0.2 cd /; echo builtin:$?",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    let elapsed = started.elapsed();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stdout, "timed:124\nown:3\nkilled:137\nhi\nusage:125\nbuiltin:127\n", "stderr was: {stderr}");
+    assert!(stderr.contains("timeout: usage:"), "stderr was: {stderr}");
+    assert!(stderr.contains("jsh: timeout: cd: a shell builtin cannot be run with a time limit"), "stderr was: {stderr}");
+    assert!(!stderr.contains("did you mean"), "stderr was: {stderr}");
+    assert!(elapsed < Duration::from_secs(5), "took {elapsed:?}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn external_commands_start_in_their_own_group_with_default_signals() {
+    // `trap ''` makes the shell ignore SIGQUIT, which takes the fork+exec
+    // path instead of posix_spawn; children must look the same either way.
+    let output = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+        .args([
+            "-c",
+            "grep SigIgn /proc/self/status; cut -d' ' -f1,5 /proc/self/stat; \
+             cut -d' ' -f1,5 /proc/self/stat | cat; trap '' QUIT; \
+             grep SigIgn /proc/self/status; cut -d' ' -f1,5 /proc/self/stat",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run james-shell -c");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 5, "stdout was: {stdout}\nstderr was: {stderr}");
+
+    let signals = [2, 3, 13, 20]; // INT, QUIT, PIPE, TSTP
+    for line in [lines[0], lines[3]] {
+        let mask = line.strip_prefix("SigIgn:").map(str::trim).expect("SigIgn line");
+        let mask = u64::from_str_radix(mask, 16).expect("hex mask");
+        for signal in signals {
+            assert_eq!(mask & 1 << (signal - 1), 0, "signal {signal} ignored: {line}");
+        }
+    }
+    for line in [lines[1], lines[2], lines[4]] {
+        let (pid, pgid) = line.split_once(' ').expect("pid and pgid");
+        assert_eq!(pid, pgid, "not a group leader: {line}");
+    }
+}
+
+/// Spawn latency of external commands, with and without posix_spawn. Run
+/// with `cargo test --release -- --ignored --nocapture spawn_latency`.
+#[test]
+#[ignore]
+fn spawn_latency() {
+    const SPAWNS: u32 = 1000;
+    let commands = vec!["/bin/true"; SPAWNS as usize];
+    for (path, prelude) in [("posix_spawn", ":"), ("fork+exec", "trap '' QUIT")] {
+        let lines: Vec<&str> = std::iter::once(prelude).chain(commands.iter().copied()).collect();
+        let started = std::time::Instant::now();
+        let output = run_shell(&lines);
+        let elapsed = started.elapsed();
+        assert!(output.status.success(), "{output:?}");
+        eprintln!("{path}: {:?} per command", elapsed / SPAWNS);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn sigint_sent_to_the_shell_reaches_what_it_waits_on() {
+    use std::io::{BufRead, BufReader, Read};
+
+    // An empty HOME keeps the user's own history and rc files out of it.
+    let home = std::env::temp_dir().join(format!("jsh_sigint_home_{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    for script in ["echo ready; sleep 5; echo after", "echo ready; x=$(sleep 5); echo after"] {
+        let started = std::time::Instant::now();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(["-c", script])
+            .env("HOME", &home)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn james-shell");
+        // Interrupt once the shell is running the script, however long it
+        // takes to get there; the command it starts next must still get it.
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut ready = String::new();
+        stdout.read_line(&mut ready).expect("read ready line");
+        assert_eq!(ready, "ready\n", "{script}");
+        let killed = Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()
+            .expect("run kill");
+        assert!(killed.success());
+        let mut rest = String::new();
+        stdout.read_to_string(&mut rest).expect("read stdout");
+        let status = child.wait().expect("wait for shell");
+
+        assert_eq!(status.code(), Some(130), "{script}: {status:?}");
+        assert!(!rest.contains("after"), "{script}: stdout was {rest}");
+        assert!(started.elapsed() < Duration::from_secs(3), "{script}: took {:?}", started.elapsed());
+    }
+    let _ = std::fs::remove_dir_all(&home);
+}
+
+#[test]
+fn wslpath_translates_between_drive_mounts_and_windows_paths() {
+    let output = run_shell_with_env(
+        &[
+            "wslpath 'C:\\Users\\me'",
+            "wslpath -w /mnt/d/src",
+            "wslpath -m /mnt/c/Users /home/me",
+            "echo status=$?",
+            "MSYSTEM=MINGW64 wslpath 'C:\\tmp'",
+            "wslpath -x /mnt/c; echo usage=$?",
+        ],
+        &[("WSL_DISTRO_NAME", "")],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stdout.contains("/mnt/c/Users/me\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("D:\\src\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("C:/Users\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("status=1\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("/c/tmp\n"), "stdout was: {stdout}");
+    assert!(stderr.contains("wslpath: /home/me: cannot translate"), "stderr was: {stderr}");
+    assert!(stdout.contains("usage=2\n"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn shopt_wslpath_hands_windows_programs_windows_paths() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("jsh_wslpath_reg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fake = dir.join("tool.exe");
+    std::fs::write(&fake, "#!/bin/sh\necho \"$@\"\n").unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+
+    let output = run_shell_with_env(
+        &[
+            "tool.exe /c /mnt/c/Users/me",
+            "shopt -s wslpath",
+            "tool.exe /c /mnt/c/Users/me",
+            "tool.exe /mnt/d/src | cat",
+            "echo /mnt/c/Users/me",
+        ],
+        &[("PATH", &path)],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(stdout.contains("/c /mnt/c/Users/me\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("/c C:\\Users\\me\n"), "stdout was: {stdout}");
+    assert!(stdout.contains("D:\\src\n"), "stdout was: {stdout}");
+    // Only programs on the Windows side get their arguments translated.
+    assert!(stdout.contains("> /mnt/c/Users/me\n"), "stdout was: {stdout}");
+}
+
+#[test]
+fn ignoreeof_makes_an_interactive_shell_ignore_ctrl_d() {
+    let run = |args: &[&str], input: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(args)
+            .env("JSH_RC", "")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn james-shell");
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().expect("wait for james-shell")
+    };
+    let ignored = |output: &std::process::Output| {
+        String::from_utf8_lossy(&output.stderr).matches("Use \"exit\" to leave the shell.").count()
+    };
+
+    // Each read at the end of a pipe is another EOF, so the shell still
+    // exits once the count runs out.
+    let output = run(&["-i"], "IGNOREEOF=2\n");
+    assert_eq!(ignored(&output), 2, "{output:?}");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(ignored(&run(&["-i"], "IGNOREEOF=x\n")), 10);
+    assert_eq!(ignored(&run(&["-i"], "IGNOREEOF=0\n")), 0);
+    assert_eq!(ignored(&run(&["-i"], "true\n")), 0);
+    assert_eq!(ignored(&run(&[], "IGNOREEOF=2\n")), 0);
+}
+
+#[test]
+fn default_prompt_shows_a_failed_status_when_interactive() {
+    let run = |args: &[&str], input: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_james-shell"))
+            .args(args)
+            .env("JSH_RC", "")
+            .env_remove("PS1")
+            .env_remove("JSH_PROMPT")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn james-shell");
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let output = child.wait_with_output().expect("wait for james-shell");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let stdout = run(&["-i"], "sh -c 'exit 3'\ntrue\nshopt -u promptstatus\nfalse\n");
+    assert_eq!(stdout, "jsh> jsh [3]> jsh> jsh> jsh> ");
+    assert_eq!(run(&["-i"], "false\nPS1='$ '\nfalse\n"), "jsh> jsh [1]> $ $ ");
+    assert_eq!(run(&[], "false\n"), "jsh> jsh> ");
+    // A pipeline's subshell still sees the option turned off.
+    let stdout = run(&["-i"], "shopt -u promptstatus\ntrue | shopt promptstatus\n");
+    assert!(stdout.contains("promptstatus") && stdout.contains("\toff\n"), "stdout was: {stdout}");
+}
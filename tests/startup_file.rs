@@ -0,0 +1,179 @@
+/// Integration tests for the `~/.jshrc` startup file.
+///
+/// Each test writes its rc file into its own temp HOME directory.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Spawn the shell with `HOME`/`USERPROFILE` set to `home` plus `envs`, feed
+/// `lines` via stdin (followed by `exit`), and return the full output.
+fn run_shell_with_home(lines: &[&str], home: &Path, envs: &[(&str, &str)]) -> std::process::Output {
+    run_shell_with_args(&[], lines, home, envs)
+}
+
+/// [`run_shell_with_home`], passing `args` on the command line.
+fn run_shell_with_args(args: &[&str], lines: &[&str], home: &Path, envs: &[(&str, &str)]) -> std::process::Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_james-shell"));
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .env("HOME", home)
+        .env("USERPROFILE", home)
+        .env_remove("JSH_RC");
+    for (key, value) in envs {
+        command.env(key, value);
+    }
+    let mut child = command.spawn().expect("spawn james-shell");
+
+    {
+        let stdin = child.stdin.as_mut().expect("stdin");
+        for line in lines {
+            writeln!(stdin, "{line}").expect("write line");
+        }
+        writeln!(stdin, "exit").expect("write exit");
+    }
+
+    child.wait_with_output().expect("wait output")
+}
+
+/// RAII temp directory — created on construction, deleted on drop.
+struct TempHome(PathBuf);
+
+impl TempHome {
+    fn new(label: &str) -> Self {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let dir = std::env::temp_dir().join(format!(
+            "jsh_test_rc_{label}_{}_{}",
+            std::process::id(),
+            unique
+        ));
+        std::fs::create_dir_all(&dir).expect("create temp home");
+        TempHome(dir)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::write(&path, contents).expect("write rc file");
+        path
+    }
+}
+
+impl Drop for TempHome {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn jshrc_aliases_and_exports_are_loaded() {
+    let home = TempHome::new("load");
+    home.write(
+        ".jshrc",
+        "# startup\nexport RC_GREETING=hello # greeting\nalias greet='echo $RC_GREETING'\nif true; then\n  alias shout='echo LOUD'\nfi\n",
+    );
+
+    let output = run_shell_with_home(&["greet", "shout"], home.path(), &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("hello"), "stdout: {stdout}\nstderr: {stderr}");
+    assert!(stdout.contains("LOUD"), "stdout: {stdout}\nstderr: {stderr}");
+}
+
+#[test]
+fn missing_jshrc_is_silently_skipped() {
+    let home = TempHome::new("missing");
+
+    let output = run_shell_with_home(&["echo started"], home.path(), &[]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("started"));
+    assert!(output.stderr.is_empty(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn jshrc_syntax_error_is_reported_and_shell_still_starts() {
+    let home = TempHome::new("error");
+    let rc = home.write(".jshrc", "alias before='echo BEFORE'\nfi\nalias after='echo AFTER'\n");
+
+    let output = run_shell_with_home(&["before", "echo status=$?"], home.path(), &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("BEFORE"), "stdout: {stdout}");
+    assert!(stdout.contains("status=0"), "stdout: {stdout}");
+    assert!(
+        stderr.contains(&format!("jsh: {}: line 2: syntax error", rc.display())),
+        "stderr: {stderr}"
+    );
+}
+
+#[test]
+fn jsh_rc_overrides_the_startup_file() {
+    let home = TempHome::new("override");
+    home.write(".jshrc", "alias which_rc='echo DEFAULT'\n");
+    let custom = home.write("custom_rc", "alias which_rc='echo CUSTOM'\n");
+
+    let output = run_shell_with_home(
+        &["which_rc"],
+        home.path(),
+        &[("JSH_RC", custom.to_str().unwrap())],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("CUSTOM"), "stdout: {stdout}");
+    assert!(!stdout.contains("DEFAULT"), "stdout: {stdout}");
+
+    // An empty $JSH_RC disables the startup file entirely.
+    let output = run_shell_with_home(&["which_rc"], home.path(), &[("JSH_RC", "")]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("DEFAULT"), "stdout: {stdout}");
+}
+
+#[test]
+fn jshrc_exit_exits_the_shell() {
+    let home = TempHome::new("exit");
+    home.write(".jshrc", "exit 7\n");
+
+    let output = run_shell_with_home(&["echo unreachable"], home.path(), &[]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("unreachable"));
+}
+
+#[test]
+fn norc_skips_the_startup_file_and_login_sources_the_profile() {
+    let home = TempHome::new("login");
+    home.write(".jshrc", "alias from_rc='echo RC'\n");
+    home.write(".jsh_profile", "export FROM_PROFILE=profile\n");
+
+    let output = run_shell_with_args(&["--norc"], &["from_rc", "echo [$FROM_PROFILE]"], home.path(), &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("RC"), "stdout: {stdout}");
+    assert!(stdout.contains("[]"), "stdout: {stdout}");
+
+    let output = run_shell_with_args(&["-l"], &["from_rc", "echo [$FROM_PROFILE]"], home.path(), &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("RC"), "stdout: {stdout}");
+    assert!(stdout.contains("[profile]"), "stdout: {stdout}");
+
+    // -c does not read the startup file, but a login shell reads its profile.
+    let output = run_shell_with_args(&["--login", "-c", "echo $FROM_PROFILE"], &[], home.path(), &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "profile\n");
+}
+
+#[test]
+fn login_shells_turn_on_huponexit_before_the_profile() {
+    let home = TempHome::new("huponexit");
+    home.write(".jsh_profile", "shopt -p huponexit\n");
+
+    let output = run_shell_with_args(&["-l", "-c", "shopt -p huponexit"], &[], home.path(), &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "shopt -s huponexit\nshopt -s huponexit\n");
+
+    let output = run_shell_with_args(&["-c", "shopt -p huponexit"], &[], home.path(), &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "shopt -u huponexit\n");
+}